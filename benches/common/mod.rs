@@ -0,0 +1,43 @@
+use rand::{distr::Uniform, prelude::*};
+
+/// 計測する要素数。
+pub const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+/// 環境変数 BENCH_SEED で指定した seed に offset を加えた値を返す。指定がなければ 0 を基準にする。
+/// criterion は知らない引数を受け付けないため、 seed は `BENCH_SEED=42 cargo bench` のように環境変数で渡す。
+/// 同じ計測の中で独立な乱数列が必要な場合は offset を変える。
+pub fn seed(offset: u64) -> u64 {
+    let base = match std::env::var("BENCH_SEED") {
+        Ok(value) => value
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("BENCH_SEED must be an unsigned integer, found {value}")),
+        Err(_) => 0,
+    };
+    base.wrapping_add(offset)
+}
+
+/// 一辺の長さを要素数に合わせて伸ばした立方体に一様に分布させた点を返す。密度は次元数や要素数によらず 1 になる。
+/// 同じ seed からは同じ点が得られる。
+pub fn uniform_points<const N: usize>(elements: usize, seed: u64) -> Vec<[f64; N]> {
+    let side = (elements as f64).powf(1.0 / N as f64);
+    let distr = Uniform::new(0.0, side).expect("invalid distribution");
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..elements)
+        .map(|_| std::array::from_fn(|_| distr.sample(&mut rng)))
+        .collect()
+}
+
+/// 次元数ごとに $body を評価する。 $body の中では次元数を定数 $n として使える。
+macro_rules! for_dimensions {
+    ($n:ident => $body:expr) => {{
+        for_dimensions!(@ 2, $n => $body);
+        for_dimensions!(@ 3, $n => $body);
+        for_dimensions!(@ 8, $n => $body);
+    }};
+    (@ $value:literal, $n:ident => $body:expr) => {{
+        const $n: usize = $value;
+        $body
+    }};
+}
+
+pub(crate) use for_dimensions;
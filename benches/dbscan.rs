@@ -0,0 +1,323 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dbscan_rust_test::{
+    adjusted_rand_index, datasets, dbscan_with_index, Dbscan, DbscanParams, IndexKind, Parallelism, SliceKdTree,
+};
+
+mod common;
+
+use common::{for_dimensions, seed, uniform_points, SIZES};
+
+const EPSILON: f64 = 1.0;
+const MIN_POINTS: usize = 4;
+
+/// dbscan_large() でクラスタリングする点の数。
+const LARGE_ELEMENTS: usize = 5_000_000;
+
+/// dbscan_large() で Dbscan::run_sampled() に用いる標本の割合。
+const SAMPLE_RATIO: usize = 10;
+
+/// 確保中のバイト数とその最大値を記録するアロケーター。
+struct PeakAllocator {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let current = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            self.peak.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator {
+    current: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
+
+/// f を 1 回実行し、その戻り値と、かかった時間をマイクロ秒で、実行中に増えたヒープの最大量をバイト数で返す。
+/// ヒープは全スレッドの確保を合わせて数える。
+fn measure<R>(f: impl FnOnce() -> R) -> (R, u128, usize) {
+    let base = ALLOCATOR.current.load(Ordering::Relaxed);
+    ALLOCATOR.peak.store(base, Ordering::Relaxed);
+    let started = Instant::now();
+    let result = f();
+    let elapsed = started.elapsed().as_micros();
+    (result, elapsed, ALLOCATOR.peak.load(Ordering::Relaxed) - base)
+}
+
+/// report_single_runs() の 1 回分の計測結果。
+struct Record {
+    seed: u64,
+    dimensions: usize,
+    elements: usize,
+    clusters: usize,
+    total_us: u128,
+    peak_heap_bytes: usize,
+    build_us: u128,
+    neighbor_queries_us: u128,
+    expansion_us: u128,
+    build_peak_heap_bytes: usize,
+    peak_heap_excluding_index_bytes: usize,
+}
+
+impl Record {
+    /// JSON のキーや CSV の列名と、その値。
+    fn columns(&self) -> [(&'static str, u128); 11] {
+        [
+            ("seed", self.seed.into()),
+            ("dimensions", self.dimensions as u128),
+            ("elements", self.elements as u128),
+            ("clusters", self.clusters as u128),
+            ("total_us", self.total_us),
+            ("peak_heap_bytes", self.peak_heap_bytes as u128),
+            ("build_us", self.build_us),
+            ("neighbor_queries_us", self.neighbor_queries_us),
+            ("expansion_us", self.expansion_us),
+            ("build_peak_heap_bytes", self.build_peak_heap_bytes as u128),
+            (
+                "peak_heap_excluding_index_bytes",
+                self.peak_heap_excluding_index_bytes as u128,
+            ),
+        ]
+    }
+}
+
+/// report_single_runs() の結果の書式。環境変数 BENCH_OUTPUT で json か csv を指定すると、
+/// 表示の代わりに target/criterion/dbscan_records.{json,csv} へ書き出す。
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl OutputFormat {
+    fn from_env() -> OutputFormat {
+        match std::env::var("BENCH_OUTPUT").as_deref() {
+            Ok("json") => OutputFormat::Json,
+            Ok("csv") => OutputFormat::Csv,
+            Ok("text") | Err(_) => OutputFormat::Text,
+            Ok(other) => panic!("BENCH_OUTPUT must be json, csv or text, found {other}"),
+        }
+    }
+}
+
+/// 各要素数について、逐次版の DBSCAN を 1 回ずつ実行して計測する。
+/// Dbscan::run_timed() で全体と段階ごとの時間を求め、入力の他に確保するヒープの最大量も求める。
+/// k-d tree の構築と、構築済みの k-d tree でのクラスタリングも別々に実行し、それぞれのヒープの最大量を求める。
+fn report_single_runs() {
+    let format = OutputFormat::from_env();
+    let mut records = Vec::new();
+    for_dimensions!(N => {
+        for elements in SIZES {
+            let points = uniform_points::<N>(elements, seed(0));
+            let dbscan = Dbscan::new(DbscanParams::new(EPSILON, MIN_POINTS));
+            let ((result, timings), total_us, peak_heap_bytes) = measure(|| dbscan.run_timed(&points));
+
+            let (kdtree, _, build_peak_heap_bytes) =
+                measure(|| SliceKdTree::construct(&points).expect("points must be finite"));
+            let (_, _, peak_heap_excluding_index_bytes) =
+                measure(|| dbscan_with_index(&points, &kdtree, EPSILON, MIN_POINTS));
+
+            records.push(Record {
+                seed: seed(0),
+                dimensions: N,
+                elements,
+                clusters: result.cluster_count,
+                total_us,
+                peak_heap_bytes,
+                build_us: timings.index_build.as_micros(),
+                neighbor_queries_us: timings.neighbor_queries.as_micros(),
+                expansion_us: timings.expansion.as_micros(),
+                build_peak_heap_bytes,
+                peak_heap_excluding_index_bytes,
+            });
+        }
+    });
+
+    let written = match format {
+        OutputFormat::Text => {
+            for record in &records {
+                let per_element = |bytes: usize| bytes as f64 / record.elements as f64;
+                println!(
+                    "dbscan/{}d/sequential/{} (seed {}): {} us (build {} us, neighbor queries {} us, expansion {} us), \
+                     peak heap {:.1} bytes per element (build {:.1}, clustering {:.1})",
+                    record.dimensions,
+                    record.elements,
+                    record.seed,
+                    record.total_us,
+                    record.build_us,
+                    record.neighbor_queries_us,
+                    record.expansion_us,
+                    per_element(record.peak_heap_bytes),
+                    per_element(record.build_peak_heap_bytes),
+                    per_element(record.peak_heap_excluding_index_bytes)
+                );
+            }
+            return;
+        }
+        OutputFormat::Json => write_records(&records, "json", write_records_json),
+        OutputFormat::Csv => write_records(&records, "csv", write_records_csv),
+    };
+    written.expect("failed to write benchmark records");
+}
+
+/// records を target/criterion/dbscan_records.{extension} に write で書き出す。
+fn write_records(
+    records: &[Record],
+    extension: &str,
+    write: impl FnOnce(&mut BufWriter<File>, &[Record]) -> io::Result<()>,
+) -> io::Result<()> {
+    let directory = Path::new("target").join("criterion");
+    fs::create_dir_all(&directory)?;
+    let path = directory.join(format!("dbscan_records.{extension}"));
+    let mut output = BufWriter::new(File::create(&path)?);
+    write(&mut output, records)?;
+    output.flush()?;
+    println!("benchmark records written to {}", path.display());
+    Ok(())
+}
+
+fn write_records_json(output: &mut BufWriter<File>, records: &[Record]) -> io::Result<()> {
+    let rows: Vec<_> = records
+        .iter()
+        .map(|r| {
+            let fields: Vec<_> = r
+                .columns()
+                .iter()
+                .map(|(name, value)| format!(r#""{name}":{value}"#))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        })
+        .collect();
+    writeln!(output, "[{}]", rows.join(","))
+}
+
+fn write_records_csv(output: &mut BufWriter<File>, records: &[Record]) -> io::Result<()> {
+    let Some(first) = records.first() else {
+        return Ok(());
+    };
+    let header: Vec<_> = first.columns().iter().map(|(name, _)| *name).collect();
+    writeln!(output, "{}", header.join(","))?;
+    for r in records {
+        let values: Vec<_> = r.columns().iter().map(|(_, value)| value.to_string()).collect();
+        writeln!(output, "{}", values.join(","))?;
+    }
+    Ok(())
+}
+
+fn dbscan(c: &mut Criterion) {
+    report_single_runs();
+    let mut group = c.benchmark_group("dbscan");
+    group.sample_size(20);
+    for_dimensions!(N => {
+        for elements in SIZES {
+            let points = uniform_points::<N>(elements, seed(0));
+            group.throughput(Throughput::Elements(elements as u64));
+            group.bench_with_input(BenchmarkId::new(format!("{N}d/sequential"), elements), &points, |b, points| {
+                let dbscan = Dbscan::new(DbscanParams::new(EPSILON, MIN_POINTS));
+                b.iter(|| dbscan.run(points))
+            });
+            // 一様な点は生成順が空間的にばらばらなので、 Morton 順に並べ替えた探索の効果が現れやすい
+            for (name, morton_order) in [("kdtree", false), ("kdtree-morton", true)] {
+                let id = BenchmarkId::new(format!("{N}d/{name}"), elements);
+                group.bench_with_input(id, &points, |b, points| {
+                    let params = DbscanParams::new(EPSILON, MIN_POINTS)
+                        .index(IndexKind::KdTree)
+                        .morton_order(morton_order);
+                    let dbscan = Dbscan::new(params);
+                    b.iter(|| dbscan.run_points(points))
+                });
+            }
+            if cfg!(feature = "parallel") {
+                group.bench_with_input(BenchmarkId::new(format!("{N}d/parallel"), elements), &points, |b, points| {
+                    let params = DbscanParams::new(EPSILON, MIN_POINTS).parallelism(Parallelism::Parallel);
+                    let dbscan = Dbscan::new(params);
+                    b.iter(|| dbscan.run(points))
+                });
+                let id = BenchmarkId::new(format!("{N}d/precomputed-counts"), elements);
+                group.bench_with_input(id, &points, |b, points| {
+                    let params = DbscanParams::new(EPSILON, MIN_POINTS).parallelism(Parallelism::PrecomputedCounts);
+                    let dbscan = Dbscan::new(params);
+                    b.iter(|| dbscan.run(points))
+                });
+            }
+        }
+    });
+    group.finish();
+}
+
+/// dbscan_datasets() で生成する各クラスターの点の数。
+const DATASET_CLUSTER_ELEMENTS: usize = 5_000;
+
+/// 一様な点とは異なり、クラスターとノイズが混ざった合成データでの計測。
+/// 計測の前に正解ラベルとの adjusted Rand index とヒープの最大量を表示し、速度と引き換えに結果が崩れていないことを確かめられるようにする。
+fn dbscan_datasets(c: &mut Criterion) {
+    let blobs = datasets::blobs(
+        &[[0.0, 0.0], [10.0, 0.0], [0.0, 10.0], [10.0, 10.0]],
+        1.0,
+        DATASET_CLUSTER_ELEMENTS,
+        seed(0),
+    )
+    .with_noise(DATASET_CLUSTER_ELEMENTS / 10, [-5.0, -5.0], [15.0, 15.0], seed(1));
+    let moons = datasets::moons(DATASET_CLUSTER_ELEMENTS, 0.05, seed(0));
+    let cases = [("2d/blobs-with-noise", blobs, 0.2), ("2d/moons", moons, 0.05)];
+
+    let mut group = c.benchmark_group("dbscan_datasets");
+    group.sample_size(20);
+    for (name, dataset, epsilon) in &cases {
+        let dbscan = Dbscan::new(DbscanParams::new(*epsilon, MIN_POINTS));
+        let (result, _, peak_heap_bytes) = measure(|| dbscan.run(&dataset.points));
+        println!(
+            "dbscan_datasets/{name}: adjusted Rand index {:.3}, peak heap {:.1} bytes per element",
+            adjusted_rand_index(&result.labels, &dataset.labels),
+            peak_heap_bytes as f64 / dataset.len() as f64
+        );
+        group.throughput(Throughput::Elements(dataset.len() as u64));
+        group.bench_with_input(BenchmarkId::new(*name, dataset.len()), &dataset.points, |b, points| {
+            b.iter(|| dbscan.run(points))
+        });
+    }
+    group.finish();
+}
+
+/// 大きな入力での厳密な DBSCAN と、標本を用いた近似版の比較。 1 回に数秒かかるため 2 次元だけを最小の回数で計測する。
+fn dbscan_large(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dbscan_large");
+    group.sample_size(10);
+    let points = uniform_points::<2>(LARGE_ELEMENTS, seed(0));
+    let dbscan = Dbscan::new(DbscanParams::new(EPSILON, MIN_POINTS));
+    group.throughput(Throughput::Elements(LARGE_ELEMENTS as u64));
+    group.bench_with_input(
+        BenchmarkId::new("2d/sequential", LARGE_ELEMENTS),
+        &points,
+        |b, points| b.iter(|| dbscan.run(points)),
+    );
+    let id = BenchmarkId::new(format!("2d/sampled-1/{SAMPLE_RATIO}"), LARGE_ELEMENTS);
+    group.bench_with_input(id, &points, |b, points| {
+        b.iter(|| dbscan.run_sampled(points, LARGE_ELEMENTS / SAMPLE_RATIO, seed(0)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, dbscan, dbscan_datasets, dbscan_large);
+criterion_main!(benches);
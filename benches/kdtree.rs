@@ -0,0 +1,117 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use dbscan_rust_test::{ImplicitKdTree, KdTree, SpatialIndex};
+
+mod common;
+
+use common::{for_dimensions, seed, uniform_points, SIZES};
+
+/// 探索に用いる点の数。計測中はこれらを順に使い回す。
+const QUERY_COUNT: usize = 1024;
+
+/// construct_large() で構築する点の数。
+const LARGE_ELEMENTS: usize = 10_000_000;
+
+fn construct(c: &mut Criterion) {
+    let mut group = c.benchmark_group("kdtree/construct");
+    for_dimensions!(N => {
+        for elements in SIZES {
+            let points = uniform_points::<N>(elements, seed(0));
+            group.throughput(Throughput::Elements(elements as u64));
+            group.bench_with_input(BenchmarkId::new(format!("{N}d"), elements), &points, |b, points| {
+                b.iter_batched(|| points.clone(), KdTree::construct_unchecked, BatchSize::LargeInput)
+            });
+        }
+    });
+    group.finish();
+}
+
+/// 大きな入力での構築。 1 回に数秒かかるため 2 次元だけを最小の回数で計測する。
+fn construct_large(c: &mut Criterion) {
+    let mut group = c.benchmark_group("kdtree/construct_large");
+    group.sample_size(10);
+    let points = uniform_points::<2>(LARGE_ELEMENTS, seed(0));
+    group.throughput(Throughput::Elements(LARGE_ELEMENTS as u64));
+    group.bench_with_input(BenchmarkId::new("2d", LARGE_ELEMENTS), &points, |b, points| {
+        b.iter_batched(|| points.clone(), KdTree::construct_unchecked, BatchSize::LargeInput)
+    });
+    #[cfg(feature = "parallel")]
+    group.bench_with_input(BenchmarkId::new("2d/parallel", LARGE_ELEMENTS), &points, |b, points| {
+        b.iter_batched(|| points.clone(), KdTree::construct_par, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+fn find_nearest_n(c: &mut Criterion) {
+    let mut group = c.benchmark_group("kdtree/find_nearest_n");
+    for_dimensions!(N => {
+        for elements in SIZES {
+            let tree = KdTree::construct_unchecked(uniform_points::<N>(elements, seed(0)));
+            let queries = uniform_points::<N>(QUERY_COUNT, seed(1));
+            for k in [1, 10] {
+                let id = BenchmarkId::new(format!("{N}d/k={k}"), elements);
+                group.bench_with_input(id, &queries, |b, queries| {
+                    let mut queries = queries.iter().cycle();
+                    b.iter(|| tree.find_nearest_n_unchecked(queries.next().expect("queries must not be empty"), k))
+                });
+            }
+        }
+    });
+    group.finish();
+}
+
+fn find_range_n(c: &mut Criterion) {
+    let mut group = c.benchmark_group("kdtree/find_range_n");
+    for_dimensions!(N => {
+        for elements in SIZES {
+            let points = uniform_points::<N>(elements, seed(0));
+            let tree = KdTree::construct_unchecked(points.clone());
+            let implicit_tree = ImplicitKdTree::construct(points).expect("points must be finite");
+            let queries = uniform_points::<N>(QUERY_COUNT, seed(1));
+            // 密度 1 の点に対して半径 1 の球にはおよそ数個の点が入る
+            group.bench_with_input(BenchmarkId::new(format!("{N}d"), elements), &queries, |b, queries| {
+                let mut queries = queries.iter().cycle();
+                b.iter(|| tree.find_range_n_unchecked(queries.next().expect("queries must not be empty"), &1.0))
+            });
+            group.bench_with_input(BenchmarkId::new(format!("{N}d/implicit"), elements), &queries, |b, queries| {
+                let mut queries = queries.iter().cycle();
+                b.iter(|| implicit_tree.range(queries.next().expect("queries must not be empty"), &1.0))
+            });
+        }
+    });
+    group.finish();
+}
+
+/// DBSCAN と同様にすべての要素を query として探索する。 query は要素の生成順、つまり空間的にばらばらな順に並ぶ。
+fn find_range_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("kdtree/find_range_batch");
+    group.sample_size(10);
+    for_dimensions!(N => {
+        for elements in SIZES {
+            let points = uniform_points::<N>(elements, seed(0));
+            let tree = KdTree::construct_unchecked(points.clone());
+            group.throughput(Throughput::Elements(elements as u64));
+            group.bench_with_input(BenchmarkId::new(format!("{N}d/single"), elements), &points, |b, points| {
+                b.iter(|| {
+                    points
+                        .iter()
+                        .map(|query| tree.find_range_n_indices(query, &1.0))
+                        .collect::<Vec<_>>()
+                })
+            });
+            group.bench_with_input(BenchmarkId::new(format!("{N}d/batch"), elements), &points, |b, points| {
+                b.iter(|| tree.find_range_batch(points, &1.0))
+            });
+        }
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    construct,
+    construct_large,
+    find_nearest_n,
+    find_range_n,
+    find_range_batch
+);
+criterion_main!(benches);
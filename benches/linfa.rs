@@ -0,0 +1,39 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dbscan_rust_test::{Dbscan, DbscanParams};
+use linfa::{traits::Transformer, ParamGuard};
+use ndarray::Array2;
+
+mod common;
+
+use common::{for_dimensions, seed, uniform_points, SIZES};
+
+const EPSILON: f64 = 1.0;
+const MIN_POINTS: usize = 4;
+
+/// 計測する要素数の上限。 linfa-clustering は時間がかかるため、 SIZES のうちこれ以下のものだけを比べる。
+const MAX_ELEMENTS: usize = 10_000;
+
+/// 同じ点と設定で、 Transformer として使った Dbscan と linfa-clustering の Dbscan を比べる。
+fn linfa(c: &mut Criterion) {
+    let mut group = c.benchmark_group("linfa");
+    group.sample_size(10);
+    for_dimensions!(N => {
+        for elements in SIZES.into_iter().filter(|&e| e <= MAX_ELEMENTS) {
+            let points = uniform_points::<N>(elements, seed(0));
+            let observations = Array2::from_shape_vec((elements, N), points.concat()).expect("must have N columns");
+            group.throughput(Throughput::Elements(elements as u64));
+            group.bench_with_input(BenchmarkId::new(format!("{N}d/dbscan-rust-test"), elements), &observations, |b, observations| {
+                let dbscan = Dbscan::new(DbscanParams::new(EPSILON, MIN_POINTS));
+                b.iter(|| dbscan.transform(observations))
+            });
+            group.bench_with_input(BenchmarkId::new(format!("{N}d/linfa-clustering"), elements), &observations, |b, observations| {
+                let params = linfa_clustering::Dbscan::params(MIN_POINTS).tolerance(EPSILON).check_unwrap();
+                b.iter(|| params.transform(observations))
+            });
+        }
+    });
+    group.finish();
+}
+
+criterion_group!(benches, linfa);
+criterion_main!(benches);
@@ -0,0 +1,125 @@
+#ifndef DBSCAN_RUST_TEST_H
+#define DBSCAN_RUST_TEST_H
+
+/* cbindgen で src/cabi.rs から生成される。直接編集しないこと。 */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// C から扱える点の次元数の上限。
+#define DBSCAN_MAX_DIMENSIONS 8
+
+// C から呼ばれる関数が返す結果。
+typedef enum DbscanStatus {
+  DBSCAN_STATUS_OK = 0,
+  // 必須のポインターに NULL が渡された。
+  DBSCAN_STATUS_NULL_POINTER = 1,
+  // 次元数が 1 から DBSCAN_MAX_DIMENSIONS の範囲にない。
+  DBSCAN_STATUS_INVALID_DIMENSIONS = 2,
+  // 出力先の容量が足りず、結果の一部だけを書き込んだ。
+  DBSCAN_STATUS_BUFFER_TOO_SMALL = 3,
+  // 内部で panic した。
+  DBSCAN_STATUS_PANIC = 4,
+  // 座標に NaN や無限大が含まれているか、 epsilon や探索半径が NaN か負である。
+  DBSCAN_STATUS_NON_FINITE_INPUT = 5,
+  // count * dimensions 個の座標の大きさが isize::MAX バイトを超える。
+  DBSCAN_STATUS_TOO_MANY_POINTS = 6,
+} DbscanStatus;
+
+// kdtree_new() で構築される k-d tree 。 C からは不透明な型として扱う。
+typedef struct DbscanKdTree DbscanKdTree;
+
+// count 個の dimensions 次元の点 (row-major) を f32 で DBSCAN し、各点のラベルを labels_out に書き込む。
+// ラベルは DbscanLabel::to_code() の整数 (ノイズは -1) になる。
+//
+// # Safety
+//
+// points は count * dimensions 個の f32 を、 labels_out は count 個の i32 を指していなければならない。
+// count が 0 の場合に限りどちらも NULL でよい。
+enum DbscanStatus dbscan_f32(const float *points,
+                             size_t count,
+                             size_t dimensions,
+                             float epsilon,
+                             size_t min_points,
+                             int32_t *labels_out);
+
+// dbscan_f32() の f64 版。
+//
+// # Safety
+//
+// points は count * dimensions 個の f64 を、 labels_out は count 個の i32 を指していなければならない。
+// count が 0 の場合に限りどちらも NULL でよい。
+enum DbscanStatus dbscan_f64(const double *points,
+                             size_t count,
+                             size_t dimensions,
+                             double epsilon,
+                             size_t min_points,
+                             int32_t *labels_out);
+
+// count 個の dimensions 次元の点 (row-major) から k-d tree を構築する。点は複製される。
+// 座標に NaN や無限大が含まれている場合や、座標の大きさが isize::MAX バイトを超える場合など、失敗した場合は NULL を返す。返されたツリーは kdtree_free() で解放しなければならない。
+//
+// # Safety
+//
+// points は count * dimensions 個の f32 を指していなければならない。 count が 0 の場合に限り NULL でよい。
+struct DbscanKdTree *kdtree_new(const float *points,
+                                size_t count,
+                                size_t dimensions);
+
+// kdtree_new() で構築したツリーを解放する。 NULL が渡された場合は何もしない。
+//
+// # Safety
+//
+// tree は kdtree_new() が返したもので、まだ解放されていてはならない。
+void kdtree_free(struct DbscanKdTree *tree);
+
+// ツリーに含まれる点の数を返す。 tree が NULL であれば 0 を返す。
+//
+// # Safety
+//
+// tree は NULL か、 kdtree_new() が返した解放されていないツリーでなければならない。
+size_t kdtree_len(const struct DbscanKdTree *tree);
+
+// ツリーの次元数を返す。 tree が NULL であれば 0 を返す。
+//
+// # Safety
+//
+// tree は NULL か、 kdtree_new() が返した解放されていないツリーでなければならない。
+size_t kdtree_dimensions(const struct DbscanKdTree *tree);
+
+// query から radius 以内 (境界を含む) にある点の、構築時の位置を順不同で indices_out に書き込む。
+// 見つかった点の総数を count_out に書き込み、 capacity を超えた分は書き込まずに BufferTooSmall を返す。
+// radius が NaN か負であれば NonFiniteInput を返す。
+//
+// # Safety
+//
+// tree は kdtree_new() が返した解放されていないツリーで、 query はツリーの次元数の f32 を、
+// indices_out は capacity 個の size_t を、 count_out は 1 個の size_t を指していなければならない。
+// capacity が 0 の場合に限り indices_out は NULL でよい。
+enum DbscanStatus kdtree_query_radius(const struct DbscanKdTree *tree,
+                                      const float *query,
+                                      float radius,
+                                      size_t *indices_out,
+                                      size_t capacity,
+                                      size_t *count_out);
+
+// query に近い順に最大 k 個の点の、構築時の位置と距離を indices_out と distances_out に書き込む。
+// 書き込んだ数を count_out に書き込む。
+//
+// # Safety
+//
+// tree は kdtree_new() が返した解放されていないツリーで、 query はツリーの次元数の f32 を、
+// indices_out は k 個の size_t を、 count_out は 1 個の size_t を指していなければならない。
+// distances_out は NULL か k 個の f32 を指していなければならず、 NULL であれば距離は書き込まない。
+// k が 0 の場合に限り indices_out は NULL でよい。
+enum DbscanStatus kdtree_query_nearest(const struct DbscanKdTree *tree,
+                                       const float *query,
+                                       size_t k,
+                                       size_t *indices_out,
+                                       float *distances_out,
+                                       size_t *count_out);
+
+#endif  /* DBSCAN_RUST_TEST_H */
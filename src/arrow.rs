@@ -0,0 +1,148 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Debug},
+};
+
+use arrow_array::{
+    cast::AsArray,
+    types::{ArrowPrimitiveType, Float32Type, Float64Type},
+    Array, ArrayRef, Int32Array, RecordBatch,
+};
+use arrow_schema::{ArrowError, DataType};
+use num_traits::Float;
+
+use crate::{
+    dbscan::{Dbscan, DbscanParams},
+    kdtree::KdTreeItem,
+};
+
+/// batch の columns の列を座標として DBSCAN を行い、各行の DbscanLabel::to_code() を Int32Array で返す。
+/// 列はすべて Float64 かすべて Float32 で、 null を含んではならない。座標は複製せずに各列のバッファを参照し、
+/// 距離は列の型によらず f64 で計算する。
+/// Polars の DataFrame は Arrow の RecordBatch に変換してから渡す。
+/// 列が見つからない、型が合わない、 null を含む場合は ArrowError::InvalidArgumentError を返す。
+/// 座標や epsilon が不正であれば crate::Error を ArrowError::ExternalError に包んで返す。
+pub fn dbscan_arrow(
+    batch: &RecordBatch,
+    columns: &[&str],
+    params: DbscanParams<f64>,
+) -> Result<Int32Array, ArrowError> {
+    if columns.is_empty() {
+        return Err(ArrowError::InvalidArgumentError(
+            "at least one column is required".into(),
+        ));
+    }
+    let arrays = columns
+        .iter()
+        .map(|&name| {
+            batch
+                .column_by_name(name)
+                .ok_or_else(|| ArrowError::InvalidArgumentError(format!("column {name} not found")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some((name, _)) = columns.iter().zip(&arrays).find(|(_, a)| a.null_count() > 0) {
+        return Err(ArrowError::InvalidArgumentError(format!(
+            "column {name} contains nulls"
+        )));
+    }
+
+    let data_type = arrays[0].data_type();
+    if let Some((name, array)) = columns.iter().zip(&arrays).find(|(_, a)| a.data_type() != data_type) {
+        return Err(ArrowError::InvalidArgumentError(format!(
+            "column {name} has type {}, expected {data_type}",
+            array.data_type()
+        )));
+    }
+    let labels = match data_type {
+        DataType::Float64 => run::<Float64Type>(&arrays, batch.num_rows(), params)?,
+        DataType::Float32 => run::<Float32Type>(&arrays, batch.num_rows(), params)?,
+        data_type => {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "coordinate columns must be Float64 or Float32, found {data_type}"
+            )))
+        }
+    };
+    Ok(Int32Array::from(labels))
+}
+
+/// 型 P の列 arrays を座標としてクラスタリングする。
+fn run<P>(arrays: &[&ArrayRef], rows: usize, params: DbscanParams<f64>) -> Result<Vec<i32>, ArrowError>
+where
+    P: ArrowPrimitiveType,
+    P::Native: Debug + Float + Into<f64> + Sync,
+{
+    let columns: Vec<&[P::Native]> = arrays.iter().map(|a| a.as_primitive::<P>().values().as_ref()).collect();
+    let items: Vec<_> = (0..rows).map(|row| ColumnRow { columns: &columns, row }).collect();
+    let result = Dbscan::new(params)
+        .try_run(items)
+        .map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+    Ok(result.labels.iter().map(|l| l.to_code()).collect())
+}
+
+/// 列ごとに分かれた座標の 1 行を指す点。距離は f64 のユークリッド距離になる。
+#[derive(Clone)]
+struct ColumnRow<'a, F> {
+    columns: &'a [&'a [F]],
+    row: usize,
+}
+
+impl<F> ColumnRow<'_, F> {
+    fn coordinate(&self, depth: usize) -> f64
+    where
+        F: Copy + Into<f64>,
+    {
+        self.columns[depth % self.columns.len()][self.row].into()
+    }
+
+    fn coordinates(&self) -> impl Iterator<Item = &F> {
+        self.columns.iter().map(|c| &c[self.row])
+    }
+}
+
+impl<F: Debug> Debug for ColumnRow<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.coordinates()).finish()
+    }
+}
+
+impl<F: Debug + Float + Into<f64>> KdTreeItem for ColumnRow<'_, F> {
+    type Measurement = f64;
+
+    fn cmp_in_depth(&self, rhs: &Self, depth: usize) -> Ordering {
+        self.coordinate(depth)
+            .partial_cmp(&rhs.coordinate(depth))
+            .expect("not total order")
+    }
+
+    fn distance(&self, other: &Self) -> f64 {
+        self.reduced_distance(other).sqrt()
+    }
+
+    fn distance_to_axis(&self, other: &Self, depth: usize) -> f64 {
+        (self.coordinate(depth) - other.coordinate(depth)).abs()
+    }
+
+    /// 平方根をとらない 2 乗のユークリッド距離。
+    fn reduced_distance(&self, other: &Self) -> f64 {
+        self.coordinates()
+            .zip(other.coordinates())
+            .map(|(&l, &r)| (l.into() - r.into()).powi(2))
+            .sum()
+    }
+
+    fn reduced_distance_to_axis(&self, other: &Self, depth: usize) -> f64 {
+        (self.coordinate(depth) - other.coordinate(depth)).powi(2)
+    }
+
+    fn reduced_to_distance(reduced: &f64) -> f64 {
+        reduced.sqrt()
+    }
+
+    fn distance_to_reduced(distance: &f64) -> f64 {
+        distance.powi(2)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.coordinates().all(|c| c.is_finite())
+    }
+}
@@ -0,0 +1,257 @@
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use num_traits::{Float, Zero};
+
+use crate::{
+    error::Error,
+    index::SpatialIndex,
+    kdtree::{validate_items, KdTreeItem},
+    knn::KBest,
+    metric::{ItemMetric, Metric},
+};
+
+/// ball tree を表す。
+/// 各ノードは中心となる要素と、部分木のすべての要素を含む半径を持つ。
+/// 座標軸による分割を行わず距離だけを用いるため、 k-d tree の枝刈りが効かない高次元の要素に向く。
+/// 距離は三角不等式を満たさなければならない。
+pub struct BallTree<T, M: Metric<T> = ItemMetric> {
+    /// 各ノードの範囲が連続するように並べ替えた要素。
+    items: Vec<T>,
+
+    /// items のそれぞれが構築時に渡された位置。
+    indices: Vec<usize>,
+
+    nodes: Vec<BallNode<M::Measurement>>,
+    metric: M,
+}
+
+struct BallNode<D> {
+    /// 中心となる要素の items 上の位置。構築中は構築時に渡された位置を持つ。
+    pivot: usize,
+
+    /// pivot から部分木の最も遠い要素までの距離。
+    radius: D,
+
+    /// 部分木の要素の items 上の範囲。
+    range: Range<usize>,
+
+    /// 子ノードの nodes 上の位置。葉ノードであれば None になる。
+    children: Option<[usize; 2]>,
+}
+
+impl<T: KdTreeItem> BallTree<T>
+where
+    T::Measurement: Float,
+{
+    /// items から ball tree を構築する。座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+    pub fn construct(items: impl Into<Vec<T>>) -> Result<BallTree<T>, Error> {
+        let items = items.into();
+        validate_items(&items)?;
+        Ok(BallTree::construct_unchecked(items))
+    }
+
+    /// construct() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+    pub fn construct_unchecked(items: impl Into<Vec<T>>) -> BallTree<T> {
+        BallTree::construct_with_metric(items, ItemMetric)
+    }
+}
+
+impl<T, M: Metric<T>> BallTree<T, M>
+where
+    M::Measurement: Float,
+{
+    /// 距離の計算に metric を用いる ball tree を構築する。
+    /// 入力を検証しないため、距離が NaN になる要素があると panic する。
+    pub fn construct_with_metric(items: impl Into<Vec<T>>, metric: M) -> BallTree<T, M> {
+        let items = items.into();
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        let mut nodes: Vec<BallNode<M::Measurement>> = Vec::new();
+        let distance = |a: usize, b: usize| metric.distance(&items[a], &items[b]);
+
+        // (範囲, 親ノードと左右のどちらの子か) を積み、親の children は子を作った時点で設定する
+        let mut pending = vec![];
+        if !items.is_empty() {
+            pending.push((0..items.len(), None::<(usize, usize)>));
+        }
+        while let Some((range, parent)) = pending.pop() {
+            let part = &mut order[range.clone()];
+
+            // 互いに遠い 2 要素を選び、両者からの距離の大きい方が最小となる要素を中心とする
+            let far_a = farthest(part, part[0], &distance);
+            let far_b = farthest(part, far_a, &distance);
+            let mut keyed: Vec<_> = part
+                .iter()
+                .map(|&i| (i, distance(i, far_a), distance(i, far_b)))
+                .collect();
+            let pivot = keyed
+                .iter()
+                .min_by(|lhs, rhs| {
+                    lhs.1
+                        .max(lhs.2)
+                        .partial_cmp(&rhs.1.max(rhs.2))
+                        .expect("not total order")
+                })
+                .expect("range must not be empty")
+                .0;
+            let radius = part
+                .iter()
+                .map(|&i| distance(i, pivot))
+                .fold(M::Measurement::zero(), M::Measurement::max);
+
+            let node_index = nodes.len();
+            nodes.push(BallNode {
+                pivot,
+                radius,
+                range: range.clone(),
+                children: None,
+            });
+            if let Some((parent, side)) = parent {
+                nodes[parent].children.get_or_insert([0; 2])[side] = node_index;
+            }
+
+            if part.len() <= LEAF_SIZE {
+                continue;
+            }
+
+            // far_a と far_b のどちらに近いかで半分ずつに分ける
+            let mid = part.len() / 2;
+            keyed.select_nth_unstable_by(mid, |lhs, rhs| {
+                (lhs.1 - lhs.2).partial_cmp(&(rhs.1 - rhs.2)).expect("not total order")
+            });
+            for (slot, (i, _, _)) in part.iter_mut().zip(keyed) {
+                *slot = i;
+            }
+            pending.push((range.start + mid..range.end, Some((node_index, 1))));
+            pending.push((range.start..range.start + mid, Some((node_index, 0))));
+        }
+
+        // pivot を構築時の位置から並べ替えた後の位置に読み替える
+        let mut positions = vec![0; order.len()];
+        for (position, &i) in order.iter().enumerate() {
+            positions[i] = position;
+        }
+        for node in &mut nodes {
+            node.pivot = positions[node.pivot];
+        }
+
+        let mut items: Vec<_> = items.into_iter().map(Some).collect();
+        BallTree {
+            items: order
+                .iter()
+                .map(|&i| items[i].take().expect("each item must be taken once"))
+                .collect(),
+            indices: order,
+            nodes,
+            metric,
+        }
+    }
+}
+
+impl<T, M: Metric<T>> BallTree<T, M> {
+    /// 距離の計算に用いられる Metric を返す。
+    pub fn metric(&self) -> &M {
+        &self.metric
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 要素とその構築時の位置を順不同で返す。
+    pub fn iter_with_indices(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.indices.iter().copied().zip(&self.items)
+    }
+}
+
+impl<T, M: Metric<T>> SpatialIndex<T> for BallTree<T, M>
+where
+    M::Measurement: Float,
+{
+    type Measurement = M::Measurement;
+
+    fn range(&self, query: &T, radius: &M::Measurement) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            // 球が query を中心とする半径 radius の球と交わらなければ枝刈り
+            let pivot_distance = self.metric.distance(query, &self.items[node.pivot]);
+            if pivot_distance - node.radius > *radius {
+                continue;
+            }
+
+            match node.children {
+                Some(children) => stack.extend(children),
+                None => {
+                    for i in node.range.clone() {
+                        if self.metric.distance(query, &self.items[i]) <= *radius {
+                            found.push(self.indices[i]);
+                        }
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, M::Measurement)> {
+        let mut best = KBest::new(k);
+        if k == 0 || self.nodes.is_empty() {
+            return best.into_vec();
+        }
+
+        // (ノード, query から球までの距離の下界) を積み、近い子ノードから探索する
+        let bound = |node: &BallNode<M::Measurement>| {
+            let pivot_distance = self.metric.distance(query, &self.items[node.pivot]);
+            (pivot_distance - node.radius).max(M::Measurement::zero())
+        };
+        let mut stack = vec![(0, bound(&self.nodes[0]))];
+        while let Some((node_index, lower_bound)) = stack.pop() {
+            if best.excludes(&lower_bound) {
+                continue;
+            }
+
+            let node = &self.nodes[node_index];
+            match node.children {
+                Some(children) => {
+                    let mut children = children.map(|c| (c, bound(&self.nodes[c])));
+                    if children[0].1 < children[1].1 {
+                        children.swap(0, 1);
+                    }
+                    stack.extend(children);
+                }
+                None => {
+                    for i in node.range.clone() {
+                        best.offer(self.indices[i], self.metric.distance(query, &self.items[i]));
+                    }
+                }
+            }
+        }
+
+        best.into_vec()
+    }
+}
+
+/// 葉ノードに格納する要素数の上限。
+const LEAF_SIZE: usize = 16;
+
+/// part の中で from から最も遠い要素を返す。
+fn farthest<D: Float>(part: &[usize], from: usize, distance: &impl Fn(usize, usize) -> D) -> usize {
+    *part
+        .iter()
+        .max_by(|&&lhs, &&rhs| {
+            distance(lhs, from)
+                .partial_cmp(&distance(rhs, from))
+                .expect("not total order")
+        })
+        .expect("range must not be empty")
+}
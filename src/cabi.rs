@@ -0,0 +1,399 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use num_traits::Float;
+
+use crate::{
+    dbscan::{Dbscan, DbscanParams},
+    kdtree::KdTree,
+};
+
+/// C から呼ばれる関数が返す結果。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbscanStatus {
+    Ok = 0,
+
+    /// 必須のポインターに NULL が渡された。
+    NullPointer = 1,
+
+    /// 次元数が 1 から DBSCAN_MAX_DIMENSIONS の範囲にない。
+    InvalidDimensions = 2,
+
+    /// 出力先の容量が足りず、結果の一部だけを書き込んだ。
+    BufferTooSmall = 3,
+
+    /// 内部で panic した。
+    Panic = 4,
+
+    /// 座標に NaN や無限大が含まれているか、 epsilon や探索半径が NaN か負である。
+    NonFiniteInput = 5,
+
+    /// count * dimensions 個の座標の大きさが isize::MAX バイトを超える。
+    TooManyPoints = 6,
+}
+
+/// C から扱える点の次元数の上限。
+pub const DBSCAN_MAX_DIMENSIONS: usize = 8;
+
+/// kdtree_new() で構築される k-d tree 。 C からは不透明な型として扱う。
+pub struct DbscanKdTree(AnyKdTree);
+
+/// 次元数ごとの KdTree 。
+enum AnyKdTree {
+    D1(KdTree<[f32; 1]>),
+    D2(KdTree<[f32; 2]>),
+    D3(KdTree<[f32; 3]>),
+    D4(KdTree<[f32; 4]>),
+    D5(KdTree<[f32; 5]>),
+    D6(KdTree<[f32; 6]>),
+    D7(KdTree<[f32; 7]>),
+    D8(KdTree<[f32; 8]>),
+}
+
+/// 実行時の次元数 $dimensions を定数 $n として $body を評価する。対応しない次元数では $otherwise を評価する。
+macro_rules! with_dimensions {
+    ($dimensions:expr, $n:ident => $body:expr, $otherwise:expr) => {
+        match $dimensions {
+            1 => with_dimensions!(@ 1, $n => $body),
+            2 => with_dimensions!(@ 2, $n => $body),
+            3 => with_dimensions!(@ 3, $n => $body),
+            4 => with_dimensions!(@ 4, $n => $body),
+            5 => with_dimensions!(@ 5, $n => $body),
+            6 => with_dimensions!(@ 6, $n => $body),
+            7 => with_dimensions!(@ 7, $n => $body),
+            8 => with_dimensions!(@ 8, $n => $body),
+            _ => $otherwise,
+        }
+    };
+    (@ $value:literal, $n:ident => $body:expr) => {{
+        const $n: usize = $value;
+        $body
+    }};
+}
+
+/// AnyKdTree の中身を $tree として $body を評価する。
+macro_rules! with_tree {
+    ($any:expr, $tree:ident => $body:expr) => {
+        match $any {
+            AnyKdTree::D1($tree) => $body,
+            AnyKdTree::D2($tree) => $body,
+            AnyKdTree::D3($tree) => $body,
+            AnyKdTree::D4($tree) => $body,
+            AnyKdTree::D5($tree) => $body,
+            AnyKdTree::D6($tree) => $body,
+            AnyKdTree::D7($tree) => $body,
+            AnyKdTree::D8($tree) => $body,
+        }
+    };
+}
+
+/// count 個の dimensions 次元の点 (row-major) を f32 で DBSCAN し、各点のラベルを labels_out に書き込む。
+/// ラベルは DbscanLabel::to_code() の整数 (ノイズは -1) になる。
+///
+/// # Safety
+///
+/// points は count * dimensions 個の f32 を、 labels_out は count 個の i32 を指していなければならない。
+/// count が 0 の場合に限りどちらも NULL でよい。
+#[no_mangle]
+pub unsafe extern "C" fn dbscan_f32(
+    points: *const f32,
+    count: usize,
+    dimensions: usize,
+    epsilon: f32,
+    min_points: usize,
+    labels_out: *mut i32,
+) -> DbscanStatus {
+    unsafe { dbscan_raw(points, count, dimensions, epsilon, min_points, labels_out) }
+}
+
+/// dbscan_f32() の f64 版。
+///
+/// # Safety
+///
+/// points は count * dimensions 個の f64 を、 labels_out は count 個の i32 を指していなければならない。
+/// count が 0 の場合に限りどちらも NULL でよい。
+#[no_mangle]
+pub unsafe extern "C" fn dbscan_f64(
+    points: *const f64,
+    count: usize,
+    dimensions: usize,
+    epsilon: f64,
+    min_points: usize,
+    labels_out: *mut i32,
+) -> DbscanStatus {
+    unsafe { dbscan_raw(points, count, dimensions, epsilon, min_points, labels_out) }
+}
+
+/// dbscan_f32() と dbscan_f64() の本体。
+unsafe fn dbscan_raw<F: std::fmt::Debug + Float + Sync>(
+    points: *const F,
+    count: usize,
+    dimensions: usize,
+    epsilon: F,
+    min_points: usize,
+    labels_out: *mut i32,
+) -> DbscanStatus {
+    if !(1..=DBSCAN_MAX_DIMENSIONS).contains(&dimensions) {
+        return DbscanStatus::InvalidDimensions;
+    }
+    let Some(coordinate_count) = coordinate_count::<F>(count, dimensions) else {
+        return DbscanStatus::TooManyPoints;
+    };
+    let Some(points) = (unsafe { raw_slice(points, coordinate_count) }) else {
+        return DbscanStatus::NullPointer;
+    };
+    let Some(labels_out) = (unsafe { raw_slice_mut(labels_out, count) }) else {
+        return DbscanStatus::NullPointer;
+    };
+    if !is_finite(points) || !is_valid_radius(epsilon) {
+        return DbscanStatus::NonFiniteInput;
+    }
+
+    catch_panic(|| {
+        let dbscan = Dbscan::new(DbscanParams::new(epsilon, min_points));
+        let result = with_dimensions!(
+            dimensions,
+            N => dbscan.run_points(to_arrays::<F, N>(points)),
+            unreachable!("dimensions must be checked")
+        );
+        for (out, label) in labels_out.iter_mut().zip(&result.labels) {
+            *out = label.to_code();
+        }
+        DbscanStatus::Ok
+    })
+}
+
+/// count 個の dimensions 次元の点 (row-major) から k-d tree を構築する。点は複製される。
+/// 座標に NaN や無限大が含まれている場合や、座標の大きさが isize::MAX バイトを超える場合など、失敗した場合は NULL を返す。返されたツリーは kdtree_free() で解放しなければならない。
+///
+/// # Safety
+///
+/// points は count * dimensions 個の f32 を指していなければならない。 count が 0 の場合に限り NULL でよい。
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_new(points: *const f32, count: usize, dimensions: usize) -> *mut DbscanKdTree {
+    let Some(coordinate_count) = coordinate_count::<f32>(count, dimensions) else {
+        return ptr::null_mut();
+    };
+    let Some(points) = (unsafe { raw_slice(points, coordinate_count) }) else {
+        return ptr::null_mut();
+    };
+    if !is_finite(points) {
+        return ptr::null_mut();
+    }
+
+    let tree = panic::catch_unwind(|| {
+        with_dimensions!(
+            dimensions,
+            N => Some(AnyKdTree::from(KdTree::construct_unchecked(to_arrays::<f32, N>(points)))),
+            None
+        )
+    });
+    match tree {
+        Ok(Some(tree)) => Box::into_raw(Box::new(DbscanKdTree(tree))),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// kdtree_new() で構築したツリーを解放する。 NULL が渡された場合は何もしない。
+///
+/// # Safety
+///
+/// tree は kdtree_new() が返したもので、まだ解放されていてはならない。
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_free(tree: *mut DbscanKdTree) {
+    if !tree.is_null() {
+        drop(unsafe { Box::from_raw(tree) });
+    }
+}
+
+/// ツリーに含まれる点の数を返す。 tree が NULL であれば 0 を返す。
+///
+/// # Safety
+///
+/// tree は NULL か、 kdtree_new() が返した解放されていないツリーでなければならない。
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_len(tree: *const DbscanKdTree) -> usize {
+    match unsafe { tree.as_ref() } {
+        Some(DbscanKdTree(tree)) => with_tree!(tree, tree => tree.len()),
+        None => 0,
+    }
+}
+
+/// ツリーの次元数を返す。 tree が NULL であれば 0 を返す。
+///
+/// # Safety
+///
+/// tree は NULL か、 kdtree_new() が返した解放されていないツリーでなければならない。
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_dimensions(tree: *const DbscanKdTree) -> usize {
+    match unsafe { tree.as_ref() } {
+        Some(DbscanKdTree(tree)) => with_tree!(tree, tree => dimensions_of(tree)),
+        None => 0,
+    }
+}
+
+/// query から radius 以内 (境界を含む) にある点の、構築時の位置を順不同で indices_out に書き込む。
+/// 見つかった点の総数を count_out に書き込み、 capacity を超えた分は書き込まずに BufferTooSmall を返す。
+/// radius が NaN か負であれば NonFiniteInput を返す。
+///
+/// # Safety
+///
+/// tree は kdtree_new() が返した解放されていないツリーで、 query はツリーの次元数の f32 を、
+/// indices_out は capacity 個の size_t を、 count_out は 1 個の size_t を指していなければならない。
+/// capacity が 0 の場合に限り indices_out は NULL でよい。
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_query_radius(
+    tree: *const DbscanKdTree,
+    query: *const f32,
+    radius: f32,
+    indices_out: *mut usize,
+    capacity: usize,
+    count_out: *mut usize,
+) -> DbscanStatus {
+    let Some(DbscanKdTree(tree)) = (unsafe { tree.as_ref() }) else {
+        return DbscanStatus::NullPointer;
+    };
+    let dimensions = with_tree!(tree, tree => dimensions_of(tree));
+    let (Some(query), Some(indices_out), Some(count_out)) = (
+        unsafe { raw_slice(query, dimensions) },
+        unsafe { raw_slice_mut(indices_out, capacity) },
+        unsafe { count_out.as_mut() },
+    ) else {
+        return DbscanStatus::NullPointer;
+    };
+    if !is_finite(query) || !is_valid_radius(radius) {
+        return DbscanStatus::NonFiniteInput;
+    }
+
+    catch_panic(|| {
+        let found = with_tree!(tree, tree => tree.find_range_n_indices(&to_array(query), &radius));
+        *count_out = found.len();
+        write_truncated(indices_out, &found)
+    })
+}
+
+/// query に近い順に最大 k 個の点の、構築時の位置と距離を indices_out と distances_out に書き込む。
+/// 書き込んだ数を count_out に書き込む。
+///
+/// # Safety
+///
+/// tree は kdtree_new() が返した解放されていないツリーで、 query はツリーの次元数の f32 を、
+/// indices_out は k 個の size_t を、 count_out は 1 個の size_t を指していなければならない。
+/// distances_out は NULL か k 個の f32 を指していなければならず、 NULL であれば距離は書き込まない。
+/// k が 0 の場合に限り indices_out は NULL でよい。
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_query_nearest(
+    tree: *const DbscanKdTree,
+    query: *const f32,
+    k: usize,
+    indices_out: *mut usize,
+    distances_out: *mut f32,
+    count_out: *mut usize,
+) -> DbscanStatus {
+    let Some(DbscanKdTree(tree)) = (unsafe { tree.as_ref() }) else {
+        return DbscanStatus::NullPointer;
+    };
+    let dimensions = with_tree!(tree, tree => dimensions_of(tree));
+    let (Some(query), Some(indices_out), Some(count_out)) = (
+        unsafe { raw_slice(query, dimensions) },
+        unsafe { raw_slice_mut(indices_out, k) },
+        unsafe { count_out.as_mut() },
+    ) else {
+        return DbscanStatus::NullPointer;
+    };
+    let mut distances_out = unsafe { raw_slice_mut(distances_out, k) }.filter(|_| !distances_out.is_null());
+    if !is_finite(query) {
+        return DbscanStatus::NonFiniteInput;
+    }
+
+    catch_panic(|| {
+        let found = with_tree!(tree, tree => tree.find_nearest_n_indices(&to_array(query), k));
+        *count_out = found.len();
+        for (i, (index, distance)) in found.into_iter().enumerate() {
+            indices_out[i] = index;
+            if let Some(distances_out) = distances_out.as_mut() {
+                distances_out[i] = distance;
+            }
+        }
+        DbscanStatus::Ok
+    })
+}
+
+macro_rules! impl_from_kdtree {
+    ($($variant:ident => $n:literal),*) => {
+        $(
+            impl From<KdTree<[f32; $n]>> for AnyKdTree {
+                fn from(tree: KdTree<[f32; $n]>) -> AnyKdTree {
+                    AnyKdTree::$variant(tree)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_kdtree!(D1 => 1, D2 => 2, D3 => 3, D4 => 4, D5 => 5, D6 => 6, D7 => 7, D8 => 8);
+
+fn dimensions_of<const N: usize>(_: &KdTree<[f32; N]>) -> usize {
+    N
+}
+
+fn to_array<F: Copy, const N: usize>(coordinates: &[F]) -> [F; N] {
+    coordinates.try_into().expect("must have N coordinates")
+}
+
+fn to_arrays<F: Copy, const N: usize>(coordinates: &[F]) -> Vec<[F; N]> {
+    coordinates.chunks_exact(N).map(to_array).collect()
+}
+
+/// coordinates に NaN や無限大が含まれていなければ true を返す。
+fn is_finite<F: Float>(coordinates: &[F]) -> bool {
+    coordinates.iter().all(|c| c.is_finite())
+}
+
+/// radius が NaN でも負でもなければ true を返す。
+fn is_valid_radius<F: Float>(radius: F) -> bool {
+    radius >= F::zero()
+}
+
+/// count 個の dimensions 次元の点の座標の数を返す。座標の大きさの合計が isize::MAX バイトを超える場合は None を返す。
+fn coordinate_count<F>(count: usize, dimensions: usize) -> Option<usize> {
+    count
+        .checked_mul(dimensions)
+        .filter(|&len| len <= isize::MAX as usize / size_of::<F>())
+}
+
+/// values を out に収まるだけ書き込む。収まらなければ BufferTooSmall を返す。
+fn write_truncated(out: &mut [usize], values: &[usize]) -> DbscanStatus {
+    let written = values.len().min(out.len());
+    out[..written].copy_from_slice(&values[..written]);
+    if written < values.len() {
+        DbscanStatus::BufferTooSmall
+    } else {
+        DbscanStatus::Ok
+    }
+}
+
+fn catch_panic(f: impl FnOnce() -> DbscanStatus) -> DbscanStatus {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(DbscanStatus::Panic)
+}
+
+/// len 個の要素を指すポインターをスライスにする。 len が 0 であれば NULL も空のスライスとして扱う。
+unsafe fn raw_slice<'a, T>(pointer: *const T, len: usize) -> Option<&'a [T]> {
+    match (pointer.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(unsafe { slice::from_raw_parts(pointer, len) }),
+    }
+}
+
+/// raw_slice() の可変版。
+unsafe fn raw_slice_mut<'a, T>(pointer: *mut T, len: usize) -> Option<&'a mut [T]> {
+    match (pointer.is_null(), len) {
+        (_, 0) => Some(&mut []),
+        (true, _) => None,
+        (false, _) => Some(unsafe { slice::from_raw_parts_mut(pointer, len) }),
+    }
+}
@@ -0,0 +1,79 @@
+//! 長時間かかる DBSCAN を途中から再開するためのチェックポイント。
+
+use alloc::vec::Vec;
+
+use crate::{
+    dbscan::{DbscanLabel, Expansion},
+    error::Error,
+};
+
+/// Dbscan::run_checkpointed() がコア点の判定と併合の途中で書き出す状態。
+/// serde feature が有効であればファイルなどに保存し、プロセスを再起動した後に同じ要素と設定で再開できる。
+/// 保存されるのは判定と併合の状態だけで、入力の要素や設定は含まない。
+/// validate() は要素数と内部の整合性しか検査しないため、異なる要素や設定で再開した結果は意味を持たない。
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DbscanCheckpoint {
+    /// コア点の判定と併合のそれぞれで調べ終えた要素の数の合計。要素数未満であればコア点の判定の途中を表す。
+    pub(crate) processed: usize,
+
+    pub(crate) cores: Vec<bool>,
+
+    /// コア点を併合する Union-Find の各要素の親の位置。根は自身を親とする。
+    pub(crate) parents: Vec<usize>,
+
+    /// 各ボーダー点を近傍に含む最初のコア点の位置。
+    pub(crate) border_cores: Vec<Option<u32>>,
+}
+
+impl DbscanCheckpoint {
+    /// 書き出した時点で調べ終えていた数。コア点の判定と併合のそれぞれで要素数ずつ数える。
+    pub fn processed(&self) -> usize {
+        self.processed
+    }
+
+    /// 書き出した時点のラベル。併合を調べ終えたコア点を含むクラスターの要素だけがラベルを持ち、
+    /// 残りの要素は DbscanLabel::Noise になる。コア点の判定の途中であれば、すべての要素が DbscanLabel::Noise になる。
+    /// 内部の状態が矛盾している (validate() が Err を返す) 場合は panic する。
+    pub fn labels(&self) -> Vec<DbscanLabel> {
+        Expansion::resume(self.clone(), self.cores.len()).labels()
+    }
+
+    /// 要素数 item_count の入力の再開に使えるかを検査する。
+    /// 外部から読み込んだチェックポイントは、 Dbscan::run_checkpointed() に渡す前にこれで検査する。
+    pub fn validate(&self, item_count: usize) -> Result<(), Error> {
+        let consistent = self.cores.len() == item_count
+            && self.parents.len() == item_count
+            && self.border_cores.len() == item_count
+            && self.processed <= 2 * item_count
+            && self
+                .parents
+                .iter()
+                .enumerate()
+                .all(|(i, &parent)| parent == i || parent < item_count && self.cores[i] && self.cores[parent])
+            && (0..item_count).all(|i| reaches_root(&self.parents, i))
+            && self
+                .border_cores
+                .iter()
+                .flatten()
+                .all(|&core| (core as usize) < item_count && self.cores[core as usize]);
+        if consistent {
+            Ok(())
+        } else {
+            Err(Error::InvalidCheckpoint)
+        }
+    }
+}
+
+/// parents を x から辿って根に着くかを調べる。
+/// 大きさの小さい方を繋ぐ Union-Find の木の高さは要素数の 2 を底とする対数以下になるため、
+/// それより多く辿っても根に着かなければ閉路がある。
+fn reaches_root(parents: &[usize], mut x: usize) -> bool {
+    for _ in 0..=usize::BITS {
+        if parents[x] == x {
+            return true;
+        }
+        x = parents[x];
+    }
+    false
+}
@@ -0,0 +1,248 @@
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use num_traits::{Float, One, Zero};
+
+use crate::{
+    error::Error,
+    index::SpatialIndex,
+    kdtree::{validate_items, KdTreeItem},
+    knn::KBest,
+    metric::{ItemMetric, Metric},
+};
+
+/// cover tree を表す。
+/// 各ノードは中心となる要素を持ち、部分木の要素を中心からの距離の最大値の半分の尺度で覆う子ノードに分ける。
+/// 同じノードの子の中心は互いにその尺度より離れているため、データの内在的な次元が低ければ、
+/// 座標の次元数やクラスターの偏りによらず探索で辿るノードの数が抑えられる。
+/// 距離は三角不等式を満たさなければならない。
+pub struct CoverTree<T, M: Metric<T> = ItemMetric> {
+    items: Vec<T>,
+    nodes: Vec<CoverNode<M::Measurement>>,
+
+    /// 各ノードが子ノードに分けずに直接持つ要素の位置を、ノードごとに連続するように並べたもの。
+    bucket: Vec<usize>,
+    metric: M,
+}
+
+struct CoverNode<D> {
+    /// 中心となる要素の位置。
+    center: usize,
+
+    /// center から部分木の最も遠い要素までの距離。
+    radius: D,
+
+    /// 子ノードの nodes 上の範囲。
+    children: Range<usize>,
+
+    /// 直接持つ要素の bucket 上の範囲。
+    bucket: Range<usize>,
+}
+
+impl<T: KdTreeItem> CoverTree<T>
+where
+    T::Measurement: Float,
+{
+    /// items から cover tree を構築する。座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+    pub fn construct(items: impl Into<Vec<T>>) -> Result<CoverTree<T>, Error> {
+        let items = items.into();
+        validate_items(&items)?;
+        Ok(CoverTree::construct_unchecked(items))
+    }
+
+    /// construct() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+    pub fn construct_unchecked(items: impl Into<Vec<T>>) -> CoverTree<T> {
+        CoverTree::construct_with_metric(items, ItemMetric)
+    }
+}
+
+impl<T, M: Metric<T>> CoverTree<T, M>
+where
+    M::Measurement: Float,
+{
+    /// 距離の計算に metric を用いる cover tree を構築する。
+    /// 入力を検証しないため、距離が NaN になる要素があると panic する。
+    pub fn construct_with_metric(items: impl Into<Vec<T>>, metric: M) -> CoverTree<T, M> {
+        let items = items.into();
+        let mut nodes: Vec<CoverNode<M::Measurement>> = Vec::new();
+        let mut bucket = Vec::new();
+        let distance = |a: usize, b: usize| metric.distance(&items[a], &items[b]);
+        let two = M::Measurement::one() + M::Measurement::one();
+
+        // (ノードの位置, 中心, 中心を除く部分木の要素と中心からの距離) を積み、ノードの子は連続した位置に確保する
+        let mut pending = vec![];
+        if !items.is_empty() {
+            nodes.push(CoverNode::empty());
+            pending.push((0, 0, (1..items.len()).map(|i| (i, distance(0, i))).collect::<Vec<_>>()));
+        }
+        while let Some((node_index, center, mut rest)) = pending.pop() {
+            let radius = rest
+                .iter()
+                .map(|&(_, d)| d)
+                .fold(M::Measurement::zero(), M::Measurement::max);
+
+            // 中心から最も遠い要素までの距離の半分を尺度とし、中心の近くに残らない要素をその尺度で覆う子に分ける。
+            // 中心の近くに残った要素は、尺度を半分ずつ小さくしながら同じように分ける
+            let mut groups = Vec::new();
+            loop {
+                let farthest = rest
+                    .iter()
+                    .map(|&(_, d)| d)
+                    .fold(M::Measurement::zero(), M::Measurement::max);
+                if rest.len() <= LEAF_SIZE || farthest.is_zero() || !farthest.is_finite() {
+                    break;
+                }
+                let scale = farthest / two;
+                let (near, mut far): (Vec<_>, Vec<_>) = rest.into_iter().partition(|&(_, d)| d <= scale);
+                rest = near;
+                while !far.is_empty() {
+                    let position = far
+                        .iter()
+                        .enumerate()
+                        .max_by(|lhs, rhs| lhs.1 .1.partial_cmp(&rhs.1 .1).expect("not total order"))
+                        .expect("far must not be empty")
+                        .0;
+                    let (child, _) = far.swap_remove(position);
+                    let mut covered = Vec::new();
+                    far.retain(|&(i, _)| {
+                        let to_child = distance(child, i);
+                        if to_child <= scale {
+                            covered.push((i, to_child));
+                        }
+                        to_child > scale
+                    });
+                    groups.push((child, covered));
+                }
+            }
+
+            let children = nodes.len()..nodes.len() + groups.len();
+            nodes.extend(groups.iter().map(|_| CoverNode::empty()));
+            let bucket_start = bucket.len();
+            bucket.extend(rest.iter().map(|&(i, _)| i));
+            nodes[node_index] = CoverNode {
+                center,
+                radius,
+                children: children.clone(),
+                bucket: bucket_start..bucket.len(),
+            };
+            for (node_index, (child, covered)) in children.zip(groups) {
+                pending.push((node_index, child, covered));
+            }
+        }
+
+        CoverTree {
+            items,
+            nodes,
+            bucket,
+            metric,
+        }
+    }
+}
+
+impl<T, M: Metric<T>> CoverTree<T, M> {
+    /// 距離の計算に用いられる Metric を返す。
+    pub fn metric(&self) -> &M {
+        &self.metric
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+}
+
+impl<D: Zero> CoverNode<D> {
+    /// 構築中に子ノードの位置を確保するための空のノード。
+    fn empty() -> CoverNode<D> {
+        CoverNode {
+            center: 0,
+            radius: D::zero(),
+            children: 0..0,
+            bucket: 0..0,
+        }
+    }
+}
+
+impl<T, M: Metric<T>> SpatialIndex<T> for CoverTree<T, M>
+where
+    M::Measurement: Float,
+{
+    type Measurement = M::Measurement;
+
+    fn range(&self, query: &T, radius: &M::Measurement) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.range_into(query, radius, &mut found);
+        found
+    }
+
+    fn range_into(&self, query: &T, radius: &M::Measurement, found: &mut Vec<usize>) {
+        found.clear();
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            // 部分木を覆う球が query を中心とする半径 radius の球と交わらなければ枝刈り
+            let center_distance = self.metric.distance(query, &self.items[node.center]);
+            if center_distance - node.radius > *radius {
+                continue;
+            }
+            if center_distance <= *radius {
+                found.push(node.center);
+            }
+            for &i in &self.bucket[node.bucket.clone()] {
+                if self.metric.distance(query, &self.items[i]) <= *radius {
+                    found.push(i);
+                }
+            }
+            stack.extend(node.children.clone());
+        }
+    }
+
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, M::Measurement)> {
+        let mut best = KBest::new(k);
+        if k == 0 || self.nodes.is_empty() {
+            return best.into_vec();
+        }
+
+        // (ノード, query から中心までの距離) を積み、部分木までの距離の下界が小さい子ノードから探索する
+        let center_distance =
+            |node_index: usize| self.metric.distance(query, &self.items[self.nodes[node_index].center]);
+        let lower_bound = |(node_index, distance): (usize, M::Measurement)| {
+            (distance - self.nodes[node_index].radius).max(M::Measurement::zero())
+        };
+        let mut stack = vec![(0, center_distance(0))];
+        while let Some((node_index, distance)) = stack.pop() {
+            if best.excludes(&lower_bound((node_index, distance))) {
+                continue;
+            }
+
+            let node = &self.nodes[node_index];
+            best.offer(node.center, distance);
+            for &i in &self.bucket[node.bucket.clone()] {
+                best.offer(i, self.metric.distance(query, &self.items[i]));
+            }
+            let mut children: Vec<_> = node.children.clone().map(|c| (c, center_distance(c))).collect();
+            children.sort_by(|&lhs, &rhs| {
+                lower_bound(rhs)
+                    .partial_cmp(&lower_bound(lhs))
+                    .expect("not total order")
+            });
+            stack.extend(children);
+        }
+
+        best.into_vec()
+    }
+}
+
+/// 子ノードに分けずに直接持つ要素の数の上限。
+const LEAF_SIZE: usize = 16;
@@ -0,0 +1,123 @@
+//! クラスタリングの検証や計測に用いる合成データの生成。
+//!
+//! どの関数も seed で決まる擬似乱数を用い、同じ引数からは同じ点と正解ラベルが得られる。
+
+use alloc::vec::Vec;
+use core::{array, f64::consts::PI, num::NonZeroUsize};
+
+use num_traits::Float;
+
+use crate::{dbscan::DbscanLabel, random::SplitMix64};
+
+/// 生成した点と、その正解ラベル。
+/// labels は points と同じ長さを持ち、生成元のクラスターを出現順に Cluster(1), Cluster(2), ... で、
+/// with_noise() で加えた点を Noise で表す。 metrics::adjusted_rand_index() などでクラスタリングの結果と比べられる。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dataset<const N: usize> {
+    /// 生成した点。
+    pub points: Vec<[f64; N]>,
+
+    /// 各点の正解ラベル。
+    pub labels: Vec<DbscanLabel>,
+}
+
+impl<const N: usize> Dataset<N> {
+    /// 点の数。
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// 点を持たなければ true を返す。
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// min と max を対角とする箱の中に一様に分布する count 個の点を、ラベル Noise として加える。
+    /// 箱の中にはクラスターの点も含まれるため、加えた点の一部はクラスターの近傍に入る。
+    pub fn with_noise(mut self, count: usize, min: [f64; N], max: [f64; N], seed: u64) -> Dataset<N> {
+        let mut rng = SplitMix64::new(seed);
+        self.points.reserve(count);
+        self.labels.reserve(count);
+        for _ in 0..count {
+            self.points
+                .push(array::from_fn(|d| min[d] + (max[d] - min[d]) * rng.next_f64()));
+            self.labels.push(DbscanLabel::Noise);
+        }
+        self
+    }
+}
+
+/// centers の各点を中心に、各軸の標準偏差 std_dev の正規分布に従う count_per_center 個ずつの点を生成する。
+pub fn blobs<const N: usize>(centers: &[[f64; N]], std_dev: f64, count_per_center: usize, seed: u64) -> Dataset<N> {
+    let mut rng = SplitMix64::new(seed);
+    let mut dataset = Dataset {
+        points: Vec::with_capacity(centers.len() * count_per_center),
+        labels: Vec::with_capacity(centers.len() * count_per_center),
+    };
+    for (i, center) in centers.iter().enumerate() {
+        let label = cluster_label(i);
+        for _ in 0..count_per_center {
+            dataset
+                .points
+                .push(array::from_fn(|d| center[d] + std_dev * normal(&mut rng)));
+            dataset.labels.push(label);
+        }
+    }
+    dataset
+}
+
+/// blobs() で生成した点に transform を掛け、各軸に沿わない細長いクラスターにする。
+/// 変換後の点は transform の行 i と元の座標の内積を i 番目の座標に持つ。 centers も変換されることに注意。
+pub fn anisotropic<const N: usize>(
+    centers: &[[f64; N]],
+    std_dev: f64,
+    count_per_center: usize,
+    transform: [[f64; N]; N],
+    seed: u64,
+) -> Dataset<N> {
+    let mut dataset = blobs(centers, std_dev, count_per_center, seed);
+    for point in &mut dataset.points {
+        let original = *point;
+        *point = array::from_fn(|i| transform[i].iter().zip(&original).map(|(t, x)| t * x).sum());
+    }
+    dataset
+}
+
+/// 互いに噛み合う 2 つの半円に沿って count_per_moon 個ずつの点を生成する。
+/// 1 つ目は原点を中心とする上半分の単位円、 2 つ目は (1, 0.5) を中心とする下半分の単位円で、
+/// 各座標に標準偏差 noise の正規分布に従うずれを加える。 k-means では分けられないが、密度に基づく方法では分けられる形の例。
+pub fn moons(count_per_moon: usize, noise: f64, seed: u64) -> Dataset<2> {
+    let mut rng = SplitMix64::new(seed);
+    let mut dataset = Dataset {
+        points: Vec::with_capacity(2 * count_per_moon),
+        labels: Vec::with_capacity(2 * count_per_moon),
+    };
+    for i in 0..2 {
+        let label = cluster_label(i);
+        for _ in 0..count_per_moon {
+            let angle = PI * rng.next_f64();
+            let (x, y) = if i == 0 {
+                (Float::cos(angle), Float::sin(angle))
+            } else {
+                (1.0 - Float::cos(angle), 0.5 - Float::sin(angle))
+            };
+            dataset
+                .points
+                .push([x + noise * normal(&mut rng), y + noise * normal(&mut rng)]);
+            dataset.labels.push(label);
+        }
+    }
+    dataset
+}
+
+/// 出現順で index 番目のクラスターのラベル。
+fn cluster_label(index: usize) -> DbscanLabel {
+    DbscanLabel::Cluster(NonZeroUsize::new(index + 1).expect("cluster id must not overflow"))
+}
+
+/// Box-Muller 法で標準正規分布に従う値を返す。
+fn normal(rng: &mut SplitMix64) -> f64 {
+    // next_f64() は [0, 1) なので、対数を取る側は (0, 1] にする。
+    let radius = Float::sqrt(-2.0 * Float::ln(1.0 - rng.next_f64()));
+    radius * Float::cos(2.0 * PI * rng.next_f64())
+}
@@ -1,81 +1,1604 @@
-use std::{cmp::Ordering, collections::VecDeque, num::NonZeroUsize};
+use alloc::{vec, vec::Vec};
+use core::{
+    cell::Cell,
+    cmp::Reverse,
+    fmt::{self, Debug, Display},
+    iter::Sum,
+    num::NonZeroUsize,
+    time::Duration,
+};
 
-use crate::kdtree::{KdTree, KdTreeItem};
+use num_traits::Float;
 
+#[cfg(feature = "parallel")]
+use crate::union_find::ConcurrentUnionFind;
+use crate::{
+    balltree::BallTree,
+    checkpoint::DbscanCheckpoint,
+    covertree::CoverTree,
+    dedup::coalesce_duplicates,
+    error::{check_radius, Error},
+    graph::NeighborGraph,
+    grid::{is_grid_suitable, GridIndex},
+    implicit_kdtree::ImplicitKdTree,
+    index::{IndexKind, SpatialIndex},
+    kdtree::{validate_items, KdTreeItem},
+    metric::{ItemMetric, Metric},
+    model::DbscanModel,
+    morton::morton_order,
+    progress::{CancellationToken, Cancelled, Monitor, ProgressEvent, Stopwatch, PROGRESS_INTERVAL},
+    random::SplitMix64,
+    slice_kdtree::{spatial_order, SliceKdTree},
+    union_find::UnionFind,
+};
+
+#[allow(deprecated)]
+pub use crate::error::DbscanError;
+
+/// DBSCAN によって各要素に付与されるラベル。
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DbscanLabel {
     Cluster(NonZeroUsize),
-    Noize,
+
+    /// どのクラスターにも属さない要素。 serde では以前の綴りの "Noize" も受け付ける。
+    #[cfg_attr(feature = "serde", serde(alias = "Noize"))]
+    Noise,
 }
 
-#[derive(Debug, Clone)]
-struct Indexed<'a, T>(usize, &'a T);
+impl DbscanLabel {
+    /// DbscanLabel::Noise の以前の綴り。値に対する match のパターンにも使えるが、参照に対しては使えない。
+    #[deprecated(note = "use DbscanLabel::Noise instead")]
+    #[allow(non_upper_case_globals)]
+    pub const Noize: DbscanLabel = DbscanLabel::Noise;
+
+    /// 他の言語やライブラリとの受け渡し向けの整数に変換する。
+    /// クラスター番号 `n` は `n - 1` に、ノイズは -1 になる。
+    ///
+    /// クラスター番号が i32 に収まらない場合は panic する。
+    pub fn to_code(self) -> i32 {
+        match self {
+            DbscanLabel::Cluster(id) => i32::try_from(id.get() - 1).expect("cluster id must fit in i32"),
+            DbscanLabel::Noise => -1,
+        }
+    }
+}
+
+/// クラスター番号 `n` を "cluster n" 、ノイズを "noise" と表示する。
+impl Display for DbscanLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbscanLabel::Cluster(id) => write!(f, "cluster {id}"),
+            DbscanLabel::Noise => write!(f, "noise"),
+        }
+    }
+}
+
+/// DbscanLabel::to_code() と同じく、クラスター番号 `n` を `n - 1` に、ノイズを -1 にする。
+impl From<DbscanLabel> for i64 {
+    fn from(label: DbscanLabel) -> i64 {
+        match label {
+            DbscanLabel::Cluster(id) => i64::try_from(id.get() - 1).expect("cluster id must fit in i64"),
+            DbscanLabel::Noise => -1,
+        }
+    }
+}
+
+/// `From<DbscanLabel> for i64` の逆変換。 -1 未満の値や usize に収まらない値は Error::InvalidLabelCode になる。
+impl TryFrom<i64> for DbscanLabel {
+    type Error = Error;
+
+    fn try_from(code: i64) -> Result<DbscanLabel, Error> {
+        if code == -1 {
+            return Ok(DbscanLabel::Noise);
+        }
+        usize::try_from(code)
+            .ok()
+            .and_then(|c| c.checked_add(1))
+            .and_then(NonZeroUsize::new)
+            .map(DbscanLabel::Cluster)
+            .ok_or(Error::InvalidLabelCode { code })
+    }
+}
+
+/// DBSCAN における要素の役割。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PointRole {
+    /// 近傍に min_points 個以上の要素を持ち、クラスターを広げる要素。
+    Core,
+
+    /// コア点ではないが、いずれかのクラスターに属する要素。
+    Border,
+
+    /// どのクラスターにも属さない要素。
+    Noise,
+}
+
+/// ボーダー点 (コア点ではないが、いずれかのコア点の近傍にある要素) に付けるラベルの決め方。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BorderPolicy {
+    /// 自身を近傍に含むコア点のうち、添字の最も小さいもののクラスターに属する。どのクラスターに属するかは要素の順序に依存する。
+    #[default]
+    FirstWins,
+
+    /// 近傍にある最も近いコア点のクラスターに属する。距離が等しければ添字の小さいコア点を選ぶ。
+    NearestCore,
+
+    /// ノイズとして扱う (DBSCAN*) 。
+    Noise,
+}
+
+/// クラスター番号の振り方。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClusterOrder {
+    /// 各クラスターに含まれる最小の添字を持つコア点の順。
+    #[default]
+    Discovery,
+
+    /// 各クラスターに含まれる最小の添字の順。
+    SmallestIndex,
+
+    /// 要素数の多い順。要素数が等しければ最小の添字の順になる。
+    /// 要素数がすべて異なれば、入力の順序を入れ替えても同じ要素の集まりに同じ番号が振られる。
+    SizeDescending,
+}
+
+/// dbscan_with_options() の設定。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DbscanOptions {
+    pub border_policy: BorderPolicy,
+}
+
+/// expand_clusters() が書き込むラベルの表現。
+pub(crate) trait Label: Copy + PartialEq {
+    const NOISE: Self;
+
+    fn cluster(id: NonZeroUsize) -> Self;
+}
+
+impl Label for DbscanLabel {
+    const NOISE: DbscanLabel = DbscanLabel::Noise;
+
+    fn cluster(id: NonZeroUsize) -> DbscanLabel {
+        DbscanLabel::Cluster(id)
+    }
+}
+
+impl Label for i32 {
+    const NOISE: i32 = -1;
+
+    fn cluster(id: NonZeroUsize) -> i32 {
+        DbscanLabel::Cluster(id).to_code()
+    }
+}
+
+/// dbscan() の結果。
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DbscanResult {
+    /// 入力と同じ順序で並んだ各要素のラベル。
+    pub labels: Vec<DbscanLabel>,
+
+    /// 見つかったクラスターの数。
+    pub cluster_count: usize,
+
+    /// クラスター番号 `n` に属する要素の添字の一覧が `cluster_members[n - 1]` に昇順で格納される。
+    pub cluster_members: Vec<Vec<usize>>,
+
+    /// クラスター番号 `n` に属する要素の数が `cluster_sizes[n - 1]` に格納される。
+    pub cluster_sizes: Vec<usize>,
+
+    /// 入力と同じ順序で並んだ各要素の役割。
+    /// コア点を区別しないアルゴリズム (hdbscan() など) や from_labels() で組み立てた結果では None になる。
+    pub roles: Option<Vec<PointRole>>,
+}
+
+impl DbscanResult {
+    /// クラスター番号が 1 から連続しているラベル列から結果を組み立てる。
+    pub fn from_labels(labels: Vec<DbscanLabel>) -> DbscanResult {
+        // 要素数を先に数え、 cluster_members を余分なく確保する
+        let mut cluster_sizes: Vec<usize> = Vec::new();
+        for label in &labels {
+            let DbscanLabel::Cluster(id) = label else {
+                continue;
+            };
+            if cluster_sizes.len() < id.get() {
+                cluster_sizes.resize(id.get(), 0);
+            }
+            cluster_sizes[id.get() - 1] += 1;
+        }
+        let mut cluster_members: Vec<Vec<usize>> = cluster_sizes.iter().map(|&n| Vec::with_capacity(n)).collect();
+        for (i, label) in labels.iter().enumerate() {
+            if let DbscanLabel::Cluster(id) = label {
+                cluster_members[id.get() - 1].push(i);
+            }
+        }
+
+        DbscanResult {
+            labels,
+            cluster_count: cluster_members.len(),
+            cluster_members,
+            cluster_sizes,
+            roles: None,
+        }
+    }
+
+    /// from_labels() と同様だが、 cores でコア点とされた要素を PointRole::Core とし、
+    /// 残りをラベルに従って PointRole::Border か PointRole::Noise とする。
+    pub fn from_labels_and_cores(labels: Vec<DbscanLabel>, cores: &[bool]) -> DbscanResult {
+        assert_eq!(labels.len(), cores.len(), "cores must have the same length as labels");
+        let roles = labels
+            .iter()
+            .zip(cores)
+            .map(|(&label, &core)| match label {
+                _ if core => PointRole::Core,
+                DbscanLabel::Cluster(_) => PointRole::Border,
+                DbscanLabel::Noise => PointRole::Noise,
+            })
+            .collect();
+        DbscanResult {
+            roles: Some(roles),
+            ..DbscanResult::from_labels(labels)
+        }
+    }
+
+    /// index 番目の要素の役割を返す。 roles が None であれば None を返す。
+    pub fn role(&self, index: usize) -> Option<PointRole> {
+        self.roles.as_ref().map(|roles| roles[index])
+    }
+
+    /// 指定したクラスターに属する要素の添字を返す。存在しないクラスターであれば空になる。
+    pub fn members(&self, cluster_id: NonZeroUsize) -> &[usize] {
+        self.cluster_members
+            .get(cluster_id.get() - 1)
+            .map_or(&[], |m| m.as_slice())
+    }
+
+    /// クラスター番号を order に従って振り直す。
+    pub fn renumber(&mut self, order: ClusterOrder) {
+        // cluster_members は昇順なので先頭が最小の添字になる
+        let smallest = |c: usize| self.cluster_members[c].first().copied().unwrap_or(usize::MAX);
+        let mut order_ids: Vec<usize> = (0..self.cluster_count).collect();
+        match order {
+            ClusterOrder::Discovery => return,
+            ClusterOrder::SmallestIndex => order_ids.sort_by_key(|&c| smallest(c)),
+            ClusterOrder::SizeDescending => order_ids.sort_by_key(|&c| (Reverse(self.cluster_sizes[c]), smallest(c))),
+        }
+
+        // order_ids[n] には新しい番号が n + 1 となるクラスターの元の番号 - 1 が入っている
+        let mut new_ids = vec![0; self.cluster_count];
+        for (n, &c) in order_ids.iter().enumerate() {
+            new_ids[c] = n;
+        }
+        for label in &mut self.labels {
+            if let DbscanLabel::Cluster(id) = label {
+                *id = NonZeroUsize::new(new_ids[id.get() - 1] + 1).expect("must be non-zero");
+            }
+        }
+        let mut members = core::mem::take(&mut self.cluster_members);
+        self.cluster_members = order_ids.iter().map(|&c| core::mem::take(&mut members[c])).collect();
+        self.cluster_sizes = order_ids.iter().map(|&c| self.cluster_sizes[c]).collect();
+    }
+
+    /// ノイズと判定された要素の添字を返す。
+    pub fn noise_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.labels
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| **l == DbscanLabel::Noise)
+            .map(|(i, _)| i)
+    }
+}
+
+/// Dbscan::run_timed() が返す、段階ごとの所要時間。
+/// 時刻を取得できない std 無しの環境や wasm32-unknown-unknown ではすべて 0 になる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DbscanTimings {
+    /// 索引の構築。 BruteForceIndex を用いた場合は 0 になる。
+    pub index_build: Duration,
 
-impl<T: KdTreeItem> KdTreeItem for Indexed<'_, T> {
-    type Measurement = T::Measurement;
+    /// 近傍の探索と数え上げ。
+    /// Parallelism::Parallel では各スレッドの合計ではなく、探索してコア点を併合する段階の経過時間になる。
+    pub neighbor_queries: Duration,
 
-    fn cmp_in_depth(&self, rhs: &Self, depth: usize) -> Ordering {
-        self.1.cmp_in_depth(rhs.1, depth)
+    /// コア点の併合、ボーダー点のラベル付け、クラスター番号の付け直しなど、近傍探索以外のクラスタリングの処理。
+    pub expansion: Duration,
+}
+
+impl DbscanTimings {
+    /// 各段階の所要時間の合計。
+    pub fn total(&self) -> Duration {
+        self.index_build + self.neighbor_queries + self.expansion
     }
+}
+
+/// items を DBSCAN でクラスタリングする。
+/// epsilon 以内に自身を含めて min_items 個以上の要素があるものをコア点とする。
+///
+/// items は `Vec<T>` でも `&[T]` でもよく、 k-d tree は items への参照の上に構築されるため要素は複製されない。
+/// 設定を増やす場合は Dbscan を用いる。
+/// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を、 epsilon が NaN であれば Error::InvalidRadius を返す。
+pub fn dbscan<T: KdTreeItem>(
+    items: impl AsRef<[T]>,
+    epsilon: T::Measurement,
+    min_items: usize,
+) -> Result<DbscanResult, Error> {
+    let items = items.as_ref();
+    check_radius(&epsilon)?;
+    validate_items(items)?;
+    Ok(dbscan_unchecked(items, epsilon, min_items))
+}
+
+/// dbscan() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+pub fn dbscan_unchecked<T: KdTreeItem>(
+    items: impl AsRef<[T]>,
+    epsilon: T::Measurement,
+    min_items: usize,
+) -> DbscanResult {
+    Dbscan::new(DbscanParams::new(epsilon, min_items))
+        .run_sequential(items.as_ref(), None, None)
+        .expect(UNMONITORED)
+}
+
+/// dbscan() と同様だが、要素間の距離を metric で計算する。
+pub fn dbscan_with_metric<T: KdTreeItem, M: Metric<T>>(
+    items: impl AsRef<[T]>,
+    epsilon: M::Measurement,
+    min_items: usize,
+    metric: M,
+) -> Result<DbscanResult, Error> {
+    let items = items.as_ref();
+    check_radius(&epsilon)?;
+    validate_items(items)?;
+    Ok(dbscan_with_metric_unchecked(items, epsilon, min_items, metric))
+}
+
+/// dbscan_with_metric() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+pub fn dbscan_with_metric_unchecked<T: KdTreeItem, M: Metric<T>>(
+    items: impl AsRef<[T]>,
+    epsilon: M::Measurement,
+    min_items: usize,
+    metric: M,
+) -> DbscanResult {
+    Dbscan::new(DbscanParams::new(epsilon, min_items).metric(metric))
+        .run_sequential(items.as_ref(), None, None)
+        .expect(UNMONITORED)
+}
+
+/// dbscan() と同様だが、 options でボーダー点の扱いなどを指定する。
+pub fn dbscan_with_options<T: KdTreeItem>(
+    items: impl AsRef<[T]>,
+    epsilon: T::Measurement,
+    min_items: usize,
+    options: DbscanOptions,
+) -> Result<DbscanResult, Error> {
+    let items = items.as_ref();
+    check_radius(&epsilon)?;
+    validate_items(items)?;
+    Ok(dbscan_with_options_unchecked(items, epsilon, min_items, options))
+}
+
+/// dbscan_with_options() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+pub fn dbscan_with_options_unchecked<T: KdTreeItem>(
+    items: impl AsRef<[T]>,
+    epsilon: T::Measurement,
+    min_items: usize,
+    options: DbscanOptions,
+) -> DbscanResult {
+    let params = DbscanParams::new(epsilon, min_items).border_policy(options.border_policy);
+    Dbscan::new(params)
+        .run_sequential(items.as_ref(), None, None)
+        .expect(UNMONITORED)
+}
+
+/// dbscan() と同様だが、各要素のラベルを DbscanLabel::to_code() の整数で返す。
+/// DbscanLabel の列や DbscanResult を経由せずに直接書き込む。
+pub fn dbscan_codes<T: KdTreeItem>(
+    items: impl AsRef<[T]>,
+    epsilon: T::Measurement,
+    min_items: usize,
+) -> Result<Vec<i32>, Error> {
+    let items = items.as_ref();
+    check_radius(&epsilon)?;
+    validate_items(items)?;
+    Ok(dbscan_codes_unchecked(items, epsilon, min_items))
+}
+
+/// dbscan_codes() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+pub fn dbscan_codes_unchecked<T: KdTreeItem>(
+    items: impl AsRef<[T]>,
+    epsilon: T::Measurement,
+    min_items: usize,
+) -> Vec<i32> {
+    let items = items.as_ref();
+    let kdtree = SliceKdTree::construct_with_metric(items, ItemMetric);
+    let (labels, _) = expand_clusters(
+        items.len(),
+        |i, found| kdtree.range_into(&items[i], &epsilon, found),
+        |i, _| kdtree.count_within_limited(&items[i], &epsilon, min_items) >= min_items,
+        None,
+    )
+    .expect(UNMONITORED);
+    labels
+}
+
+/// dbscan() と同様だが、近傍探索に index を用いる。
+/// index は items と同じ要素から同じ順序で構築されていなければならない。
+pub fn dbscan_with_index<T, I: SpatialIndex<T>>(
+    items: impl AsRef<[T]>,
+    index: &I,
+    epsilon: I::Measurement,
+    min_items: usize,
+) -> DbscanResult {
+    let items = items.as_ref();
+    let (labels, cores) = expand_clusters(
+        items.len(),
+        |i, found| index.range_into(&items[i], &epsilon, found),
+        |i, _| index.count_within_limited(&items[i], &epsilon, min_items) >= min_items,
+        None,
+    )
+    .expect(UNMONITORED);
+    DbscanResult::from_labels_and_cores(labels, &cores)
+}
+
+/// 近傍探索の代わりに、あらかじめ求めた graph の隣接要素を近傍とする DBSCAN 。
+/// 隣接要素の数に自身を加えた数が min_items 以上の要素をコア点とする。
+/// graph が対称でなければ、要素 i の隣接要素として挙げられた要素だけを i の近傍として扱う。
+pub fn dbscan_from_graph(graph: &NeighborGraph, min_items: usize) -> DbscanResult {
+    let (labels, cores) = expand_clusters(
+        graph.len(),
+        |i, found| {
+            found.clear();
+            found.extend_from_slice(graph.neighbors(i));
+        },
+        |i, _| graph.neighbors(i).len() + 1 >= min_items,
+        None,
+    )
+    .expect(UNMONITORED);
+    DbscanResult::from_labels_and_cores(labels, &cores)
+}
+
+/// epsilons のそれぞれについて dbscan() を行い、 epsilons の順に結果を返す。
+/// k-d tree の構築と近傍探索は最大の epsilon で 1 回だけ行い、各 epsilon の近傍はその結果を距離で絞り込んで求める。
+/// 絞り込んだ近傍の並びは epsilon ごとに探索した場合と変わらないため、要素が少なく dbscan() が総当たりを選ぶ場合を除き
+/// 結果は dbscan() と一致する。最大の epsilon での近傍をすべて保持するため、その近傍の数に比例したメモリを使う。
+pub fn dbscan_sweep<T: KdTreeItem>(
+    items: impl AsRef<[T]>,
+    epsilons: &[T::Measurement],
+    min_items: usize,
+) -> Result<Vec<DbscanResult>, Error> {
+    let items = items.as_ref();
+    for epsilon in epsilons {
+        check_radius(epsilon)?;
+    }
+    validate_items(items)?;
+    let Some(max_epsilon) = epsilons
+        .iter()
+        .max_by(|lhs, rhs| lhs.partial_cmp(rhs).expect("epsilon must not be NaN"))
+    else {
+        return Ok(Vec::new());
+    };
+
+    // 最大の epsilon での近傍を Metric::reduced_distance() の尺度の距離とともに、要素ごとに CSR 形式で持つ
+    let kdtree = SliceKdTree::construct_with_metric(items, ItemMetric);
+    let mut offsets = Vec::with_capacity(items.len() + 1);
+    let mut neighbors = Vec::new();
+    offsets.push(0);
+    for item in items {
+        kdtree.search_range(item, max_epsilon, |index, distance| neighbors.push((index, distance)));
+        offsets.push(neighbors.len());
+    }
+
+    let results = epsilons
+        .iter()
+        .map(|epsilon| {
+            let epsilon = &T::distance_to_reduced(epsilon);
+            let within = |i: usize| {
+                neighbors[offsets[i]..offsets[i + 1]]
+                    .iter()
+                    .filter(move |(_, d)| d <= epsilon)
+                    .map(|&(n, _)| n)
+            };
+            let (labels, cores) = expand_clusters(
+                items.len(),
+                |i, found| {
+                    found.clear();
+                    found.extend(within(i));
+                },
+                |i, _| within(i).count() >= min_items,
+                None,
+            )
+            .expect(UNMONITORED);
+            DbscanResult::from_labels_and_cores(labels, &cores)
+        })
+        .collect();
+    Ok(results)
+}
+
+/// dbscan() と同様だが、近傍探索に kind で指定したインデックスを用いる。
+/// インデックスの選び方は Dbscan::run_points() と同じになる。
+pub fn dbscan_with_index_kind<T: Debug + Float + Sync, const N: usize>(
+    items: impl AsRef<[[T; N]]>,
+    epsilon: T,
+    min_items: usize,
+    kind: IndexKind,
+) -> DbscanResult {
+    Dbscan::new(DbscanParams::new(epsilon, min_items).index(kind)).run_points(items)
+}
+
+/// 0..len から count 個の位置を seed で決まる擬似乱数で重複なく選び、昇順に返す。
+/// 先頭から順に、残りの位置の数に対する選ぶべき数の割合を確率として選ぶ (Knuth の Algorithm S) 。
+fn sample_indices(len: usize, count: usize, seed: u64) -> Vec<usize> {
+    let mut random = SplitMix64::new(seed);
+    let mut sample = Vec::with_capacity(count);
+    for i in 0..len {
+        let needed = count - sample.len();
+        if needed == 0 {
+            break;
+        }
+        if random.below(len - i) < needed {
+            sample.push(i);
+        }
+    }
+    sample
+}
+
+/// monitor を渡さない処理は中断されない。
+pub(crate) const UNMONITORED: &str = "must not be cancelled without monitor";
+
+/// 中断を要求しないトークンを渡した処理は中断されない。
+const UNCANCELLED: &str = "must not be cancelled without request";
 
-    fn distance(&self, other: &Self) -> Self::Measurement {
-        self.1.distance(other.1)
+/// IndexKind::Auto で BruteForceIndex を選ぶ要素数の上限。これより多いと k-d tree の方が速くなる。
+const BRUTE_FORCE_MAX_ITEMS: usize = 64;
+
+/// 重み付きの DBSCAN 。 epsilon 以内にある要素 (自身を含む) の重みの合計が min_weight 以上のものをコア点とする。
+/// weights は items と同じ長さでなければならない。
+pub fn dbscan_weighted<T, W>(
+    items: impl AsRef<[T]>,
+    weights: impl AsRef<[W]>,
+    epsilon: T::Measurement,
+    min_weight: W,
+) -> Result<DbscanResult, Error>
+where
+    T: KdTreeItem,
+    W: Copy + PartialOrd + Sum<W>,
+{
+    let items = items.as_ref();
+    check_radius(&epsilon)?;
+    validate_items(items)?;
+    Ok(dbscan_weighted_unchecked(items, weights, epsilon, min_weight))
+}
+
+/// dbscan_weighted() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+pub fn dbscan_weighted_unchecked<T, W>(
+    items: impl AsRef<[T]>,
+    weights: impl AsRef<[W]>,
+    epsilon: T::Measurement,
+    min_weight: W,
+) -> DbscanResult
+where
+    T: KdTreeItem,
+    W: Copy + PartialOrd + Sum<W>,
+{
+    let (items, weights) = (items.as_ref(), weights.as_ref());
+    assert_eq!(items.len(), weights.len(), "weights must have the same length as items");
+
+    let kdtree = SliceKdTree::construct_with_metric(items, ItemMetric);
+    let (labels, cores) = expand_clusters(
+        items.len(),
+        |i, found| kdtree.range_into(&items[i], &epsilon, found),
+        |i, found| {
+            kdtree.range_into(&items[i], &epsilon, found);
+            found.iter().map(|&n| weights[n]).sum::<W>() >= min_weight
+        },
+        None,
+    )
+    .expect(UNMONITORED);
+    DbscanResult::from_labels_and_cores(labels, &cores)
+}
+
+/// 要素数 len の集合について、 is_core でコア点を判定し、近傍にあるコア点同士を Union-Find で併合してクラスターを求め、
+/// 各要素のラベルとコア点かどうかを返す。
+/// is_core は要素 i がコア点であれば true を返す。渡されたバッファは近傍を書き込む作業用に使ってよい。
+/// 近傍の数の上限付きの数え上げなど、近傍を列挙するより安価な判定があればそれを用いる。
+/// neighbors はコア点の近傍 (自身を含む) の位置を渡されたバッファに書き込む。
+/// ボーダー点は、自身を近傍に含むコア点のうち添字の最も小さいもののクラスターに属する。
+/// クラスター番号は各クラスターに含まれるコア点の最小の添字の順に振られる。
+/// monitor があれば進捗を通知し、中断が要求されるとその時点までのラベルを Err で返す。
+/// monitor がチェックポイントを扱う場合は、再開する状態があればそこから続け、
+/// 一定の間隔と中断した時点でその時点の状態を書き出す。
+/// ボーダー点を近傍に含むコア点の位置は u32 で持つため、 len は u32::MAX 以下でなければならない。
+pub(crate) fn expand_clusters<L: Label>(
+    len: usize,
+    mut neighbors: impl FnMut(usize, &mut Vec<usize>),
+    mut is_core: impl FnMut(usize, &mut Vec<usize>) -> bool,
+    mut monitor: Option<&mut Monitor<'_>>,
+) -> Result<(Vec<L>, Vec<bool>), Vec<L>> {
+    assert!(u32::try_from(len).is_ok(), "too many items to expand clusters");
+
+    let mut state = match monitor.as_deref_mut().and_then(Monitor::take_resume) {
+        Some(resume) => Expansion::resume(resume, len),
+        None => Expansion::new(len),
+    };
+
+    // 近傍のリストは保持せず、 buffer を使い回す
+    let mut buffer = Vec::new();
+
+    // 各段階で要素を 1 つ調べるたびに数え、一定の間隔で進捗を通知する。中断が要求されていれば false を返す
+    let proceed = |processed: usize, monitor: &mut Option<&mut Monitor<'_>>| match monitor {
+        Some(monitor) if processed.is_multiple_of(PROGRESS_INTERVAL) => monitor.report(processed, 2 * len, 0),
+        _ => true,
+    };
+
+    // 全要素がコア点かどうかを判定する
+    while state.processed < len {
+        state.save(&mut monitor, false);
+        let item = state.processed;
+        state.cores[item] = is_core(item, &mut buffer);
+        state.processed += 1;
+        if !proceed(state.processed, &mut monitor) {
+            state.save(&mut monitor, true);
+            return Err(state.labels());
+        }
     }
 
-    fn distance_to_axis(&self, other: &Self, depth: usize) -> Self::Measurement {
-        self.1.distance_to_axis(other.1, depth)
+    // 近傍にあるコア点同士を併合し、ボーダー点には自身を近傍に含む最初のコア点を記録する
+    while state.processed < 2 * len {
+        state.save(&mut monitor, false);
+        let item = state.processed - len;
+        if state.cores[item] {
+            neighbors(item, &mut buffer);
+            for &neighbor in &buffer {
+                if state.cores[neighbor] {
+                    state.union_find.union(item, neighbor);
+                } else if state.border_cores[neighbor].is_none() {
+                    state.border_cores[neighbor] = Some(item as u32);
+                }
+            }
+        }
+        state.processed += 1;
+        if !proceed(state.processed, &mut monitor) {
+            state.save(&mut monitor, true);
+            return Err(state.labels());
+        }
     }
+
+    Ok((state.labels(), state.cores))
 }
 
-pub fn dbscan<T: KdTreeItem>(items: impl Into<Vec<T>>, epsilon: T::Measurement, min_items: usize) -> Vec<DbscanLabel> {
-    let items = items.into();
-    let indexed_items: Vec<_> = items.iter().enumerate().map(|(i, item)| Indexed(i, item)).collect();
+/// expand_clusters() の途中の状態。 DbscanCheckpoint はこれを書き出したもの。
+pub(crate) struct Expansion {
+    /// コア点の判定と併合のそれぞれで調べ終えた要素の数の合計。
+    processed: usize,
+
+    cores: Vec<bool>,
+    union_find: UnionFind,
 
-    let kdtree = KdTree::construct(indexed_items.clone());
-    let mut core_neighbor_groups = VecDeque::with_capacity(indexed_items.len() / min_items);
+    /// 各ボーダー点を近傍に含む最初のコア点の位置。
+    border_cores: Vec<Option<u32>>,
+}
 
-    let mut cluster_id = NonZeroUsize::new(1).expect("must be 1");
-    let mut labels = Vec::with_capacity(indexed_items.len());
-    let mut visited = Vec::with_capacity(indexed_items.len());
-    labels.resize(indexed_items.len(), DbscanLabel::Noize);
-    visited.resize(indexed_items.len(), false);
+impl Expansion {
+    fn new(len: usize) -> Expansion {
+        Expansion {
+            processed: 0,
+            cores: vec![false; len],
+            union_find: UnionFind::new(len),
+            border_cores: vec![None; len],
+        }
+    }
 
-    for item in &indexed_items {
-        if visited[item.0] {
+    pub(crate) fn resume(checkpoint: DbscanCheckpoint, len: usize) -> Expansion {
+        assert!(checkpoint.validate(len).is_ok(), "checkpoint must match the items");
+        Expansion {
+            processed: checkpoint.processed,
+            cores: checkpoint.cores,
+            union_find: UnionFind::from_parents(checkpoint.parents),
+            border_cores: checkpoint.border_cores,
+        }
+    }
+
+    /// 併合を調べ終えたコア点を含むクラスターのラベルを付ける。それ以外の要素は L::NOISE になる。
+    /// コア点の判定の途中であれば、すべての要素が L::NOISE になる。
+    pub(crate) fn labels<L: Label>(&mut self) -> Vec<L> {
+        let len = self.cores.len();
+        let merged = self.processed.saturating_sub(len);
+        let mut labels = vec![L::NOISE; len];
+        let mut root_labels = vec![L::NOISE; len];
+        let mut cluster_id = NonZeroUsize::new(1).expect("must be 1");
+        for i in (0..len).filter(|&i| self.cores[i]) {
+            let root = self.union_find.find(i);
+
+            // クラスターで最初に現れるコア点がまだ調べていない要素であれば、クラスターのどのコア点も調べていない
+            if root_labels[root] == L::NOISE && i < merged {
+                root_labels[root] = L::cluster(cluster_id);
+                cluster_id = cluster_id.saturating_add(1);
+            }
+            labels[i] = root_labels[root];
+        }
+        for (i, core) in self.border_cores.iter().enumerate() {
+            if let Some(core) = core {
+                labels[i] = labels[*core as usize];
+            }
+        }
+        labels
+    }
+
+    /// monitor がチェックポイントを扱い、 force であるか前回から間隔が空いていれば、現在の状態を書き出す。
+    fn save(&self, monitor: &mut Option<&mut Monitor<'_>>, force: bool) {
+        let Some(monitor) = monitor.as_deref_mut() else {
+            return;
+        };
+        if !(monitor.checkpoint_due(self.processed) || force && monitor.is_checkpointing()) {
+            return;
+        }
+        monitor.save_checkpoint(&DbscanCheckpoint {
+            processed: self.processed,
+            cores: self.cores.clone(),
+            parents: self.union_find.parents().to_vec(),
+            border_cores: self.border_cores.clone(),
+        });
+    }
+}
+
+/// expand_clusters() の結果のボーダー点のラベルを policy に従って付け直す。
+/// nearest_core はボーダー点の近傍で最も近いコア点の位置を返す。
+fn apply_border_policy<L: Label>(
+    labels: &mut [L],
+    cores: &[bool],
+    policy: BorderPolicy,
+    nearest_core: impl Fn(usize) -> Option<usize>,
+) {
+    if policy == BorderPolicy::FirstWins {
+        return;
+    }
+
+    for i in 0..labels.len() {
+        if cores[i] || labels[i] == L::NOISE {
             continue;
         }
+        labels[i] = match policy {
+            BorderPolicy::FirstWins => labels[i],
+            BorderPolicy::NearestCore => {
+                let core = nearest_core(i).expect("border point must have a core neighbor");
+                labels[core]
+            }
+            BorderPolicy::Noise => L::NOISE,
+        };
+    }
+}
 
-        visited[item.0] = true;
-        let neighbors = kdtree.find_range_n(item, &epsilon);
+/// dbscan() の並列版。各要素の近傍探索を rayon で並列に行い、コア点同士を Union-Find で併合する。
+/// 結果は dbscan() と一致する。
+#[cfg(feature = "parallel")]
+pub fn dbscan_par<T>(items: impl AsRef<[T]>, epsilon: T::Measurement, min_items: usize) -> DbscanResult
+where
+    T: KdTreeItem + Sync,
+    T::Measurement: Sync,
+{
+    Dbscan::new(DbscanParams::new(epsilon, min_items).parallelism(Parallelism::Parallel)).run(items)
+}
 
-        // コア点であればクラスターを生成
-        if neighbors.len() >= min_items {
-            let cluster_label = DbscanLabel::Cluster(cluster_id);
-            labels[item.0] = cluster_label;
+/// DBSCAN の近傍探索を並列に行うかどうか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Parallelism {
+    /// 全要素のコア点の判定、近傍にあるコア点同士の Union-Find による併合とボーダー点の割り当てを順に逐次で行う。
+    /// 近傍のリストは保持しないため、追加のメモリは要素数に比例する分だけになる。
+    #[default]
+    Sequential,
 
-            // コア点候補は VecDeque で先頭から探索する
-            core_neighbor_groups.push_back(neighbors);
-            while let Some(neighbors) = core_neighbor_groups.pop_front() {
-                for neighbor in neighbors {
-                    if !visited[neighbor.0] {
-                        visited[neighbor.0] = true;
-                        labels[neighbor.0] = cluster_label;
+    /// Sequential の各段階を rayon で並列に行う。 parallel feature が無効な場合は Sequential と同じになる。
+    /// ラベルは IndexKind::KdTree を指定した Sequential と一致する。
+    Parallel,
 
-                        let sub_neighbors = kdtree.find_range_n(neighbor, &epsilon);
-                        if sub_neighbors.len() >= min_items {
-                            core_neighbor_groups.push_back(sub_neighbors);
-                        }
-                    }
+    /// 全要素がコア点かどうかを近傍の数え上げで先に rayon で並列に判定し、コア点の併合は Sequential と同様に逐次に行う。
+    /// ラベルは IndexKind::KdTree を指定した Sequential と一致する。進捗の通知と中断の確認は併合の段階でだけ行う。
+    /// parallel feature が無効な場合は Sequential と同じになる。
+    PrecomputedCounts,
+
+    /// PrecomputedCounts と同様だが、近傍の数だけでなく近傍のリストも並列に求めて保持し、併合ではそれを用いる。
+    /// 併合での探索が不要になる代わりに、近傍の総数に比例するメモリを使う。
+    PrecomputedNeighbors,
+}
+
+/// Dbscan の設定。 new() で必須の値を指定し、残りは各メソッドで上書きする。
+#[derive(Debug, Clone)]
+pub struct DbscanParams<D, M = ItemMetric> {
+    pub(crate) epsilon: D,
+    pub(crate) min_points: usize,
+    pub(crate) metric: M,
+    index: IndexKind,
+    border_policy: BorderPolicy,
+    pub(crate) cluster_order: ClusterOrder,
+    pub(crate) parallelism: Parallelism,
+    deduplicate: bool,
+    morton_order: bool,
+}
+
+impl<D> DbscanParams<D> {
+    /// epsilon 以内に自身を含めて min_points 個以上の要素があるものをコア点とする設定を作る。
+    /// 距離は要素自身の KdTreeItem::distance() で計算し、インデックスは IndexKind::Auto で選ぶ。
+    pub fn new(epsilon: D, min_points: usize) -> DbscanParams<D> {
+        DbscanParams {
+            epsilon,
+            min_points,
+            metric: ItemMetric,
+            index: IndexKind::default(),
+            border_policy: BorderPolicy::default(),
+            cluster_order: ClusterOrder::default(),
+            parallelism: Parallelism::default(),
+            deduplicate: false,
+            morton_order: false,
+        }
+    }
+}
+
+impl<D, M> DbscanParams<D, M> {
+    pub fn epsilon(self, epsilon: D) -> DbscanParams<D, M> {
+        DbscanParams { epsilon, ..self }
+    }
+
+    pub fn min_points(self, min_points: usize) -> DbscanParams<D, M> {
+        DbscanParams { min_points, ..self }
+    }
+
+    /// 要素間の距離を metric で計算する。
+    pub fn metric<M2>(self, metric: M2) -> DbscanParams<D, M2> {
+        DbscanParams {
+            epsilon: self.epsilon,
+            min_points: self.min_points,
+            metric,
+            index: self.index,
+            border_policy: self.border_policy,
+            cluster_order: self.cluster_order,
+            parallelism: self.parallelism,
+            deduplicate: self.deduplicate,
+            morton_order: self.morton_order,
+        }
+    }
+
+    /// 近傍探索に用いるインデックスの種類。
+    pub fn index(self, index: IndexKind) -> DbscanParams<D, M> {
+        DbscanParams { index, ..self }
+    }
+
+    pub fn border_policy(self, border_policy: BorderPolicy) -> DbscanParams<D, M> {
+        DbscanParams { border_policy, ..self }
+    }
+
+    /// クラスター番号の振り方。
+    pub fn cluster_order(self, cluster_order: ClusterOrder) -> DbscanParams<D, M> {
+        DbscanParams { cluster_order, ..self }
+    }
+
+    pub fn parallelism(self, parallelism: Parallelism) -> DbscanParams<D, M> {
+        DbscanParams { parallelism, ..self }
+    }
+
+    /// true にすると、 run_points() で座標が完全に一致する要素を 1 つにまとめてからインデックスを構築する。
+    /// まとめた要素は重複した数を重みとして近傍の数に数えるため、結果はまとめない場合と同じになる。
+    /// 同じ点が大量にある入力で近傍のリストが膨らむのを防ぐ。 run() では無視される。
+    pub fn deduplicate(self, deduplicate: bool) -> DbscanParams<D, M> {
+        DbscanParams { deduplicate, ..self }
+    }
+
+    /// true にすると、 run_points() で要素を morton_order() の順に並べ替えてからインデックスを構築し、その順に探索する。
+    /// 空間的に近い要素が続けて探索され、インデックスの同じノードや近くの要素を辿りやすくなるため、
+    /// 入力が空間的にばらばらな順で並んでいる大きな入力で速くなる。並べ替えた列の分だけメモリを余分に使う。
+    /// コア点とクラスターの分かれ方は変わらないが、ボーダー点がどのクラスターに属するかは異なる場合がある。 run() では無視される。
+    pub fn morton_order(self, morton_order: bool) -> DbscanParams<D, M> {
+        DbscanParams { morton_order, ..self }
+    }
+}
+
+/// DbscanParams の設定で DBSCAN を行う。
+#[derive(Debug, Clone)]
+pub struct Dbscan<D, M = ItemMetric> {
+    params: DbscanParams<D, M>,
+}
+
+impl<D, M> Dbscan<D, M> {
+    pub fn new(params: DbscanParams<D, M>) -> Dbscan<D, M> {
+        Dbscan { params }
+    }
+
+    pub fn params(&self) -> &DbscanParams<D, M> {
+        &self.params
+    }
+
+    /// items をクラスタリングする。
+    /// IndexKind::Auto は要素がごく少なければ BruteForceIndex を、そうでなければ KdTree を選ぶ。
+    /// IndexKind::Grid, IndexKind::BallTree, IndexKind::CoverTree は座標の配列にしか使えないため、 run_points() を用いなければならない。
+    /// Parallelism::Sequential 以外では index の指定によらず KdTree を用いる。
+    pub fn run<T>(&self, items: impl AsRef<[T]>) -> DbscanResult
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: Sync,
+    {
+        self.execute(items.as_ref(), None).expect(UNMONITORED)
+    }
+
+    /// run() と同様だが、索引の構築、近傍探索、コア点の併合のそれぞれにかかった時間も返す。
+    /// 探索のたびに時刻を取得するため、その分だけ run() より遅くなる。
+    pub fn run_timed<T>(&self, items: impl AsRef<[T]>) -> (DbscanResult, DbscanTimings)
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: Sync,
+    {
+        let mut progress = |_| ();
+        let cancellation = CancellationToken::new();
+        let mut monitor = Monitor::new(&mut progress, &cancellation).with_timings();
+        let result = self.execute(items.as_ref(), Some(&mut monitor)).expect(UNCANCELLED);
+        (result, monitor.timings().expect("monitor must record timings"))
+    }
+
+    /// run() と同様だが、先に validate_items() で入力を検証する。
+    /// NaN を含む要素は比較できず run() が panic するため、外部から受け取った値にはこちらを用いる。
+    pub fn try_run<T>(&self, items: impl AsRef<[T]>) -> Result<DbscanResult, Error>
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: PartialOrd + Sync,
+    {
+        let items = items.as_ref();
+        check_radius(&self.params.epsilon)?;
+        validate_items(items)?;
+        Ok(self.run(items))
+    }
+
+    /// run() と同様だが、処理の途中で progress に進捗を通知し、 cancellation で中断できる。
+    /// 中断された場合は Err(Cancelled) を返し、 partial には中断した時点までのラベルが入る。
+    /// 併合を調べ終えたコア点を含むクラスターの要素だけがラベルを持ち、クラスターは一部の要素しか含まないことがある。
+    /// コア点の判定の途中で中断された場合は、すべて DbscanLabel::Noise になる。
+    pub fn run_with_progress<T>(
+        &self,
+        items: impl AsRef<[T]>,
+        mut progress: impl FnMut(ProgressEvent),
+        cancellation: &CancellationToken,
+    ) -> Result<DbscanResult, Cancelled<Vec<DbscanLabel>>>
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: Sync,
+    {
+        let mut monitor = Monitor::new(&mut progress, cancellation);
+        self.execute(items.as_ref(), Some(&mut monitor))
+            .map_err(|partial| Cancelled { partial })
+    }
+
+    /// run_with_progress() と同様だが、進捗を通知せずに cancellation による中断だけを受け付ける。
+    /// 中断は一定の数の要素を調べるごとに確認される。
+    pub fn run_cancellable<T>(
+        &self,
+        items: impl AsRef<[T]>,
+        cancellation: &CancellationToken,
+    ) -> Result<DbscanResult, Cancelled<Vec<DbscanLabel>>>
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: Sync,
+    {
+        self.run_with_progress(items, |_| (), cancellation)
+    }
+
+    /// run_cancellable() と同様だが、コア点の判定と併合で要素を interval 個調べるごとにその時点の状態を DbscanCheckpoint として save に渡す。
+    /// resume に以前書き出したチェックポイントを渡すと、その状態からコア点の判定または併合を再開する。
+    /// 途中で中断された場合も、中断した時点の状態を save に渡してから Err(Cancelled) を返す。
+    /// 再開には書き出したときと同じ items と設定を用いなければならない。
+    /// Parallelism の指定によらず Parallelism::Sequential と同様に逐次に処理する。
+    /// border_policy によるボーダー点のラベルの付け直しとクラスター番号の振り直しは併合を終えた後に行い、チェックポイントには含まれない。
+    /// interval が 0 の場合と、 resume が items と一致しない (DbscanCheckpoint::validate() が Err を返す) 場合は panic する。
+    pub fn run_checkpointed<T>(
+        &self,
+        items: impl AsRef<[T]>,
+        resume: Option<DbscanCheckpoint>,
+        interval: usize,
+        mut save: impl FnMut(&DbscanCheckpoint),
+        cancellation: &CancellationToken,
+    ) -> Result<DbscanResult, Cancelled<Vec<DbscanLabel>>>
+    where
+        T: KdTreeItem,
+        M: Metric<T, Measurement = D>,
+    {
+        assert!(interval > 0, "checkpoint interval must not be zero");
+        let items = items.as_ref();
+        if let Some(resume) = &resume {
+            resume.validate(items.len()).expect("checkpoint must match the items");
+        }
+        let mut progress = |_| ();
+        let mut monitor = Monitor::new(&mut progress, cancellation).with_checkpoint(resume, interval, &mut save);
+        self.run_sequential(items, None, Some(&mut monitor))
+            .map_err(|partial| Cancelled { partial })
+    }
+
+    /// run() でクラスタリングし、結果とコア点を保持した DbscanModel を返す。
+    /// DbscanModel::predict() で新しい要素を既存のクラスターに分類できる。
+    pub fn fit<T>(&self, items: impl AsRef<[T]>) -> DbscanModel<T, M>
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: Clone + Sync,
+    {
+        let items = items.as_ref();
+        let result = self.run(items);
+        DbscanModel::new(items, result, self.params.epsilon.clone(), self.params.metric.clone())
+    }
+
+    /// 近似版の run() 。 items から seed で決まる sample_size 個の要素を選んでクラスタリングし、
+    /// 残りの要素を DbscanModel::predict_one() と同様に epsilon 以内で最も近い標本のコア点のクラスターに分類する。
+    /// 標本の密度は全体の sample_size / items.len() 倍になるため、 min_points も同じ比率で減らした値 (切り上げ、 1 以上) を用いる。
+    /// epsilon は変えないため、標本の要素の間隔が epsilon に比べて広くなりすぎるとクラスターが分断される。
+    /// コア点は標本の中からしか選ばれない。 sample_size が要素数以上であれば run() と同じ結果になる。
+    pub fn run_sampled<T>(&self, items: impl AsRef<[T]>, sample_size: usize, seed: u64) -> DbscanResult
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: Clone + Sync,
+    {
+        let items = items.as_ref();
+        if sample_size >= items.len() {
+            return self.run(items);
+        }
+
+        let sample = sample_indices(items.len(), sample_size, seed);
+        let sample_items: Vec<T> = sample.iter().map(|&i| items[i].clone()).collect();
+        let min_points = (self.params.min_points * sample_size).div_ceil(items.len()).max(1);
+        let params = self
+            .params
+            .clone()
+            .min_points(min_points)
+            .cluster_order(ClusterOrder::Discovery);
+        let model = Dbscan::new(params).fit(&sample_items);
+
+        let mut labels = vec![DbscanLabel::Noise; items.len()];
+        let mut cores = vec![false; items.len()];
+        let mut sampled = vec![false; items.len()];
+        let sample_roles = model.result().roles.as_deref().expect("result must have roles");
+        for (n, &i) in sample.iter().enumerate() {
+            labels[i] = model.result().labels[n];
+            cores[i] = sample_roles[n] == PointRole::Core;
+            sampled[i] = true;
+        }
+
+        // 空間的に近い要素から順に分類すると、続く探索が同じノードを辿りやすい
+        let unsampled: Vec<u32> = spatial_order(items)
+            .into_iter()
+            .filter(|&i| !sampled[i as usize])
+            .collect();
+        let predict = |&i: &u32| model.predict_one(&items[i as usize]);
+        #[cfg(feature = "parallel")]
+        let predicted: Vec<DbscanLabel> = if self.params.parallelism == Parallelism::Parallel {
+            use rayon::prelude::*;
 
-                    if labels[neighbor.0] == DbscanLabel::Noize {
-                        labels[neighbor.0] = cluster_label;
+            unsampled.par_iter().map(predict).collect()
+        } else {
+            unsampled.iter().map(predict).collect()
+        };
+        #[cfg(not(feature = "parallel"))]
+        let predicted: Vec<DbscanLabel> = unsampled.iter().map(predict).collect();
+        for (&i, label) in unsampled.iter().zip(predicted) {
+            labels[i as usize] = label;
+        }
+
+        let mut result = DbscanResult::from_labels_and_cores(labels, &cores);
+        result.renumber(self.params.cluster_order);
+        result
+    }
+
+    fn execute<T>(&self, items: &[T], monitor: Option<&mut Monitor<'_>>) -> Result<DbscanResult, Vec<DbscanLabel>>
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: Sync,
+    {
+        #[cfg(feature = "parallel")]
+        match self.params.parallelism {
+            Parallelism::Parallel => return self.run_par(items, None, monitor),
+            Parallelism::PrecomputedCounts => return self.run_precomputed(items, None, false, monitor),
+            Parallelism::PrecomputedNeighbors => return self.run_precomputed(items, None, true, monitor),
+            Parallelism::Sequential => (),
+        }
+        self.run_sequential(items, None, monitor)
+    }
+
+    /// 近傍 neighbors を持つ要素がコア点かどうか。 multiplicities があれば各要素をその数だけあるものとして数える。
+    fn is_core(&self, neighbors: &[usize], multiplicities: Option<&[usize]>) -> bool {
+        match multiplicities {
+            Some(multiplicities) => {
+                neighbors.iter().map(|&n| multiplicities[n]).sum::<usize>() >= self.params.min_points
+            }
+            None => neighbors.len() >= self.params.min_points,
+        }
+    }
+
+    fn run_sequential<T: KdTreeItem>(
+        &self,
+        items: &[T],
+        multiplicities: Option<&[usize]>,
+        mut monitor: Option<&mut Monitor<'_>>,
+    ) -> Result<DbscanResult, Vec<DbscanLabel>>
+    where
+        M: Metric<T, Measurement = D>,
+    {
+        let params = &self.params;
+        match params.index {
+            IndexKind::Auto if items.len() <= BRUTE_FORCE_MAX_ITEMS => {
+                self.run_brute_force(items, multiplicities, monitor)
+            }
+            IndexKind::BruteForce => self.run_brute_force(items, multiplicities, monitor),
+            IndexKind::Auto | IndexKind::KdTree => {
+                let kdtree = build_index(&mut monitor, || {
+                    SliceKdTree::construct_with_metric(items, params.metric.clone())
+                });
+                let range = |i, found: &mut _| kdtree.range_into(&items[i], &params.epsilon, found);
+                let count = |i, limit| kdtree.count_within_limited(&items[i], &params.epsilon, limit);
+                self.cluster(items, range, CoreHint::Count(&count), multiplicities, monitor)
+            }
+            IndexKind::ImplicitKdTree => {
+                let kdtree = build_index(&mut monitor, || {
+                    ImplicitKdTree::construct_with_metric(items, params.metric.clone())
+                });
+                let range = |i, found: &mut _| kdtree.range_into(&items[i], &params.epsilon, found);
+                let count = |i, limit| kdtree.count_within_limited(&items[i], &params.epsilon, limit);
+                self.cluster(items, range, CoreHint::Count(&count), multiplicities, monitor)
+            }
+            IndexKind::Grid | IndexKind::BallTree | IndexKind::CoverTree => {
+                panic!("{:?} is only available in Dbscan::run_points()", params.index)
+            }
+        }
+    }
+
+    fn run_brute_force<T: KdTreeItem>(
+        &self,
+        items: &[T],
+        multiplicities: Option<&[usize]>,
+        monitor: Option<&mut Monitor<'_>>,
+    ) -> Result<DbscanResult, Vec<DbscanLabel>>
+    where
+        M: Metric<T, Measurement = D>,
+    {
+        // items を複製しないよう、 BruteForceIndex を作らずにすべての要素との距離を直接計算する
+        let (metric, epsilon) = (&self.params.metric, &self.params.epsilon);
+        let range = |i, found: &mut Vec<usize>| {
+            found.clear();
+            found.extend(brute_force_range(metric, items, i, epsilon));
+        };
+        let count = |i, limit| brute_force_range(metric, items, i, epsilon).take(limit).count();
+        self.cluster(items, range, CoreHint::Count(&count), multiplicities, monitor)
+    }
+
+    /// range で近傍の位置を求めてコア点を併合し、 border_policy に従ってボーダー点のラベルを決める。
+    /// hint があれば、近傍を列挙せずにコア点を判定する。
+    fn cluster<T>(
+        &self,
+        items: &[T],
+        range: impl Fn(usize, &mut Vec<usize>),
+        hint: CoreHint<'_>,
+        multiplicities: Option<&[usize]>,
+        mut monitor: Option<&mut Monitor<'_>>,
+    ) -> Result<DbscanResult, Vec<DbscanLabel>>
+    where
+        M: Metric<T, Measurement = D>,
+    {
+        let params = &self.params;
+        let started = Stopwatch::start();
+
+        // 所要時間を記録する場合は、近傍探索にかかった時間を query_time に積算する
+        let timing = monitor.as_ref().is_some_and(|monitor| monitor.is_timing());
+        let query_time = Cell::new(Duration::ZERO);
+        let range = |i, found: &mut Vec<usize>| timed(timing, &query_time, || range(i, found));
+
+        // 重複をまとめた要素は近傍の数と重みの合計が異なるため、数え上げで判定できるのはまとめていない場合だけになる
+        let is_core = |i, found: &mut Vec<usize>| match (hint, multiplicities) {
+            #[cfg(feature = "parallel")]
+            (CoreHint::Precomputed(cores), _) => cores[i],
+            (CoreHint::Count(count), None) => {
+                timed(timing, &query_time, || count(i, params.min_points)) >= params.min_points
+            }
+            _ => {
+                range(i, found);
+                self.is_core(found, multiplicities)
+            }
+        };
+        let (mut labels, cores) = expand_clusters(items.len(), &range, is_core, monitor.as_deref_mut())?;
+        apply_border_policy(&mut labels, &cores, params.border_policy, |i| {
+            let mut neighbors = Vec::new();
+            range(i, &mut neighbors);
+            nearest_core(&params.metric, items, i, neighbors.into_iter().filter(|&n| cores[n]))
+        });
+        let mut result = DbscanResult::from_labels_and_cores(labels, &cores);
+        result.renumber(params.cluster_order);
+        if let Some(monitor) = monitor.as_deref_mut() {
+            monitor.add_time(query_time.get(), |t| &mut t.neighbor_queries);
+            monitor.add_time(started.elapsed().saturating_sub(query_time.get()), |t| &mut t.expansion);
+        }
+        finish(result, 2 * items.len(), monitor)
+    }
+
+    /// Parallelism::PrecomputedCounts と Parallelism::PrecomputedNeighbors の実装。
+    /// 全要素がコア点かどうかを並列に判定し、 keep_neighbors であれば近傍のリストも保持してから cluster() で併合する。
+    #[cfg(feature = "parallel")]
+    fn run_precomputed<T>(
+        &self,
+        items: &[T],
+        multiplicities: Option<&[usize]>,
+        keep_neighbors: bool,
+        mut monitor: Option<&mut Monitor<'_>>,
+    ) -> Result<DbscanResult, Vec<DbscanLabel>>
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: Sync,
+    {
+        use rayon::prelude::*;
+
+        let params = &self.params;
+        let kdtree = build_index(&mut monitor, || {
+            SliceKdTree::construct_par_with_metric(items, params.metric.clone())
+        });
+        let started = Stopwatch::start();
+        let neighbors: Vec<Box<[usize]>> = if keep_neighbors {
+            (0..items.len())
+                .into_par_iter()
+                .map(|i| {
+                    let mut found = Vec::new();
+                    kdtree.range_into(&items[i], &params.epsilon, &mut found);
+                    found.into_boxed_slice()
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let cores: Vec<bool> = (0..items.len())
+            .into_par_iter()
+            .map_init(Vec::new, |found, i| match (neighbors.get(i), multiplicities) {
+                (Some(neighbors), _) => self.is_core(neighbors, multiplicities),
+                (None, None) => {
+                    kdtree.count_within_limited(&items[i], &params.epsilon, params.min_points) >= params.min_points
+                }
+                (None, Some(_)) => {
+                    kdtree.range_into(&items[i], &params.epsilon, found);
+                    self.is_core(found, multiplicities)
+                }
+            })
+            .collect();
+        if let Some(monitor) = monitor.as_deref_mut() {
+            monitor.add_time(started.elapsed(), |t| &mut t.neighbor_queries);
+        }
+
+        let hint = CoreHint::Precomputed(&cores);
+        if keep_neighbors {
+            let range = |i: usize, found: &mut Vec<usize>| {
+                found.clear();
+                found.extend_from_slice(&neighbors[i]);
+            };
+            self.cluster(items, range, hint, multiplicities, monitor)
+        } else {
+            let range = |i, found: &mut _| kdtree.range_into(&items[i], &params.epsilon, found);
+            self.cluster(items, range, hint, multiplicities, monitor)
+        }
+    }
+
+    /// run() の並列版。 monitor があれば一定の数の要素を処理するごとに進捗を通知する。
+    #[cfg(feature = "parallel")]
+    fn run_par<T>(
+        &self,
+        items: &[T],
+        multiplicities: Option<&[usize]>,
+        mut monitor: Option<&mut Monitor<'_>>,
+    ) -> Result<DbscanResult, Vec<DbscanLabel>>
+    where
+        T: KdTreeItem + Sync,
+        M: Metric<T, Measurement = D> + Sync,
+        D: Sync,
+    {
+        use rayon::prelude::*;
+
+        let params = &self.params;
+        let kdtree = build_index(&mut monitor, || {
+            SliceKdTree::construct_par_with_metric(items, params.metric.clone())
+        });
+        let started = Stopwatch::start();
+        let positions: Vec<usize> = (0..items.len()).collect();
+
+        // 進捗を通知する場合は chunk_size ずつ処理し、その間に通知する
+        let len = items.len();
+        let chunk_size = match monitor {
+            Some(_) => PARALLEL_PROGRESS_CHUNK,
+            None => len.max(1),
+        };
+        let mut report = |processed: usize| match monitor.as_mut() {
+            Some(monitor) => monitor.report(processed, 2 * len, 0),
+            None => true,
+        };
+
+        // コア点の判定。近傍を書き込むバッファはスレッドごとに使い回す
+        let mut is_core: Vec<bool> = Vec::with_capacity(len);
+        for chunk in positions.chunks(chunk_size) {
+            is_core.par_extend(chunk.par_iter().map_init(Vec::new, |found, &i| {
+                if multiplicities.is_none() {
+                    let count = kdtree.count_within_limited(&items[i], &params.epsilon, params.min_points);
+                    return count >= params.min_points;
+                }
+                kdtree.range_into(&items[i], &params.epsilon, found);
+                self.is_core(found, multiplicities)
+            }));
+            if !report(is_core.len()) {
+                return Err(vec![DbscanLabel::Noise; len]);
+            }
+        }
+
+        // 近傍にあるコア点同士を併合し、ボーダー点は border_policy に従って近傍のコア点を 1 つ記録する
+        let union_find = ConcurrentUnionFind::new(len);
+        let mut border_cores: Vec<Option<usize>> = Vec::with_capacity(len);
+
+        // border_cores に記録済みの範囲のコア点を含むクラスターにラベルを付ける。それ以外の要素は Noise になる
+        let label_merged = |border_cores: &[Option<usize>]| {
+            let mut cluster_id = NonZeroUsize::new(1).expect("must be 1");
+            let mut root_labels = vec![DbscanLabel::Noise; len];
+            let mut labels = vec![DbscanLabel::Noise; len];
+            for i in (0..len).filter(|&i| is_core[i]) {
+                let root = union_find.find(i);
+                if root_labels[root] == DbscanLabel::Noise && i < border_cores.len() {
+                    root_labels[root] = DbscanLabel::Cluster(cluster_id);
+                    cluster_id = cluster_id.saturating_add(1);
+                }
+                labels[i] = root_labels[root];
+            }
+            for (i, core) in border_cores.iter().enumerate() {
+                if let Some(core) = core {
+                    labels[i] = root_labels[union_find.find(*core)];
+                }
+            }
+            labels
+        };
+
+        for chunk in positions.chunks(chunk_size) {
+            border_cores.par_extend(chunk.par_iter().map_init(Vec::new, |found, &i| {
+                kdtree.range_into(&items[i], &params.epsilon, found);
+                if is_core[i] {
+                    for &neighbor in found.iter().filter(|&&n| is_core[n]) {
+                        union_find.union(i, neighbor);
                     }
+                    return None;
                 }
+
+                let cores = found.iter().copied().filter(|&n| is_core[n]);
+                match params.border_policy {
+                    BorderPolicy::FirstWins => cores.min(),
+                    BorderPolicy::NearestCore => nearest_core(&params.metric, items, i, cores),
+                    BorderPolicy::Noise => None,
+                }
+            }));
+            if !report(len + border_cores.len()) {
+                return Err(label_merged(&border_cores));
             }
+        }
 
-            cluster_id = cluster_id.saturating_add(1);
+        let queried = Stopwatch::start();
+        let labels = label_merged(&border_cores);
+        let mut result = DbscanResult::from_labels_and_cores(labels, &is_core);
+        result.renumber(params.cluster_order);
+        if let Some(monitor) = monitor.as_deref_mut() {
+            monitor.add_time(started.elapsed().saturating_sub(queried.elapsed()), |t| {
+                &mut t.neighbor_queries
+            });
+            monitor.add_time(queried.elapsed(), |t| &mut t.expansion);
         }
+        finish(result, 2 * len, monitor)
     }
+}
 
-    labels
+impl<F: Debug + Float + Sync> Dbscan<F> {
+    /// run() と同様だが、座標の配列について IndexKind のすべての種類を使える。 GridIndex の格子の大きさは epsilon になる。
+    /// IndexKind::Auto では、要素がごく少なければ構築の手間のない BruteForceIndex を、
+    /// 3 次元以下で要素が外接直方体に十分密に分布していれば GridIndex を、そうでなければ KdTree を用いる。
+    /// deduplicate が有効であれば、座標が一致する要素をまとめてからクラスタリングする。
+    pub fn run_points<const N: usize>(&self, items: impl AsRef<[[F; N]]>) -> DbscanResult {
+        let items = items.as_ref();
+        if !self.params.deduplicate {
+            return self.run_points_weighted(items, None);
+        }
+
+        let coalesced = coalesce_duplicates(items);
+        let result = self.run_points_weighted(&coalesced.items, Some(&coalesced.multiplicities));
+        let mut expanded = DbscanResult::from_labels(coalesced.expand(&result.labels));
+        expanded.roles = result.roles.map(|roles| coalesced.expand(&roles));
+        let mut result = expanded;
+        result.renumber(self.params.cluster_order);
+        result
+    }
+
+    fn run_points_weighted<const N: usize>(&self, items: &[[F; N]], multiplicities: Option<&[usize]>) -> DbscanResult {
+        if !self.params.morton_order {
+            return self.run_points_in_order(items, multiplicities);
+        }
+
+        let order = morton_order(items);
+        let sorted: Vec<[F; N]> = order.iter().map(|&i| items[i]).collect();
+        let sorted_multiplicities: Option<Vec<usize>> =
+            multiplicities.map(|multiplicities| order.iter().map(|&i| multiplicities[i]).collect());
+        let result = self.run_points_in_order(&sorted, sorted_multiplicities.as_deref());
+
+        let mut labels = vec![DbscanLabel::Noise; items.len()];
+        let mut cores = vec![false; items.len()];
+        for (k, &i) in order.iter().enumerate() {
+            labels[i] = result.labels[k];
+            cores[i] = result.role(k) == Some(PointRole::Core);
+        }
+        // 番号は並べ替えた順で振られているので、元の順で最初に現れるコア点の順に振り直す
+        let mut new_ids = vec![None; result.cluster_count];
+        let mut next_id = 0;
+        for i in 0..items.len() {
+            if let (true, DbscanLabel::Cluster(id)) = (cores[i], labels[i]) {
+                new_ids[id.get() - 1].get_or_insert_with(|| {
+                    next_id += 1;
+                    NonZeroUsize::new(next_id).expect("must be non-zero")
+                });
+            }
+        }
+        for label in &mut labels {
+            if let DbscanLabel::Cluster(id) = label {
+                *id = new_ids[id.get() - 1].expect("every cluster must have a core point");
+            }
+        }
+        let mut result = DbscanResult::from_labels_and_cores(labels, &cores);
+        result.renumber(self.params.cluster_order);
+        result
+    }
+
+    /// run_points_weighted() のうち、 items をそのままの順で扱う部分。
+    fn run_points_in_order<const N: usize>(&self, items: &[[F; N]], multiplicities: Option<&[usize]>) -> DbscanResult {
+        let params = &self.params;
+        #[cfg(feature = "parallel")]
+        match params.parallelism {
+            Parallelism::Parallel => return self.run_par(items, multiplicities, None).expect(UNMONITORED),
+            Parallelism::PrecomputedCounts => {
+                return self
+                    .run_precomputed(items, multiplicities, false, None)
+                    .expect(UNMONITORED)
+            }
+            Parallelism::PrecomputedNeighbors => {
+                return self
+                    .run_precomputed(items, multiplicities, true, None)
+                    .expect(UNMONITORED)
+            }
+            Parallelism::Sequential => (),
+        }
+
+        let kind = match params.index {
+            IndexKind::Auto if items.len() <= BRUTE_FORCE_MAX_ITEMS => IndexKind::BruteForce,
+            IndexKind::Auto if is_grid_suitable(items, params.epsilon) => IndexKind::Grid,
+            IndexKind::Auto => IndexKind::KdTree,
+            kind => kind,
+        };
+        match kind {
+            IndexKind::Grid => {
+                let grid = GridIndex::new_unchecked(items, params.epsilon);
+                self.cluster(
+                    items,
+                    |i, found| grid.range_into(&items[i], &params.epsilon, found),
+                    CoreHint::None,
+                    multiplicities,
+                    None,
+                )
+            }
+            IndexKind::BallTree => {
+                let ball_tree = BallTree::construct_unchecked(items);
+                self.cluster(
+                    items,
+                    |i, found| ball_tree.range_into(&items[i], &params.epsilon, found),
+                    CoreHint::None,
+                    multiplicities,
+                    None,
+                )
+            }
+            IndexKind::CoverTree => {
+                let cover_tree = CoverTree::construct_unchecked(items);
+                self.cluster(
+                    items,
+                    |i, found| cover_tree.range_into(&items[i], &params.epsilon, found),
+                    CoreHint::None,
+                    multiplicities,
+                    None,
+                )
+            }
+            IndexKind::BruteForce => self.run_brute_force(items, multiplicities, None),
+            _ => Dbscan::new(params.clone().index(IndexKind::KdTree)).run_sequential(items, multiplicities, None),
+        }
+        .expect(UNMONITORED)
+    }
+
+    /// run_points() と同様だが、先に validate_items() で入力を検証する。
+    pub fn try_run_points<const N: usize>(&self, items: impl AsRef<[[F; N]]>) -> Result<DbscanResult, Error> {
+        let items = items.as_ref();
+        check_radius(&self.params.epsilon)?;
+        validate_items(items)?;
+        Ok(self.run_points(items))
+    }
+}
+
+/// 最後の進捗を通知して result を返す。中断が要求されていれば result のラベルを Err で返す。
+fn finish(
+    result: DbscanResult,
+    total: usize,
+    monitor: Option<&mut Monitor<'_>>,
+) -> Result<DbscanResult, Vec<DbscanLabel>> {
+    let cancelled = monitor.is_some_and(|monitor| !monitor.report(total, total, result.cluster_count));
+    if cancelled {
+        Err(result.labels)
+    } else {
+        Ok(result)
+    }
+}
+
+/// Dbscan::cluster() に渡す、近傍を列挙するより安価なコア点の判定。
+#[derive(Clone, Copy)]
+enum CoreHint<'a> {
+    /// 近傍を列挙して判定する。
+    None,
+
+    /// 要素 i の近傍を limit を上限として数える。重複をまとめていない場合にだけ用いる。
+    Count(&'a dyn Fn(usize, usize) -> usize),
+
+    /// 各要素がコア点かどうかをあらかじめ判定したもの。
+    #[cfg(feature = "parallel")]
+    Precomputed(&'a [bool]),
+}
+
+/// monitor が所要時間を記録していれば、 build() で索引を構築するのにかかった時間を加える。
+fn build_index<I>(monitor: &mut Option<&mut Monitor<'_>>, build: impl FnOnce() -> I) -> I {
+    let started = Stopwatch::start();
+    let index = build();
+    if let Some(monitor) = monitor.as_deref_mut() {
+        monitor.add_time(started.elapsed(), |t| &mut t.index_build);
+    }
+    index
+}
+
+/// enabled であれば f を実行するのにかかった時間を total に加える。
+fn timed<R>(enabled: bool, total: &Cell<Duration>, f: impl FnOnce() -> R) -> R {
+    if !enabled {
+        return f();
+    }
+    let started = Stopwatch::start();
+    let result = f();
+    total.set(total.get() + started.elapsed());
+    result
+}
+
+/// Parallelism::Parallel で進捗を通知する場合に、一度に並列に処理する要素の数。
+#[cfg(feature = "parallel")]
+const PARALLEL_PROGRESS_CHUNK: usize = 65536;
+
+/// items のうち items[item] から epsilon 以内 (境界を含む) にある要素の位置を、すべての要素との距離を計算して昇順に返す。
+fn brute_force_range<'a, T, M: Metric<T>>(
+    metric: &'a M,
+    items: &'a [T],
+    item: usize,
+    epsilon: &'a M::Measurement,
+) -> impl Iterator<Item = usize> + 'a {
+    (0..items.len()).filter(move |&j| metric.distance(&items[item], &items[j]) <= *epsilon)
+}
+
+/// candidates のうち items[item] に最も近い要素の位置を返す。距離が等しければ位置の小さいものを選ぶ。
+fn nearest_core<T, M: Metric<T>>(
+    metric: &M,
+    items: &[T],
+    item: usize,
+    candidates: impl Iterator<Item = usize>,
+) -> Option<usize> {
+    candidates
+        .map(|c| (c, metric.distance(&items[item], &items[c])))
+        .min_by(|(lhs, lhs_distance), (rhs, rhs_distance)| {
+            lhs_distance
+                .partial_cmp(rhs_distance)
+                .expect("not total order")
+                .then(lhs.cmp(rhs))
+        })
+        .map(|(c, _)| c)
 }
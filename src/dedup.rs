@@ -0,0 +1,78 @@
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+
+use num_traits::Float;
+
+/// 座標が完全に一致する要素を 1 つにまとめた結果。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coalesced<T> {
+    /// 重複を除いた要素。元の列で最初に現れた順に並ぶ。
+    pub items: Vec<T>,
+
+    /// items の各要素が元の列に現れた回数。
+    pub multiplicities: Vec<usize>,
+
+    /// 元の列の各要素がまとめられた items での位置。
+    pub groups: Vec<usize>,
+}
+
+impl<T> Coalesced<T> {
+    /// items の各要素に付けた値 values を、元の列の各要素に付けた値に広げる。
+    pub fn expand<V: Copy>(&self, values: &[V]) -> Vec<V> {
+        assert_eq!(
+            values.len(),
+            self.items.len(),
+            "values must have the same length as items"
+        );
+        self.groups.iter().map(|&g| values[g]).collect()
+    }
+}
+
+/// items のうち座標が == で等しい要素を 1 つにまとめ、まとめた数を重みとして記録する。
+/// NaN を含む要素はどれとも等しくならないため、そのまま残る。 0.0 と -0.0 は等しいものとしてまとめる。
+pub fn coalesce_duplicates<F: Float, const N: usize>(items: &[[F; N]]) -> Coalesced<[F; N]> {
+    // 座標の辞書順に並べると等しい要素が隣り合う。安定ソートなので同じ座標の中では元の順を保つ
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by(|&lhs, &rhs| cmp_coordinates(&items[lhs], &items[rhs]));
+
+    // 各要素の代表 (同じ座標で最初に現れた要素) の位置
+    let mut representatives = vec![0; items.len()];
+    let mut run_start = 0;
+    for (n, &i) in order.iter().enumerate() {
+        if items[i] != items[order[run_start]] {
+            run_start = n;
+        }
+        representatives[i] = order[run_start];
+    }
+
+    // 代表を元の列での順に番号付けする
+    let mut coalesced = Coalesced {
+        items: Vec::new(),
+        multiplicities: Vec::new(),
+        groups: vec![0; items.len()],
+    };
+    for (i, &representative) in representatives.iter().enumerate() {
+        let group = if representative == i {
+            coalesced.items.push(items[i]);
+            coalesced.multiplicities.push(0);
+            coalesced.items.len() - 1
+        } else {
+            coalesced.groups[representative]
+        };
+        coalesced.groups[i] = group;
+        coalesced.multiplicities[group] += 1;
+    }
+    coalesced
+}
+
+/// 座標を辞書順に比べる。 NaN は他のどの値よりも大きいものとする。
+fn cmp_coordinates<F: Float, const N: usize>(lhs: &[F; N], rhs: &[F; N]) -> Ordering {
+    lhs.iter()
+        .zip(rhs)
+        .map(|(l, r)| match l.partial_cmp(r) {
+            Some(ordering) => ordering,
+            None => l.is_nan().cmp(&r.is_nan()),
+        })
+        .find(|&ordering| ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
@@ -0,0 +1,79 @@
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+
+/// KdTree::construct() や dbscan() など、入力を検証する関数が返すエラー。
+/// 検証を省く場合は KdTree::construct_unchecked() などの `_unchecked` の付いた関数を用いる。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// 座標に NaN や無限大を含む要素があった。 indices にはそれらの位置が昇順で入る。
+    NonFiniteInput { indices: Vec<usize> },
+
+    /// 探索の基準となる要素の座標に NaN や無限大が含まれていた。
+    NonFiniteQuery,
+
+    /// epsilon や探索半径が NaN など、自身と比較できない値だった。格子の大きさであれば正でなかった。
+    InvalidRadius,
+
+    /// 位置 index の要素の座標の数が expected と異なり found だった。
+    /// expected は指定された次元数か、次元数を指定しない場合は先頭の要素の座標の数になる。
+    DimensionMismatch {
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    /// DbscanLabel::to_code() の整数として解釈できない値 code だった。
+    InvalidLabelCode { code: i64 },
+
+    /// 向きを持たないゼロベクトルを CosinePoint にしようとした。
+    ZeroVector,
+
+    /// DbscanCheckpoint が入力の要素数と一致しないか、内部の状態が矛盾していた。
+    InvalidCheckpoint,
+}
+
+/// 以前の名前。 Error に統合された。
+#[deprecated(note = "use Error instead")]
+pub type DbscanError = Error;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NonFiniteInput { indices } => {
+                write!(f, "non-finite coordinates in items at indices ")?;
+                for (i, index) in indices.iter().take(NON_FINITE_DISPLAY_LIMIT).enumerate() {
+                    write!(f, "{}{index}", if i == 0 { "" } else { ", " })?;
+                }
+                if indices.len() > NON_FINITE_DISPLAY_LIMIT {
+                    write!(f, ", ... ({} in total)", indices.len())?;
+                }
+                Ok(())
+            }
+            Error::NonFiniteQuery => write!(f, "query has non-finite coordinates"),
+            Error::InvalidRadius => write!(f, "radius must be comparable"),
+            Error::DimensionMismatch { index, expected, found } => {
+                write!(
+                    f,
+                    "expected {expected} coordinates in item at index {index}, found {found}"
+                )
+            }
+            Error::InvalidLabelCode { code } => write!(f, "{code} is not a valid label code"),
+            Error::ZeroVector => write!(f, "zero vector has no direction"),
+            Error::InvalidCheckpoint => write!(f, "checkpoint does not match the items"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// Error::NonFiniteInput の表示に含める位置の数の上限。
+const NON_FINITE_DISPLAY_LIMIT: usize = 10;
+
+/// radius が自身と比較できる (NaN でない) かを調べる。
+pub(crate) fn check_radius<D: PartialOrd>(radius: &D) -> Result<(), Error> {
+    if radius.partial_cmp(radius).is_some() {
+        Ok(())
+    } else {
+        Err(Error::InvalidRadius)
+    }
+}
@@ -0,0 +1,126 @@
+use alloc::vec::Vec;
+
+use num_traits::Float;
+
+use crate::{
+    dbscan::{expand_clusters, DbscanResult, UNMONITORED},
+    error::{check_radius, Error},
+    index::SpatialIndex,
+    kdtree::{validate_items, KdTree, KdTreeItem},
+    lof::{lof_with_nearest, LofResult},
+    metric::{ItemMetric, Metric},
+};
+
+/// 構築済みの KdTree を保持し、同じ要素に対する DBSCAN や近傍の計算に使い回すためのハンドル。
+/// 要素はすべて KdTree に移され、各メソッドの結果は fit() に渡された時点での位置で要素を参照する。
+pub struct FittedIndex<T, M = ItemMetric> {
+    kdtree: KdTree<T, M>,
+}
+
+impl<T: KdTreeItem> FittedIndex<T> {
+    /// items から KdTree を構築する。座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+    pub fn fit(items: impl Into<Vec<T>>) -> Result<FittedIndex<T>, Error> {
+        let items = items.into();
+        validate_items(&items)?;
+        Ok(FittedIndex::fit_with_metric(items, ItemMetric))
+    }
+}
+
+impl<T: KdTreeItem, M: Metric<T>> FittedIndex<T, M> {
+    /// 距離の計算に metric を用いる KdTree を構築する。入力は検証しない。
+    pub fn fit_with_metric(items: impl Into<Vec<T>>, metric: M) -> FittedIndex<T, M> {
+        FittedIndex {
+            kdtree: KdTree::construct_with_metric(items, metric),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.kdtree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kdtree.is_empty()
+    }
+
+    /// 保持している KdTree を返す。
+    pub fn kdtree(&self) -> &KdTree<T, M> {
+        &self.kdtree
+    }
+
+    /// 保持している要素を dbscan() と同様にクラスタリングする。
+    /// epsilon が NaN であれば Error::InvalidRadius を返す。
+    pub fn dbscan(&self, epsilon: M::Measurement, min_items: usize) -> Result<DbscanResult, Error> {
+        check_radius(&epsilon)?;
+        let items = self.items();
+        let (labels, cores) = expand_clusters(
+            items.len(),
+            |i, found| self.kdtree.find_range_into(items[i], &epsilon, found),
+            |i, _| self.kdtree.count_within_limited(items[i], &epsilon, min_items) >= min_items,
+            None,
+        )
+        .expect(UNMONITORED);
+        Ok(DbscanResult::from_labels_and_cores(labels, &cores))
+    }
+
+    /// 各要素について、近い順に k 個の要素 (自身を含む) の位置と距離を返す。
+    pub fn knn(&self, k: usize) -> Vec<Vec<(usize, M::Measurement)>> {
+        self.items()
+            .into_iter()
+            .map(|item| self.kdtree.find_nearest_n_indices(item, k))
+            .collect()
+    }
+
+    /// 各要素から k 番目に近い要素 (自身を含む) までの距離を、要素の順に返す。
+    /// 要素数が k に満たなければ最も遠い要素までの距離になる。 k は 1 以上でなければならない。
+    pub fn kdist(&self, k: usize) -> Vec<M::Measurement> {
+        assert!(k > 0, "k must be positive");
+        self.knn(k)
+            .into_iter()
+            .map(|neighbors| neighbors.last().expect("item itself must be found").1.clone())
+            .collect()
+    }
+
+    /// 保持している要素の Local Outlier Factor を local_outlier_factor() と同様に求める。
+    pub fn lof(&self, k: usize) -> LofResult<M::Measurement>
+    where
+        M::Measurement: Float,
+    {
+        let items = self.items();
+        lof_with_nearest(items.len(), k, |i, count| {
+            self.kdtree.find_nearest_n_indices(items[i], count)
+        })
+    }
+
+    /// 要素への参照を fit() に渡された時点での順に並べる。
+    fn items(&self) -> Vec<&T> {
+        let mut items = Vec::with_capacity(self.kdtree.len());
+        items.resize_with(self.kdtree.len(), || None);
+        for (index, item) in self.kdtree.iter_with_indices() {
+            items[index] = Some(item);
+        }
+        items
+            .into_iter()
+            .map(|item| item.expect("all items must be present"))
+            .collect()
+    }
+}
+
+impl<T: KdTreeItem, M: Metric<T>> SpatialIndex<T> for FittedIndex<T, M> {
+    type Measurement = M::Measurement;
+
+    fn range(&self, query: &T, radius: &Self::Measurement) -> Vec<usize> {
+        self.kdtree.find_range_n_indices(query, radius)
+    }
+
+    fn range_into(&self, query: &T, radius: &Self::Measurement, found: &mut Vec<usize>) {
+        self.kdtree.find_range_into(query, radius, found)
+    }
+
+    fn count_within_limited(&self, query: &T, radius: &Self::Measurement, limit: usize) -> usize {
+        self.kdtree.count_within_limited(query, radius, limit)
+    }
+
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, Self::Measurement)> {
+        self.kdtree.find_nearest_n_indices(query, k)
+    }
+}
@@ -0,0 +1,70 @@
+use core::cmp::Ordering;
+
+use crate::{
+    dbscan::{dbscan, dbscan_unchecked, DbscanResult},
+    error::Error,
+    kdtree::KdTreeItem,
+    metric::{Haversine, Metric},
+};
+
+/// 地球の平均半径 (メートル) 。
+pub const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// 緯度・経度 (度) で表された地球上の点。距離は大圏距離 (メートル) で計算される。
+/// 経度は -180 以上 180 以下でなければならない。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl GeoPoint {
+    pub fn new(lat: f64, lon: f64) -> GeoPoint {
+        GeoPoint { lat, lon }
+    }
+
+    #[inline]
+    fn to_radians(self) -> [f64; 2] {
+        [self.lat.to_radians(), self.lon.to_radians()]
+    }
+}
+
+impl KdTreeItem for GeoPoint {
+    type Measurement = f64;
+
+    fn cmp_in_depth(&self, rhs: &Self, depth: usize) -> Ordering {
+        let (lhs, rhs) = if depth.is_multiple_of(2) {
+            (self.lat, rhs.lat)
+        } else {
+            (self.lon, rhs.lon)
+        };
+        lhs.partial_cmp(&rhs).expect("not total order")
+    }
+
+    fn distance(&self, other: &Self) -> f64 {
+        Haversine.distance(&self.to_radians(), &other.to_radians()) * EARTH_RADIUS_METERS
+    }
+
+    fn distance_to_axis(&self, other: &Self, depth: usize) -> f64 {
+        Haversine.distance_to_axis(&self.to_radians(), &other.to_radians(), depth) * EARTH_RADIUS_METERS
+    }
+
+    fn is_finite(&self) -> bool {
+        self.lat.is_finite() && self.lon.is_finite()
+    }
+}
+
+/// GeoPoint の列を DBSCAN でクラスタリングする。 epsilon_meters は大圏距離 (メートル) で指定する。
+/// 緯度や経度に NaN や無限大を含む点があれば Error::NonFiniteInput を返す。
+pub fn dbscan_geo(
+    points: impl AsRef<[GeoPoint]>,
+    epsilon_meters: f64,
+    min_items: usize,
+) -> Result<DbscanResult, Error> {
+    dbscan(points, epsilon_meters, min_items)
+}
+
+/// dbscan_geo() と同様だが、入力を検証しない。 NaN を含む点があると panic する。
+pub fn dbscan_geo_unchecked(points: impl AsRef<[GeoPoint]>, epsilon_meters: f64, min_items: usize) -> DbscanResult {
+    dbscan_unchecked(points, epsilon_meters, min_items)
+}
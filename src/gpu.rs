@@ -0,0 +1,423 @@
+//! wgpu の計算シェーダーで総当たりの範囲探索を行うインデックス。 gpu feature で有効になる。
+//!
+//! 1 回の探索ごとに GPU へ処理を投げると往復の待ち時間が支配的になるため、多数の要素を探索する場合は
+//! GpuIndex::range_batch() や GpuIndex::dbscan() でまとめて探索する。
+
+use std::{
+    fmt::{self, Display},
+    sync::mpsc,
+};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    dbscan::{expand_clusters, DbscanResult, UNMONITORED},
+    index::SpatialIndex,
+};
+
+/// 1 回の dispatch で探索する要素の数の上限。 1 回の処理が長引いて GPU のウォッチドッグに打ち切られるのを防ぐ。
+const QUERIES_PER_DISPATCH: usize = 16384;
+
+/// シェーダーのワークグループの大きさ。 gpu.wgsl の @workgroup_size と一致させる。
+const WORKGROUP_SIZE: u32 = 64;
+
+/// GpuIndex の構築に失敗したことを表すエラー。
+#[derive(Debug)]
+pub enum GpuError {
+    /// 計算シェーダーを使えるアダプターが見つからなかった。
+    NoAdapter(wgpu::RequestAdapterError),
+
+    /// アダプターからデバイスを得られなかった。
+    RequestDevice(wgpu::RequestDeviceError),
+
+    /// 要素の座標が bytes バイトあり、 1 つのストレージバッファーの上限 limit バイトに収まらない。
+    TooManyItems { bytes: u64, limit: u64 },
+}
+
+impl Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuError::NoAdapter(e) => write!(f, "no GPU adapter is available: {e}"),
+            GpuError::RequestDevice(e) => write!(f, "failed to request a GPU device: {e}"),
+            GpuError::TooManyItems { bytes, limit } => {
+                write!(
+                    f,
+                    "items take {bytes} bytes, exceeding the storage buffer limit of {limit} bytes"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+/// 座標を GPU のメモリに置き、ユークリッド距離での範囲探索を計算シェーダーの総当たりで行うインデックス。
+/// 距離は f32 で計算するため、探索半径の境界ちょうどにある要素の判定は CPU での計算と異なる場合がある。
+/// 座標に NaN や無限大を含んではならない。
+pub struct GpuIndex<const N: usize> {
+    items: Vec<[f32; N]>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    layout: wgpu::BindGroupLayout,
+    count: wgpu::ComputePipeline,
+    fill: wgpu::ComputePipeline,
+    distances: wgpu::ComputePipeline,
+    items_buffer: wgpu::Buffer,
+}
+
+/// gpu.wgsl のエントリーポイント。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kernel {
+    /// 各探索の基準から半径以内にある要素を数える。
+    Count,
+
+    /// 各探索の基準から半径以内にある要素の位置を、 Count で数えた数から求めた位置に書き出す。
+    Fill,
+
+    /// 1 つの基準から全要素への 2 乗の距離を書き出す。
+    Distances,
+}
+
+/// シェーダーに渡す探索の設定。 gpu.wgsl の Params と同じ並びで、 uniform の境界に合わせて 32 バイトにする。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    item_count: u32,
+    query_count: u32,
+    dimensions: u32,
+    reduced_radius: f32,
+    row_stride: u32,
+    padding: [u32; 3],
+}
+
+impl<const N: usize> GpuIndex<N> {
+    /// 既定のアダプターを選び、 items を GPU のメモリに転送する。
+    pub fn new(items: impl Into<Vec<[f32; N]>>) -> Result<GpuIndex<N>, GpuError> {
+        let items = items.into();
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .map_err(GpuError::NoAdapter)?;
+        let limits = adapter.limits();
+        let bytes = (items.len() * N * size_of::<f32>()) as u64;
+        let limit = storage_limit(&limits);
+        if bytes > limit {
+            return Err(GpuError::TooManyItems { bytes, limit });
+        }
+
+        let descriptor = wgpu::DeviceDescriptor {
+            label: Some("dbscan-rust-test"),
+            required_limits: limits,
+            ..Default::default()
+        };
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&descriptor)).map_err(GpuError::RequestDevice)?;
+        let module = device.create_shader_module(wgpu::include_wgsl!("gpu.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(3, true),
+                storage_entry(4, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let (count, fill, distances) = (pipeline("count"), pipeline("fill"), pipeline("distances"));
+        let items_buffer = storage_buffer(&device, bytemuck::cast_slice(items.as_flattened()));
+
+        Ok(GpuIndex {
+            items,
+            device,
+            queue,
+            layout,
+            count,
+            fill,
+            distances,
+            items_buffer,
+        })
+    }
+
+    /// 要素の数を返す。
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// 要素を持たなければ true を返す。
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 構築に用いた要素。
+    pub fn items(&self) -> &[[f32; N]] {
+        &self.items
+    }
+
+    /// queries のそれぞれから radius 以内 (境界を含む) にある要素の数を返す。
+    pub fn count_batch(&self, queries: &[[f32; N]], radius: f32) -> Vec<usize> {
+        let mut counts = Vec::with_capacity(queries.len());
+        for chunk in queries.chunks(QUERIES_PER_DISPATCH) {
+            let chunk_buffer = storage_buffer(&self.device, bytemuck::cast_slice(chunk.as_flattened()));
+            let output = self.dispatch(Kernel::Count, &chunk_buffer, chunk.len(), radius, None, chunk.len());
+            counts.extend(output.into_iter().map(|c| c as usize));
+        }
+        counts
+    }
+
+    /// queries のそれぞれから radius 以内 (境界を含む) にある要素の位置を昇順に返す。
+    pub fn range_batch(&self, queries: &[[f32; N]], radius: f32) -> Vec<Vec<usize>> {
+        let (offsets, neighbors) = self.range_csr(queries, radius);
+        offsets
+            .windows(2)
+            .map(|w| neighbors[w[0]..w[1]].iter().map(|&n| n as usize).collect())
+            .collect()
+    }
+
+    /// 全要素の近傍を range_batch() でまとめて求めてから DBSCAN を行う。
+    /// epsilon 以内に自身を含めて min_points 個以上の要素があるものをコア点とする。近傍の総数に比例するメモリを使う。
+    pub fn dbscan(&self, epsilon: f32, min_points: usize) -> DbscanResult {
+        let (offsets, neighbors) = self.range_csr(&self.items, epsilon);
+        let (labels, cores) = expand_clusters(
+            self.items.len(),
+            |i, found| {
+                found.clear();
+                found.extend(neighbors[offsets[i]..offsets[i + 1]].iter().map(|&n| n as usize));
+            },
+            |i, _| offsets[i + 1] - offsets[i] >= min_points,
+            None,
+        )
+        .expect(UNMONITORED);
+        DbscanResult::from_labels_and_cores(labels, &cores)
+    }
+
+    /// range_batch() の結果を CSR 形式で返す。 queries[q] の近傍は neighbors[offsets[q]..offsets[q + 1]] に入る。
+    fn range_csr(&self, queries: &[[f32; N]], radius: f32) -> (Vec<usize>, Vec<u32>) {
+        // シェーダーは書き出す位置を u32 で数えるため、その範囲にも収める
+        let max_output = (storage_limit(&self.device.limits()) / 4).min(u32::MAX.into()) as usize;
+
+        let mut offsets = vec![0];
+        let mut neighbors = Vec::new();
+        for chunk in queries.chunks(QUERIES_PER_DISPATCH) {
+            let chunk_buffer = storage_buffer(&self.device, bytemuck::cast_slice(chunk.as_flattened()));
+            let counts = self.dispatch(Kernel::Count, &chunk_buffer, chunk.len(), radius, None, chunk.len());
+
+            // 出力がストレージバッファーに収まるよう、近傍の数の合計が max_output を超えない範囲ずつ書き出す
+            let mut start = 0;
+            while start < chunk.len() {
+                let mut end = start;
+                let mut total = 0;
+                while end < chunk.len() && (end == start || total + counts[end] as usize <= max_output) {
+                    total += counts[end] as usize;
+                    end += 1;
+                }
+                let mut local_offsets = Vec::with_capacity(end - start);
+                let mut offset = 0u32;
+                for &count in &counts[start..end] {
+                    local_offsets.push(offset);
+                    offset += count;
+                    offsets.push(neighbors.len() + offset as usize);
+                }
+                let sub_chunk = &chunk[start..end];
+                let sub_buffer = storage_buffer(&self.device, bytemuck::cast_slice(sub_chunk.as_flattened()));
+                let offsets_buffer = storage_buffer(&self.device, bytemuck::cast_slice(&local_offsets));
+                let filled = self.dispatch(
+                    Kernel::Fill,
+                    &sub_buffer,
+                    sub_chunk.len(),
+                    radius,
+                    Some(&offsets_buffer),
+                    total,
+                );
+                neighbors.extend_from_slice(&filled);
+                start = end;
+            }
+        }
+        (offsets, neighbors)
+    }
+
+    /// kernel を実行し、 output_len 個の u32 の出力を読み戻す。
+    /// Kernel::Fill では offsets に各探索の結果を書き出し始める位置を渡す。
+    fn dispatch(
+        &self,
+        kernel: Kernel,
+        queries: &wgpu::Buffer,
+        query_count: usize,
+        radius: f32,
+        offsets: Option<&wgpu::Buffer>,
+        output_len: usize,
+    ) -> Vec<u32> {
+        let (pipeline, invocations) = match kernel {
+            Kernel::Count => (&self.count, query_count),
+            Kernel::Fill => (&self.fill, query_count),
+            Kernel::Distances => (&self.distances, self.items.len()),
+        };
+        if invocations == 0 || output_len == 0 {
+            return vec![0; output_len];
+        }
+
+        // 1 次元あたりのワークグループ数の上限を超える場合は 2 次元に並べる
+        let max_groups = self.device.limits().max_compute_workgroups_per_dimension;
+        let groups = (invocations as u32).div_ceil(WORKGROUP_SIZE);
+        let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+        let params = Params {
+            item_count: self.items.len() as u32,
+            query_count: query_count as u32,
+            dimensions: N as u32,
+            reduced_radius: radius * radius,
+            row_stride: x * WORKGROUP_SIZE,
+            padding: [0; 3],
+        };
+        let params_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let size = (output_len * size_of::<u32>()) as u64;
+        let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // 使わない offsets にも何かを結び付けなければならない
+        let unused_offsets = offsets.is_none().then(|| storage_buffer(&self.device, &[0; 4]));
+        let offsets = offsets.or(unused_offsets.as_ref()).expect("must have offsets");
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                bind_entry(0, &self.items_buffer),
+                bind_entry(1, queries),
+                bind_entry(2, &params_buffer),
+                bind_entry(3, offsets),
+                bind_entry(4, &output),
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(x, y, 1);
+        }
+        encoder.copy_buffer_to_buffer(&output, 0, &staging, 0, size);
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).expect("receiver must be alive");
+        });
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .expect("GPU must finish the dispatch");
+        receiver
+            .recv()
+            .expect("map callback must be called")
+            .expect("output must be mappable");
+        let view = slice.get_mapped_range().expect("output must be mapped");
+        let output = bytemuck::pod_collect_to_vec(&view);
+        drop(view);
+        staging.unmap();
+        output
+    }
+}
+
+/// GPU で計算するのは範囲探索と、最近傍探索のための全要素との距離だけで、近い順の選択は CPU で行う。
+/// 1 回の探索ごとに GPU との往復が生じるため、多数の要素を探索する場合は range_batch() を用いる。
+impl<const N: usize> SpatialIndex<[f32; N]> for GpuIndex<N> {
+    type Measurement = f32;
+
+    fn range(&self, query: &[f32; N], radius: &f32) -> Vec<usize> {
+        self.range_batch(std::slice::from_ref(query), *radius)
+            .pop()
+            .expect("must have one result")
+    }
+
+    fn nearest_n(&self, query: &[f32; N], k: usize) -> Vec<(usize, f32)> {
+        if k == 0 || self.items.is_empty() {
+            return vec![];
+        }
+        let query_buffer = storage_buffer(&self.device, bytemuck::cast_slice(query));
+        let distances = self.dispatch(Kernel::Distances, &query_buffer, 1, 0.0, None, self.items.len());
+        let mut found: Vec<(usize, f32)> = distances
+            .into_iter()
+            .enumerate()
+            .map(|(i, bits)| (i, f32::from_bits(bits).sqrt()))
+            .collect();
+        let k = k.min(found.len());
+        let by_distance = |a: &(usize, f32), b: &(usize, f32)| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0));
+        if k < found.len() {
+            found.select_nth_unstable_by(k - 1, by_distance);
+            found.truncate(k);
+        }
+        found.sort_unstable_by(by_distance);
+        found
+    }
+}
+
+/// 1 つのストレージバッファーとして結び付けられるバイト数の上限。
+fn storage_limit(limits: &wgpu::Limits) -> u64 {
+    limits.max_storage_buffer_binding_size.min(limits.max_buffer_size)
+}
+
+/// contents を初期値に持つストレージバッファーを作る。空のバッファーは結び付けられないため、最低でも 4 バイトにする。
+fn storage_buffer(device: &wgpu::Device, contents: &[u8]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: if contents.is_empty() { &[0; 4] } else { contents },
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn bind_entry(binding: u32, buffer: &wgpu::Buffer) -> wgpu::BindGroupEntry<'_> {
+    wgpu::BindGroupEntry {
+        binding,
+        resource: buffer.as_entire_binding(),
+    }
+}
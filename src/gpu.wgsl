@@ -0,0 +1,68 @@
+// GpuIndex の計算シェーダー。座標は要素ごとに dimensions 個の f32 を並べた配列で受け取る。
+
+struct Params {
+    item_count: u32,
+    query_count: u32,
+    dimensions: u32,
+    reduced_radius: f32,
+    // 2 次元に並べたワークグループの 1 行あたりの呼び出しの数
+    row_stride: u32,
+}
+
+@group(0) @binding(0) var<storage, read> items: array<f32>;
+@group(0) @binding(1) var<storage, read> queries: array<f32>;
+@group(0) @binding(2) var<uniform> params: Params;
+@group(0) @binding(3) var<storage, read> offsets: array<u32>;
+@group(0) @binding(4) var<storage, read_write> output: array<u32>;
+
+fn invocation_index(id: vec3<u32>) -> u32 {
+    return id.x + id.y * params.row_stride;
+}
+
+fn reduced_distance(query: u32, item: u32) -> f32 {
+    var sum = 0.0;
+    for (var d = 0u; d < params.dimensions; d++) {
+        let difference = queries[query * params.dimensions + d] - items[item * params.dimensions + d];
+        sum += difference * difference;
+    }
+    return sum;
+}
+
+@compute @workgroup_size(64)
+fn count(@builtin(global_invocation_id) id: vec3<u32>) {
+    let query = invocation_index(id);
+    if query >= params.query_count {
+        return;
+    }
+    var found = 0u;
+    for (var item = 0u; item < params.item_count; item++) {
+        if reduced_distance(query, item) <= params.reduced_radius {
+            found++;
+        }
+    }
+    output[query] = found;
+}
+
+@compute @workgroup_size(64)
+fn fill(@builtin(global_invocation_id) id: vec3<u32>) {
+    let query = invocation_index(id);
+    if query >= params.query_count {
+        return;
+    }
+    var position = offsets[query];
+    for (var item = 0u; item < params.item_count; item++) {
+        if reduced_distance(query, item) <= params.reduced_radius {
+            output[position] = item;
+            position++;
+        }
+    }
+}
+
+@compute @workgroup_size(64)
+fn distances(@builtin(global_invocation_id) id: vec3<u32>) {
+    let item = invocation_index(id);
+    if item >= params.item_count {
+        return;
+    }
+    output[item] = bitcast<u32>(reduced_distance(0u, item));
+}
@@ -0,0 +1,69 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    error::{check_radius, Error},
+    index::SpatialIndex,
+    kdtree::{validate_items, KdTreeItem},
+    metric::ItemMetric,
+    slice_kdtree::SliceKdTree,
+};
+
+/// 距離が一定以下の要素どうしを結んだ無向グラフの隣接関係を CSR 形式で持つ。
+/// 要素 i の隣接要素は neighbors[offsets[i]..offsets[i + 1]] に位置の昇順で入る。自分自身は含まない。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NeighborGraph {
+    /// 各要素の隣接要素の neighbors 上での開始位置。要素数 + 1 個あり、末尾は neighbors の長さになる。
+    pub offsets: Vec<usize>,
+
+    /// 隣接要素の位置を要素の順につなげたもの。各辺は両端の要素から 1 回ずつ、計 2 回現れる。
+    pub neighbors: Vec<usize>,
+}
+
+impl NeighborGraph {
+    /// 要素の数を返す。
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 要素 i の隣接要素の位置を返す。
+    pub fn neighbors(&self, i: usize) -> &[usize] {
+        &self.neighbors[self.offsets[i]..self.offsets[i + 1]]
+    }
+
+    /// 要素 i の隣接要素の数を返す。
+    pub fn degree(&self, i: usize) -> usize {
+        self.offsets[i + 1] - self.offsets[i]
+    }
+
+    /// 辺の数を返す。
+    pub fn edge_count(&self) -> usize {
+        self.neighbors.len() / 2
+    }
+}
+
+/// points のうち距離が radius 以下 (境界を含む) の要素どうしを結んだグラフを作る。
+/// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を、 radius が NaN であれば Error::InvalidRadius を返す。
+pub fn neighbor_graph<T: KdTreeItem>(points: impl AsRef<[T]>, radius: T::Measurement) -> Result<NeighborGraph, Error> {
+    let points = points.as_ref();
+    check_radius(&radius)?;
+    validate_items(points)?;
+
+    let tree = SliceKdTree::construct_with_metric(points, ItemMetric);
+    let mut graph = NeighborGraph {
+        offsets: vec![0],
+        neighbors: Vec::new(),
+    };
+    let mut found = Vec::new();
+    for (i, point) in points.iter().enumerate() {
+        tree.range_into(point, &radius, &mut found);
+        found.retain(|&j| j != i);
+        found.sort_unstable();
+        graph.neighbors.extend_from_slice(&found);
+        graph.offsets.push(graph.neighbors.len());
+    }
+    Ok(graph)
+}
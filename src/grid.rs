@@ -0,0 +1,288 @@
+use alloc::{vec, vec::Vec};
+use core::{fmt::Debug, ops::Range};
+
+use num_traits::Float;
+
+use crate::{
+    error::Error,
+    index::SpatialIndex,
+    kdtree::{validate_items, KdTreeItem},
+};
+
+/// 要素を一辺 cell_size の格子に振り分けたインデックス。距離はユークリッド距離で計算される。
+/// 密度が一様な要素に対して cell_size を探索半径程度にすると KdTree より速いが、
+/// 範囲探索で調べるセルの数は次元数について指数的に増えるため低次元向けである。
+#[derive(Debug, Clone)]
+pub struct GridIndex<T, const N: usize> {
+    /// セルの順に並べ替えた要素。
+    items: Vec<[T; N]>,
+
+    /// items のそれぞれが構築時に渡された位置。
+    indices: Vec<usize>,
+
+    cell_size: T,
+    cells: Cells<N>,
+
+    /// 要素が存在するセルの座標の最小値と最大値。
+    bounds: Option<([i64; N], [i64; N])>,
+}
+
+/// 各セルに属する要素の items 上の範囲。
+#[derive(Debug, Clone)]
+enum Cells<const N: usize> {
+    /// 外接直方体のすべてのセルについて、範囲の始点を軸 0 が最も速く変わる順に並べたもの。末尾に番兵を持つ。
+    Dense(Vec<usize>),
+
+    /// 要素の存在するセルだけを持つ。外接直方体が要素数に比べて大きすぎる場合に用いる。
+    Sparse(CellMap<[i64; N], Range<usize>>),
+}
+
+/// Cells::Sparse のセルの表。 std が無ければ HashMap の代わりに BTreeMap を用いる。
+#[cfg(feature = "std")]
+type CellMap<K, V> = std::collections::HashMap<K, V>;
+#[cfg(not(feature = "std"))]
+type CellMap<K, V> = alloc::collections::BTreeMap<K, V>;
+
+impl<T: Debug + Float, const N: usize> GridIndex<T, N> {
+    /// items を一辺 cell_size の格子に振り分ける。
+    /// cell_size が正でなければ Error::InvalidRadius を、座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+    pub fn new(items: impl Into<Vec<[T; N]>>, cell_size: T) -> Result<GridIndex<T, N>, Error> {
+        if cell_size.is_nan() || cell_size <= T::zero() {
+            return Err(Error::InvalidRadius);
+        }
+        let items = items.into();
+        validate_items(&items)?;
+        Ok(GridIndex::new_unchecked(items, cell_size))
+    }
+
+    /// new() と同様だが、入力を検証しない。 cell_size が正でないか、 NaN を含む要素があると panic する。
+    pub fn new_unchecked(items: impl Into<Vec<[T; N]>>, cell_size: T) -> GridIndex<T, N> {
+        assert!(cell_size > T::zero(), "cell_size must be positive");
+
+        let items = items.into();
+        let item_cells: Vec<_> = items.iter().map(|item| cell_of(item, cell_size)).collect();
+        let bounds = item_cells.iter().fold(None::<([i64; N], [i64; N])>, |bounds, cell| {
+            Some(match bounds {
+                Some((min, max)) => (
+                    core::array::from_fn(|a| cell[a].min(min[a])),
+                    core::array::from_fn(|a| cell[a].max(max[a])),
+                ),
+                None => (*cell, *cell),
+            })
+        });
+
+        let (indices, cells) = match bounds {
+            Some((lower, upper)) if box_cell_count(lower, upper) <= (DENSE_CELLS_PER_ITEM * items.len()) as f64 => {
+                // 外接直方体のセルごとに数えて並べる
+                let linear: Vec<_> = item_cells.iter().map(|cell| linear_index(cell, lower, upper)).collect();
+                let mut starts = vec![0; box_cell_count(lower, upper) as usize + 1];
+                for &l in &linear {
+                    starts[l + 1] += 1;
+                }
+                for l in 1..starts.len() {
+                    starts[l] += starts[l - 1];
+                }
+                let mut next = starts.clone();
+                let mut indices = vec![0; items.len()];
+                for (i, &l) in linear.iter().enumerate() {
+                    indices[next[l]] = i;
+                    next[l] += 1;
+                }
+                (indices, Cells::Dense(starts))
+            }
+            _ => {
+                let mut indices: Vec<_> = (0..items.len()).collect();
+                indices.sort_by_key(|&i| item_cells[i]);
+                let mut cells = CellMap::new();
+                let mut start = 0;
+                for end in 1..=indices.len() {
+                    if end == indices.len() || item_cells[indices[end]] != item_cells[indices[start]] {
+                        cells.insert(item_cells[indices[start]], start..end);
+                        start = end;
+                    }
+                }
+                (indices, Cells::Sparse(cells))
+            }
+        };
+
+        GridIndex {
+            items: indices.iter().map(|&i| items[i]).collect(),
+            indices,
+            cell_size,
+            cells,
+            bounds,
+        }
+    }
+
+    pub fn cell_size(&self) -> T {
+        self.cell_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// min から max まで (両端を含む) の直方体に含まれる、要素が存在するセルについて f を呼ぶ。
+    fn for_each_cell(&self, min: [i64; N], max: [i64; N], mut f: impl FnMut(&[i64; N], Range<usize>)) {
+        let Some((lower, upper)) = self.bounds else {
+            return;
+        };
+        let min: [i64; N] = core::array::from_fn(|a| min[a].max(lower[a]));
+        let max: [i64; N] = core::array::from_fn(|a| max[a].min(upper[a]));
+        if (0..N).any(|a| min[a] > max[a]) {
+            return;
+        }
+
+        if let Cells::Sparse(cells) = &self.cells {
+            // 直方体のセル数が要素の存在するセル数より多ければ、存在するセルを直接走査する
+            if box_cell_count(min, max) > cells.len() as f64 {
+                for (cell, range) in cells {
+                    if (0..N).all(|a| min[a] <= cell[a] && cell[a] <= max[a]) {
+                        f(cell, range.clone());
+                    }
+                }
+                return;
+            }
+        }
+
+        let mut cell = min;
+        loop {
+            match &self.cells {
+                Cells::Dense(starts) => {
+                    let l = linear_index(&cell, lower, upper);
+                    if starts[l] < starts[l + 1] {
+                        f(&cell, starts[l]..starts[l + 1]);
+                    }
+                }
+                Cells::Sparse(cells) => {
+                    if let Some(range) = cells.get(&cell) {
+                        f(&cell, range.clone());
+                    }
+                }
+            }
+
+            // 次のセルに進める
+            let mut axis = 0;
+            loop {
+                if axis == N {
+                    return;
+                }
+                if cell[axis] < max[axis] {
+                    cell[axis] += 1;
+                    break;
+                }
+                cell[axis] = min[axis];
+                axis += 1;
+            }
+        }
+    }
+}
+
+impl<T: Debug + Float, const N: usize> SpatialIndex<[T; N]> for GridIndex<T, N> {
+    type Measurement = T;
+
+    fn range(&self, query: &[T; N], radius: &T) -> Vec<usize> {
+        let min = cell_of(&query.map(|x| x - *radius), self.cell_size);
+        let max = cell_of(&query.map(|x| x + *radius), self.cell_size);
+
+        // 比較は平方根をとらない 2 乗の距離で行う
+        let reduced_radius = <[T; N]>::distance_to_reduced(radius);
+        let mut found = Vec::new();
+        self.for_each_cell(min, max, |_, range| {
+            for i in range {
+                if self.items[i].reduced_distance(query) <= reduced_radius {
+                    found.push(self.indices[i]);
+                }
+            }
+        });
+        found
+    }
+
+    fn nearest_n(&self, query: &[T; N], k: usize) -> Vec<(usize, T)> {
+        let Some((lower, upper)) = self.bounds else {
+            return vec![];
+        };
+        if k == 0 {
+            return vec![];
+        }
+
+        // query のセルを中心に、チェビシェフ距離が ring のセルを順に調べる
+        let center = cell_of(query, self.cell_size);
+        let max_ring = (0..N)
+            .map(|a| (center[a] - lower[a]).max(upper[a] - center[a]))
+            .max()
+            .unwrap_or(0);
+        let mut candidates = Vec::new();
+        for ring in 0..=max_ring {
+            let min = center.map(|c| c - ring);
+            let max = center.map(|c| c + ring);
+            self.for_each_cell(min, max, |cell, range| {
+                if ring == 0 || (0..N).any(|a| (cell[a] - center[a]).abs() == ring) {
+                    candidates.extend(range.map(|i| (self.indices[i], self.items[i].distance(query))));
+                }
+            });
+
+            candidates.sort_by(|lhs: &(usize, T), rhs| lhs.1.partial_cmp(&rhs.1).expect("not total order"));
+            candidates.truncate(k);
+
+            // 未探索のセルにある要素は query から ring * cell_size 以上離れている
+            let explored = T::from(ring).expect("ring must be representable") * self.cell_size;
+            if candidates.len() == k && candidates[k - 1].1 <= explored {
+                break;
+            }
+        }
+
+        candidates
+    }
+}
+
+/// 外接直方体のセル数が要素数のこの倍数以下であれば、すべてのセルを配列で持つ。
+const DENSE_CELLS_PER_ITEM: usize = 8;
+
+/// 格子あたりの要素数がこれを下回るほど疎であれば is_grid_suitable() は false を返す。
+const MIN_ITEMS_PER_CELL: f64 = 1.0 / 8.0;
+
+fn cell_of<T: Float, const N: usize>(point: &[T; N], cell_size: T) -> [i64; N] {
+    point.map(|x| {
+        (x / cell_size)
+            .floor()
+            .to_i64()
+            .expect("cell coordinate must fit in i64")
+    })
+}
+
+/// min から max まで (両端を含む) の直方体に含まれるセルの数。
+fn box_cell_count<const N: usize>(min: [i64; N], max: [i64; N]) -> f64 {
+    (0..N).map(|a| (max[a] - min[a]) as f64 + 1.0).product()
+}
+
+/// lower から upper までの直方体における cell の通し番号。軸 0 が最も速く変わる。
+fn linear_index<const N: usize>(cell: &[i64; N], lower: [i64; N], upper: [i64; N]) -> usize {
+    (0..N).rev().fold(0, |l, a| {
+        l * (upper[a] - lower[a] + 1) as usize + (cell[a] - lower[a]) as usize
+    })
+}
+
+/// items の外接直方体を一辺 cell_size の格子に分けたとき、低次元で十分に密であれば true を返す。
+pub(crate) fn is_grid_suitable<T: Float, const N: usize>(items: &[[T; N]], cell_size: T) -> bool {
+    if N > 3 || items.is_empty() || cell_size <= T::zero() {
+        return false;
+    }
+
+    let mut box_cells = 1.0;
+    for axis in 0..N {
+        let (min, max) = items
+            .iter()
+            .fold((T::infinity(), T::neg_infinity()), |(min, max), item| {
+                (min.min(item[axis]), max.max(item[axis]))
+            });
+        let extent = ((max - min) / cell_size).floor().to_f64().unwrap_or(f64::INFINITY);
+        box_cells *= extent + 1.0;
+    }
+
+    items.len() as f64 >= box_cells * MIN_ITEMS_PER_CELL
+}
@@ -0,0 +1,254 @@
+use alloc::{vec, vec::Vec};
+use core::num::NonZeroUsize;
+
+use num_traits::Float;
+
+use crate::{
+    dbscan::{DbscanLabel, DbscanResult},
+    error::Error,
+    kdtree::{validate_items, KdTreeItem},
+    linkage::{merge_edges, Merge},
+    metric::ItemMetric,
+    slice_kdtree::SliceKdTree,
+};
+
+/// 凝縮木の辺。 child が要素数未満なら要素、そうでなければクラスターを表す。
+struct CondensedEdge {
+    parent: usize,
+    child: usize,
+    lambda: f64,
+    child_size: usize,
+}
+
+/// items に HDBSCAN を適用する。
+///
+/// コア距離は自身を含めて min_samples 番目に近い要素までの距離とし、
+/// 要素数が min_cluster_size 未満に分裂した部分木はクラスターとみなさずノイズとして扱う。
+/// クラスターは凝縮木の安定度 (Excess of Mass) によって選択される。
+///
+/// 相互到達可能距離の最小全域木は Prim 法で密に計算するため、時間計算量は O(n^2) になる。
+/// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+pub fn hdbscan<T>(items: impl AsRef<[T]>, min_cluster_size: usize, min_samples: usize) -> Result<DbscanResult, Error>
+where
+    T: KdTreeItem,
+    T::Measurement: Float,
+{
+    let items = items.as_ref();
+    validate_items(items)?;
+    Ok(hdbscan_unchecked(items, min_cluster_size, min_samples))
+}
+
+/// hdbscan() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+pub fn hdbscan_unchecked<T>(items: impl AsRef<[T]>, min_cluster_size: usize, min_samples: usize) -> DbscanResult
+where
+    T: KdTreeItem,
+    T::Measurement: Float,
+{
+    let items = items.as_ref();
+    let n = items.len();
+    if n < 2 {
+        return DbscanResult::from_labels(vec![DbscanLabel::Noise; n]);
+    }
+    let min_cluster_size = min_cluster_size.max(2);
+
+    let core_distances = core_distances(items, min_samples.max(1));
+    let mst = mutual_reachability_mst(items, &core_distances);
+    let merges = merge_edges(n, mst);
+    let condensed = condense(n, &merges, min_cluster_size);
+    let selected = select_clusters(n, &condensed);
+
+    DbscanResult::from_labels(assign_labels(n, &condensed, &selected))
+}
+
+/// 各要素のコア距離を k-d tree で求める。
+fn core_distances<T: KdTreeItem>(items: &[T], min_samples: usize) -> Vec<T::Measurement> {
+    let kdtree = SliceKdTree::construct_with_metric(items, ItemMetric);
+    items
+        .iter()
+        .map(|item| {
+            let mut neighbors = kdtree.nearest_n_indices(item, min_samples);
+            neighbors.pop().expect("must contain the item itself").1
+        })
+        .collect()
+}
+
+/// 相互到達可能距離による完全グラフの最小全域木を Prim 法で求める。
+fn mutual_reachability_mst<T>(items: &[T], core_distances: &[T::Measurement]) -> Vec<(usize, usize, T::Measurement)>
+where
+    T: KdTreeItem,
+    T::Measurement: Float,
+{
+    let n = items.len();
+    let mut in_tree = vec![false; n];
+    let mut best_distances = vec![T::Measurement::infinity(); n];
+    let mut best_sources = vec![0; n];
+    let mut edges = Vec::with_capacity(n - 1);
+
+    let mut current = 0;
+    in_tree[current] = true;
+    for _ in 1..n {
+        let mut next = None;
+        for j in 0..n {
+            if in_tree[j] {
+                continue;
+            }
+
+            let distance = items[current]
+                .distance(&items[j])
+                .max(core_distances[current])
+                .max(core_distances[j]);
+            if distance < best_distances[j] {
+                best_distances[j] = distance;
+                best_sources[j] = current;
+            }
+            if next.is_none_or(|k: usize| best_distances[j] < best_distances[k]) {
+                next = Some(j);
+            }
+        }
+
+        let next = next.expect("unvisited item must exist");
+        in_tree[next] = true;
+        edges.push((best_sources[next], next, best_distances[next]));
+        current = next;
+    }
+
+    edges
+}
+
+/// 併合木を min_cluster_size で凝縮する。クラスター番号は根を n として上から順に振られる。
+fn condense<M: Float>(n: usize, merges: &[Merge<M>], min_cluster_size: usize) -> Vec<CondensedEdge> {
+    let node_size = |node: usize| if node < n { 1 } else { merges[node - n].size };
+    let mut condensed = Vec::new();
+    let mut next_cluster = n + 1;
+
+    // (併合木のノード, そのノードが属する凝縮木のクラスター)
+    let mut stack = vec![(n + merges.len() - 1, n)];
+    while let Some((node, cluster)) = stack.pop() {
+        let merge = &merges[node - n];
+        let distance = merge.distance.to_f64().unwrap_or(f64::INFINITY);
+        let lambda = if distance > 0.0 { 1.0 / distance } else { f64::INFINITY };
+
+        let children = [merge.left, merge.right];
+        let is_large = children.map(|c| node_size(c) >= min_cluster_size);
+        for (child, large) in children.into_iter().zip(is_large) {
+            if large && is_large.iter().all(|&l| l) {
+                // 両方とも十分大きければそれぞれ新しいクラスターになる
+                condensed.push(CondensedEdge {
+                    parent: cluster,
+                    child: next_cluster,
+                    lambda,
+                    child_size: node_size(child),
+                });
+                if child >= n {
+                    stack.push((child, next_cluster));
+                }
+                next_cluster += 1;
+            } else if large {
+                // 片方だけが大きければ親クラスターがそのまま続く
+                if child >= n {
+                    stack.push((child, cluster));
+                } else {
+                    condensed.push(CondensedEdge {
+                        parent: cluster,
+                        child,
+                        lambda,
+                        child_size: 1,
+                    });
+                }
+            } else {
+                // 小さい部分木の要素はこの lambda でクラスターから脱落する
+                let mut leaves = vec![child];
+                while let Some(leaf) = leaves.pop() {
+                    if leaf < n {
+                        condensed.push(CondensedEdge {
+                            parent: cluster,
+                            child: leaf,
+                            lambda,
+                            child_size: 1,
+                        });
+                    } else {
+                        leaves.extend([merges[leaf - n].left, merges[leaf - n].right]);
+                    }
+                }
+            }
+        }
+    }
+
+    condensed
+}
+
+/// 凝縮木の各クラスターの安定度を求め、 Excess of Mass で選択されたクラスターに true を立てる。
+/// 返り値の添字は「クラスター番号 - n」で、根は選択されない。
+fn select_clusters(n: usize, condensed: &[CondensedEdge]) -> Vec<bool> {
+    let cluster_count = condensed.iter().map(|e| e.child + 1).max().unwrap_or(n + 1).max(n + 1) - n;
+    let mut birth_lambdas = vec![0.0; cluster_count];
+    let mut parents = vec![None; cluster_count];
+    for edge in condensed.iter().filter(|e| e.child >= n) {
+        birth_lambdas[edge.child - n] = edge.lambda;
+        parents[edge.child - n] = Some(edge.parent - n);
+    }
+
+    let mut stabilities = vec![0.0; cluster_count];
+    for edge in condensed {
+        let birth = birth_lambdas[edge.parent - n];
+        if edge.lambda > birth {
+            stabilities[edge.parent - n] += (edge.lambda - birth) * edge.child_size as f64;
+        }
+    }
+
+    let mut children = vec![Vec::new(); cluster_count];
+    for (cluster, parent) in parents.iter().enumerate() {
+        if let Some(parent) = parent {
+            children[*parent].push(cluster);
+        }
+    }
+
+    // 子のクラスターは常に親より大きい番号を持つので、番号の大きい方から処理すればよい
+    let mut selected = vec![true; cluster_count];
+    for cluster in (1..cluster_count).rev() {
+        let children_stability: f64 = children[cluster].iter().map(|&c| stabilities[c]).sum();
+        if !children[cluster].is_empty() && children_stability > stabilities[cluster] {
+            selected[cluster] = false;
+            stabilities[cluster] = children_stability;
+        }
+    }
+
+    // 根は選択せず、選択されたクラスターの子孫は選択から外す
+    selected[0] = false;
+    let mut covered = vec![false; cluster_count];
+    for cluster in 1..cluster_count {
+        let parent = parents[cluster].expect("non-root cluster must have a parent");
+        covered[cluster] = covered[parent] || selected[parent];
+        if covered[cluster] {
+            selected[cluster] = false;
+        }
+    }
+
+    selected
+}
+
+/// 各要素に、それが脱落したクラスターの祖先 (自身を含む) のうち選択されたもののラベルを付ける。
+fn assign_labels(n: usize, condensed: &[CondensedEdge], selected: &[bool]) -> Vec<DbscanLabel> {
+    let mut parents = vec![None; selected.len()];
+    for edge in condensed.iter().filter(|e| e.child >= n) {
+        parents[edge.child - n] = Some(edge.parent - n);
+    }
+
+    // 親は常に子より小さい番号を持つので、番号の小さい方から伝播させればよい
+    let mut cluster_id = NonZeroUsize::new(1).expect("must be 1");
+    let mut cluster_labels = vec![DbscanLabel::Noise; selected.len()];
+    for cluster in 0..selected.len() {
+        if selected[cluster] {
+            cluster_labels[cluster] = DbscanLabel::Cluster(cluster_id);
+            cluster_id = cluster_id.saturating_add(1);
+        } else if let Some(parent) = parents[cluster] {
+            cluster_labels[cluster] = cluster_labels[parent];
+        }
+    }
+
+    let mut labels = vec![DbscanLabel::Noise; n];
+    for edge in condensed.iter().filter(|e| e.child < n) {
+        labels[edge.child] = cluster_labels[edge.parent - n];
+    }
+    labels
+}
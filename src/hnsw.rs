@@ -0,0 +1,424 @@
+//! HNSW (Hierarchical Navigable Small World) グラフによる近似近傍探索と、それを用いた近似 DBSCAN 。
+//!
+//! 埋め込みベクトルのような高次元の要素では木構造の枝刈りが効かず、厳密な近傍探索はほぼ総当たりになる。
+//! HNSW は近い要素どうしを結んだ階層的なグラフを貪欲に辿ることで、取りこぼしを許す代わりに少ない距離計算で近傍を求める。
+
+use alloc::{
+    collections::{BTreeSet, BinaryHeap},
+    vec,
+    vec::Vec,
+};
+use core::cmp::{Ordering, Reverse};
+
+use num_traits::Float;
+
+use crate::{
+    dbscan::{dbscan_from_graph, DbscanParams, DbscanResult},
+    error::{check_radius, Error},
+    graph::NeighborGraph,
+    index::SpatialIndex,
+    kdtree::{validate_items, KdTreeItem},
+    metric::{ItemMetric, Metric},
+    random::SplitMix64,
+};
+
+/// HnswIndex の構築と探索の設定。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HnswOptions {
+    /// 各要素が上の階層で持つ辺の数の上限。最下層ではこの 2 倍まで持つ。大きいほど再現率が上がり、構築が遅くなる。
+    pub max_connections: usize,
+
+    /// 構築時に辺の候補として探索する要素の数。大きいほどグラフの質が上がり、構築が遅くなる。
+    pub ef_construction: usize,
+
+    /// 探索時に保持する候補の数。 nearest_n() では k と大きい方を用いる。大きいほど再現率が上がり、探索が遅くなる。
+    pub ef_search: usize,
+
+    /// 各要素の階層を決める擬似乱数の seed 。
+    pub seed: u64,
+}
+
+impl Default for HnswOptions {
+    fn default() -> HnswOptions {
+        HnswOptions {
+            max_connections: 16,
+            ef_construction: 100,
+            ef_search: 64,
+            seed: 0,
+        }
+    }
+}
+
+/// HNSW グラフによる近似的な近傍探索のインデックス。
+/// 要素は構築時の位置で参照され、 SpatialIndex として dbscan_with_index() などに渡せるが、
+/// range() と nearest_n() は近傍の一部を取りこぼしうる。返した要素が範囲外であることはない。
+/// 距離は三角不等式を満たさなくてもよいが、近い要素ほど近くに並ぶ距離でなければ再現率が下がる。
+pub struct HnswIndex<T, M = ItemMetric> {
+    items: Vec<T>,
+    metric: M,
+    options: HnswOptions,
+
+    /// links[i][level] は要素 i の階層 level での隣接要素。要素 i は階層 0 から links[i].len() - 1 までに現れる。
+    links: Vec<Vec<Vec<usize>>>,
+
+    /// 最上層の探索を始める要素。
+    entry_point: Option<usize>,
+}
+
+/// 距離と要素の位置の組。距離が等しければ位置で比べる。
+#[derive(Debug, Clone, Copy)]
+struct Scored<D>(D, usize);
+
+impl<D: PartialOrd> PartialEq for Scored<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<D: PartialOrd> Eq for Scored<D> {}
+
+impl<D: PartialOrd> PartialOrd for Scored<D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<D: PartialOrd> Ord for Scored<D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .partial_cmp(&other.0)
+            .expect("not total order")
+            .then(self.1.cmp(&other.1))
+    }
+}
+
+impl<T: KdTreeItem> HnswIndex<T>
+where
+    T::Measurement: Float,
+{
+    /// 既定の設定で HNSW グラフを構築する。座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+    pub fn construct(items: impl Into<Vec<T>>) -> Result<HnswIndex<T>, Error> {
+        let items = items.into();
+        validate_items(&items)?;
+        Ok(HnswIndex::construct_unchecked(items))
+    }
+
+    /// construct() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+    pub fn construct_unchecked(items: impl Into<Vec<T>>) -> HnswIndex<T> {
+        HnswIndex::construct_with_options(items, ItemMetric, HnswOptions::default())
+    }
+}
+
+impl<T, M: Metric<T>> HnswIndex<T, M>
+where
+    M::Measurement: Float,
+{
+    /// 距離の計算に metric を用い、 options の設定で HNSW グラフを構築する。
+    /// 要素を位置の順に 1 つずつグラフに挿入するため、同じ入力と設定からは同じグラフが得られる。
+    /// 入力を検証しないため、距離が NaN になる要素があると panic する。
+    pub fn construct_with_options(items: impl Into<Vec<T>>, metric: M, options: HnswOptions) -> HnswIndex<T, M> {
+        let items = items.into();
+        let mut index = HnswIndex {
+            links: Vec::with_capacity(items.len()),
+            items,
+            metric,
+            options: HnswOptions {
+                max_connections: options.max_connections.max(2),
+                ef_construction: options.ef_construction.max(1),
+                ..options
+            },
+            entry_point: None,
+        };
+
+        // 階層 l に現れる確率が max_connections^-l になるように階層を選ぶ
+        let mut rng = SplitMix64::new(options.seed);
+        let level_scale = 1.0 / Float::ln(index.options.max_connections as f64);
+        for i in 0..index.items.len() {
+            let level = (-Float::ln(1.0 - rng.next_f64()) * level_scale) as usize;
+            index.insert(i, level);
+        }
+        index
+    }
+
+    /// 要素 i を階層 0 から level までに挿入する。
+    fn insert(&mut self, i: usize, level: usize) {
+        self.links.push(vec![Vec::new(); level + 1]);
+        let Some(entry_point) = self.entry_point else {
+            self.entry_point = Some(i);
+            return;
+        };
+
+        // 各階層で選んだ隣接要素は、すべての階層を探索し終えてからまとめて辺として加える
+        let query = &self.items[i];
+        let top_level = self.links[entry_point].len() - 1;
+        let mut entries = vec![Scored(
+            self.metric.distance(query, &self.items[entry_point]),
+            entry_point,
+        )];
+        for l in (level + 1..=top_level).rev() {
+            entries = self.search_layer(query, entries, 1, l);
+        }
+        let mut chosen = Vec::new();
+        for l in (0..=level.min(top_level)).rev() {
+            let candidates = self.search_layer(query, entries, self.options.ef_construction, l);
+            chosen.push((l, self.select_neighbors(&candidates, self.max_links(l))));
+            entries = candidates;
+        }
+
+        for (l, neighbors) in chosen {
+            for Scored(_, n) in neighbors {
+                self.links[i][l].push(n);
+                self.links[n][l].push(i);
+                if self.links[n][l].len() > self.max_links(l) {
+                    self.shrink_links(n, l);
+                }
+            }
+        }
+
+        if level > top_level {
+            self.entry_point = Some(i);
+        }
+    }
+
+    /// 階層 level で各要素が持つ辺の数の上限。
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 {
+            self.options.max_connections * 2
+        } else {
+            self.options.max_connections
+        }
+    }
+
+    /// 要素 n の階層 level の辺を、上限の数まで選び直す。
+    fn shrink_links(&mut self, n: usize, level: usize) {
+        let base = &self.items[n];
+        let mut candidates: Vec<_> = self.links[n][level]
+            .iter()
+            .map(|&m| Scored(self.metric.distance(base, &self.items[m]), m))
+            .collect();
+        candidates.sort_unstable();
+        let selected = self.select_neighbors(&candidates, self.max_links(level));
+        self.links[n][level] = selected.into_iter().map(|Scored(_, m)| m).collect();
+    }
+
+    /// 距離の昇順に並んだ candidates から最大 count 個の隣接要素を選ぶ。
+    /// 選んだ要素のどれよりも基準の要素に近い候補を優先し、異なる方向の要素どうしが結ばれるようにする。
+    /// それで count 個に満たなければ、残りの候補を近い順に加える。
+    fn select_neighbors(&self, candidates: &[Scored<M::Measurement>], count: usize) -> Vec<Scored<M::Measurement>> {
+        let mut selected: Vec<Scored<M::Measurement>> = Vec::with_capacity(count);
+        let mut skipped = Vec::new();
+        for &candidate in candidates {
+            if selected.len() == count {
+                break;
+            }
+            let item = &self.items[candidate.1];
+            if selected
+                .iter()
+                .all(|s| self.metric.distance(item, &self.items[s.1]) > candidate.0)
+            {
+                selected.push(candidate);
+            } else {
+                skipped.push(candidate);
+            }
+        }
+        let missing = count - selected.len();
+        selected.extend(skipped.into_iter().take(missing));
+        selected
+    }
+
+    /// 階層 level で entries から貪欲に辿り、 query に近い最大 ef 個の要素を距離の昇順に返す。
+    fn search_layer(
+        &self,
+        query: &T,
+        entries: Vec<Scored<M::Measurement>>,
+        ef: usize,
+        level: usize,
+    ) -> Vec<Scored<M::Measurement>> {
+        let mut visited: BTreeSet<usize> = entries.iter().map(|s| s.1).collect();
+        let mut candidates: BinaryHeap<Reverse<Scored<M::Measurement>>> =
+            entries.iter().copied().map(Reverse).collect();
+        let mut found: BinaryHeap<Scored<M::Measurement>> = entries.into_iter().collect();
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            let farthest = found.peek().expect("found must not be empty");
+            if found.len() >= ef && current.0 > farthest.0 {
+                break;
+            }
+            for &n in &self.links[current.1][level] {
+                if !visited.insert(n) {
+                    continue;
+                }
+                let distance = self.metric.distance(query, &self.items[n]);
+                if found.len() < ef || distance < found.peek().expect("found must not be empty").0 {
+                    candidates.push(Reverse(Scored(distance, n)));
+                    found.push(Scored(distance, n));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    /// query に近い最大 ef 個の要素を距離の昇順に返す。
+    fn search(&self, query: &T, ef: usize) -> Vec<Scored<M::Measurement>> {
+        let Some(entry_point) = self.entry_point else {
+            return Vec::new();
+        };
+        let mut entries = vec![Scored(
+            self.metric.distance(query, &self.items[entry_point]),
+            entry_point,
+        )];
+        for l in (1..self.links[entry_point].len()).rev() {
+            entries = self.search_layer(query, entries, 1, l);
+        }
+        self.search_layer(query, entries, ef.max(1), 0)
+    }
+}
+
+impl<T, M> HnswIndex<T, M> {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn options(&self) -> &HnswOptions {
+        &self.options
+    }
+}
+
+impl<T, M: Metric<T>> SpatialIndex<T> for HnswIndex<T, M>
+where
+    M::Measurement: Float,
+{
+    type Measurement = M::Measurement;
+
+    /// ef_search 個の候補を探し、そのすべてが radius 以内であれば候補の数を倍にして探し直す。
+    fn range(&self, query: &T, radius: &M::Measurement) -> Vec<usize> {
+        let mut ef = self.options.ef_search.max(1);
+        loop {
+            let found = self.search(query, ef);
+            let exhausted = found.len() < ef || ef >= self.items.len();
+            if exhausted || found.last().is_some_and(|s| s.0 > *radius) {
+                return found
+                    .into_iter()
+                    .take_while(|s| s.0 <= *radius)
+                    .map(|Scored(_, i)| i)
+                    .collect();
+            }
+            ef *= 2;
+        }
+    }
+
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, M::Measurement)> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut found = self.search(query, self.options.ef_search.max(k));
+        found.truncate(k);
+        found.into_iter().map(|Scored(d, i)| (i, d)).collect()
+    }
+}
+
+/// HnswIndex で求めた近似的な近傍を用いる DBSCAN 。
+/// 各要素の近傍を HnswIndex::range() で求め、要素 j が要素 i の近傍であれば i も j の近傍とする。
+/// 距離は対称なのでこの補完で誤った近傍は加わらず、片方の探索で取りこぼした組を拾える。
+/// 近傍を取りこぼすとコア点が減り、クラスターが分断されたりノイズが増えたりしうるが、クラスターの結合が誤って起きることはない。
+/// 補完のために全要素の近傍を保持するため、近傍の総数に比例するメモリを使う。
+#[derive(Debug, Clone)]
+pub struct ApproxDbscan<D, M = ItemMetric> {
+    params: DbscanParams<D, M>,
+    options: HnswOptions,
+}
+
+impl<D, M> ApproxDbscan<D, M> {
+    /// params の epsilon, min_points, metric, cluster_order を用いる。
+    /// Parallelism::Sequential 以外では近傍探索を並列に行い、それ以外の設定は無視する。ボーダー点は BorderPolicy::FirstWins で割り当てる。
+    pub fn new(params: DbscanParams<D, M>, options: HnswOptions) -> ApproxDbscan<D, M> {
+        ApproxDbscan { params, options }
+    }
+
+    pub fn params(&self) -> &DbscanParams<D, M> {
+        &self.params
+    }
+
+    pub fn options(&self) -> &HnswOptions {
+        &self.options
+    }
+}
+
+impl<D: Float + Sync, M> ApproxDbscan<D, M> {
+    /// items をクラスタリングする。入力を検証しないため、距離が NaN になる要素があると panic する。
+    /// 外部から受け取った値には try_run() を用いる。
+    pub fn run<T: Clone + Sync>(&self, items: impl AsRef<[T]>) -> DbscanResult
+    where
+        M: Metric<T, Measurement = D> + Sync,
+    {
+        let items = items.as_ref();
+        let params = &self.params;
+        let index = HnswIndex::construct_with_options(items.to_vec(), params.metric.clone(), self.options);
+        let range = |i: usize| index.range(&items[i], &params.epsilon);
+
+        #[cfg(feature = "parallel")]
+        let neighbors: Vec<Vec<usize>> = if params.parallelism == crate::dbscan::Parallelism::Sequential {
+            (0..items.len()).map(range).collect()
+        } else {
+            use rayon::prelude::*;
+
+            (0..items.len()).into_par_iter().map(range).collect()
+        };
+        #[cfg(not(feature = "parallel"))]
+        let neighbors: Vec<Vec<usize>> = (0..items.len()).map(range).collect();
+
+        let mut result = dbscan_from_graph(&symmetrize(&neighbors), params.min_points);
+        result.renumber(params.cluster_order);
+        result
+    }
+
+    /// run() と同様だが、先に validate_items() で入力を検証する。
+    /// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を、 epsilon が NaN であれば Error::InvalidRadius を返す。
+    pub fn try_run<T: KdTreeItem + Sync>(&self, items: impl AsRef<[T]>) -> Result<DbscanResult, Error>
+    where
+        M: Metric<T, Measurement = D> + Sync,
+    {
+        let items = items.as_ref();
+        check_radius(&self.params.epsilon)?;
+        validate_items(items)?;
+        Ok(self.run(items))
+    }
+}
+
+/// neighbors[i] に j があれば i と j を結んだ、自身を含まない無向グラフを作る。
+fn symmetrize(neighbors: &[Vec<usize>]) -> NeighborGraph {
+    let mut adjacency = vec![Vec::new(); neighbors.len()];
+    for (i, found) in neighbors.iter().enumerate() {
+        for &j in found.iter().filter(|&&j| j != i) {
+            adjacency[i].push(j);
+            adjacency[j].push(i);
+        }
+    }
+
+    let mut graph = NeighborGraph {
+        offsets: vec![0],
+        neighbors: Vec::new(),
+    };
+    for mut adjacent in adjacency {
+        adjacent.sort_unstable();
+        adjacent.dedup();
+        graph.neighbors.extend_from_slice(&adjacent);
+        graph.offsets.push(graph.neighbors.len());
+    }
+    graph
+}
@@ -0,0 +1,244 @@
+use alloc::{collections::BinaryHeap, vec, vec::Vec};
+use core::{
+    cmp::Ordering,
+    ops::{ControlFlow, Range},
+};
+
+use crate::{
+    error::Error,
+    index::SpatialIndex,
+    kdtree::{count_until, validate_items, KdTreeItem, KdTreeOptions},
+    metric::{ItemMetric, Metric},
+    slice_kdtree::{offer, Candidate},
+};
+
+/// 左右の子の位置を持たない静的な k-d tree 。
+/// 分割に用いる要素を完全二分木の幅優先順 (Eytzinger 配置) に並べ、 1 から数えたノード i の子を 2i と 2i + 1 とする。
+/// 最下段の子は葉で、それぞれ最大 bucket_size 個の要素を分割に用いた要素の後ろにまとめて持つ。
+/// 根に近いノードが先頭にまとまるため探索のキャッシュ効率がよく、要素のほかには元の位置と葉の範囲だけを持つ。
+/// 要素の追加や削除はできず、要素数は u32::MAX までに制限される。
+pub struct ImplicitKdTree<T, M = ItemMetric> {
+    /// 先頭の splits 個が分割に用いる要素で、ノード i の要素が items[i - 1] に入る。続いて葉の要素が葉の順に並ぶ。
+    items: Vec<T>,
+
+    /// items のそれぞれが構築時に渡された位置。
+    indices: Vec<u32>,
+
+    /// 分割に用いる要素の数。 2 の冪から 1 を引いた数になる。
+    splits: usize,
+
+    /// 葉 j の要素が items[splits + leaf_starts[j]..splits + leaf_starts[j + 1]] に入る。
+    leaf_starts: Vec<u32>,
+
+    metric: M,
+}
+
+impl<T: KdTreeItem> ImplicitKdTree<T> {
+    /// items から k-d tree を構築する。座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+    pub fn construct(items: impl Into<Vec<T>>) -> Result<ImplicitKdTree<T>, Error> {
+        let items = items.into();
+        validate_items(&items)?;
+        Ok(ImplicitKdTree::construct_with_metric(items, ItemMetric))
+    }
+}
+
+impl<T: KdTreeItem, M: Metric<T>> ImplicitKdTree<T, M> {
+    /// 距離の計算に metric を用いる k-d tree を構築する。入力は検証しない。
+    pub fn construct_with_metric(items: impl Into<Vec<T>>, metric: M) -> ImplicitKdTree<T, M> {
+        ImplicitKdTree::construct_with_options(items, metric, KdTreeOptions::default())
+    }
+
+    /// options の設定で k-d tree を構築する。
+    pub fn construct_with_options(items: impl Into<Vec<T>>, metric: M, options: KdTreeOptions) -> ImplicitKdTree<T, M> {
+        let items = items.into();
+        let len = u32::try_from(items.len()).expect("ImplicitKdTree supports at most u32::MAX items");
+        let bucket_size = options.bucket_size.max(1);
+
+        // すべての葉が bucket_size 個以下になる最小の段数まで分割する
+        let mut leaf_count = 1;
+        while items.len() + 1 > (bucket_size + 1) * leaf_count {
+            leaf_count *= 2;
+        }
+        let splits = leaf_count - 1;
+
+        // (ノード, order 上の範囲, 深さ) を積む。分割に用いる要素の位置は slots に、葉の範囲は leaf_ranges に記録する
+        let mut order: Vec<u32> = (0..len).collect();
+        let mut slots = vec![0; splits];
+        let mut leaf_ranges = vec![0..0; leaf_count];
+        let mut stack = vec![(1, 0..items.len(), 0)];
+        while let Some((node, range, depth)) = stack.pop() {
+            if node > splits {
+                leaf_ranges[node - leaf_count] = range;
+                continue;
+            }
+            let mid = range.start + range.len() / 2;
+            order[range.clone()].select_nth_unstable_by(mid - range.start, |&lhs, &rhs| {
+                items[lhs as usize].cmp_in_depth(&items[rhs as usize], depth)
+            });
+            slots[node - 1] = order[mid];
+            stack.push((2 * node, range.start..mid, depth + 1));
+            stack.push((2 * node + 1, mid + 1..range.end, depth + 1));
+        }
+
+        let mut indices = slots;
+        let mut leaf_starts = Vec::with_capacity(leaf_count + 1);
+        for range in leaf_ranges {
+            leaf_starts.push((indices.len() - splits) as u32);
+            indices.extend_from_slice(&order[range]);
+        }
+        leaf_starts.push((indices.len() - splits) as u32);
+
+        let mut taken: Vec<Option<T>> = items.into_iter().map(Some).collect();
+        let items = indices
+            .iter()
+            .map(|&i| taken[i as usize].take().expect("each item must be placed once"))
+            .collect();
+        ImplicitKdTree {
+            items,
+            indices,
+            splits,
+            leaf_starts,
+            metric,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 葉のノード node が持つ要素の items 上の範囲を返す。
+    fn leaf_range(&self, node: usize) -> Range<usize> {
+        let leaf = node - self.splits - 1;
+        let (start, end) = (self.leaf_starts[leaf] as usize, self.leaf_starts[leaf + 1] as usize);
+        self.splits + start..self.splits + end
+    }
+
+    /// query から radius 以内にある要素の元の位置を found に渡す。
+    fn search_range(&self, query: &T, radius: &M::Measurement, mut found: impl FnMut(usize)) {
+        self.search_range_while(query, radius, |index| {
+            found(index);
+            ControlFlow::Continue(())
+        });
+    }
+
+    /// search_range() と同様だが、 found が ControlFlow::Break を返したら探索を打ち切る。
+    /// 打ち切った後は found を呼ばない。
+    fn search_range_while(&self, query: &T, radius: &M::Measurement, mut found: impl FnMut(usize) -> ControlFlow<()>) {
+        let radius = self.metric.distance_to_reduced(radius);
+        let mut stack = vec![(1, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            if node > self.splits {
+                let range = self.leaf_range(node);
+                let indices = &self.indices[range.clone()];
+                let mut flow = ControlFlow::Continue(());
+                self.metric
+                    .reduced_range_batch(query, self.items[range].iter(), &radius, |position, _| {
+                        if flow.is_continue() {
+                            flow = found(indices[position] as usize);
+                        }
+                    });
+                if flow.is_break() {
+                    return;
+                }
+                continue;
+            }
+
+            let item = &self.items[node - 1];
+            if self.metric.reduced_distance(query, item) <= radius && found(self.indices[node - 1] as usize).is_break()
+            {
+                return;
+            }
+
+            // radius が分割面に届いていれば逆側も探索する。分割面上の要素はどちらの側にも入りうるので境界を含める
+            let (first, second) = children(node, query.cmp_in_depth(item, depth));
+            if self.metric.reduced_distance_to_axis(query, item, depth) <= radius {
+                stack.push((second, depth + 1));
+            }
+            stack.push((first, depth + 1));
+        }
+    }
+
+    /// 厳密な k 近傍探索で (元の位置, reduced_distance()) を集める。
+    fn search_nearest_n(&self, query: &T, max_count: usize) -> BinaryHeap<Candidate<M::Measurement>> {
+        let mut candidates = BinaryHeap::with_capacity(max_count);
+        if max_count == 0 {
+            return candidates;
+        }
+
+        // (ノード, 深さ, 逆側として積まれたかどうか) を積む。逆側は query が属する側の探索が終わってから判定される
+        let mut stack = vec![(1, 0, false)];
+        while let Some((node, depth, opposite)) = stack.pop() {
+            if opposite && candidates.len() >= max_count {
+                let parent = &self.items[node / 2 - 1];
+                let axis_distance = self.metric.reduced_distance_to_axis(query, parent, depth - 1);
+                if axis_distance >= candidates.peek().expect("must exist").1 {
+                    continue;
+                }
+            }
+
+            if node > self.splits {
+                for i in self.leaf_range(node) {
+                    let distance = self.metric.reduced_distance(query, &self.items[i]);
+                    offer(
+                        &mut candidates,
+                        max_count,
+                        Candidate(self.indices[i] as usize, distance),
+                    );
+                }
+                continue;
+            }
+
+            let item = &self.items[node - 1];
+            let distance = self.metric.reduced_distance(query, item);
+            offer(
+                &mut candidates,
+                max_count,
+                Candidate(self.indices[node - 1] as usize, distance),
+            );
+
+            let (first, second) = children(node, query.cmp_in_depth(item, depth));
+            stack.push((second, depth + 1, true));
+            stack.push((first, depth + 1, false));
+        }
+        candidates
+    }
+}
+
+impl<T: KdTreeItem, M: Metric<T>> SpatialIndex<T> for ImplicitKdTree<T, M> {
+    type Measurement = M::Measurement;
+
+    fn range(&self, query: &T, radius: &Self::Measurement) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.search_range(query, radius, |index| found.push(index));
+        found
+    }
+
+    fn range_into(&self, query: &T, radius: &Self::Measurement, found: &mut Vec<usize>) {
+        found.clear();
+        self.search_range(query, radius, |index| found.push(index));
+    }
+
+    fn count_within_limited(&self, query: &T, radius: &Self::Measurement, limit: usize) -> usize {
+        count_until(limit, |count| self.search_range_while(query, radius, |_| count()))
+    }
+
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, Self::Measurement)> {
+        self.search_nearest_n(query, k)
+            .into_sorted_vec()
+            .into_iter()
+            .map(|c| (c.0, self.metric.reduced_to_distance(&c.1)))
+            .collect()
+    }
+}
+
+/// node の子を (query が属する側, 逆側) の順で返す。 ordering は query と node の要素の比較になる。
+fn children(node: usize, ordering: Ordering) -> (usize, usize) {
+    match ordering {
+        Ordering::Less => (2 * node, 2 * node + 1),
+        Ordering::Equal | Ordering::Greater => (2 * node + 1, 2 * node),
+    }
+}
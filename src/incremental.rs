@@ -0,0 +1,217 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    num::NonZeroUsize,
+};
+
+use crate::{
+    dbscan::DbscanLabel,
+    error::{check_radius, Error},
+    kdtree::{KdTree, KdTreeItem},
+};
+
+/// 要素の挿入と削除に追従してラベルを更新する DBSCAN 。
+///
+/// 要素は insert() が返す ID で識別され、削除された ID は再利用されない。
+/// 更新の際は変更のあった近傍に関わるクラスターだけを再展開するため、
+/// クラスター番号は 1 から連続するとは限らない。
+pub struct IncrementalDbscan<T: KdTreeItem> {
+    epsilon: T::Measurement,
+    min_items: usize,
+    points: Vec<Option<T>>,
+    labels: Vec<DbscanLabel>,
+    cluster_members: HashMap<NonZeroUsize, Vec<usize>>,
+    next_cluster_id: NonZeroUsize,
+
+    /// 生存している要素を格納するツリー。ツリー上の位置は ID と一致する。
+    tree: KdTree<T>,
+}
+
+impl<T: KdTreeItem> IncrementalDbscan<T> {
+    /// epsilon が NaN など自身と比較できない値であれば Error::InvalidRadius を返す。
+    pub fn new(epsilon: T::Measurement, min_items: usize) -> Result<IncrementalDbscan<T>, Error> {
+        check_radius(&epsilon)?;
+        Ok(IncrementalDbscan {
+            epsilon,
+            min_items,
+            points: vec![],
+            labels: vec![],
+            cluster_members: HashMap::new(),
+            next_cluster_id: NonZeroUsize::new(1).expect("must be 1"),
+            tree: KdTree::construct_unchecked(vec![]),
+        })
+    }
+
+    /// 生存している要素の数を返す。
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 現在のクラスターの数を返す。
+    pub fn cluster_count(&self) -> usize {
+        self.cluster_members.len()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&T> {
+        self.points.get(id).and_then(|p| p.as_ref())
+    }
+
+    /// 要素のラベルを返す。削除済みまたは存在しない ID であれば None になる。
+    pub fn label(&self, id: usize) -> Option<DbscanLabel> {
+        self.get(id).map(|_| self.labels[id])
+    }
+
+    /// ID 順に並んだラベルを返す。削除済みの ID は Noise になる。
+    pub fn labels(&self) -> &[DbscanLabel] {
+        &self.labels
+    }
+
+    /// 指定したクラスターに属する要素の ID を返す。順序は不定。
+    pub fn members(&self, cluster_id: NonZeroUsize) -> &[usize] {
+        self.cluster_members.get(&cluster_id).map_or(&[], |m| m.as_slice())
+    }
+
+    /// 要素を挿入し、その ID を返す。
+    /// KdTree::try_insert() と同様に、座標に NaN や無限大を含む要素や既存の要素と座標の数が異なる要素は拒み、何も変更しない。
+    pub fn insert(&mut self, point: T) -> Result<usize, Error> {
+        let id = self.tree.try_insert(point.clone())?;
+        debug_assert_eq!(id, self.points.len());
+        self.points.push(Some(point));
+        self.labels.push(DbscanLabel::Noise);
+
+        // 新しい要素の近傍はコア点の判定が変わりうる
+        let neighbors = self.neighbors(id);
+        self.relabel(neighbors);
+
+        Ok(id)
+    }
+
+    /// 要素を削除し、削除された要素を返す。
+    pub fn remove(&mut self, id: usize) -> Option<T> {
+        self.get(id)?;
+
+        let mut neighbors = self.neighbors(id);
+        neighbors.retain(|&n| n != id);
+        if let DbscanLabel::Cluster(cluster_id) = self.labels[id] {
+            if let Some(members) = self.cluster_members.get_mut(&cluster_id) {
+                members.retain(|&m| m != id);
+
+                // 自身だけのクラスター (min_items が 1 以下の孤立した要素) は近傍がなく再展開されないため、ここで取り除く
+                if members.is_empty() {
+                    self.cluster_members.remove(&cluster_id);
+                }
+            }
+        }
+
+        let point = self.points[id].take();
+        self.labels[id] = DbscanLabel::Noise;
+        if let Some(point) = &point {
+            self.tree.remove_matching(point, |_, index| index == id);
+        }
+
+        // 削除された要素が属していたクラスターは分裂しうるため再展開する
+        self.relabel(neighbors);
+
+        point
+    }
+
+    /// seeds と、 seeds が属するクラスターの要素をすべて再展開する。
+    fn relabel(&mut self, seeds: Vec<usize>) {
+        let mut affected_clusters = HashSet::new();
+        let mut region: Vec<usize> = seeds;
+        for &seed in &region {
+            if let DbscanLabel::Cluster(cluster_id) = self.labels[seed] {
+                affected_clusters.insert(cluster_id);
+            }
+        }
+        for cluster_id in &affected_clusters {
+            region.extend(self.cluster_members.remove(cluster_id).unwrap_or_default());
+        }
+        region.sort_unstable();
+        region.dedup();
+        for &r in &region {
+            self.labels[r] = DbscanLabel::Noise;
+        }
+
+        let mut assigned = HashSet::new();
+        let mut core_cache = HashMap::new();
+        let mut queue = VecDeque::new();
+        let mut i = 0;
+        while i < region.len() {
+            let start = region[i];
+            i += 1;
+            if assigned.contains(&start) || !self.is_core_cached(&mut core_cache, start) {
+                continue;
+            }
+
+            let cluster_id = self.next_cluster_id;
+            self.next_cluster_id = self.next_cluster_id.saturating_add(1);
+            let mut members = vec![start];
+            assigned.insert(start);
+            self.labels[start] = DbscanLabel::Cluster(cluster_id);
+
+            queue.push_back(start);
+            while let Some(core) = queue.pop_front() {
+                for neighbor in self.neighbors(core) {
+                    if assigned.contains(&neighbor) {
+                        continue;
+                    }
+
+                    let neighbor_is_core = self.is_core_cached(&mut core_cache, neighbor);
+                    if let DbscanLabel::Cluster(other_id) = self.labels[neighbor] {
+                        if !neighbor_is_core {
+                            // 他のクラスターのボーダー点は奪わない
+                            continue;
+                        }
+
+                        // 他のクラスターのコア点に届いた場合は併合されるので、そのクラスターも再展開する
+                        for member in self.cluster_members.remove(&other_id).unwrap_or_default() {
+                            self.labels[member] = DbscanLabel::Noise;
+                            region.push(member);
+                        }
+                    }
+
+                    assigned.insert(neighbor);
+                    members.push(neighbor);
+                    self.labels[neighbor] = DbscanLabel::Cluster(cluster_id);
+                    if neighbor_is_core {
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+            self.cluster_members.insert(cluster_id, members);
+        }
+
+        // どこにも属さなかった要素は、再展開しなかったクラスターのボーダー点になりうる
+        for &r in &region {
+            if assigned.contains(&r) || self.points[r].is_none() {
+                continue;
+            }
+            let core_label = self
+                .neighbors(r)
+                .into_iter()
+                .filter(|&n| n != r && self.labels[n] != DbscanLabel::Noise)
+                .find(|&n| self.is_core_cached(&mut core_cache, n))
+                .map(|n| self.labels[n]);
+            if let Some(DbscanLabel::Cluster(cluster_id)) = core_label {
+                self.labels[r] = DbscanLabel::Cluster(cluster_id);
+                self.cluster_members.entry(cluster_id).or_default().push(r);
+            }
+        }
+    }
+
+    fn is_core_cached(&self, cache: &mut HashMap<usize, bool>, id: usize) -> bool {
+        *cache
+            .entry(id)
+            .or_insert_with(|| self.neighbors(id).len() >= self.min_items)
+    }
+
+    /// 生存している要素のうち、 id の要素から epsilon 以内にあるもの (自身を含む) の ID を返す。
+    fn neighbors(&self, id: usize) -> Vec<usize> {
+        let query = self.points[id].as_ref().expect("must be alive");
+        self.tree.find_range_n_indices(query, &self.epsilon)
+    }
+}
@@ -0,0 +1,139 @@
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{
+    kdtree::{KdTree, KdTreeItem},
+    metric::{ItemMetric, Metric},
+};
+
+/// 近傍探索を提供するインデックスが実装するトレイト。
+/// 要素はインデックスの構築時に渡された位置で参照される。
+/// dbscan_with_index() などのクラスタリングはこのトレイトだけを通して近傍を求めるため、
+/// ball tree や R-tree など任意のインデックスを実装して渡すことができる。
+pub trait SpatialIndex<T> {
+    type Measurement: Debug + PartialOrd;
+
+    /// query から radius 以内 (境界を含む) にある要素の位置をすべて返す。順序は不定。
+    fn range(&self, query: &T, radius: &Self::Measurement) -> Vec<usize>;
+
+    /// range() と同様だが、結果を found に書き込む。 found は先に空にされる。
+    /// 既定では range() の結果を複製するため、確保を省けるのは上書きした実装だけである。
+    fn range_into(&self, query: &T, radius: &Self::Measurement, found: &mut Vec<usize>) {
+        found.clear();
+        found.extend(self.range(query, radius));
+    }
+
+    /// query から radius 以内 (境界を含む) にある要素の数を、 limit を上限として返す。
+    /// 既定では range() の結果を数えるため、 limit に達した時点で探索を打ち切れるのは上書きした実装だけである。
+    fn count_within_limited(&self, query: &T, radius: &Self::Measurement, limit: usize) -> usize {
+        self.range(query, radius).len().min(limit)
+    }
+
+    /// query に最も近い要素の位置と距離を返す。要素がなければ None を返す。
+    fn nearest(&self, query: &T) -> Option<(usize, Self::Measurement)> {
+        self.nearest_n(query, 1).into_iter().next()
+    }
+
+    /// query に近い順に最大 k 個の要素の位置と距離を返す。
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, Self::Measurement)>;
+}
+
+impl<T: KdTreeItem, M: Metric<T>> SpatialIndex<T> for KdTree<T, M> {
+    type Measurement = M::Measurement;
+
+    fn range(&self, query: &T, radius: &Self::Measurement) -> Vec<usize> {
+        self.find_range_n_indices(query, radius)
+    }
+
+    fn range_into(&self, query: &T, radius: &Self::Measurement, found: &mut Vec<usize>) {
+        self.find_range_into(query, radius, found)
+    }
+
+    fn count_within_limited(&self, query: &T, radius: &Self::Measurement, limit: usize) -> usize {
+        KdTree::count_within_limited(self, query, radius, limit)
+    }
+
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, Self::Measurement)> {
+        self.find_nearest_n_indices(query, k)
+    }
+}
+
+/// すべての要素との距離を計算する総当たりのインデックス。
+/// 要素数が少ない場合や、 Metric が分割面による枝刈りに向かない場合、他のインデックスの検証に用いる。
+#[derive(Debug, Clone)]
+pub struct BruteForceIndex<T, M = ItemMetric> {
+    items: Vec<T>,
+    metric: M,
+}
+
+impl<T: KdTreeItem> BruteForceIndex<T> {
+    pub fn new(items: impl Into<Vec<T>>) -> BruteForceIndex<T> {
+        BruteForceIndex::with_metric(items, ItemMetric)
+    }
+}
+
+impl<T, M: Metric<T>> BruteForceIndex<T, M> {
+    /// 距離の計算に metric を用いるインデックスを作る。
+    pub fn with_metric(items: impl Into<Vec<T>>, metric: M) -> BruteForceIndex<T, M> {
+        BruteForceIndex {
+            items: items.into(),
+            metric,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl<T, M: Metric<T>> SpatialIndex<T> for BruteForceIndex<T, M> {
+    type Measurement = M::Measurement;
+
+    fn range(&self, query: &T, radius: &Self::Measurement) -> Vec<usize> {
+        (0..self.items.len())
+            .filter(|&i| self.metric.distance(query, &self.items[i]) <= *radius)
+            .collect()
+    }
+
+    fn count_within_limited(&self, query: &T, radius: &Self::Measurement, limit: usize) -> usize {
+        self.items
+            .iter()
+            .filter(|item| self.metric.distance(query, item) <= *radius)
+            .take(limit)
+            .count()
+    }
+
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, Self::Measurement)> {
+        let mut candidates: Vec<_> = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (i, self.metric.distance(query, item)))
+            .collect();
+        candidates.sort_by(|lhs, rhs| lhs.1.partial_cmp(&rhs.1).expect("not total order"));
+        candidates.truncate(k);
+        candidates
+    }
+}
+
+/// dbscan_with_index_kind() で用いるインデックスの種類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexKind {
+    /// 要素の数と分布から BruteForceIndex, GridIndex, KdTree のいずれかを選ぶ。
+    #[default]
+    Auto,
+    KdTree,
+
+    /// 子の位置を持たない ImplicitKdTree 。構築時に要素を複製する代わりに探索のキャッシュ効率がよい。
+    ImplicitKdTree,
+    Grid,
+    BallTree,
+
+    /// CoverTree 。クラスターの偏りが大きい場合や高次元でも内在的な次元が低い場合に KdTree より安定して速い。
+    CoverTree,
+    BruteForce,
+}
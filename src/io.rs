@@ -0,0 +1,64 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::dbscan::DbscanResult;
+
+/// result を `index,label` のヘッダーを持つ CSV で書き出す。
+/// index は入力での要素の位置、 label は DbscanLabel::to_code() の整数 (ノイズは -1) になる。
+pub fn write_csv(result: &DbscanResult, writer: impl Write) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    writeln!(writer, "index,label")?;
+    for (index, label) in result.labels.iter().enumerate() {
+        writeln!(writer, "{index},{}", label.to_code())?;
+    }
+    writer.flush()
+}
+
+/// result を `[{"index":0,"label":0},...]` の形の JSON で書き出す。各値は write_csv() と同じになる。
+pub fn write_json(result: &DbscanResult, writer: impl Write) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    write!(writer, "[")?;
+    for (index, label) in result.labels.iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        write!(writer, r#"{separator}{{"index":{index},"label":{}}}"#, label.to_code())?;
+    }
+    writeln!(writer, "]")?;
+    writer.flush()
+}
+
+/// result を INT64 の index 列と INT32 の label 列を持つ Parquet で書き出す。各値は write_csv() と同じで、圧縮はしない。
+#[cfg(feature = "parquet")]
+pub fn write_parquet(result: &DbscanResult, writer: impl Write + Send) -> parquet::errors::Result<()> {
+    use std::sync::Arc;
+
+    use parquet::{
+        data_type::{Int32Type, Int64Type},
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::parser::parse_message_type,
+    };
+
+    let schema = parse_message_type("message dbscan { REQUIRED INT64 index; REQUIRED INT32 label; }")?;
+    let properties = WriterProperties::builder().build();
+    let mut writer = SerializedFileWriter::new(writer, Arc::new(schema), Arc::new(properties))?;
+
+    for (chunk_index, labels) in result.labels.chunks(PARQUET_ROW_GROUP_SIZE).enumerate() {
+        let start = (chunk_index * PARQUET_ROW_GROUP_SIZE) as i64;
+        let indices: Vec<i64> = (start..start + labels.len() as i64).collect();
+        let codes: Vec<i32> = labels.iter().map(|l| l.to_code()).collect();
+
+        let mut row_group = writer.next_row_group()?;
+        let mut column = row_group.next_column()?.expect("index column must exist");
+        column.typed::<Int64Type>().write_batch(&indices, None, None)?;
+        column.close()?;
+        let mut column = row_group.next_column()?.expect("label column must exist");
+        column.typed::<Int32Type>().write_batch(&codes, None, None)?;
+        column.close()?;
+        row_group.close()?;
+    }
+
+    writer.close()?;
+    Ok(())
+}
+
+/// write_parquet() で 1 つの row group に書き込む行数。
+#[cfg(feature = "parquet")]
+const PARQUET_ROW_GROUP_SIZE: usize = 1 << 20;
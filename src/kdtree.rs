@@ -1,9 +1,22 @@
-use num_traits::Float;
-use std::{cmp::Ordering, collections::BinaryHeap, fmt::Debug, num::NonZeroUsize};
+use alloc::{collections::BinaryHeap, vec, vec::Vec};
+use core::{
+    cmp::Ordering,
+    fmt::Debug,
+    num::NonZero,
+    ops::{ControlFlow, Range},
+};
+use num_traits::{Float, One};
+
+use crate::{
+    error::{check_radius, Error},
+    metric::{ItemMetric, Metric},
+    progress::{CancellationToken, Cancelled, CANCELLATION_CHECK_INTERVAL},
+    slice_kdtree::spatial_order,
+};
 
 /// KdTree に格納する要素が実装しなければいけないトレイト。
 pub trait KdTreeItem: Debug + Clone {
-    type Measurement: Debug + PartialOrd;
+    type Measurement: Debug + PartialOrd + Clone;
 
     /// 指定されたツリー深度で要素同士を比較する。
     /// 一般的に ```components[depth % N]``` が比較されるように実装される。
@@ -14,6 +27,95 @@ pub trait KdTreeItem: Debug + Clone {
 
     /// もう一方の要素の軸との距離を計算する。 distance() と一貫性があればよい。
     fn distance_to_axis(&self, other: &Self, depth: usize) -> Self::Measurement;
+
+    /// distance() と大小関係が一致する、より安価に計算できる値を返す。探索中の比較にはこちらが使われる。
+    /// 既定では distance() をそのまま返す。
+    fn reduced_distance(&self, other: &Self) -> Self::Measurement {
+        self.distance(other)
+    }
+
+    /// distance_to_axis() を reduced_distance() と同じ尺度で返す。
+    fn reduced_distance_to_axis(&self, other: &Self, depth: usize) -> Self::Measurement {
+        self.distance_to_axis(other, depth)
+    }
+
+    /// reduced_distance() の尺度の値を distance() の尺度に変換する。
+    fn reduced_to_distance(reduced: &Self::Measurement) -> Self::Measurement {
+        reduced.clone()
+    }
+
+    /// distance() の尺度の値を reduced_distance() の尺度に変換する。
+    fn distance_to_reduced(distance: &Self::Measurement) -> Self::Measurement {
+        distance.clone()
+    }
+
+    /// 座標に NaN や無限大を含まなければ true を返す。 validate_items() が入力の検証に用いる。
+    /// 既定では常に true を返す。
+    fn is_finite(&self) -> bool {
+        true
+    }
+
+    /// 座標の数が型で決まらず要素ごとに異なりうる場合に、その数を返す。 validate_items() が入力の検証に用いる。
+    /// 既定では常に None を返す。
+    fn runtime_dimensions(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// items のすべての要素が KdTreeItem::is_finite() を満たし、 KdTreeItem::runtime_dimensions() が一致するかを調べる。
+/// 先頭の要素と座標の数が異なる要素があれば、最初のものを Error::DimensionMismatch で返す。
+/// そうでなく満たさない要素があれば、その位置を Error::NonFiniteInput で返す。
+pub fn validate_items<T: KdTreeItem>(items: impl AsRef<[T]>) -> Result<(), Error> {
+    let items = items.as_ref();
+    if let Some(expected) = items.first().and_then(T::runtime_dimensions) {
+        let mismatch = items.iter().enumerate().find_map(|(i, item)| {
+            item.runtime_dimensions()
+                .filter(|&found| found != expected)
+                .map(|found| (i, found))
+        });
+        if let Some((index, found)) = mismatch {
+            return Err(Error::DimensionMismatch { index, expected, found });
+        }
+    }
+
+    let indices: Vec<_> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| !item.is_finite())
+        .map(|(i, _)| i)
+        .collect();
+    if indices.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::NonFiniteInput { indices })
+    }
+}
+
+/// search(count) で見つかった要素の数を limit を上限として数える。
+/// search は見つけた要素ごとに count() を呼び、それが ControlFlow::Break を返したら探索を打ち切る。
+pub(crate) fn count_until(limit: usize, search: impl FnOnce(&mut dyn FnMut() -> ControlFlow<()>)) -> usize {
+    if limit == 0 {
+        return 0;
+    }
+    let mut found = 0;
+    search(&mut || {
+        found += 1;
+        if found < limit {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        }
+    });
+    found
+}
+
+/// query が KdTreeItem::is_finite() を満たすかを調べる。
+pub(crate) fn check_query<T: KdTreeItem>(query: &T) -> Result<(), Error> {
+    if query.is_finite() {
+        Ok(())
+    } else {
+        Err(Error::NonFiniteQuery)
+    }
 }
 
 impl<T: Debug + Float, const N: usize> KdTreeItem for [T; N] {
@@ -24,204 +126,1421 @@ impl<T: Debug + Float, const N: usize> KdTreeItem for [T; N] {
     }
 
     fn distance(&self, other: &Self) -> Self::Measurement {
+        self.reduced_distance(other).sqrt()
+    }
+
+    fn distance_to_axis(&self, other: &Self, depth: usize) -> Self::Measurement {
+        let i = depth % N;
+        (self[i] - other[i]).abs()
+    }
+
+    /// 平方根をとらない 2 乗のユークリッド距離。
+    fn reduced_distance(&self, other: &Self) -> Self::Measurement {
         (0..N)
             .map(|i| (self[i] - other[i]).powi(2))
             .fold(T::zero(), |a, x| a + x)
-            .sqrt()
     }
 
-    fn distance_to_axis(&self, other: &Self, depth: usize) -> Self::Measurement {
+    fn reduced_distance_to_axis(&self, other: &Self, depth: usize) -> Self::Measurement {
         let i = depth % N;
-        (self[i] - other[i]).abs()
+        (self[i] - other[i]).powi(2)
+    }
+
+    fn reduced_to_distance(reduced: &T) -> T {
+        reduced.sqrt()
+    }
+
+    fn distance_to_reduced(distance: &T) -> T {
+        distance.powi(2)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.iter().all(|c| c.is_finite())
     }
 }
 
 /// k-d tree を表す。
-pub struct KdTree<T> {
+/// 距離は M で計算され、既定では要素自身の KdTreeItem::distance() が使われる。
+/// serde feature が有効であれば構築済みのツリーをそのまま保存して復元できる。復元時に構造の整合性は検査しない。
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KdTree<T, M = ItemMetric> {
     nodes: Vec<Node<T>>,
-    root_index: Option<NonZeroUsize>,
+    root_index: Option<NodeIndex>,
+    metric: M,
+
+    /// 葉ノードに格納する要素数の上限。
+    bucket_size: usize,
+
+    /// 削除されていない要素の数。
+    len: usize,
+
+    /// nodes に残っている削除済みの要素の数。
+    removed_count: usize,
+
+    /// 次に insert() される要素に割り当てる位置。
+    next_index: usize,
+}
+
+/// k-d tree の構築時の設定。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KdTreeOptions {
+    /// 葉ノードに格納する要素数の上限。これ以下の要素数の部分木は分割せず、探索時には線形に走査する。
+    /// 1 を指定すると 1 ノードに 1 要素を持つ通常の k-d tree になる。 0 は 1 として扱われる。
+    pub bucket_size: usize,
+}
+
+impl Default for KdTreeOptions {
+    fn default() -> KdTreeOptions {
+        KdTreeOptions {
+            bucket_size: DEFAULT_BUCKET_SIZE,
+        }
+    }
+}
+
+/// KdTree::stats() が返すツリーの統計。偏った入力で構築や探索が遅くなっていないかを調べるのに用いる。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KdTreeStats {
+    /// 削除されていない要素の数。
+    pub len: usize,
+
+    /// 削除済みで、まだ詰め直されていない要素の数。
+    pub removed: usize,
+
+    /// 根から辿れるノードの数と、そのうちの葉ノードの数。
+    pub node_count: usize,
+    pub leaf_count: usize,
+
+    /// 根から最も深いノードまでのノードの数。
+    pub depth: usize,
+
+    /// 同じ要素数で構築し直した場合の深さ。
+    pub balanced_depth: usize,
+
+    /// depth の balanced_depth に対する比。構築直後は 1 前後で、大きいほど偏っている。要素がなければ 1 になる。
+    pub balance_factor: f64,
+
+    /// ノードと葉のバケットが確保しているメモリのおおよそのバイト数。要素が指す先のヒープは含まない。
+    pub memory_bytes: usize,
+}
+
+/// KdTree::knn_cancellable() などの結果。中断された場合は Cancelled::partial に途中までの結果が入る。
+pub type CancellableKnn<'a, T, D> = Result<Vec<Vec<(&'a T, D)>>, Cancelled<Vec<Vec<(&'a T, D)>>>>;
+
+/// ノードや要素の位置を格納する整数の型。
+/// compact-index feature が有効であれば u32 になってノードが小さくなる代わりに、 1 つのツリーの要素数が u32::MAX までに制限される。
+#[cfg(feature = "compact-index")]
+type StoredIndex = u32;
+#[cfg(not(feature = "compact-index"))]
+type StoredIndex = usize;
+
+#[cfg(feature = "compact-index")]
+fn store_index(index: usize) -> StoredIndex {
+    u32::try_from(index).expect("compact-index supports at most u32::MAX items")
+}
+
+#[cfg(not(feature = "compact-index"))]
+fn store_index(index: usize) -> StoredIndex {
+    index
+}
+
+#[cfg(feature = "compact-index")]
+#[inline]
+fn load_index(index: StoredIndex) -> usize {
+    index as usize
+}
+
+#[cfg(not(feature = "compact-index"))]
+#[inline]
+fn load_index(index: StoredIndex) -> usize {
+    index
+}
+
+/// nodes の位置に 1 を足した値。 Option に包んでも大きさは変わらない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub(crate) struct NodeIndex(NonZero<StoredIndex>);
+
+impl NodeIndex {
+    /// value が 0 であれば None を返す。
+    pub(crate) fn new(value: usize) -> Option<NodeIndex> {
+        NonZero::new(store_index(value)).map(NodeIndex)
+    }
+
+    #[inline]
+    pub(crate) fn get(self) -> usize {
+        load_index(self.0.get())
+    }
 }
 
 #[derive(Debug)]
-struct Node<T> {
-    item: T,
-    left_index: Option<NonZeroUsize>,
-    right_index: Option<NonZeroUsize>,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Entry<T> {
+    pub(crate) item: T,
+    /// construct() に渡された時点での要素の位置。 insert() された要素には続きの位置が割り当てられる。
+    index: StoredIndex,
+
+    /// remove() された要素や、部分木の再構築で置き換えられた要素は true になり、探索結果から除外される。
+    pub(crate) removed: bool,
 }
 
 #[derive(Debug)]
-struct NeighborCandidate<'a, T: KdTreeItem>(&'a T, T::Measurement);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Node<T> {
+    /// 分割面を与える要素。葉ノードでは bucket と同様に扱われる。
+    pub(crate) entry: Entry<T>,
+    pub(crate) left_index: Option<NodeIndex>,
+    pub(crate) right_index: Option<NodeIndex>,
+
+    /// 葉ノードが entry のほかに持つ要素。内部ノードでは常に空になる。
+    pub(crate) bucket: Vec<Entry<T>>,
+}
+
+impl<T> Entry<T> {
+    pub(crate) fn new(item: T, index: usize) -> Entry<T> {
+        Entry {
+            item,
+            index: store_index(index),
+            removed: false,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn index(&self) -> usize {
+        load_index(self.index)
+    }
+}
+
+impl<T> Node<T> {
+    pub(crate) fn is_leaf(&self) -> bool {
+        self.left_index.is_none() && self.right_index.is_none()
+    }
 
-impl<T: KdTreeItem> PartialEq for NeighborCandidate<'_, T> {
+    pub(crate) fn entries(&self) -> impl Iterator<Item = &Entry<T>> + '_ {
+        core::iter::once(&self.entry).chain(&self.bucket)
+    }
+
+    /// entries() の position 番目の要素を返す。
+    fn entry_at(&self, position: usize) -> &Entry<T> {
+        match position {
+            0 => &self.entry,
+            _ => &self.bucket[position - 1],
+        }
+    }
+
+    fn entries_mut(&mut self) -> impl Iterator<Item = &mut Entry<T>> + '_ {
+        core::iter::once(&mut self.entry).chain(&mut self.bucket)
+    }
+}
+
+#[derive(Debug)]
+struct NeighborCandidate<'a, T, D>(&'a Entry<T>, D);
+
+impl<T, D: PartialOrd> PartialEq for NeighborCandidate<'_, T, D> {
     fn eq(&self, other: &Self) -> bool {
         self.1 == other.1
     }
 }
 
-impl<T: KdTreeItem> Eq for NeighborCandidate<'_, T> {}
+impl<T, D: PartialOrd> Eq for NeighborCandidate<'_, T, D> {}
 
-impl<T: KdTreeItem> PartialOrd for NeighborCandidate<'_, T> {
+impl<T, D: PartialOrd> PartialOrd for NeighborCandidate<'_, T, D> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<T: KdTreeItem> Ord for NeighborCandidate<'_, T> {
+impl<T, D: PartialOrd> Ord for NeighborCandidate<'_, T, D> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.1.partial_cmp(&other.1).expect("not total order")
     }
 }
 
 impl<T: KdTreeItem> KdTree<T> {
-    pub fn construct(items: impl Into<Vec<T>>) -> KdTree<T> {
-        let mut items: Vec<_> = items.into();
-        let mut nodes = Vec::with_capacity(items.len());
+    /// items から k-d tree を構築する。座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+    pub fn construct(items: impl Into<Vec<T>>) -> Result<KdTree<T>, Error> {
+        let items = items.into();
+        validate_items(&items)?;
+        Ok(KdTree::construct_unchecked(items))
+    }
+
+    /// construct() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+    pub fn construct_unchecked(items: impl Into<Vec<T>>) -> KdTree<T> {
+        KdTree::construct_with_metric(items, ItemMetric)
+    }
+
+    /// construct() と同様だが、 items を反復して要素を受け取る。ファイルや生成器から読み出す要素を、
+    /// 呼び出し側で Vec に集めずにそのまま渡せる。確保する大きさには items の size_hint() の下限を用いる。
+    /// 座標に NaN や無限大を含む要素があれば、すべての要素を読み終えてから Error::NonFiniteInput を返す。
+    pub fn construct_from_iter(items: impl IntoIterator<Item = T>) -> Result<KdTree<T>, Error> {
+        let mut non_finite = Vec::new();
+        let items = items.into_iter().enumerate().inspect(|(i, item)| {
+            if !item.is_finite() {
+                non_finite.push(*i);
+            }
+        });
+        let items = collect_indexed(items, None);
+        if !non_finite.is_empty() {
+            return Err(Error::NonFiniteInput { indices: non_finite });
+        }
+        Ok(KdTree::from_indexed(items, ItemMetric, KdTreeOptions::default(), None).expect(UNCANCELLED))
+    }
+
+    /// construct() の並列版。左右の部分木の構築を rayon で並列に行う。
+    /// 得られるツリーの構造は construct() と同一になる。
+    #[cfg(feature = "parallel")]
+    pub fn construct_par(items: impl Into<Vec<T>>) -> KdTree<T>
+    where
+        T: Send,
+    {
+        KdTree::construct_par_with_metric(items, ItemMetric)
+    }
+}
+
+impl<T: KdTreeItem, M: Metric<T>> KdTree<T, M> {
+    /// 距離の計算に metric を用いる k-d tree を構築する。
+    pub fn construct_with_metric(items: impl Into<Vec<T>>, metric: M) -> KdTree<T, M> {
+        KdTree::construct_with_options(items, metric, KdTreeOptions::default())
+    }
+
+    /// construct_with_metric() の並列版。
+    #[cfg(feature = "parallel")]
+    pub fn construct_par_with_metric(items: impl Into<Vec<T>>, metric: M) -> KdTree<T, M>
+    where
+        T: Send,
+    {
+        KdTree::construct_par_with_options(items, metric, KdTreeOptions::default())
+    }
+
+    /// construct_from_iter() と同様だが、距離の計算に metric を用いる。入力は検証しない。
+    /// size_hint には要素数の見込みを渡せる。 None であれば items の size_hint() の下限を用いる。
+    pub fn construct_from_iter_with_metric(
+        items: impl IntoIterator<Item = T>,
+        metric: M,
+        size_hint: Option<usize>,
+    ) -> KdTree<T, M> {
+        let items = collect_indexed(items.into_iter().enumerate(), size_hint);
+        KdTree::from_indexed(items, metric, KdTreeOptions::default(), None).expect(UNCANCELLED)
+    }
+
+    /// options の設定で k-d tree を構築する。
+    pub fn construct_with_options(items: impl Into<Vec<T>>, metric: M, options: KdTreeOptions) -> KdTree<T, M> {
+        KdTree::construct_cancellable(items, metric, options, None).expect(UNCANCELLED)
+    }
+
+    /// construct_with_options() と同様だが、部分木を分割するたびに cancellation を確認し、
+    /// 中断が要求されていれば構築を打ち切って Err(Cancelled) を返す。
+    pub fn try_construct_with_options(
+        items: impl Into<Vec<T>>,
+        metric: M,
+        options: KdTreeOptions,
+        cancellation: &CancellationToken,
+    ) -> Result<KdTree<T, M>, Cancelled> {
+        KdTree::construct_cancellable(items, metric, options, Some(cancellation))
+    }
+
+    fn construct_cancellable(
+        items: impl Into<Vec<T>>,
+        metric: M,
+        options: KdTreeOptions,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<KdTree<T, M>, Cancelled> {
+        let items = items.into().into_iter().enumerate().collect();
+        KdTree::from_indexed(items, metric, options, cancellation)
+    }
+
+    /// (元の位置, 要素) の列から k-d tree を構築する。
+    fn from_indexed(
+        mut items: Vec<(usize, T)>,
+        metric: M,
+        options: KdTreeOptions,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<KdTree<T, M>, Cancelled> {
+        let bucket_size = options.bucket_size.max(1);
+        let mut nodes = Vec::with_capacity(node_count(items.len(), bucket_size));
+
+        let root_index = construct_part_cancellable(&mut nodes, &mut items, 0, bucket_size, cancellation)?;
+
+        Ok(KdTree {
+            nodes,
+            root_index,
+            metric,
+            bucket_size,
+            len: items.len(),
+            removed_count: 0,
+            next_index: items.len(),
+        })
+    }
+
+    /// construct_with_options() の並列版。
+    #[cfg(feature = "parallel")]
+    pub fn construct_par_with_options(items: impl Into<Vec<T>>, metric: M, options: KdTreeOptions) -> KdTree<T, M>
+    where
+        T: Send,
+    {
+        let bucket_size = options.bucket_size.max(1);
+        let mut items: Vec<_> = items.into().into_iter().enumerate().collect();
+        let mut slots: Vec<Option<Node<T>>> = Vec::new();
+        slots.resize_with(node_count(items.len(), bucket_size), || None);
+
+        let root_index = construct_part_par(&mut slots, 0, &mut items, 0, bucket_size);
+        let nodes = slots
+            .into_iter()
+            .map(|n| n.expect("all slots must be filled"))
+            .collect::<Vec<_>>();
+
+        KdTree {
+            nodes,
+            root_index,
+            metric,
+            bucket_size,
+            len: items.len(),
+            removed_count: 0,
+            next_index: items.len(),
+        }
+    }
+
+    /// 距離の計算に用いられる Metric を返す。
+    pub fn metric(&self) -> &M {
+        &self.metric
+    }
+
+    /// 要素を挿入し、割り当てられた位置を返す。
+    /// 座標に NaN や無限大を含む要素は Error::NonFiniteInput で、既存の要素と座標の数が異なる要素は
+    /// Error::DimensionMismatch で拒み、木を変更しない。いずれもエラーには挿入した場合に割り当てられた位置が入る。
+    pub fn try_insert(&mut self, item: T) -> Result<usize, Error> {
+        let index = self.next_index;
+        let existing = self.root_index.map(|root| &self.nodes[root.get() - 1].entry.item);
+        if let (Some(expected), Some(found)) = (existing.and_then(T::runtime_dimensions), item.runtime_dimensions()) {
+            if found != expected {
+                return Err(Error::DimensionMismatch { index, expected, found });
+            }
+        }
+        if !item.is_finite() {
+            return Err(Error::NonFiniteInput { indices: vec![index] });
+        }
+        Ok(self.insert(item))
+    }
+
+    /// try_insert() と同様だが、入力を検証しない。 NaN を含む要素を挿入すると、その後の探索や再構築で panic する。
+    /// 要素は葉ノードのバケットに追加され、あふれた葉は分割される。
+    /// 挿入によって深さが偏った部分木は scapegoat tree の要領で再構築される。
+    pub fn insert(&mut self, item: T) -> usize {
+        let index = self.next_index;
+        self.next_index += 1;
+        self.len += 1;
+        let entry = Entry::new(item, index);
+
+        // 葉ノードか空いている子の位置に着くまで、経路を記録しながら降りる
+        let mut path = Vec::new();
+        let mut current = self.root_index;
+        let mut went_left = false;
+        while let Some(node_index) = current {
+            path.push(node_index);
+            let node = &self.nodes[node_index.get() - 1];
+            if node.is_leaf() {
+                break;
+            }
+            // 等しい要素が片側に偏って木が深くならないよう、等しい場合は要素の番号で左右に振り分ける
+            went_left = match entry.item.cmp_in_depth(&node.entry.item, path.len() - 1) {
+                Ordering::Less => true,
+                Ordering::Equal => index.is_multiple_of(2),
+                Ordering::Greater => false,
+            };
+            current = if went_left { node.left_index } else { node.right_index };
+        }
+
+        match current {
+            Some(leaf_index) => {
+                let leaf = &mut self.nodes[leaf_index.get() - 1];
+                leaf.bucket.push(entry);
+                if leaf.entries().filter(|e| !e.removed).count() > self.bucket_size {
+                    let new_root = self.rebuild_subtree(leaf_index, path.len() - 1);
+                    self.replace_subtree(&path, new_root);
+                    let path_end = path.len() - 1;
+                    path[path_end] = new_root.expect("split leaf must not be empty");
+                }
+            }
+            None => {
+                let new_index = allocate_node(
+                    &mut self.nodes,
+                    Node {
+                        entry,
+                        left_index: None,
+                        right_index: None,
+                        bucket: Vec::new(),
+                    },
+                );
+                match path.last() {
+                    Some(&parent) if went_left => self.nodes[parent.get() - 1].left_index = Some(new_index),
+                    Some(&parent) => self.nodes[parent.get() - 1].right_index = Some(new_index),
+                    None => self.root_index = Some(new_index),
+                }
+                path.push(new_index);
+            }
+        }
+
+        let max_depth = (self.nodes.len() as f64).ln() / (1.0 / SCAPEGOAT_ALPHA).ln();
+        if (path.len() - 1) as f64 > max_depth {
+            self.rebuild_scapegoat(&path);
+        }
+
+        // 葉の分割や再構築で置き換えられた要素が増えすぎたら詰め直す
+        if self.removed_count > self.len + COMPACTION_SLACK {
+            self.rebuild();
+        }
+
+        index
+    }
+
+    /// item と等しい要素を 1 つ削除し、その位置を返す。該当する要素がなければ None を返す。
+    pub fn remove(&mut self, item: &T) -> Option<usize>
+    where
+        T: PartialEq,
+    {
+        self.remove_matching(item, |entry_item, _| entry_item == item)
+    }
+
+    /// item の位置にあり、 matches を満たす要素を 1 つ削除してその位置を返す。
+    pub(crate) fn remove_matching(&mut self, item: &T, matches: impl Fn(&T, usize) -> bool) -> Option<usize> {
+        // cmp_in_depth() が Equal の要素は構築時にどちらの部分木にも入りうるので両側を探す
+        let mut stack = vec![(self.root_index, 0)];
+        let mut found = None;
+        while let Some((node_index, depth)) = stack.pop() {
+            let Some(node_index) = node_index else {
+                continue;
+            };
+            let node = &self.nodes[node_index.get() - 1];
+            if let Some(position) = node.entries().position(|e| !e.removed && matches(&e.item, e.index())) {
+                found = Some((node_index, position));
+                break;
+            }
+            match item.cmp_in_depth(&node.entry.item, depth) {
+                Ordering::Less => stack.push((node.left_index, depth + 1)),
+                Ordering::Greater => stack.push((node.right_index, depth + 1)),
+                Ordering::Equal => stack.extend([(node.left_index, depth + 1), (node.right_index, depth + 1)]),
+            }
+        }
+
+        let (node_index, position) = found?;
+        let entry = self.nodes[node_index.get() - 1]
+            .entries_mut()
+            .nth(position)
+            .expect("found entry must exist");
+        entry.removed = true;
+        let index = entry.index();
+        self.len -= 1;
+        self.removed_count += 1;
+
+        // 削除済みの要素が半数を超えたら詰め直す
+        if self.removed_count > self.len + COMPACTION_SLACK {
+            self.rebuild();
+        }
+
+        Some(index)
+    }
+
+    /// ツリー全体を生きている要素だけで構築し直し、偏りと削除済みの要素をなくす。要素の位置は保たれる。
+    /// insert() と remove() も偏りが大きくなれば部分的に構築し直すが、長く使い続けるツリーは
+    /// needs_rebalance() を確かめて呼ぶとよい。
+    pub fn rebalance(&mut self) {
+        self.rebuild();
+    }
+
+    /// 根から最も深いノードまでのノードの数を返す。要素がなければ 0 を返す。
+    pub fn depth(&self) -> usize {
+        self.stats().depth
+    }
 
-        let root_index = construct_part(&mut nodes, &mut items, 0);
+    /// rebalance() で探索が速くなりそうであれば true を返す。
+    /// 深さが同じ要素数で構築し直した場合の REBALANCE_DEPTH_RATIO 倍を超えるか、
+    /// 削除済みの要素が生きている要素より多い場合がこれにあたる。
+    pub fn needs_rebalance(&self) -> bool {
+        self.depth() > REBALANCE_DEPTH_RATIO * self.balanced_depth() || self.removed_count > self.len
+    }
+
+    /// ツリーの深さやノード数などの統計を返す。ノードをすべて辿るため要素数に比例した時間がかかる。
+    pub fn stats(&self) -> KdTreeStats {
+        let (mut node_count, mut leaf_count, mut depth) = (0, 0, 0);
+        let mut stack = vec![(self.root_index, 1)];
+        while let Some((node_index, node_depth)) = stack.pop() {
+            let Some(node) = self.get_node(node_index) else {
+                continue;
+            };
+            node_count += 1;
+            if node.is_leaf() {
+                leaf_count += 1;
+            }
+            depth = depth.max(node_depth);
+            stack.extend([(node.left_index, node_depth + 1), (node.right_index, node_depth + 1)]);
+        }
+
+        let balanced_depth = self.balanced_depth();
+        let balance_factor = match balanced_depth {
+            0 => 1.0,
+            _ => depth as f64 / balanced_depth as f64,
+        };
+        let buckets: usize = self.nodes.iter().map(|n| n.bucket.capacity()).sum();
+        let memory_bytes =
+            self.nodes.capacity() * core::mem::size_of::<Node<T>>() + buckets * core::mem::size_of::<Entry<T>>();
 
-        KdTree { nodes, root_index }
+        KdTreeStats {
+            len: self.len,
+            removed: self.removed_count,
+            node_count,
+            leaf_count,
+            depth,
+            balanced_depth,
+            balance_factor,
+            memory_bytes,
+        }
+    }
+
+    /// ツリーの構造を、根から深さ優先で 1 行に 1 ノードずつ out に書き出す。
+    /// 各行は深さに応じて字下げされ、分割に用いた軸の番号 (深さ) と要素、葉であればバケット内の要素を含む。
+    /// 削除済みの要素には `(removed)` が付く。デバッグ用で、書式は変わりうる。
+    pub fn dump(&self, out: &mut impl core::fmt::Write) -> core::fmt::Result {
+        let mut stack = vec![(self.root_index, 0)];
+        while let Some((node_index, depth)) = stack.pop() {
+            let Some(node) = self.get_node(node_index) else {
+                continue;
+            };
+            write!(out, "{:indent$}", "", indent = 2 * depth)?;
+            if node.is_leaf() {
+                write!(out, "leaf:")?;
+                for entry in node.entries() {
+                    write!(out, " ")?;
+                    write_entry(out, entry)?;
+                }
+            } else {
+                write!(out, "split at depth {depth}: ")?;
+                write_entry(out, &node.entry)?;
+            }
+            writeln!(out)?;
+            stack.extend([(node.right_index, depth + 1), (node.left_index, depth + 1)]);
+        }
+        Ok(())
+    }
+
+    /// 生きている要素から構築し直した場合の深さを返す。
+    fn balanced_depth(&self) -> usize {
+        (usize::BITS - node_count(self.len, self.bucket_size).leading_zeros()) as usize
+    }
+
+    /// 削除されていない要素の数を返す。
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 削除されていない要素を順不同で返す。
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.live_entries().map(|e| &e.item)
+    }
+
+    /// iter() と同様だが、各要素の位置も返す。
+    pub fn iter_with_indices(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.live_entries().map(|e| (e.index(), &e.item))
+    }
+
+    /// 削除されていない要素を中間順 (左部分木, 自身, 右部分木) で返す。
+    /// 各部分木について、分割面より手前 (左) の要素がすべて先に現れる。
+    /// 葉ノードのバケット内の要素は順不同で現れるため、 1 次元の要素であっても全体が整列するとは限らない。
+    pub fn iter_in_order(&self) -> impl Iterator<Item = &T> + '_ {
+        let mut stack = Vec::new();
+        let mut current = self.get_node(self.root_index);
+        let nodes = core::iter::from_fn(move || {
+            while let Some(node) = current {
+                stack.push(node);
+                current = self.get_node(node.left_index);
+            }
+
+            let node = stack.pop()?;
+            current = self.get_node(node.right_index);
+            Some(node)
+        });
+        nodes.flat_map(|n| n.entries()).filter(|e| !e.removed).map(|e| &e.item)
     }
 
     pub fn root(&self) -> Option<&T> {
-        self.get_node(self.root_index).map(|n| &n.item)
+        self.get_node(self.root_index).map(|n| &n.entry.item)
     }
 
-    pub fn find_nearest<'a>(&'a self, query: &'a T) -> Option<&'a T> {
-        self.find_nearest_n(query, 1).into_iter().next()
+    /// query に最も近い要素を返す。 query の座標に NaN や無限大があれば Error::NonFiniteQuery を返す。
+    pub fn find_nearest<'a>(&'a self, query: &T) -> Result<Option<&'a T>, Error> {
+        check_query(query)?;
+        Ok(self.find_nearest_unchecked(query))
     }
 
-    pub fn find_nearest_n<'a>(&'a self, query: &'a T, max_count: usize) -> Vec<&'a T> {
-        let mut candidates = BinaryHeap::with_capacity(max_count);
-        self.find_nearest_n_depth(&mut candidates, max_count, self.get_node(self.root_index), query, 0);
-        candidates.into_sorted_vec().into_iter().map(|c| c.0).collect()
+    /// find_nearest() と同様だが、 query を検証しない。
+    pub fn find_nearest_unchecked<'a>(&'a self, query: &T) -> Option<&'a T> {
+        self.find_nearest_n_unchecked(query, 1).into_iter().next()
     }
 
-    pub fn find_range_n<'a>(&'a self, query: &'a T, range: &T::Measurement) -> Vec<&'a T> {
-        let mut candidates = Vec::new();
-        self.find_range_n_depth(&mut candidates, self.get_node(self.root_index), query, range, 0);
-        candidates.into_iter().map(|c| c.0).collect()
+    /// query に近い順に最大 max_count 個の要素を返す。 query の座標に NaN や無限大があれば Error::NonFiniteQuery を返す。
+    pub fn find_nearest_n<'a>(&'a self, query: &T, max_count: usize) -> Result<Vec<&'a T>, Error> {
+        check_query(query)?;
+        Ok(self.find_nearest_n_unchecked(query, max_count))
     }
 
-    fn find_nearest_n_depth<'a>(
+    /// find_nearest_n() と同様だが、 query を検証しない。
+    pub fn find_nearest_n_unchecked<'a>(&'a self, query: &T, max_count: usize) -> Vec<&'a T> {
+        let candidates = self.collect_nearest_n(query, max_count);
+        candidates.into_sorted_vec().into_iter().map(|c| &c.0.item).collect()
+    }
+
+    /// find_nearest_n() と同様だが、 query からの距離も返す。近い順に並ぶ。
+    pub fn find_nearest_n_with_distances<'a>(&'a self, query: &T, max_count: usize) -> Vec<(&'a T, M::Measurement)> {
+        let candidates = self.collect_nearest_n(query, max_count);
+        candidates
+            .into_sorted_vec()
+            .into_iter()
+            .map(|c| (&c.0.item, self.metric.reduced_to_distance(&c.1)))
+            .collect()
+    }
+
+    /// find_nearest_n_with_distances() と同様だが、要素の代わりに construct() に渡された時点での位置を返す。
+    pub fn find_nearest_n_indices(&self, query: &T, max_count: usize) -> Vec<(usize, M::Measurement)> {
+        let candidates = self.collect_nearest_n(query, max_count);
+        candidates
+            .into_sorted_vec()
+            .into_iter()
+            .map(|c| (c.0.index(), self.metric.reduced_to_distance(&c.1)))
+            .collect()
+    }
+
+    /// filter が true を返す要素だけを対象に、 query に近い順に最大 max_count 個の要素とその距離を返す。
+    /// 条件を満たさない要素は候補にならないため、結果を取り出してから捨てるよりも探索が少なく済む。
+    /// query は検証しない。
+    pub fn find_nearest_n_filtered<'a>(
         &'a self,
-        candidates: &mut BinaryHeap<NeighborCandidate<'a, T>>,
-        max_candidates: usize,
-        root: Option<&'a Node<T>>,
-        query: &'a T,
-        depth: usize,
-    ) {
-        let Some(root) = root else {
-            return;
-        };
+        query: &T,
+        max_count: usize,
+        filter: impl Fn(&T) -> bool,
+    ) -> Vec<(&'a T, M::Measurement)> {
+        let candidates = self.collect_nearest_n_where(query, max_count, |entry| filter(&entry.item));
+        candidates
+            .into_sorted_vec()
+            .into_iter()
+            .map(|c| (&c.0.item, self.metric.reduced_to_distance(&c.1)))
+            .collect()
+    }
+
+    /// find_nearest_n_indices() と同様だが、 construct() に渡された時点で self_index の位置にあった要素を除く。
+    /// ツリーに含まれる要素を query として、自身を除いた近傍を求めるのに用いる。
+    /// 自身と座標が重なる他の要素は除かない。
+    pub fn find_nearest_n_excluding_self(
+        &self,
+        query: &T,
+        self_index: usize,
+        max_count: usize,
+    ) -> Vec<(usize, M::Measurement)> {
+        let candidates = self.collect_nearest_n_where(query, max_count, |entry| entry.index() != self_index);
+        candidates
+            .into_sorted_vec()
+            .into_iter()
+            .map(|c| (c.0.index(), self.metric.reduced_to_distance(&c.1)))
+            .collect()
+    }
+
+    /// 近似的な k 近傍探索。分割面の反対側は、現在の候補の最遠距離を (1 + epsilon) で割った範囲を跨ぐときだけ探索する。
+    /// このため返される i 番目の要素の距離は、真の i 番目の距離の (1 + epsilon) 倍以内に収まる。
+    /// max_visits を指定すると訪問するノード数をその数までに制限し、この場合は上記の保証は失われる。
+    pub fn find_nearest_approx<'a>(
+        &'a self,
+        query: &T,
+        k: usize,
+        epsilon: M::Measurement,
+        max_visits: Option<usize>,
+    ) -> Vec<(&'a T, M::Measurement)>
+    where
+        M::Measurement: Float,
+    {
+        let scale = M::Measurement::one() + epsilon;
+        let crosses =
+            |axis: &_, max: &_| self.metric.reduced_to_distance(axis) * scale < self.metric.reduced_to_distance(max);
+        let candidates = self.search_nearest_n(query, k, max_visits.unwrap_or(usize::MAX), crosses, |_| true);
+        candidates
+            .into_sorted_vec()
+            .into_iter()
+            .map(|c| (&c.0.item, self.metric.reduced_to_distance(&c.1)))
+            .collect()
+    }
+
+    /// queries のそれぞれについて、近い順に k 個の要素とその距離を返す。
+    pub fn knn<'a>(&'a self, queries: &[T], k: usize) -> Vec<Vec<(&'a T, M::Measurement)>> {
+        queries
+            .iter()
+            .map(|query| self.find_nearest_n_with_distances(query, k))
+            .collect()
+    }
+
+    /// knn() の並列版。
+    #[cfg(feature = "parallel")]
+    pub fn knn_par<'a>(&'a self, queries: &[T], k: usize) -> Vec<Vec<(&'a T, M::Measurement)>>
+    where
+        T: Sync,
+        M: Sync,
+        M::Measurement: Send,
+    {
+        use rayon::prelude::*;
 
-        // root が candidates に入るなら入れる
-        let root_distance = query.distance(&root.item);
-        if candidates.len() < max_candidates {
-            candidates.push(NeighborCandidate(&root.item, root_distance));
-        } else if root_distance < candidates.peek().expect("must exist").1 {
-            candidates.pop();
-            candidates.push(NeighborCandidate(&root.item, root_distance));
+        queries
+            .par_iter()
+            .map(|query| self.find_nearest_n_with_distances(query, k))
+            .collect()
+    }
+
+    /// knn() と同様だが、一定の数の query を処理するごとに cancellation を確認する。
+    /// 中断された場合は Err(Cancelled) を返し、 partial には先頭から処理を終えた query の結果が入る。
+    pub fn knn_cancellable<'a>(
+        &'a self,
+        queries: &[T],
+        k: usize,
+        cancellation: &CancellationToken,
+    ) -> CancellableKnn<'a, T, M::Measurement> {
+        let mut results = Vec::with_capacity(queries.len());
+        for chunk in queries.chunks(CANCELLATION_CHECK_INTERVAL) {
+            if cancellation.is_cancelled() {
+                return Err(Cancelled { partial: results });
+            }
+            results.extend(chunk.iter().map(|query| self.find_nearest_n_with_distances(query, k)));
         }
+        Ok(results)
+    }
 
-        let (left_subtree, right_subtree) = (self.get_node(root.left_index), self.get_node(root.right_index));
-        let (first_subtree, second_subtree) = match query.cmp_in_depth(&root.item, depth) {
-            Ordering::Less => (left_subtree, right_subtree),
-            Ordering::Equal | Ordering::Greater => (right_subtree, left_subtree),
-        };
+    /// knn_cancellable() の並列版。 partial の内容は knn_cancellable() と同様に先頭からの連続した結果になる。
+    #[cfg(feature = "parallel")]
+    pub fn knn_par_cancellable<'a>(
+        &'a self,
+        queries: &[T],
+        k: usize,
+        cancellation: &CancellationToken,
+    ) -> CancellableKnn<'a, T, M::Measurement>
+    where
+        T: Sync,
+        M: Sync,
+        M::Measurement: Send,
+    {
+        use rayon::prelude::*;
+
+        // スレッド数ぶんの区間をまとめて並列に処理し、その間に確認する
+        let chunk_size = CANCELLATION_CHECK_INTERVAL * rayon::current_num_threads();
+        let mut results = Vec::with_capacity(queries.len());
+        for chunk in queries.chunks(chunk_size) {
+            if cancellation.is_cancelled() {
+                return Err(Cancelled { partial: results });
+            }
+            results.par_extend(
+                chunk
+                    .par_iter()
+                    .map(|query| self.find_nearest_n_with_distances(query, k)),
+            );
+        }
+        Ok(results)
+    }
 
-        // query が属する sub-tree の探索
-        self.find_nearest_n_depth(candidates, max_candidates, first_subtree, query, depth + 1);
+    /// query から radius 以内 (境界を含む) にある要素をすべて返す。順序は不定。
+    /// query の座標に NaN や無限大があれば Error::NonFiniteQuery を、 radius が NaN であれば Error::InvalidRadius を返す。
+    pub fn find_range_n<'a>(&'a self, query: &T, radius: &M::Measurement) -> Result<Vec<&'a T>, Error> {
+        check_query(query)?;
+        check_radius(radius)?;
+        Ok(self.find_range_n_unchecked(query, radius))
+    }
 
-        if candidates.len() < max_candidates {
-            // max_candidate に達してない場合は無条件で逆側も探索
-            self.find_nearest_n_depth(candidates, max_candidates, second_subtree, query, depth + 1);
-        } else {
-            let axis_distance = query.distance_to_axis(&root.item, depth);
-            let max_candidate_distance = &candidates.peek().expect("must exist").1;
-            // candidate の最遠半径が現在の分割面を跨いでいれば逆側も探索
-            if axis_distance < *max_candidate_distance {
-                self.find_nearest_n_depth(candidates, max_candidates, second_subtree, query, depth + 1);
+    /// find_range_n() と同様だが、 query と radius を検証しない。
+    pub fn find_range_n_unchecked<'a>(&'a self, query: &T, radius: &M::Measurement) -> Vec<&'a T> {
+        let mut found = Vec::new();
+        self.search_range(query, radius, |entry, _| found.push(&entry.item));
+        found
+    }
+
+    /// find_range_n_unchecked() と同様だが、 filter が true を返す要素だけを返す。
+    pub fn find_range_n_filtered<'a>(
+        &'a self,
+        query: &T,
+        radius: &M::Measurement,
+        filter: impl Fn(&T) -> bool,
+    ) -> Vec<&'a T> {
+        let mut found = Vec::new();
+        self.search_range(query, radius, |entry, _| {
+            if filter(&entry.item) {
+                found.push(&entry.item);
+            }
+        });
+        found
+    }
+
+    /// find_range_n() と同様だが、 query からの距離も返す。
+    pub fn find_range_n_with_distances<'a>(
+        &'a self,
+        query: &T,
+        radius: &M::Measurement,
+    ) -> Vec<(&'a T, M::Measurement)> {
+        let mut found = Vec::new();
+        self.search_range(query, radius, |entry, distance| {
+            found.push((&entry.item, self.metric.reduced_to_distance(&distance)))
+        });
+        found
+    }
+
+    /// find_range_n() と同様だが、要素の代わりに construct() に渡された時点での位置を返す。
+    pub fn find_range_n_indices(&self, query: &T, radius: &M::Measurement) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.find_range_into(query, radius, &mut found);
+        found
+    }
+
+    /// find_range_n_indices() と同様だが、結果を found に書き込む。 found は先に空にされる。
+    /// 同じ found を使い回すことで、繰り返し探索する場合の確保を省ける。
+    pub fn find_range_into(&self, query: &T, radius: &M::Measurement, found: &mut Vec<usize>) {
+        found.clear();
+        self.search_range(query, radius, |entry, _| found.push(entry.index()));
+    }
+
+    /// query から radius 以内 (境界を含む) にある要素の数を返す。結果の Vec は作らない。
+    /// query と radius は検証しない。
+    pub fn count_within(&self, query: &T, radius: &M::Measurement) -> usize {
+        self.count_within_limited(query, radius, usize::MAX)
+    }
+
+    /// count_within() と同様だが、 limit 個を数えた時点で探索を打ち切り limit を返す。
+    /// DBSCAN のコア判定のように、一定数以上あるかだけを知りたい場合に用いる。
+    pub fn count_within_limited(&self, query: &T, radius: &M::Measurement, limit: usize) -> usize {
+        count_until(limit, |count| self.search_range_while(query, radius, |_, _| count()))
+    }
+
+    /// query から radius 以内 (境界を含む) に要素があれば true を返す。最初の 1 つが見つかった時点で探索を打ち切る。
+    /// query と radius は検証しない。
+    pub fn any_within(&self, query: &T, radius: &M::Measurement) -> bool {
+        self.count_within_limited(query, radius, 1) > 0
+    }
+
+    /// queries のそれぞれについて、 radius 以内にある要素の位置を find_range_n_indices() と同様に返す。
+    /// 空間的に近い query から順に探索するため、 query ごとに呼ぶよりも同じノードを続けて辿りやすい。
+    /// query と radius は検証しない。
+    pub fn find_range_batch(&self, queries: &[T], radius: &M::Measurement) -> Vec<Vec<usize>> {
+        let mut results = vec![Vec::new(); queries.len()];
+        for i in spatial_order(queries) {
+            let i = i as usize;
+            self.find_range_into(&queries[i], radius, &mut results[i]);
+        }
+        results
+    }
+
+    /// find_range_batch() の並列版。
+    #[cfg(feature = "parallel")]
+    pub fn find_range_batch_par(&self, queries: &[T], radius: &M::Measurement) -> Vec<Vec<usize>>
+    where
+        T: Sync,
+        M: Sync,
+        M::Measurement: Sync,
+    {
+        use rayon::prelude::*;
+
+        // 並べ替えた順のまま連続した区間を各スレッドに割り当て、結果を元の位置に戻す
+        let order = spatial_order(queries);
+        let found: Vec<_> = order
+            .par_iter()
+            .map(|&i| self.find_range_n_indices(&queries[i as usize], radius))
+            .collect();
+        let mut results = vec![Vec::new(); queries.len()];
+        for (&i, found) in order.iter().zip(found) {
+            results[i as usize] = found;
+        }
+        results
+    }
+
+    /// query から radius 以内にある要素の位置を、 Metric::reduced_distance() の尺度の距離とともに found に渡す。
+    pub(crate) fn search_range_indices(
+        &self,
+        query: &T,
+        radius: &M::Measurement,
+        mut found: impl FnMut(usize, M::Measurement),
+    ) {
+        self.search_range(query, radius, |entry, distance| found(entry.index(), distance));
+    }
+
+    /// query に近い順に最大 max_candidates 個の要素を集める。
+    /// crosses(分割面までの距離, 候補の最遠距離) が true のとき分割面の反対側も探索する。
+    /// 候補の距離も crosses() に渡される距離も Metric::reduced_distance() の尺度になる。
+    /// 訪問したノード数が max_visits に達すると探索を打ち切る。 accept が false を返す要素は候補にしない。
+    fn search_nearest_n<'a>(
+        &'a self,
+        query: &T,
+        max_candidates: usize,
+        max_visits: usize,
+        crosses: impl Fn(&M::Measurement, &M::Measurement) -> bool,
+        accept: impl Fn(&Entry<T>) -> bool,
+    ) -> BinaryHeap<NeighborCandidate<'a, T, M::Measurement>> {
+        let mut candidates = BinaryHeap::with_capacity(max_candidates);
+        if max_candidates == 0 {
+            return candidates;
+        }
+
+        // (ノード, 深さ, 分割面を跨ぐかどうかを判定する親ノード) を積む。
+        // 逆側の部分木は query が属する部分木の探索が終わってから判定されるよう、先に積んでおく
+        let mut stack: Vec<(_, _, Option<&Node<T>>)> = vec![(self.get_node(self.root_index), 0, None)];
+        let mut visits = 0;
+        while let Some((node, depth, split)) = stack.pop() {
+            let Some(node) = node else {
+                continue;
+            };
+            if let Some(split) = split {
+                // max_candidate に達してない場合は無条件で逆側も探索し、
+                // 達していれば candidate の最遠半径が親の分割面を跨ぐときだけ探索する
+                if candidates.len() >= max_candidates {
+                    let axis_distance = self
+                        .metric
+                        .reduced_distance_to_axis(query, &split.entry.item, depth - 1);
+                    let max_candidate_distance = &candidates.peek().expect("must exist").1;
+                    if !crosses(&axis_distance, max_candidate_distance) {
+                        continue;
+                    }
+                }
+            }
+            if visits == max_visits {
+                break;
             }
+            visits += 1;
+
+            // node の要素 (葉であればバケット内のすべての要素) が candidates に入るなら入れる
+            for entry in node.entries().filter(|e| !e.removed && accept(e)) {
+                let distance = self.metric.reduced_distance(query, &entry.item);
+                if candidates.len() < max_candidates {
+                    candidates.push(NeighborCandidate(entry, distance));
+                } else if distance < candidates.peek().expect("must exist").1 {
+                    candidates.pop();
+                    candidates.push(NeighborCandidate(entry, distance));
+                }
+            }
+            if node.is_leaf() {
+                continue;
+            }
+
+            let (first_subtree, second_subtree) = self.split_subtrees(node, query, depth);
+            stack.push((second_subtree, depth + 1, Some(node)));
+            stack.push((first_subtree, depth + 1, None));
         }
+
+        candidates
     }
 
-    fn find_range_n_depth<'a>(
+    /// 厳密な k 近傍探索で候補を集める。
+    fn collect_nearest_n<'a>(
         &'a self,
-        candidates: &mut Vec<NeighborCandidate<'a, T>>,
-        root: Option<&'a Node<T>>,
-        query: &'a T,
-        range: &T::Measurement,
-        depth: usize,
+        query: &T,
+        max_count: usize,
+    ) -> BinaryHeap<NeighborCandidate<'a, T, M::Measurement>> {
+        self.collect_nearest_n_where(query, max_count, |_| true)
+    }
+
+    /// collect_nearest_n() と同様だが、 accept が false を返す要素は候補にしない。
+    fn collect_nearest_n_where<'a>(
+        &'a self,
+        query: &T,
+        max_count: usize,
+        accept: impl Fn(&Entry<T>) -> bool,
+    ) -> BinaryHeap<NeighborCandidate<'a, T, M::Measurement>> {
+        self.search_nearest_n(query, max_count, usize::MAX, |axis, max| axis < max, accept)
+    }
+
+    /// query から range 以内にある要素を、 Metric::reduced_distance() の尺度の距離とともに found に渡す。
+    fn search_range<'a>(
+        &'a self,
+        query: &T,
+        range: &M::Measurement,
+        mut found: impl FnMut(&'a Entry<T>, M::Measurement),
     ) {
-        let Some(root) = root else {
-            return;
-        };
+        self.search_range_while(query, range, |entry, distance| {
+            found(entry, distance);
+            ControlFlow::Continue(())
+        });
+    }
+
+    /// search_range() と同様だが、 found が ControlFlow::Break を返したら探索を打ち切る。
+    /// 打ち切った後は found を呼ばない。
+    fn search_range_while<'a>(
+        &'a self,
+        query: &T,
+        range: &M::Measurement,
+        mut found: impl FnMut(&'a Entry<T>, M::Measurement) -> ControlFlow<()>,
+    ) {
+        let range = self.metric.distance_to_reduced(range);
+        let mut stack = vec![(self.get_node(self.root_index), 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let Some(node) = node else {
+                continue;
+            };
+
+            // node の要素 (葉であればバケット内のすべての要素) をまとめて調べ、 range 以内のものを found に渡す
+            let entries = node.entries().map(|e| &e.item);
+            let mut flow = ControlFlow::Continue(());
+            self.metric
+                .reduced_range_batch(query, entries, &range, |position, distance| {
+                    let entry = node.entry_at(position);
+                    if flow.is_continue() && !entry.removed {
+                        flow = found(entry, distance);
+                    }
+                });
+            if flow.is_break() {
+                return;
+            }
+            if node.is_leaf() {
+                continue;
+            }
+
+            let (first_subtree, second_subtree) = self.split_subtrees(node, query, depth);
 
-        // root が candidates に入るなら入れる
-        let root_distance = query.distance(&root.item);
-        if root_distance <= *range {
-            candidates.push(NeighborCandidate(&root.item, root_distance));
+            // range が現在の分割面に届いていれば逆側も探索する。分割面上の要素はどちらの側にも入りうるので境界を含める
+            let axis_distance = self.metric.reduced_distance_to_axis(query, &node.entry.item, depth);
+            if axis_distance <= range {
+                stack.push((second_subtree, depth + 1));
+            }
+            stack.push((first_subtree, depth + 1));
         }
+    }
 
-        let (left_subtree, right_subtree) = (self.get_node(root.left_index), self.get_node(root.right_index));
-        let (first_subtree, second_subtree) = match query.cmp_in_depth(&root.item, depth) {
+    /// node の子を (query が属する側, 逆側) の順で返す。
+    fn split_subtrees(&self, node: &Node<T>, query: &T, depth: usize) -> (Option<&Node<T>>, Option<&Node<T>>) {
+        let (left_subtree, right_subtree) = (self.get_node(node.left_index), self.get_node(node.right_index));
+        match query.cmp_in_depth(&node.entry.item, depth) {
             Ordering::Less => (left_subtree, right_subtree),
             Ordering::Equal | Ordering::Greater => (right_subtree, left_subtree),
-        };
+        }
+    }
+
+    /// path (根から挿入した要素を含むノードまで) 上で最初に偏りが大きくなった祖先を根とする部分木を再構築する。
+    fn rebuild_scapegoat(&mut self, path: &[NodeIndex]) {
+        let mut child_size = self.subtree_size(path.last().copied());
+        for depth in (0..path.len() - 1).rev() {
+            let node = &self.nodes[path[depth].get() - 1];
+            let sibling = if node.left_index == Some(path[depth + 1]) {
+                node.right_index
+            } else {
+                node.left_index
+            };
+            let size = 1 + child_size + self.subtree_size(sibling);
+            if child_size as f64 > SCAPEGOAT_ALPHA * size as f64 {
+                let new_root = self.rebuild_subtree(path[depth], depth);
+                self.replace_subtree(&path[..=depth], new_root);
+                return;
+            }
+            child_size = size;
+        }
+    }
+
+    /// root を根とする部分木の生きている要素から新しい部分木を構築し、その根を返す。
+    /// 置き換えられた要素はすべて削除済みになる。
+    fn rebuild_subtree(&mut self, root: NodeIndex, depth: usize) -> Option<NodeIndex> {
+        let mut items = Vec::new();
+        let mut stack = vec![Some(root)];
+        while let Some(node_index) = stack.pop() {
+            let Some(node_index) = node_index else {
+                continue;
+            };
+            let node = &mut self.nodes[node_index.get() - 1];
+            for entry in node.entries_mut().filter(|e| !e.removed) {
+                items.push((entry.index(), entry.item.clone()));
+                entry.removed = true;
+            }
+            stack.extend([node.left_index, node.right_index]);
+        }
 
-        self.find_range_n_depth(candidates, first_subtree, query, range, depth + 1);
+        self.removed_count += items.len();
+        construct_part(&mut self.nodes, &mut items, depth, self.bucket_size)
+    }
+
+    /// ツリー全体を生きている要素だけで構築し直す。要素の位置は保たれる。
+    fn rebuild(&mut self) {
+        let mut items: Vec<_> = core::mem::take(&mut self.nodes)
+            .into_iter()
+            .flat_map(|n| core::iter::once(n.entry).chain(n.bucket))
+            .filter(|e| !e.removed)
+            .map(|e| (e.index(), e.item))
+            .collect();
+        self.nodes.reserve(node_count(items.len(), self.bucket_size));
+        self.root_index = construct_part(&mut self.nodes, &mut items, 0, self.bucket_size);
+        self.removed_count = 0;
+    }
+
+    /// path (根から置き換える部分木の根まで) の末尾の部分木を new に置き換える。
+    fn replace_subtree(&mut self, path: &[NodeIndex], new: Option<NodeIndex>) {
+        let [.., parent, old] = path else {
+            self.root_index = new;
+            return;
+        };
+        let parent = &mut self.nodes[parent.get() - 1];
+        if parent.left_index == Some(*old) {
+            parent.left_index = new;
+        } else {
+            parent.right_index = new;
+        }
+    }
 
-        // range が現在の分割面を跨いでいれば逆側も探索
-        let axis_distance = query.distance_to_axis(&root.item, depth);
-        if axis_distance < *range {
-            self.find_range_n_depth(candidates, second_subtree, query, range, depth + 1);
+    fn subtree_size(&self, root: Option<NodeIndex>) -> usize {
+        let mut size = 0;
+        let mut stack = vec![root];
+        while let Some(node_index) = stack.pop() {
+            let Some(node_index) = node_index else {
+                continue;
+            };
+            let node = &self.nodes[node_index.get() - 1];
+            size += 1;
+            stack.extend([node.left_index, node.right_index]);
         }
+        size
+    }
+
+    fn live_entries(&self) -> impl Iterator<Item = &Entry<T>> + '_ {
+        self.nodes.iter().flat_map(|n| n.entries()).filter(|e| !e.removed)
     }
 
     #[inline]
-    fn get_node(&self, index: Option<NonZeroUsize>) -> Option<&Node<T>> {
+    pub(crate) fn get_node(&self, index: Option<NodeIndex>) -> Option<&Node<T>> {
         index.map(|ip1| &self.nodes[ip1.get() - 1])
     }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn root_node(&self) -> Option<&Node<T>> {
+        self.get_node(self.root_index)
+    }
 }
 
-fn construct_part<T: KdTreeItem>(nodes: &mut Vec<Node<T>>, items: &mut [T], depth: usize) -> Option<NonZeroUsize> {
-    match items.len() {
-        0 => None,
-        1 => {
-            let index = allocate_node(
-                nodes,
-                Node {
-                    item: items[0].clone(),
-                    left_index: None,
-                    right_index: None,
-                },
-            );
-            Some(index)
-        }
-        _ => {
-            items.sort_unstable_by(|lhs, rhs| lhs.cmp_in_depth(rhs, depth));
-
-            let mid = items.len() / 2;
-            let (left_slice, mid_right) = items.split_at_mut(mid);
-            let (mid_item, right_slice) = mid_right.split_first_mut().expect("right split must exist");
-
-            let left_index = construct_part(nodes, left_slice, depth + 1);
-            let right_index = construct_part(nodes, right_slice, depth + 1);
-            let mid_node_index = allocate_node(
-                nodes,
-                Node {
-                    item: mid_item.clone(),
-                    left_index,
-                    right_index,
-                },
-            );
+impl<F: Debug + Float, const N: usize, M: Metric<[F; N]>> KdTree<[F; N], M> {
+    /// 各座標が min_corner 以上 max_corner 以下 (境界を含む) の軸平行な箱に入る要素をすべて返す。順序は不定。
+    /// いずれかの軸で min_corner が max_corner より大きい場合や、角の座標に NaN がある場合は何も返さない。
+    pub fn find_in_box(&self, min_corner: &[F; N], max_corner: &[F; N]) -> Vec<&[F; N]> {
+        let mut found = Vec::new();
+        self.search_box(min_corner, max_corner, |entry| found.push(&entry.item));
+        found
+    }
 
-            Some(mid_node_index)
+    /// find_in_box() と同様だが、要素の代わりに construct() に渡された時点での位置を返す。
+    pub fn find_in_box_indices(&self, min_corner: &[F; N], max_corner: &[F; N]) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.search_box(min_corner, max_corner, |entry| found.push(entry.index()));
+        found
+    }
+
+    /// min_corner と max_corner を角とする箱に入る要素を found に渡す。
+    fn search_box<'a>(&'a self, min_corner: &[F; N], max_corner: &[F; N], mut found: impl FnMut(&'a Entry<[F; N]>)) {
+        let contains = |item: &[F; N]| (0..N).all(|i| min_corner[i] <= item[i] && item[i] <= max_corner[i]);
+        let mut stack = vec![(self.get_node(self.root_index), 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let Some(node) = node else {
+                continue;
+            };
+            for entry in node.entries().filter(|e| !e.removed && contains(&e.item)) {
+                found(entry);
+            }
+            if node.is_leaf() {
+                continue;
+            }
+
+            // 箱が分割面のどちら側に掛かるかで子を選ぶ。分割面上の要素はどちらの側にも入りうるので境界を含める
+            let axis = depth % N;
+            let split = node.entry.item[axis];
+            if min_corner[axis] <= split {
+                stack.push((self.get_node(node.left_index), depth + 1));
+            }
+            if split <= max_corner[axis] {
+                stack.push((self.get_node(node.right_index), depth + 1));
+            }
+        }
+    }
+}
+
+/// dump() の 1 要素分として、元の位置と要素を書き出す。
+fn write_entry<T: Debug>(out: &mut impl core::fmt::Write, entry: &Entry<T>) -> core::fmt::Result {
+    write!(out, "#{} {:?}", entry.index(), entry.item)?;
+    if entry.removed {
+        write!(out, " (removed)")?;
+    }
+    Ok(())
+}
+
+/// (元の位置, 要素) を反復する items を Vec に集める。 size_hint がなければ items の size_hint() の下限だけ先に確保する。
+fn collect_indexed<T>(items: impl Iterator<Item = (usize, T)>, size_hint: Option<usize>) -> Vec<(usize, T)> {
+    let mut collected = Vec::with_capacity(size_hint.unwrap_or_else(|| items.size_hint().0));
+    collected.extend(items);
+    collected
+}
+
+/// token を渡さない構築は中断されない。
+const UNCANCELLED: &str = "must not be cancelled without token";
+
+/// items から部分木を構築し、その根を返す。
+/// ノードは左部分木, 右部分木, 中央の順に nodes へ追加される。
+fn construct_part<T: KdTreeItem>(
+    nodes: &mut Vec<Node<T>>,
+    items: &mut [(usize, T)],
+    depth: usize,
+    bucket_size: usize,
+) -> Option<NodeIndex> {
+    construct_part_cancellable(nodes, items, depth, bucket_size, None).expect(UNCANCELLED)
+}
+
+/// construct_part() と同様だが、分割のたびに cancellation を確認し、中断が要求されていれば Err を返す。
+/// その場合 nodes には構築途中のノードが残る。
+fn construct_part_cancellable<T: KdTreeItem>(
+    nodes: &mut Vec<Node<T>>,
+    items: &mut [(usize, T)],
+    depth: usize,
+    bucket_size: usize,
+    cancellation: Option<&CancellationToken>,
+) -> Result<Option<NodeIndex>, Cancelled> {
+    enum Task {
+        /// items[range] から部分木を構築し、その根を roots に積む。
+        Split(Range<usize>, usize),
+        /// roots に積まれた左右の部分木の根を items[mid] の子とする。
+        Join(usize),
+    }
+
+    let mut tasks = vec![Task::Split(0..items.len(), depth)];
+    let mut roots = Vec::new();
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Split(range, depth) => {
+                let part = &mut items[range.clone()];
+                if part.is_empty() {
+                    roots.push(None);
+                } else if part.len() <= bucket_size {
+                    // bucket_size 以下であれば分割せずに葉とする
+                    roots.push(Some(allocate_leaf(nodes, part)));
+                } else {
+                    if cancellation.is_some_and(CancellationToken::is_cancelled) {
+                        return Err(Cancelled::default());
+                    }
+                    // 中央値の位置だけを確定させ、左右はそれ以下とそれ以上の要素に分ける
+                    part.select_nth_unstable_by(part.len() / 2, |lhs, rhs| lhs.1.cmp_in_depth(&rhs.1, depth));
+                    let mid = range.start + part.len() / 2;
+                    tasks.push(Task::Join(mid));
+                    tasks.push(Task::Split(mid + 1..range.end, depth + 1));
+                    tasks.push(Task::Split(range.start..mid, depth + 1));
+                }
+            }
+            Task::Join(mid) => {
+                let right_index = roots.pop().expect("right subtree must be built");
+                let left_index = roots.pop().expect("left subtree must be built");
+                let mid_item = &items[mid];
+                let mid_node_index = allocate_node(
+                    nodes,
+                    Node {
+                        entry: Entry::new(mid_item.1.clone(), mid_item.0),
+                        left_index,
+                        right_index,
+                        bucket: Vec::new(),
+                    },
+                );
+                roots.push(Some(mid_node_index));
+            }
+        }
+    }
+
+    Ok(roots.pop().expect("root must be built"))
+}
+
+/// items をすべて持つ葉ノードを追加する。
+fn allocate_leaf<T: KdTreeItem>(nodes: &mut Vec<Node<T>>, items: &[(usize, T)]) -> NodeIndex {
+    let mut entries = items.iter().map(|(index, item)| Entry::new(item.clone(), *index));
+    let entry = entries.next().expect("items must not be empty");
+    allocate_node(
+        nodes,
+        Node {
+            entry,
+            left_index: None,
+            right_index: None,
+            bucket: entries.collect(),
+        },
+    )
+}
+
+/// len 個の要素から construct_part() で構築される部分木のノード数を返す。
+fn node_count(len: usize, bucket_size: usize) -> usize {
+    let mut count = 0;
+    let mut stack = vec![len];
+    while let Some(len) = stack.pop() {
+        match len {
+            0 => (),
+            _ if len <= bucket_size => count += 1,
+            _ => {
+                count += 1;
+                stack.extend([len / 2, len - len / 2 - 1]);
+            }
+        }
+    }
+    count
+}
+
+/// KdTreeOptions::default() で用いられる葉ノードの要素数の上限。
+pub const DEFAULT_BUCKET_SIZE: usize = 16;
+
+/// scapegoat tree の平衡パラメーター。部分木の大きさが親の α 倍を超えたら再構築する。
+const SCAPEGOAT_ALPHA: f64 = 0.7;
+
+/// needs_rebalance() が偏りすぎとみなす、構築し直した場合の深さに対する比。
+const REBALANCE_DEPTH_RATIO: usize = 2;
+
+/// 削除済み要素の詰め直しを始めるまでに許容する余分な要素数。
+const COMPACTION_SLACK: usize = 64;
+
+/// これ以下の要素数の部分木は construct_part_par() 内でも逐次構築する。
+#[cfg(feature = "parallel")]
+const PARALLEL_CONSTRUCTION_CUTOFF: usize = 4096;
+
+/// construct_part() と同じ配置 (左部分木, 右部分木, 中央の順) で slots を埋める。
+/// 中央値で分割するため再帰の深さは要素数の対数に収まる。
+/// slots は node_count(items.len(), bucket_size) と同じ長さで、 slots[0] が nodes[base] に相当する。
+#[cfg(feature = "parallel")]
+fn construct_part_par<T: KdTreeItem + Send>(
+    slots: &mut [Option<Node<T>>],
+    base: usize,
+    items: &mut [(usize, T)],
+    depth: usize,
+    bucket_size: usize,
+) -> Option<NodeIndex> {
+    if items.len() <= PARALLEL_CONSTRUCTION_CUTOFF.max(bucket_size) {
+        let mut nodes = Vec::with_capacity(slots.len());
+        let shift = |index: Option<NodeIndex>| index.and_then(|i| NodeIndex::new(i.get() + base));
+        let root_index = construct_part(&mut nodes, items, depth, bucket_size);
+        for (slot, node) in slots.iter_mut().zip(nodes) {
+            *slot = Some(Node {
+                left_index: shift(node.left_index),
+                right_index: shift(node.right_index),
+                ..node
+            });
         }
+        return shift(root_index);
     }
+
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |lhs, rhs| lhs.1.cmp_in_depth(&rhs.1, depth));
+
+    let (left_slice, mid_right) = items.split_at_mut(mid);
+    let (mid_item, right_slice) = mid_right.split_first_mut().expect("right split must exist");
+    let (left_slots, right_mid_slots) = slots.split_at_mut(node_count(left_slice.len(), bucket_size));
+    let (right_slots, mid_slot) = right_mid_slots.split_at_mut(node_count(right_slice.len(), bucket_size));
+
+    let right_base = base + left_slots.len();
+    let (left_index, right_index) = rayon::join(
+        || construct_part_par(left_slots, base, left_slice, depth + 1, bucket_size),
+        || construct_part_par(right_slots, right_base, right_slice, depth + 1, bucket_size),
+    );
+    mid_slot[0] = Some(Node {
+        entry: Entry::new(mid_item.1.clone(), mid_item.0),
+        left_index,
+        right_index,
+        bucket: Vec::new(),
+    });
+
+    NodeIndex::new(base + slots.len())
 }
 
-fn allocate_node<T: KdTreeItem>(nodes: &mut Vec<Node<T>>, node: Node<T>) -> NonZeroUsize {
+fn allocate_node<T: KdTreeItem>(nodes: &mut Vec<Node<T>>, node: Node<T>) -> NodeIndex {
     nodes.push(node);
-    NonZeroUsize::new(nodes.len()).expect("must not be empty")
+    NodeIndex::new(nodes.len()).expect("must not be empty")
 }
@@ -0,0 +1,152 @@
+use alloc::{vec, vec::Vec};
+use core::fmt::Debug;
+
+use num_traits::Float;
+
+use crate::{
+    error::Error,
+    kdtree::{validate_items, KdTree, KdTreeItem},
+    random::SplitMix64,
+};
+
+/// kmeans() の結果。
+#[derive(Debug, Clone, PartialEq)]
+pub struct KMeansResult<F, const N: usize> {
+    /// 各クラスターの重心。
+    pub centroids: Vec<[F; N]>,
+
+    /// 各要素が属するクラスターの centroids での位置。
+    pub assignments: Vec<usize>,
+
+    /// 各要素から属するクラスターの重心までの距離の 2 乗の合計。
+    pub inertia: F,
+
+    /// 行った Lloyd 法の反復の回数。
+    pub iterations: usize,
+
+    /// 反復の間に割り当てが変化しなくなったかどうか。 false であれば max_iterations で打ち切られた。
+    pub converged: bool,
+}
+
+/// items を k 個のクラスターに分ける k-means 法。
+/// 初期の重心を seed で決まる k-means++ で選び、割り当てが変わらなくなるか max_iterations 回に達するまで Lloyd 法を繰り返す。
+/// 各反復では重心から KdTree を構築し、各要素に最も近い重心を最近傍探索で求める。
+/// 要素を失ったクラスターの重心は直前の位置のまま残す。 k は要素数までに制限され、要素があれば 1 以上でなければならない。
+/// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+pub fn kmeans<F: Debug + Float, const N: usize>(
+    items: impl AsRef<[[F; N]]>,
+    k: usize,
+    max_iterations: usize,
+    seed: u64,
+) -> Result<KMeansResult<F, N>, Error> {
+    let items = items.as_ref();
+    validate_items(items)?;
+    let k = k.min(items.len());
+    assert!(k > 0 || items.is_empty(), "k must be positive");
+
+    let mut centroids = initial_centroids(items, k, seed);
+    let mut assignments = vec![usize::MAX; items.len()];
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < max_iterations {
+        iterations += 1;
+        let changed = assign(items, &centroids, &mut assignments);
+        if !changed {
+            converged = true;
+            break;
+        }
+        update_centroids(items, &assignments, &mut centroids);
+    }
+    if !converged {
+        // 最後に更新した重心に割り当て直す
+        converged = !assign(items, &centroids, &mut assignments);
+    }
+
+    let inertia = items
+        .iter()
+        .zip(&assignments)
+        .map(|(item, &c)| item.reduced_distance(&centroids[c]))
+        .fold(F::zero(), |sum, d| sum + d);
+    Ok(KMeansResult {
+        centroids,
+        assignments,
+        inertia,
+        iterations,
+        converged,
+    })
+}
+
+/// k-means++ で k 個の初期の重心を選ぶ。
+/// 最初の重心を一様に選び、以降は既に選んだ重心までの距離の 2 乗に比例する確率で要素を選ぶ。
+fn initial_centroids<F: Debug + Float, const N: usize>(items: &[[F; N]], k: usize, seed: u64) -> Vec<[F; N]> {
+    let mut random = SplitMix64::new(seed);
+    let mut centroids = Vec::with_capacity(k);
+    if k == 0 {
+        return centroids;
+    }
+    centroids.push(items[random.below(items.len())]);
+
+    // 各要素から最も近い重心までの距離の 2 乗
+    let mut nearest: Vec<F> = items.iter().map(|item| item.reduced_distance(&centroids[0])).collect();
+    while centroids.len() < k {
+        let total = nearest.iter().fold(F::zero(), |sum, &d| sum + d);
+        let chosen = if total > F::zero() {
+            let mut remaining = F::from(random.next_f64()).expect("must be representable") * total;
+            nearest
+                .iter()
+                .position(|&d| {
+                    remaining = remaining - d;
+                    remaining < F::zero()
+                })
+                .unwrap_or_else(|| {
+                    nearest
+                        .iter()
+                        .rposition(|&d| d > F::zero())
+                        .expect("total must be positive")
+                })
+        } else {
+            // すべての要素が重心と一致していれば一様に選ぶ
+            random.below(items.len())
+        };
+        let centroid = items[chosen];
+        for (d, item) in nearest.iter_mut().zip(items) {
+            *d = d.min(item.reduced_distance(&centroid));
+        }
+        centroids.push(centroid);
+    }
+    centroids
+}
+
+/// 各要素を最も近い重心に割り当てる。割り当てが 1 つでも変わったら true を返す。
+fn assign<F: Debug + Float, const N: usize>(items: &[[F; N]], centroids: &[[F; N]], assignments: &mut [usize]) -> bool {
+    let kdtree = KdTree::construct_unchecked(centroids);
+    let mut changed = false;
+    for (item, assignment) in items.iter().zip(assignments) {
+        let (nearest, _) = kdtree.find_nearest_n_indices(item, 1)[0];
+        changed |= *assignment != nearest;
+        *assignment = nearest;
+    }
+    changed
+}
+
+/// 各クラスターの重心を、割り当てられた要素の座標の平均に置き換える。
+fn update_centroids<F: Debug + Float, const N: usize>(
+    items: &[[F; N]],
+    assignments: &[usize],
+    centroids: &mut [[F; N]],
+) {
+    let mut sums = vec![[F::zero(); N]; centroids.len()];
+    let mut counts = vec![0usize; centroids.len()];
+    for (item, &c) in items.iter().zip(assignments) {
+        for (s, &x) in sums[c].iter_mut().zip(item) {
+            *s = *s + x;
+        }
+        counts[c] += 1;
+    }
+    for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+        if count > 0 {
+            let count = F::from(count).expect("count must be representable");
+            *centroid = sum.map(|s| s / count);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use num_traits::Float;
+
+use crate::{
+    error::Error,
+    index::SpatialIndex,
+    kdtree::{check_query, validate_items, KdTreeItem},
+    metric::ItemMetric,
+    slice_kdtree::SliceKdTree,
+};
+
+/// knn_classify() や knn_regress() で近傍に与える重み。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KnnWeighting {
+    /// すべての近傍を等しく扱う。
+    #[default]
+    Uniform,
+
+    /// 距離の逆数で重み付けする。 query と重なる近傍があれば、それらだけを等しく扱う。
+    Distance,
+}
+
+/// train_points の中で query に近い k 個の要素のラベルの多数決で query のラベルを推定する。
+/// 重みの合計が等しいラベルが複数あれば、より近い近傍を持つものを選ぶ。 k が 0 か要素がなければ None を返す。
+/// train_points の座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を、
+/// query の座標に NaN や無限大があれば Error::NonFiniteQuery を返す。
+///
+/// # Panics
+/// train_points と train_labels の長さが異なる場合。
+pub fn knn_classify<T: KdTreeItem, L: Clone + PartialEq>(
+    train_points: impl AsRef<[T]>,
+    train_labels: impl AsRef<[L]>,
+    query: &T,
+    k: usize,
+    weighting: KnnWeighting,
+) -> Result<Option<L>, Error>
+where
+    T::Measurement: Float,
+{
+    let (train_points, train_labels) = (train_points.as_ref(), train_labels.as_ref());
+    assert_eq!(
+        train_points.len(),
+        train_labels.len(),
+        "train_labels must have the same length as train_points"
+    );
+    validate_items(train_points)?;
+    check_query(query)?;
+    let tree = SliceKdTree::construct_with_metric(train_points, ItemMetric);
+    Ok(knn_classify_with_index(&tree, train_labels, query, k, weighting))
+}
+
+/// 近傍探索に index を用いる knn_classify() 。 train_labels は index と同じ順で並んでいなければならない。
+/// 入力は検証しない。
+pub fn knn_classify_with_index<T, I: SpatialIndex<T>, L: Clone + PartialEq>(
+    index: &I,
+    train_labels: impl AsRef<[L]>,
+    query: &T,
+    k: usize,
+    weighting: KnnWeighting,
+) -> Option<L>
+where
+    I::Measurement: Float,
+{
+    let train_labels = train_labels.as_ref();
+    let neighbors = index.nearest_n(query, k);
+
+    // 近い近傍から順に集計するので、重みが等しければ先に現れたラベルが残る
+    let mut votes: Vec<(&L, I::Measurement)> = Vec::new();
+    for (i, weight) in weights(&neighbors, weighting) {
+        let label = &train_labels[i];
+        match votes.iter_mut().find(|(voted, _)| *voted == label) {
+            Some((_, total)) => *total = *total + weight,
+            None => votes.push((label, weight)),
+        }
+    }
+    votes
+        .into_iter()
+        .fold(None, |best: Option<(&L, I::Measurement)>, (label, total)| match best {
+            Some((_, best_total)) if best_total >= total => best,
+            _ => Some((label, total)),
+        })
+        .map(|(label, _)| label.clone())
+}
+
+/// train_points の中で query に近い k 個の要素の train_targets の加重平均で query の値を推定する。
+/// k が 0 か要素がなければ None を返す。エラーと panic の条件は knn_classify() と同じ。
+pub fn knn_regress<T: KdTreeItem>(
+    train_points: impl AsRef<[T]>,
+    train_targets: impl AsRef<[T::Measurement]>,
+    query: &T,
+    k: usize,
+    weighting: KnnWeighting,
+) -> Result<Option<T::Measurement>, Error>
+where
+    T::Measurement: Float,
+{
+    let (train_points, train_targets) = (train_points.as_ref(), train_targets.as_ref());
+    assert_eq!(
+        train_points.len(),
+        train_targets.len(),
+        "train_targets must have the same length as train_points"
+    );
+    validate_items(train_points)?;
+    check_query(query)?;
+    let tree = SliceKdTree::construct_with_metric(train_points, ItemMetric);
+    Ok(knn_regress_with_index(&tree, train_targets, query, k, weighting))
+}
+
+/// 近傍探索に index を用いる knn_regress() 。 train_targets は index と同じ順で並んでいなければならない。
+/// 入力は検証しない。
+pub fn knn_regress_with_index<T, I: SpatialIndex<T>>(
+    index: &I,
+    train_targets: impl AsRef<[I::Measurement]>,
+    query: &T,
+    k: usize,
+    weighting: KnnWeighting,
+) -> Option<I::Measurement>
+where
+    I::Measurement: Float,
+{
+    let train_targets = train_targets.as_ref();
+    let neighbors = index.nearest_n(query, k);
+    weighted_mean(train_targets, &neighbors, weighting)
+}
+
+/// neighbors の位置にある targets の加重平均を返す。 neighbors が空であれば None を返す。
+fn weighted_mean<D: Float>(targets: &[D], neighbors: &[(usize, D)], weighting: KnnWeighting) -> Option<D> {
+    if neighbors.is_empty() {
+        return None;
+    }
+    let (sum, total) = weights(neighbors, weighting).fold((D::zero(), D::zero()), |(sum, total), (i, weight)| {
+        (sum + targets[i] * weight, total + weight)
+    });
+    Some(sum / total)
+}
+
+/// 近傍の位置と重みを返す。重みが 0 になる近傍は含めない。
+fn weights<D: Float>(neighbors: &[(usize, D)], weighting: KnnWeighting) -> impl Iterator<Item = (usize, D)> + '_ {
+    // 距離で重み付けする場合、 query と重なる近傍があれば逆数が無限大になるのでそれらだけを数える
+    let overlapping = weighting == KnnWeighting::Distance && neighbors.iter().any(|&(_, d)| d == D::zero());
+    neighbors.iter().filter_map(move |&(i, distance)| match weighting {
+        KnnWeighting::Uniform => Some((i, D::one())),
+        KnnWeighting::Distance if overlapping => (distance == D::zero()).then_some((i, D::one())),
+        KnnWeighting::Distance => Some((i, distance.recip())),
+    })
+}
+
+/// SpatialIndex::nearest_n() の実装が、見つけた要素のうち距離の小さい k 個を (位置, 距離) の昇順で保つ。
+pub(crate) struct KBest<D> {
+    k: usize,
+    candidates: Vec<(usize, D)>,
+}
+
+impl<D: PartialOrd> KBest<D> {
+    pub fn new(k: usize) -> KBest<D> {
+        KBest {
+            k,
+            candidates: Vec::with_capacity(k + 1),
+        }
+    }
+
+    /// k 個が集まっていて、 distance がそのうち最も遠いもの以上であれば true を返す。
+    /// 部分木までの距離の下界を渡せば、その部分木を探索せずに済むかが分かる。
+    pub fn excludes(&self, distance: &D) -> bool {
+        self.candidates.len() == self.k && self.candidates.last().is_none_or(|worst| *distance >= worst.1)
+    }
+
+    /// 位置 index の要素が distance にあることを伝え、 k 個のうちに入るなら加える。
+    pub fn offer(&mut self, index: usize, distance: D) {
+        if self.excludes(&distance) {
+            return;
+        }
+
+        // 挿入ソートで距離の昇順を保つ
+        let position = self
+            .candidates
+            .iter()
+            .position(|c| c.1.partial_cmp(&distance) == Some(Ordering::Greater))
+            .unwrap_or(self.candidates.len());
+        self.candidates.insert(position, (index, distance));
+        self.candidates.truncate(self.k);
+    }
+
+    pub fn into_vec(self) -> Vec<(usize, D)> {
+        self.candidates
+    }
+}
@@ -0,0 +1,141 @@
+//! k-d tree による近傍探索と、それを用いた DBSCAN の実装。
+//!
+//! std feature を無効にすると no_std + alloc でビルドできる。その場合 io, snapshot, incremental などの
+//! std に依存するモジュールは使えない。
+//!
+//! cabi や wasm feature で C や JS から使う場合は、 `cargo rustc --lib --crate-type cdylib --features cabi` のように
+//! crate-type を指定してビルドする。
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod balltree;
+#[cfg(feature = "cabi")]
+pub mod cabi;
+pub mod checkpoint;
+pub mod covertree;
+pub mod datasets;
+pub mod dbscan;
+pub mod dedup;
+pub mod error;
+pub mod fitted;
+pub mod geo;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod graph;
+pub mod grid;
+pub mod hdbscan;
+pub mod hnsw;
+pub mod implicit_kdtree;
+#[cfg(feature = "std")]
+pub mod incremental;
+pub mod index;
+#[cfg(feature = "std")]
+pub mod io;
+pub mod kdtree;
+pub mod kmeans;
+pub mod knn;
+#[cfg(feature = "linfa")]
+pub mod linfa;
+pub mod linkage;
+pub mod lof;
+pub mod meanshift;
+pub mod metric;
+pub mod metrics;
+pub mod model;
+pub mod morton;
+#[cfg(feature = "ndarray")]
+pub mod ndarray;
+pub mod optics;
+pub mod pca;
+pub mod periodic;
+pub mod point;
+pub mod preprocess;
+pub mod progress;
+mod random;
+pub mod rtree;
+#[cfg(feature = "simd")]
+pub mod simd;
+pub mod slice_kdtree;
+#[cfg(feature = "std")]
+pub mod snapshot;
+pub mod summary;
+mod union_find;
+pub mod vptree;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use crate::{
+    balltree::BallTree,
+    checkpoint::DbscanCheckpoint,
+    covertree::CoverTree,
+    dbscan::{
+        dbscan, dbscan_codes, dbscan_codes_unchecked, dbscan_from_graph, dbscan_sweep, dbscan_unchecked,
+        dbscan_weighted, dbscan_weighted_unchecked, dbscan_with_index, dbscan_with_index_kind, dbscan_with_metric,
+        dbscan_with_metric_unchecked, dbscan_with_options, dbscan_with_options_unchecked, BorderPolicy, ClusterOrder,
+        Dbscan, DbscanLabel, DbscanOptions, DbscanParams, DbscanResult, DbscanTimings, Parallelism, PointRole,
+    },
+    dedup::{coalesce_duplicates, Coalesced},
+    error::Error,
+    fitted::FittedIndex,
+    geo::{dbscan_geo, dbscan_geo_unchecked, GeoPoint},
+    graph::{neighbor_graph, NeighborGraph},
+    grid::GridIndex,
+    hdbscan::{hdbscan, hdbscan_unchecked},
+    hnsw::{ApproxDbscan, HnswIndex, HnswOptions},
+    implicit_kdtree::ImplicitKdTree,
+    index::{BruteForceIndex, IndexKind, SpatialIndex},
+    kdtree::{validate_items, CancellableKnn, KdTree, KdTreeItem, KdTreeOptions, KdTreeStats},
+    kmeans::{kmeans, KMeansResult},
+    knn::{knn_classify, knn_classify_with_index, knn_regress, knn_regress_with_index, KnnWeighting},
+    linkage::{single_linkage, Dendrogram, Merge},
+    lof::{local_outlier_factor, local_outlier_factor_with_index, LofResult},
+    meanshift::{meanshift, MeanShiftResult},
+    metric::Metric,
+    metrics::{
+        adjusted_rand_index, davies_bouldin_index, noise_ratio, normalized_mutual_information, silhouette_score,
+    },
+    model::DbscanModel,
+    morton::morton_order,
+    optics::{optics, optics_unchecked, OpticsResult},
+    periodic::{dbscan_periodic, dbscan_periodic_unchecked, PeriodicKdTree},
+    point::{CosinePoint, DynPoint, IntPoint, Point2, Point2F32, Point3, Point3F32},
+    progress::{CancellationToken, Cancelled, ProgressEvent},
+    rtree::{dbscan_rects, dbscan_rects_unchecked, RTree, Rect},
+    slice_kdtree::SliceKdTree,
+    summary::ClusterSummary,
+    vptree::VpTree,
+};
+
+#[allow(deprecated)]
+pub use crate::error::DbscanError;
+
+#[cfg(feature = "std")]
+pub use crate::{
+    incremental::IncrementalDbscan,
+    snapshot::{KdTreeSnapshot, SnapshotError},
+};
+
+#[cfg(feature = "parallel")]
+pub use crate::dbscan::dbscan_par;
+
+#[cfg(feature = "f16")]
+pub use crate::point::HalfPoint;
+
+#[cfg(feature = "arrow")]
+pub use crate::arrow::dbscan_arrow;
+
+#[cfg(feature = "ndarray")]
+pub use crate::ndarray::dbscan_array;
+
+#[cfg(feature = "simd")]
+pub use crate::simd::SimdEuclidean;
+
+#[cfg(feature = "gpu")]
+pub use crate::gpu::{GpuError, GpuIndex};
+
+#[cfg(feature = "mmap")]
+pub use crate::snapshot::MappedSnapshot;
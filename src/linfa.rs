@@ -0,0 +1,65 @@
+use core::fmt::Debug;
+
+use ::linfa::{
+    traits::{Fit, Transformer},
+    DatasetBase,
+};
+use ::ndarray::{Array1, ArrayBase, Data, Ix2};
+use num_traits::Float;
+
+use crate::dbscan::{Dbscan, DbscanLabel, DbscanResult};
+
+/// linfa の Transformer として、 observations の各行を点としてクラスタリングする。
+/// linfa-clustering の Dbscan と同様に、各行のクラスター番号を 0 から振り、ノイズを None とする。
+/// 座標に NaN や無限大があると panic するため、検証が必要であれば Fit を用いる。
+impl<F, D> Transformer<&ArrayBase<D, Ix2>, Array1<Option<usize>>> for Dbscan<F>
+where
+    F: Debug + Float + Sync,
+    D: Data<Elem = F>,
+{
+    fn transform(&self, observations: &ArrayBase<D, Ix2>) -> Array1<Option<usize>> {
+        let rows = crate::ndarray::rows(observations.view());
+        self.run(rows).labels.iter().map(|&l| memberships(l)).collect()
+    }
+}
+
+/// linfa の Transformer として、データセットの targets をクラスター番号に置き換える。
+impl<F, D, T> Transformer<DatasetBase<ArrayBase<D, Ix2>, T>, DatasetBase<ArrayBase<D, Ix2>, Array1<Option<usize>>>>
+    for Dbscan<F>
+where
+    F: Debug + Float + Sync,
+    D: Data<Elem = F>,
+{
+    fn transform(
+        &self,
+        dataset: DatasetBase<ArrayBase<D, Ix2>, T>,
+    ) -> DatasetBase<ArrayBase<D, Ix2>, Array1<Option<usize>>> {
+        let memberships = self.transform(&dataset.records);
+        dataset.with_targets(memberships)
+    }
+}
+
+/// linfa の Fit として、データセットの records の各行を点として try_run() でクラスタリングする。
+/// 固有メソッドの Dbscan::fit() と名前が重なるため、 `Fit::fit(&dbscan, &dataset)` のように呼ぶ。
+/// 入力の検証に失敗した場合は linfa::Error::Parameters にエラーの内容を入れて返す。
+impl<F, D, T> Fit<ArrayBase<D, Ix2>, T, ::linfa::Error> for Dbscan<F>
+where
+    F: Debug + Float + Sync,
+    D: Data<Elem = F>,
+{
+    type Object = DbscanResult;
+
+    fn fit(&self, dataset: &DatasetBase<ArrayBase<D, Ix2>, T>) -> Result<DbscanResult, ::linfa::Error> {
+        let rows = crate::ndarray::rows(dataset.records.view());
+        self.try_run(rows)
+            .map_err(|e| ::linfa::Error::Parameters(e.to_string()))
+    }
+}
+
+/// DbscanLabel を linfa-clustering の形式に変換する。
+fn memberships(label: DbscanLabel) -> Option<usize> {
+    match label {
+        DbscanLabel::Cluster(id) => Some(id.get() - 1),
+        DbscanLabel::Noise => None,
+    }
+}
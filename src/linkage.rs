@@ -0,0 +1,192 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    dbscan::{DbscanLabel, DbscanResult},
+    error::Error,
+    kdtree::{validate_items, KdTreeItem},
+    metric::ItemMetric,
+    slice_kdtree::SliceKdTree,
+    union_find::UnionFind,
+};
+
+/// 併合木における 1 回の併合。
+/// left と right は要素数 n 未満なら要素の位置、そうでなければ n + i で i 番目の併合で作られたノードを表す。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merge<D> {
+    pub left: usize,
+    pub right: usize,
+
+    /// 併合した 2 つのクラスターの間の距離。
+    pub distance: D,
+
+    /// 併合後のクラスターの要素数。
+    pub size: usize,
+}
+
+/// single_linkage() で求めた単連結法の併合木。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dendrogram<D> {
+    len: usize,
+
+    /// 距離の短い順に並んだ併合。要素数が 1 以上であれば要素数 - 1 個ある。
+    merges: Vec<Merge<D>>,
+}
+
+impl<D: PartialOrd> Dendrogram<D> {
+    /// 要素の数を返す。
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn merges(&self) -> &[Merge<D>] {
+        &self.merges
+    }
+
+    /// 距離が height 以下の併合だけを行った時点のクラスターをラベルにする。
+    /// 要素数が min_cluster_size に満たないクラスターの要素はノイズとし、クラスター番号は最小の添字の順に振る。
+    /// min_cluster_size が 1 以下であればすべての要素がいずれかのクラスターに属する。
+    pub fn cut(&self, height: &D, min_cluster_size: usize) -> DbscanResult {
+        // ノード n + i に含まれる要素の 1 つ
+        let mut representatives: Vec<usize> = (0..self.len).collect();
+        let mut union_find = UnionFind::new(self.len);
+        for merge in self.merges.iter().take_while(|m| m.distance <= *height) {
+            let (left, right) = (representatives[merge.left], representatives[merge.right]);
+            union_find.union(left, right);
+            representatives.push(left);
+        }
+
+        let roots: Vec<usize> = (0..self.len).map(|i| union_find.find(i)).collect();
+        let mut sizes = vec![0; self.len];
+        for &root in &roots {
+            sizes[root] += 1;
+        }
+        let mut cluster_ids = vec![None; self.len];
+        let mut cluster_count = 0;
+        let labels = roots
+            .iter()
+            .map(|&root| {
+                if sizes[root] < min_cluster_size {
+                    return DbscanLabel::Noise;
+                }
+                let id = *cluster_ids[root].get_or_insert_with(|| {
+                    cluster_count += 1;
+                    cluster_count
+                });
+                DbscanLabel::Cluster(id.try_into().expect("must be non-zero"))
+            })
+            .collect();
+        DbscanResult::from_labels(labels)
+    }
+}
+
+/// items に単連結法の階層的クラスタリングを適用する。
+/// 最小全域木を k-d tree を用いた Borůvka 法で求め、その辺を短い順に併合する。
+/// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+pub fn single_linkage<T: KdTreeItem>(items: impl AsRef<[T]>) -> Result<Dendrogram<T::Measurement>, Error> {
+    let items = items.as_ref();
+    validate_items(items)?;
+    let edges = minimum_spanning_tree(items);
+    Ok(Dendrogram {
+        len: items.len(),
+        merges: merge_edges(items.len(), edges),
+    })
+}
+
+/// fold_subtrees() で求める、部分木の要素がすべて同じ成分に属していないことを表す値。
+const MIXED: usize = usize::MAX;
+
+/// fold_subtrees() で求める、空の部分木を表す値。
+const EMPTY: usize = usize::MAX - 1;
+
+/// items の最小全域木の辺を Borůvka 法で求める。
+/// 各段階で成分ごとに他の成分への最短の辺を k-d tree の最近傍探索で求めて併合する。
+/// 探索ではすべての要素が自身と同じ成分に属する部分木を飛ばす。
+fn minimum_spanning_tree<T: KdTreeItem>(items: &[T]) -> Vec<(usize, usize, T::Measurement)> {
+    let n = items.len();
+    let kdtree = SliceKdTree::construct_with_metric(items, ItemMetric);
+    let mut union_find = UnionFind::new(n);
+    let mut edges = Vec::with_capacity(n.saturating_sub(1));
+    let mut components = vec![0; n];
+    let mut uniform = vec![MIXED; n];
+    while edges.len() + 1 < n {
+        for (i, component) in components.iter_mut().enumerate() {
+            *component = union_find.find(i);
+        }
+        kdtree.fold_subtrees(
+            EMPTY,
+            |part| {
+                let component = components[part[0] as usize];
+                if part.iter().all(|&i| components[i as usize] == component) {
+                    component
+                } else {
+                    MIXED
+                }
+            },
+            |left, pivot, right| {
+                let component = components[pivot];
+                if [left, right].iter().all(|&c| c == component || c == EMPTY) {
+                    component
+                } else {
+                    MIXED
+                }
+            },
+            &mut uniform,
+        );
+
+        // 成分の根ごとに、その成分から他の成分への最短の辺 (reduced_distance() の尺度)
+        let mut shortest: Vec<Option<(usize, usize, T::Measurement)>> = vec![None; n];
+        for (i, item) in items.iter().enumerate() {
+            let component = components[i];
+            let bound = shortest[component].as_ref().map(|(_, _, distance)| distance.clone());
+            let nearest = kdtree.nearest_where(
+                item,
+                bound,
+                |key| uniform[key] == component,
+                |j| components[j] != component,
+            );
+            if let Some((j, distance)) = nearest {
+                shortest[component] = Some((i, j, distance));
+            }
+        }
+
+        for (a, b, distance) in shortest.into_iter().flatten() {
+            if union_find.find(a) != union_find.find(b) {
+                union_find.union(a, b);
+                edges.push((a, b, T::reduced_to_distance(&distance)));
+            }
+        }
+    }
+    edges
+}
+
+/// 全域木の辺を短い順に併合して単連結法の併合木を作る。
+/// i 番目の併合で作られるノードは n + i として参照される。
+pub(crate) fn merge_edges<D: PartialOrd>(n: usize, mut edges: Vec<(usize, usize, D)>) -> Vec<Merge<D>> {
+    edges.sort_unstable_by(|lhs, rhs| lhs.2.partial_cmp(&rhs.2).expect("not total order"));
+
+    let mut union_find = UnionFind::new(n);
+    let mut component_nodes: Vec<usize> = (0..n).collect();
+    let mut component_sizes = vec![1; n];
+    let mut merges = Vec::with_capacity(edges.len());
+
+    for (a, b, distance) in edges {
+        let (root_a, root_b) = (union_find.find(a), union_find.find(b));
+        let size = component_sizes[root_a] + component_sizes[root_b];
+        merges.push(Merge {
+            left: component_nodes[root_a],
+            right: component_nodes[root_b],
+            distance,
+            size,
+        });
+
+        let root = union_find.union(root_a, root_b);
+        component_nodes[root] = n + merges.len() - 1;
+        component_sizes[root] = size;
+    }
+
+    merges
+}
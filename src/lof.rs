@@ -0,0 +1,133 @@
+use alloc::vec::Vec;
+
+use num_traits::Float;
+
+use crate::{
+    error::Error,
+    index::SpatialIndex,
+    kdtree::{validate_items, KdTreeItem},
+    metric::ItemMetric,
+    slice_kdtree::SliceKdTree,
+};
+
+/// local_outlier_factor() の結果。各ベクタは要素の順に並ぶ。
+#[derive(Debug, Clone, PartialEq)]
+pub struct LofResult<D> {
+    /// 各要素から k 番目に近い他の要素までの距離 (k-distance) 。
+    pub k_distances: Vec<D>,
+
+    /// 各要素の局所到達可能密度。 k 近傍への到達可能距離の平均の逆数で、平均が 0 であれば無限大になる。
+    pub local_reachability_densities: Vec<D>,
+
+    /// 各要素の LOF 。 1 前後であれば周囲と同程度の密度で、大きいほど外れ値らしい。
+    pub scores: Vec<D>,
+}
+
+impl<D: Float> LofResult<D> {
+    /// 距離 distance にある要素から要素 o への到達可能距離 max(k-distance(o), distance) を返す。
+    pub fn reachability_distance(&self, o: usize, distance: D) -> D {
+        self.k_distances[o].max(distance)
+    }
+
+    /// LOF が threshold を超える要素の位置を返す。
+    pub fn outliers(&self, threshold: D) -> Vec<usize> {
+        (0..self.scores.len()).filter(|&i| self.scores[i] > threshold).collect()
+    }
+}
+
+/// items の各要素の Local Outlier Factor を k 近傍から求める。
+/// k は要素数 - 1 までに制限され、要素が 2 つ以上あれば 1 以上でなければならない。
+/// k 番目の近傍と等距離の要素が複数あっても、近傍には k 個だけを含める。
+/// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+pub fn local_outlier_factor<T: KdTreeItem>(items: impl AsRef<[T]>, k: usize) -> Result<LofResult<T::Measurement>, Error>
+where
+    T::Measurement: Float,
+{
+    let items = items.as_ref();
+    validate_items(items)?;
+    let tree = SliceKdTree::construct_with_metric(items, ItemMetric);
+    Ok(local_outlier_factor_with_index(items, &tree, k))
+}
+
+/// 近傍探索に index を用いる local_outlier_factor() 。 index は items と同じ順で要素を持たなければならない。
+/// 入力は検証しない。
+pub fn local_outlier_factor_with_index<T, I: SpatialIndex<T>>(
+    items: impl AsRef<[T]>,
+    index: &I,
+    k: usize,
+) -> LofResult<I::Measurement>
+where
+    I::Measurement: Float,
+{
+    let items = items.as_ref();
+    lof_with_nearest(items.len(), k, |i, count| index.nearest_n(&items[i], count))
+}
+
+/// len 個の要素の LOF を求める。 nearest(i, count) は要素 i に近い順に count 個の要素 (自身を含む) の位置と距離を返す。
+pub(crate) fn lof_with_nearest<D: Float>(
+    len: usize,
+    k: usize,
+    nearest: impl Fn(usize, usize) -> Vec<(usize, D)>,
+) -> LofResult<D> {
+    let k = k.min(len.saturating_sub(1));
+    assert!(k > 0 || len <= 1, "k must be positive");
+
+    // 自身を除いた k 近傍。自身と重なる要素があれば自身が k + 1 個に含まれないこともあるので、その場合は最も遠いものを除く
+    let neighbors: Vec<Vec<(usize, D)>> = (0..len)
+        .map(|i| {
+            let mut found = nearest(i, k + 1);
+            match found.iter().position(|&(j, _)| j == i) {
+                Some(position) => {
+                    found.remove(position);
+                }
+                None => found.truncate(k),
+            }
+            found
+        })
+        .collect();
+
+    let k_distances: Vec<D> = neighbors
+        .iter()
+        .map(|found| found.last().map_or(D::zero(), |&(_, distance)| distance))
+        .collect();
+
+    // 到達可能距離の平均。局所到達可能密度はこの逆数になる
+    let mean_reachabilities: Vec<D> = neighbors
+        .iter()
+        .map(|found| {
+            mean(
+                found.iter().map(|&(o, distance)| k_distances[o].max(distance)),
+                found.len(),
+            )
+        })
+        .collect();
+    let local_reachability_densities: Vec<D> = mean_reachabilities.iter().map(|&r| r.recip()).collect();
+
+    // 近傍の密度の平均と自身の密度の比。自身の到達可能距離が 0 であれば重複した要素の中にあるので 1 とする
+    let scores = neighbors
+        .iter()
+        .zip(&mean_reachabilities)
+        .map(|(found, &reachability)| {
+            if found.is_empty() || reachability == D::zero() {
+                return D::one();
+            }
+            let density = mean(found.iter().map(|&(o, _)| local_reachability_densities[o]), found.len());
+            density * reachability
+        })
+        .collect();
+
+    LofResult {
+        k_distances,
+        local_reachability_densities,
+        scores,
+    }
+}
+
+/// count 個の値の平均を返す。 count が 0 であれば 0 を返す。
+fn mean<D: Float>(values: impl Iterator<Item = D>, count: usize) -> D {
+    if count == 0 {
+        return D::zero();
+    }
+    let sum = values.fold(D::zero(), |sum, value| sum + value);
+    sum / D::from(count).expect("count must be representable")
+}
@@ -1,37 +1,361 @@
-mod dbscan;
-mod kdtree;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
 
-use crate::dbscan::dbscan;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use dbscan_rust_test::{Dbscan, DbscanLabel, DbscanParams, DynPoint, KdTree, Parallelism};
 
-use std::time::Instant;
+/// CSV の点のクラスタリングや近傍の計算を行う。
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 
-use rand::{distr::Uniform, prelude::*, rng};
+    /// 並列処理に用いるスレッド数。既定では論理コア数になる。
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// CSV の点をクラスタリングし、各点のラベルを書き出す。
+    Cluster(ClusterArgs),
+
+    /// CSV の各点に近い順に k 個の点の位置と距離を書き出す。
+    Knn(KnnArgs),
+
+    /// CSV の各点から k 番目に近い点 (自身を含む) までの距離を大きい順に書き出す。 epsilon を選ぶ目安になる。
+    Kdist(KdistArgs),
+}
+
+#[derive(Debug, Args)]
+struct ClusterArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[arg(long)]
+    eps: f64,
+
+    #[arg(long)]
+    min_pts: usize,
+
+    /// 近傍探索を並列に行う。
+    #[arg(long)]
+    parallel: bool,
+}
 
-fn main() {
-    let element_counts = vec![
-        10000, 20000, 50000, 80000, 100000, 200000, 300000, 400000, 500000, 800000, 1000000, 5000000, 10000000,
-    ];
-    for elements in element_counts {
-        test_dbscan(elements);
+#[derive(Debug, Args)]
+struct KnnArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[arg(long, short)]
+    k: usize,
+}
+
+#[derive(Debug, Args)]
+struct KdistArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// 距離を求める近傍の順位。 DBSCAN の minPts と同じ値を指定する。
+    #[arg(long, short)]
+    k: usize,
+}
+
+/// CSV を読んで結果を書き出すサブコマンドに共通の引数。
+#[derive(Debug, Args)]
+struct InputArgs {
+    /// 1 行に 1 点の座標をカンマ区切りで並べた CSV 。先頭行が数値でなければヘッダーとみなす。
+    #[arg(long)]
+    input: PathBuf,
+
+    /// 書き出し先。省略すると標準出力に書き出す。
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    format: OutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Csv,
+    Json,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = set_threads(cli.threads).and_then(|()| match cli.command {
+        Command::Cluster(args) => cluster(args),
+        Command::Knn(args) => knn(args),
+        Command::Kdist(args) => kdist(args),
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn set_threads(threads: Option<usize>) -> Result<(), String> {
+    match threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|e| format!("failed to configure threads: {e}")),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn set_threads(threads: Option<usize>) -> Result<(), String> {
+    match threads {
+        Some(threads) if threads > 1 => Err("--threads requires the parallel feature".to_string()),
+        _ => Ok(()),
     }
 }
 
-fn test_dbscan(elements: usize) {
-    let range_scale = (elements as f32).powf(1.0 / 3.0) / 10.0;
-    let uniform_distr = Uniform::new(0.0, 10.0 * range_scale).expect("invalid distribution");
-    let mut rng = rng();
-    let data: Vec<[f32; 3]> = (0..elements)
-        .map(|_| {
-            [
-                uniform_distr.sample(&mut rng),
-                uniform_distr.sample(&mut rng),
-                uniform_distr.sample(&mut rng),
-            ]
+/// 座標を固定長の配列として扱う次元数の上限。これより次元数の大きい CSV は DynPoint として扱う。
+const MAX_DIMENSIONS: usize = 8;
+
+/// 実行時の次元数 $dimensions を定数 $n として $body を評価する。対応しない次元数では Err を返す。
+macro_rules! with_dimensions {
+    ($dimensions:expr, $n:ident => $body:expr) => {
+        match $dimensions {
+            1 => with_dimensions!(@ 1, $n => $body),
+            2 => with_dimensions!(@ 2, $n => $body),
+            3 => with_dimensions!(@ 3, $n => $body),
+            4 => with_dimensions!(@ 4, $n => $body),
+            5 => with_dimensions!(@ 5, $n => $body),
+            6 => with_dimensions!(@ 6, $n => $body),
+            7 => with_dimensions!(@ 7, $n => $body),
+            8 => with_dimensions!(@ 8, $n => $body),
+            d => return Err(format!("{d} dimensions are not supported, must be 1 to {MAX_DIMENSIONS}")),
+        }
+    };
+    (@ $value:literal, $n:ident => $body:expr) => {{
+        const $n: usize = $value;
+        $body
+    }};
+}
+
+/// CSV から読み込んだ点。各行の座標を row-major で並べて持つ。
+struct CsvPoints {
+    header: Option<String>,
+    lines: Vec<String>,
+    coordinates: Vec<f64>,
+    dimensions: usize,
+}
+
+impl CsvPoints {
+    /// 1 行に 1 点の座標をカンマ区切りで並べた CSV を読む。
+    /// 先頭行が数値として読めなければヘッダーとみなし、空行は読み飛ばす。
+    fn read(path: &PathBuf) -> Result<CsvPoints, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut points = CsvPoints {
+            header: None,
+            lines: Vec::new(),
+            coordinates: Vec::new(),
+            dimensions: 0,
+        };
+        for (number, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let row: Result<Vec<f64>, _> = line.split(',').map(|c| c.trim().parse()).collect();
+            let row = match row {
+                Ok(row) => row,
+                Err(_) if number == 0 => {
+                    points.header = Some(line.to_string());
+                    continue;
+                }
+                Err(e) => return Err(format!("line {}: {e}", number + 1)),
+            };
+            if row.iter().any(|c| !c.is_finite()) {
+                return Err(format!("line {}: coordinates must be finite", number + 1));
+            }
+
+            if points.lines.is_empty() {
+                points.dimensions = row.len();
+            } else if row.len() != points.dimensions {
+                return Err(format!(
+                    "line {}: expected {} columns, found {}",
+                    number + 1,
+                    points.dimensions,
+                    row.len()
+                ));
+            }
+            points.lines.push(line.to_string());
+            points.coordinates.extend(row);
+        }
+        Ok(points)
+    }
+
+    fn to_dyn(&self) -> Vec<DynPoint> {
+        DynPoint::from_rows(&self.coordinates, self.dimensions).expect("must have dimensions columns")
+    }
+
+    fn to_array<const N: usize>(&self) -> Vec<[f64; N]> {
+        self.coordinates
+            .chunks_exact(N)
+            .map(|c| c.try_into().expect("must have N columns"))
+            .collect()
+    }
+}
+
+/// args.output が指定されていればそのファイルを、なければ標準出力を開く。
+fn open_output(args: &InputArgs) -> Result<BufWriter<Box<dyn Write>>, String> {
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path).map_err(|e| format!("{}: {e}", path.display()))?),
+        None => Box::new(io::stdout().lock()),
+    };
+    Ok(BufWriter::new(output))
+}
+
+/// CSV の点をクラスタリングし、 DbscanLabel::to_code() のラベルを書き出す。
+/// CSV では各行の末尾にラベルの列を加え、 JSON では dbscan_rust_test::io::write_json() の形式で書き出す。
+fn cluster(args: ClusterArgs) -> Result<(), String> {
+    let points = CsvPoints::read(&args.input.input)?;
+    let parallelism = if args.parallel {
+        Parallelism::Parallel
+    } else {
+        Parallelism::Sequential
+    };
+    let dbscan = Dbscan::new(DbscanParams::new(args.eps, args.min_pts).parallelism(parallelism));
+    let result = match points.dimensions {
+        0 => dbscan.run_points::<1>([]),
+        d if d > MAX_DIMENSIONS => dbscan.run(points.to_dyn()),
+        d => with_dimensions!(d, N => dbscan.run_points(points.to_array::<N>())),
+    };
+
+    let mut output = open_output(&args.input)?;
+    let written = match args.input.format {
+        OutputFormat::Csv => write_labels_csv(&mut output, &points, &result.labels),
+        OutputFormat::Json => dbscan_rust_test::io::write_json(&result, &mut output),
+    };
+    written.map_err(|e| format!("failed to write labels: {e}"))?;
+
+    let noise = result.labels.iter().filter(|&&l| l == DbscanLabel::Noise).count();
+    eprintln!(
+        "{} points, {} clusters, {noise} noise",
+        points.lines.len(),
+        result.cluster_count
+    );
+    Ok(())
+}
+
+fn write_labels_csv(mut output: impl Write, points: &CsvPoints, labels: &[DbscanLabel]) -> io::Result<()> {
+    if let Some(header) = &points.header {
+        writeln!(output, "{header},label")?;
+    }
+    for (line, label) in points.lines.iter().zip(labels) {
+        writeln!(output, "{line},{}", label.to_code())?;
+    }
+    output.flush()
+}
+
+/// CSV の各点について、近い順に k 個の点 (自身を含む) の位置と距離を求める。
+fn nearest_neighbors(points: &CsvPoints, k: usize) -> Result<Vec<Vec<(usize, f64)>>, String> {
+    if points.lines.is_empty() {
+        return Ok(Vec::new());
+    }
+    if points.dimensions > MAX_DIMENSIONS {
+        let items = points.to_dyn();
+        let kdtree = KdTree::construct_unchecked(items.clone());
+        return Ok(map_items(&items, |item| kdtree.find_nearest_n_indices(item, k)));
+    }
+    Ok(with_dimensions!(points.dimensions, N => {
+        let items = points.to_array::<N>();
+        let kdtree = KdTree::construct_unchecked(items.clone());
+        map_items(&items, |item| kdtree.find_nearest_n_indices(item, k))
+    }))
+}
+
+/// items の各要素に f を適用する。 parallel feature が有効であれば rayon で並列に行う。
+fn map_items<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync + Send) -> Vec<R> {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        items.par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.iter().map(f).collect()
+    }
+}
+
+/// CSV の各点に近い順に k 個の点を書き出す。
+/// CSV では 1 行に (点の位置, 順位, 近傍の位置, 距離) を、 JSON では点ごとに近傍の配列を書き出す。
+fn knn(args: KnnArgs) -> Result<(), String> {
+    let points = CsvPoints::read(&args.input.input)?;
+    let neighbors = nearest_neighbors(&points, args.k)?;
+
+    let mut output = open_output(&args.input)?;
+    let written = match args.input.format {
+        OutputFormat::Csv => write_knn_csv(&mut output, &neighbors),
+        OutputFormat::Json => write_knn_json(&mut output, &neighbors),
+    };
+    written.map_err(|e| format!("failed to write neighbors: {e}"))
+}
+
+fn write_knn_csv(mut output: impl Write, neighbors: &[Vec<(usize, f64)>]) -> io::Result<()> {
+    writeln!(output, "index,rank,neighbor,distance")?;
+    for (index, neighbors) in neighbors.iter().enumerate() {
+        for (rank, (neighbor, distance)) in neighbors.iter().enumerate() {
+            writeln!(output, "{index},{},{neighbor},{distance}", rank + 1)?;
+        }
+    }
+    output.flush()
+}
+
+fn write_knn_json(mut output: impl Write, neighbors: &[Vec<(usize, f64)>]) -> io::Result<()> {
+    let rows: Vec<_> = neighbors
+        .iter()
+        .map(|neighbors| {
+            let neighbors: Vec<_> = neighbors
+                .iter()
+                .map(|(neighbor, distance)| format!(r#"{{"index":{neighbor},"distance":{distance}}}"#))
+                .collect();
+            format!("[{}]", neighbors.join(","))
         })
         .collect();
+    writeln!(output, "[{}]", rows.join(","))?;
+    output.flush()
+}
+
+/// CSV の各点から k 番目に近い点までの距離を大きい順に書き出す。点の数が k に満たなければ最も遠い点までの距離になる。
+fn kdist(args: KdistArgs) -> Result<(), String> {
+    let points = CsvPoints::read(&args.input.input)?;
+    let neighbors = nearest_neighbors(&points, args.k.max(1))?;
+    let mut distances: Vec<f64> = neighbors
+        .iter()
+        .filter_map(|neighbors| neighbors.last().map(|(_, distance)| *distance))
+        .collect();
+    distances.sort_unstable_by(|lhs, rhs| rhs.total_cmp(lhs));
+
+    let mut output = open_output(&args.input)?;
+    let written = match args.input.format {
+        OutputFormat::Csv => write_kdist_csv(&mut output, &distances),
+        OutputFormat::Json => write_kdist_json(&mut output, &distances),
+    };
+    written.map_err(|e| format!("failed to write distances: {e}"))
+}
+
+fn write_kdist_csv(mut output: impl Write, distances: &[f64]) -> io::Result<()> {
+    writeln!(output, "rank,distance")?;
+    for (rank, distance) in distances.iter().enumerate() {
+        writeln!(output, "{},{distance}", rank + 1)?;
+    }
+    output.flush()
+}
 
-    let now = Instant::now();
-    let _labels = dbscan(data, 0.05, 6);
-    let elapsed = now.elapsed();
-    println!("{elements} items: {}us", elapsed.as_micros());
+fn write_kdist_json(mut output: impl Write, distances: &[f64]) -> io::Result<()> {
+    let distances: Vec<_> = distances.iter().map(f64::to_string).collect();
+    writeln!(output, "[{}]", distances.join(","))?;
+    output.flush()
 }
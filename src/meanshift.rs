@@ -0,0 +1,101 @@
+use alloc::{vec, vec::Vec};
+use core::{cmp::Reverse, fmt::Debug};
+
+use num_traits::Float;
+
+use crate::{
+    error::{check_radius, Error},
+    index::SpatialIndex,
+    kdtree::{validate_items, KdTree, KdTreeItem},
+    metric::ItemMetric,
+    slice_kdtree::SliceKdTree,
+};
+
+/// meanshift() の結果。
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeanShiftResult<F, const N: usize> {
+    /// 各クラスターの中心。窓に含まれる要素の多いものから順に並ぶ。
+    pub centers: Vec<[F; N]>,
+
+    /// 各要素が属するクラスターの centers での位置。
+    pub assignments: Vec<usize>,
+}
+
+/// 窓の移動量がこれと bandwidth の積以下になったら収束したものとみなす。
+const CONVERGENCE_RATIO: f64 = 1e-3;
+
+/// 平坦なカーネルによる mean-shift 法。
+/// 各要素から始めて、 bandwidth 以内 (境界を含む) の要素の平均へ窓を移す操作を、移動量が十分小さくなるか
+/// max_iterations 回に達するまで繰り返す。窓の要素は k-d tree の範囲探索で求める。
+/// 収束した位置は窓に含まれる要素の多い順に調べ、既に選んだ中心から bandwidth 以内にあるものを除いて中心とする。
+/// 各要素は最も近い中心のクラスターに属する。
+/// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を、 bandwidth が NaN であれば Error::InvalidRadius を返す。
+pub fn meanshift<F: Debug + Float, const N: usize>(
+    items: impl AsRef<[[F; N]]>,
+    bandwidth: F,
+    max_iterations: usize,
+) -> Result<MeanShiftResult<F, N>, Error> {
+    let items = items.as_ref();
+    check_radius(&bandwidth)?;
+    validate_items(items)?;
+
+    let kdtree = SliceKdTree::construct_with_metric(items, ItemMetric);
+    let tolerance = bandwidth * F::from(CONVERGENCE_RATIO).expect("ratio must be representable");
+    let mut found = Vec::new();
+    let mut modes: Vec<([F; N], usize)> = items
+        .iter()
+        .map(|&item| {
+            let mut center = item;
+            let mut count = 0;
+            for _ in 0..max_iterations {
+                kdtree.range_into(&center, &bandwidth, &mut found);
+                if found.is_empty() {
+                    break;
+                }
+                count = found.len();
+                let shifted = mean(items, &found);
+                let shift = center.distance(&shifted);
+                center = shifted;
+                if shift <= tolerance {
+                    break;
+                }
+            }
+            (center, count)
+        })
+        .collect();
+
+    // 要素の多い窓から順に、既に選んだ中心と重ならないものを中心とする。要素数が等しければ始点の順になる
+    modes.sort_by_key(|&(_, count)| Reverse(count));
+    let mut kept = KdTree::construct_unchecked(Vec::new());
+    for (center, _) in modes {
+        let overlapping = kept
+            .find_nearest_n_with_distances(&center, 1)
+            .first()
+            .is_some_and(|&(_, distance)| distance <= bandwidth);
+        if !overlapping {
+            kept.insert(center);
+        }
+    }
+    let mut centers = vec![[F::zero(); N]; kept.len()];
+    for (index, &center) in kept.iter_with_indices() {
+        centers[index] = center;
+    }
+
+    let assignments = items
+        .iter()
+        .map(|item| kept.find_nearest_n_indices(item, 1)[0].0)
+        .collect();
+    Ok(MeanShiftResult { centers, assignments })
+}
+
+/// items のうち members の位置にある要素の座標の平均を返す。 members は空であってはならない。
+fn mean<F: Float, const N: usize>(items: &[[F; N]], members: &[usize]) -> [F; N] {
+    let mut sum = [F::zero(); N];
+    for &i in members {
+        for (s, &x) in sum.iter_mut().zip(&items[i]) {
+            *s = *s + x;
+        }
+    }
+    let count = F::from(members.len()).expect("count must be representable");
+    sum.map(|s| s / count)
+}
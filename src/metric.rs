@@ -0,0 +1,234 @@
+use core::fmt::Debug;
+
+use num_traits::Float;
+
+use crate::kdtree::KdTreeItem;
+
+/// KdTree が要素間の距離を計算するためのトレイト。
+/// 要素の並び (KdTreeItem::cmp_in_depth()) はそのままに、距離の定義だけを差し替えられる。
+pub trait Metric<T>: Clone {
+    type Measurement: Debug + PartialOrd + Clone;
+
+    /// 2 要素間の距離を計算する。
+    fn distance(&self, lhs: &T, rhs: &T) -> Self::Measurement;
+
+    /// query から rhs を通る分割面の反対側にある任意の要素までの距離の下界を計算する。
+    /// 分割面は KdTreeItem::cmp_in_depth() の depth に対応する軸で決まる。
+    fn distance_to_axis(&self, query: &T, rhs: &T, depth: usize) -> Self::Measurement;
+
+    /// distance() と大小関係が一致する、より安価に計算できる値を返す。 KdTree の探索中の比較にはこちらが使われる。
+    /// 既定では distance() をそのまま返す。
+    fn reduced_distance(&self, lhs: &T, rhs: &T) -> Self::Measurement {
+        self.distance(lhs, rhs)
+    }
+
+    /// distance_to_axis() を reduced_distance() と同じ尺度で返す。
+    fn reduced_distance_to_axis(&self, query: &T, rhs: &T, depth: usize) -> Self::Measurement {
+        self.distance_to_axis(query, rhs, depth)
+    }
+
+    /// reduced_distance() の尺度の値を distance() の尺度に変換する。
+    fn reduced_to_distance(&self, reduced: &Self::Measurement) -> Self::Measurement {
+        reduced.clone()
+    }
+
+    /// distance() の尺度の値を reduced_distance() の尺度に変換する。
+    fn distance_to_reduced(&self, distance: &Self::Measurement) -> Self::Measurement {
+        distance.clone()
+    }
+
+    /// others のうち query からの reduced_distance() が reduced_radius 以内 (境界を含む) にあるものについて、
+    /// found(others 上の位置, reduced_distance()) を呼ぶ。 KdTree は葉ノードの要素をまとめてこれで調べる。
+    /// 既定では reduced_distance() を 1 つずつ計算する。
+    fn reduced_range_batch<'a>(
+        &self,
+        query: &T,
+        others: impl Iterator<Item = &'a T>,
+        reduced_radius: &Self::Measurement,
+        mut found: impl FnMut(usize, Self::Measurement),
+    ) where
+        T: 'a,
+    {
+        for (position, other) in others.enumerate() {
+            let distance = self.reduced_distance(query, other);
+            if distance <= *reduced_radius {
+                found(position, distance);
+            }
+        }
+    }
+}
+
+/// 要素自身の KdTreeItem::distance() をそのまま用いる Metric 。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItemMetric;
+
+impl<T: KdTreeItem> Metric<T> for ItemMetric {
+    type Measurement = T::Measurement;
+
+    fn distance(&self, lhs: &T, rhs: &T) -> Self::Measurement {
+        lhs.distance(rhs)
+    }
+
+    fn distance_to_axis(&self, query: &T, rhs: &T, depth: usize) -> Self::Measurement {
+        query.distance_to_axis(rhs, depth)
+    }
+
+    fn reduced_distance(&self, lhs: &T, rhs: &T) -> Self::Measurement {
+        lhs.reduced_distance(rhs)
+    }
+
+    fn reduced_distance_to_axis(&self, query: &T, rhs: &T, depth: usize) -> Self::Measurement {
+        query.reduced_distance_to_axis(rhs, depth)
+    }
+
+    fn reduced_to_distance(&self, reduced: &Self::Measurement) -> Self::Measurement {
+        T::reduced_to_distance(reduced)
+    }
+
+    fn distance_to_reduced(&self, distance: &Self::Measurement) -> Self::Measurement {
+        T::distance_to_reduced(distance)
+    }
+}
+
+/// ユークリッド距離 (L2) 。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Euclidean;
+
+impl<T: Debug + Float, const N: usize> Metric<[T; N]> for Euclidean {
+    type Measurement = T;
+
+    fn distance(&self, lhs: &[T; N], rhs: &[T; N]) -> T {
+        self.reduced_distance(lhs, rhs).sqrt()
+    }
+
+    fn distance_to_axis(&self, query: &[T; N], rhs: &[T; N], depth: usize) -> T {
+        let i = depth % N;
+        (query[i] - rhs[i]).abs()
+    }
+
+    fn reduced_distance(&self, lhs: &[T; N], rhs: &[T; N]) -> T {
+        lhs.reduced_distance(rhs)
+    }
+
+    fn reduced_distance_to_axis(&self, query: &[T; N], rhs: &[T; N], depth: usize) -> T {
+        query.reduced_distance_to_axis(rhs, depth)
+    }
+
+    fn reduced_to_distance(&self, reduced: &T) -> T {
+        reduced.sqrt()
+    }
+
+    fn distance_to_reduced(&self, distance: &T) -> T {
+        distance.powi(2)
+    }
+}
+
+/// マンハッタン距離 (L1) 。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Manhattan;
+
+impl<T: Debug + Float, const N: usize> Metric<[T; N]> for Manhattan {
+    type Measurement = T;
+
+    fn distance(&self, lhs: &[T; N], rhs: &[T; N]) -> T {
+        (0..N).map(|i| (lhs[i] - rhs[i]).abs()).fold(T::zero(), |a, x| a + x)
+    }
+
+    fn distance_to_axis(&self, query: &[T; N], rhs: &[T; N], depth: usize) -> T {
+        let i = depth % N;
+        (query[i] - rhs[i]).abs()
+    }
+}
+
+/// チェビシェフ距離 (L∞) 。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chebyshev;
+
+impl<T: Debug + Float, const N: usize> Metric<[T; N]> for Chebyshev {
+    type Measurement = T;
+
+    fn distance(&self, lhs: &[T; N], rhs: &[T; N]) -> T {
+        (0..N).map(|i| (lhs[i] - rhs[i]).abs()).fold(T::zero(), |a, x| a.max(x))
+    }
+
+    fn distance_to_axis(&self, query: &[T; N], rhs: &[T; N], depth: usize) -> T {
+        let i = depth % N;
+        (query[i] - rhs[i]).abs()
+    }
+}
+
+/// コサイン距離 (1 - cos θ) 。
+/// 座標軸による分割面との距離の下界が得られないため、 KdTree の枝刈りは行われず全要素が走査される。
+/// ゼロベクトルとの距離は 1 として扱う。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cosine;
+
+impl<T: Debug + Float, const N: usize> Metric<[T; N]> for Cosine {
+    type Measurement = T;
+
+    fn distance(&self, lhs: &[T; N], rhs: &[T; N]) -> T {
+        let dot = (0..N).map(|i| lhs[i] * rhs[i]).fold(T::zero(), |a, x| a + x);
+        let norms = (0..N).map(|i| lhs[i].powi(2)).fold(T::zero(), |a, x| a + x).sqrt()
+            * (0..N).map(|i| rhs[i].powi(2)).fold(T::zero(), |a, x| a + x).sqrt();
+        if norms == T::zero() {
+            return T::one();
+        }
+        (T::one() - dot / norms).max(T::zero())
+    }
+
+    fn distance_to_axis(&self, _query: &[T; N], _rhs: &[T; N], _depth: usize) -> T {
+        T::zero()
+    }
+}
+
+/// `[緯度, 経度]` (ラジアン) で表された球面上の点の大圏距離。距離は中心角 (ラジアン) で返す。
+/// 経度は -π 以上 π 以下でなければならない。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Haversine;
+
+impl Haversine {
+    /// 緯度 latitude の点から、経度差 longitude_diff (0 以上 π 以下) だけ離れた半子午線までの中心角。
+    fn distance_to_meridian<T: Float>(latitude: T, longitude_diff: T) -> T {
+        if longitude_diff >= T::from(core::f64::consts::FRAC_PI_2).expect("must be representable") {
+            // 最近点は近い方の極
+            T::from(core::f64::consts::FRAC_PI_2).expect("must be representable") - latitude.abs()
+        } else {
+            (latitude.cos() * longitude_diff.sin()).asin()
+        }
+    }
+}
+
+impl<T: Debug + Float> Metric<[T; 2]> for Haversine {
+    type Measurement = T;
+
+    fn distance(&self, lhs: &[T; 2], rhs: &[T; 2]) -> T {
+        let two = T::one() + T::one();
+        let half_lat = (rhs[0] - lhs[0]) / two;
+        let half_lon = (rhs[1] - lhs[1]) / two;
+        let a = half_lat.sin().powi(2) + lhs[0].cos() * rhs[0].cos() * half_lon.sin().powi(2);
+        two * a.sqrt().min(T::one()).asin()
+    }
+
+    fn distance_to_axis(&self, query: &[T; 2], rhs: &[T; 2], depth: usize) -> T {
+        if depth.is_multiple_of(2) {
+            // 緯度の差は大圏距離の下界になる
+            return (query[0] - rhs[0]).abs();
+        }
+
+        // 経度による分割では、分割面の子午線と ±180° の子午線の両方が境界になる
+        let pi = T::from(core::f64::consts::PI).expect("must be representable");
+        let mut longitude_diff = (query[1] - rhs[1]).abs();
+        if longitude_diff > pi {
+            longitude_diff = pi + pi - longitude_diff;
+        }
+        let to_split = Haversine::distance_to_meridian(query[0], longitude_diff);
+        let to_antimeridian = Haversine::distance_to_meridian(query[0], pi - query[1].abs());
+        to_split.min(to_antimeridian)
+    }
+}
@@ -0,0 +1,242 @@
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::{fmt::Debug, num::NonZeroUsize};
+
+use num_traits::Float;
+
+use crate::{dbscan::DbscanLabel, kdtree::KdTreeItem};
+
+/// ノイズを除いた要素について、シルエット係数の平均を返す。値は -1 から 1 で、大きいほどクラスターがよく分かれている。
+/// 要素が多いと計算に O(n^2) かかるため、クラスターに属する要素から等間隔に最大 sample_size 個を選んで平均する。
+/// 選んだ各要素の係数は、すべての要素との距離から正確に計算する。
+/// クラスターが 2 つ未満であれば None を返す。要素が 1 つだけのクラスターの係数は 0 とする。
+pub fn silhouette_score<T, F>(items: impl AsRef<[T]>, labels: &[DbscanLabel], sample_size: usize) -> Option<F>
+where
+    T: KdTreeItem<Measurement = F>,
+    F: Float,
+{
+    let items = items.as_ref();
+    assert_eq!(items.len(), labels.len(), "labels must have the same length as items");
+    let Clusters {
+        members, assignments, ..
+    } = Clusters::new(labels);
+    if members.len() < 2 {
+        return None;
+    }
+
+    let clustered: Vec<usize> = (0..items.len()).filter(|&i| assignments[i].is_some()).collect();
+    let sample_size = sample_size.min(clustered.len());
+    if sample_size == 0 {
+        return None;
+    }
+
+    let mut sum = F::zero();
+    for n in 0..sample_size {
+        let i = clustered[n * clustered.len() / sample_size];
+        let own = assignments[i].expect("sampled item must be clustered");
+        if members[own].len() == 1 {
+            continue;
+        }
+
+        // 自身のクラスターの他の要素との平均距離と、他のクラスターとの平均距離の最小値
+        let mean_distance = |c: usize| {
+            let total = members[c]
+                .iter()
+                .map(|&j| items[i].distance(&items[j]))
+                .fold(F::zero(), |a, d| a + d);
+            let count = if c == own {
+                members[c].len() - 1
+            } else {
+                members[c].len()
+            };
+            total / F::from(count).expect("count must be representable")
+        };
+        let a = mean_distance(own);
+        let b = (0..members.len())
+            .filter(|&c| c != own)
+            .map(mean_distance)
+            .fold(F::infinity(), F::min);
+        let max = a.max(b);
+        if max > F::zero() {
+            sum = sum + (b - a) / max;
+        }
+    }
+    Some(sum / F::from(sample_size).expect("sample size must be representable"))
+}
+
+/// ノイズを除いた要素について Davies–Bouldin 指数を返す。値は 0 以上で、小さいほどクラスターがよく分かれている。
+/// 各クラスターの重心までの平均距離を広がりとし、他のクラスターとの (広がりの和 / 重心間の距離) の最大値を平均する。
+/// 重心が一致するクラスターがあれば無限大になる。クラスターが 2 つ未満であれば None を返す。
+pub fn davies_bouldin_index<F: Debug + Float, const N: usize>(
+    items: impl AsRef<[[F; N]]>,
+    labels: &[DbscanLabel],
+) -> Option<F> {
+    let items = items.as_ref();
+    assert_eq!(items.len(), labels.len(), "labels must have the same length as items");
+    let members = Clusters::new(labels).members;
+    if members.len() < 2 {
+        return None;
+    }
+
+    let centroids: Vec<[F; N]> = members
+        .iter()
+        .map(|cluster| {
+            let count = F::from(cluster.len()).expect("count must be representable");
+            let mut centroid = [F::zero(); N];
+            for &i in cluster {
+                for (c, &x) in centroid.iter_mut().zip(&items[i]) {
+                    *c = *c + x;
+                }
+            }
+            centroid.map(|c| c / count)
+        })
+        .collect();
+    let spreads: Vec<F> = members
+        .iter()
+        .zip(&centroids)
+        .map(|(cluster, centroid)| {
+            let total = cluster
+                .iter()
+                .map(|&i| items[i].distance(centroid))
+                .fold(F::zero(), |a, d| a + d);
+            total / F::from(cluster.len()).expect("count must be representable")
+        })
+        .collect();
+
+    let total = (0..members.len())
+        .map(|c| {
+            (0..members.len())
+                .filter(|&d| d != c)
+                .map(|d| (spreads[c] + spreads[d]) / centroids[c].distance(&centroids[d]))
+                .fold(F::neg_infinity(), F::max)
+        })
+        .fold(F::zero(), |a, r| a + r);
+    Some(total / F::from(members.len()).expect("cluster count must be representable"))
+}
+
+/// ノイズと判定された要素の割合を返す。 labels が空であれば 0 を返す。
+pub fn noise_ratio(labels: &[DbscanLabel]) -> f64 {
+    if labels.is_empty() {
+        return 0.0;
+    }
+    let noise = labels.iter().filter(|&&l| l == DbscanLabel::Noise).count();
+    noise as f64 / labels.len() as f64
+}
+
+/// 2 つのラベルの列の一致度を調整ランド指数で返す。偶然に一致する分を差し引いた値で、完全に一致すれば 1 、無相関であれば 0 前後になる。
+/// ラベルは DbscanLabel でも DbscanLabel::to_code() の整数でもよく、クラスターの番号の振り方によらない。
+/// ノイズは 1 つのクラスターとして扱う。どちらの列も全体が 1 つのクラスターであるなど値が定まらない場合は 1 を返す。
+pub fn adjusted_rand_index<L: Ord>(labels_a: &[L], labels_b: &[L]) -> f64 {
+    let table = Contingency::new(labels_a, labels_b);
+    let pairs = |n: usize| (n * n.saturating_sub(1) / 2) as f64;
+    let index: f64 = table.joint.values().map(|&n| pairs(n)).sum();
+    let sum_a: f64 = table.a.values().map(|&n| pairs(n)).sum();
+    let sum_b: f64 = table.b.values().map(|&n| pairs(n)).sum();
+    let expected = sum_a * sum_b / pairs(table.total).max(1.0);
+    let max = (sum_a + sum_b) / 2.0;
+    if max == expected {
+        return 1.0;
+    }
+    (index - expected) / (max - expected)
+}
+
+/// 2 つのラベルの列の一致度を正規化相互情報量で返す。値は 0 から 1 で、完全に一致すれば 1 になる。
+/// 相互情報量を 2 つの列のエントロピーの算術平均で割る。ラベルの扱いは adjusted_rand_index() と同じで、
+/// どちらの列もエントロピーが 0 であれば 1 を返す。
+pub fn normalized_mutual_information<L: Ord>(labels_a: &[L], labels_b: &[L]) -> f64 {
+    let table = Contingency::new(labels_a, labels_b);
+    let total = table.total as f64;
+    let entropy = |counts: &BTreeMap<&L, usize>| -> f64 {
+        counts
+            .values()
+            .map(|&n| {
+                let p = n as f64 / total;
+                -p * Float::ln(p)
+            })
+            .sum()
+    };
+    let (entropy_a, entropy_b) = (entropy(&table.a), entropy(&table.b));
+    if entropy_a == 0.0 && entropy_b == 0.0 {
+        return 1.0;
+    }
+
+    let mutual_information: f64 = table
+        .joint
+        .iter()
+        .map(|(&(a, b), &n)| {
+            let n = n as f64;
+            n / total * Float::ln(total * n / (table.a[a] as f64 * table.b[b] as f64))
+        })
+        .sum();
+    (mutual_information / ((entropy_a + entropy_b) / 2.0)).clamp(0.0, 1.0)
+}
+
+/// 2 つのラベルの列の分割表。
+struct Contingency<'a, L> {
+    joint: BTreeMap<(&'a L, &'a L), usize>,
+    a: BTreeMap<&'a L, usize>,
+    b: BTreeMap<&'a L, usize>,
+    total: usize,
+}
+
+impl<'a, L: Ord> Contingency<'a, L> {
+    fn new(labels_a: &'a [L], labels_b: &'a [L]) -> Contingency<'a, L> {
+        assert_eq!(labels_a.len(), labels_b.len(), "labels must have the same length");
+        let mut table = Contingency {
+            joint: BTreeMap::new(),
+            a: BTreeMap::new(),
+            b: BTreeMap::new(),
+            total: labels_a.len(),
+        };
+        for (a, b) in labels_a.iter().zip(labels_b) {
+            *table.joint.entry((a, b)).or_insert(0) += 1;
+            *table.a.entry(a).or_insert(0) += 1;
+            *table.b.entry(b).or_insert(0) += 1;
+        }
+        table
+    }
+}
+
+/// labels を要素を持つクラスターごとに分けたもの。クラスターは番号の順に 0 から数え直す。
+pub(crate) struct Clusters {
+    /// 各クラスターの元の番号。
+    pub ids: Vec<NonZeroUsize>,
+
+    /// 各クラスターに属する要素の位置。昇順に並ぶ。
+    pub members: Vec<Vec<usize>>,
+
+    /// 各要素が属するクラスター。ノイズはどのクラスターにも属さない。
+    pub assignments: Vec<Option<usize>>,
+}
+
+impl Clusters {
+    pub fn new(labels: &[DbscanLabel]) -> Clusters {
+        let mut ids: Vec<NonZeroUsize> = labels
+            .iter()
+            .filter_map(|&l| match l {
+                DbscanLabel::Cluster(id) => Some(id),
+                DbscanLabel::Noise => None,
+            })
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut members = vec![Vec::new(); ids.len()];
+        let assignments = labels
+            .iter()
+            .enumerate()
+            .map(|(i, &l)| {
+                let DbscanLabel::Cluster(id) = l else {
+                    return None;
+                };
+                let c = ids.binary_search(&id).expect("id must be collected");
+                members[c].push(i);
+                Some(c)
+            })
+            .collect();
+        Clusters {
+            ids,
+            members,
+            assignments,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use alloc::vec::Vec;
+
+use crate::{
+    dbscan::{DbscanLabel, DbscanResult, PointRole},
+    kdtree::{KdTree, KdTreeItem},
+    metric::{ItemMetric, Metric},
+};
+
+/// Dbscan::fit() でクラスタリングした結果と、新しい要素の分類に用いるコア点を保持する。
+pub struct DbscanModel<T, M: Metric<T> = ItemMetric> {
+    result: DbscanResult,
+    epsilon: M::Measurement,
+
+    /// コア点だけを格納するツリー。ツリー上の位置は core_labels の位置と一致する。
+    cores: KdTree<T, M>,
+    core_labels: Vec<DbscanLabel>,
+}
+
+impl<T: KdTreeItem, M: Metric<T>> DbscanModel<T, M> {
+    /// items とその DBSCAN の結果 result から、 result.roles でコア点とされた要素を保持したモデルを作る。
+    pub(crate) fn new(items: &[T], result: DbscanResult, epsilon: M::Measurement, metric: M) -> DbscanModel<T, M> {
+        let roles = result.roles.as_deref().expect("result must have roles");
+        let (cores, core_labels): (Vec<T>, Vec<DbscanLabel>) = (0..items.len())
+            .filter(|&i| roles[i] == PointRole::Core)
+            .map(|i| (items[i].clone(), result.labels[i]))
+            .unzip();
+        DbscanModel {
+            result,
+            epsilon,
+            cores: KdTree::construct_with_metric(cores, metric),
+            core_labels,
+        }
+    }
+
+    /// 学習に用いた要素のクラスタリングの結果を返す。
+    pub fn result(&self) -> &DbscanResult {
+        &self.result
+    }
+
+    pub fn into_result(self) -> DbscanResult {
+        self.result
+    }
+
+    /// 保持しているコア点の数を返す。
+    pub fn core_count(&self) -> usize {
+        self.core_labels.len()
+    }
+
+    /// point を、 epsilon 以内にあるコア点のうち最も近いもののクラスターに分類する。
+    /// epsilon 以内にコア点がなければ DbscanLabel::Noise を返す。モデルの要素やクラスターは変化しない。
+    pub fn predict_one(&self, point: &T) -> DbscanLabel {
+        // epsilon 以内だけを探索すれば、近くにコア点がない場合に探索を早く打ち切れる
+        let mut nearest: Option<(usize, M::Measurement)> = None;
+        self.cores
+            .search_range_indices(point, &self.epsilon, |index, distance| {
+                if nearest.as_ref().is_none_or(|(_, d)| distance < *d) {
+                    nearest = Some((index, distance));
+                }
+            });
+        nearest.map_or(DbscanLabel::Noise, |(index, _)| self.core_labels[index])
+    }
+
+    /// points の各要素を predict_one() で分類する。
+    pub fn predict(&self, points: impl AsRef<[T]>) -> Vec<DbscanLabel> {
+        points.as_ref().iter().map(|point| self.predict_one(point)).collect()
+    }
+}
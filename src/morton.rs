@@ -0,0 +1,75 @@
+//! Morton 順 (Z 曲線) による点の並べ替え。
+//!
+//! 外接直方体の中で各軸の座標を整数に量子化し、そのビットを軸の順に交互に並べた値 (Morton 符号) の順に並べる。
+//! 空間的に近い点ほど列の中でも近くに並ぶため、並べ替えた列から索引を構築して順に探索すると、
+//! 続く探索が同じノードや近くの要素を辿りやすくなる。
+
+use alloc::vec::Vec;
+
+use num_traits::Float;
+
+/// Morton 符号のビット数。
+const KEY_BITS: usize = u64::BITS as usize;
+
+/// items の位置を Morton 符号の順に並べて返す。符号が等しい要素は元の位置の順になる。
+/// 各軸には 64 / N ビット (1 以上 32 以下) を割り当て、 N が 64 を超える場合は先頭の 64 軸だけを用いる。
+/// NaN や無限大の座標は外接直方体の計算から除き、 +∞ はその軸の最大値に、 NaN と -∞ は最小値に寄せる。
+pub fn morton_order<F: Float, const N: usize>(items: &[[F; N]]) -> Vec<usize> {
+    let axes = N.min(KEY_BITS);
+    let bits = KEY_BITS.checked_div(axes).unwrap_or(0).min(32);
+
+    let mut min = [F::infinity(); N];
+    let mut max = [F::neg_infinity(); N];
+    for item in items {
+        for d in 0..axes {
+            if item[d].is_finite() {
+                min[d] = min[d].min(item[d]);
+                max[d] = max[d].max(item[d]);
+            }
+        }
+    }
+
+    let max_cell = (1u64 << bits) - 1;
+    let scale: [F; N] = core::array::from_fn(|d| {
+        let width = max[d] - min[d];
+        if width > F::zero() && width.is_finite() {
+            F::from(max_cell).expect("cell count must be representable") / width
+        } else {
+            F::zero()
+        }
+    });
+    let quantize = |x: F, d: usize| {
+        if x == F::infinity() {
+            max_cell
+        } else if x.is_finite() {
+            ((x - min[d]) * scale[d]).to_u64().map_or(0, |cell| cell.min(max_cell))
+        } else {
+            0
+        }
+    };
+
+    let mut keyed: Vec<(u64, usize)> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let mut cells = [0u64; N];
+            for d in 0..axes {
+                cells[d] = quantize(item[d], d);
+            }
+            (interleave(&cells[..axes], bits), i)
+        })
+        .collect();
+    keyed.sort_unstable();
+    keyed.into_iter().map(|(_, i)| i).collect()
+}
+
+/// cells の各値の下位 bits ビットを、上位のビットから軸の順に交互に並べる。
+fn interleave(cells: &[u64], bits: usize) -> u64 {
+    let mut key = 0;
+    for b in (0..bits).rev() {
+        for cell in cells {
+            key = (key << 1) | ((cell >> b) & 1);
+        }
+    }
+    key
+}
@@ -0,0 +1,89 @@
+use alloc::vec::Vec;
+use core::{cmp::Ordering, fmt::Debug};
+
+use ::ndarray::{Array1, ArrayView1, ArrayView2, Axis};
+use num_traits::Float;
+
+use crate::{
+    dbscan::{Dbscan, DbscanParams},
+    error::Error,
+    kdtree::{KdTree, KdTreeItem},
+};
+
+/// ArrayView2 の 1 行を 1 つの点とみなす。距離はユークリッド距離で、座標は複製せずに元の配列を参照する。
+impl<F: Debug + Float> KdTreeItem for ArrayView1<'_, F> {
+    type Measurement = F;
+
+    fn cmp_in_depth(&self, rhs: &Self, depth: usize) -> Ordering {
+        let i = depth % self.len();
+        self[i].partial_cmp(&rhs[i]).expect("not total order")
+    }
+
+    fn distance(&self, other: &Self) -> F {
+        self.reduced_distance(other).sqrt()
+    }
+
+    fn distance_to_axis(&self, other: &Self, depth: usize) -> F {
+        let i = depth % self.len();
+        (self[i] - other[i]).abs()
+    }
+
+    /// 平方根をとらない 2 乗のユークリッド距離。
+    fn reduced_distance(&self, other: &Self) -> F {
+        self.iter()
+            .zip(other)
+            .map(|(&l, &r)| (l - r).powi(2))
+            .fold(F::zero(), |a, x| a + x)
+    }
+
+    fn reduced_distance_to_axis(&self, other: &Self, depth: usize) -> F {
+        let i = depth % self.len();
+        (self[i] - other[i]).powi(2)
+    }
+
+    fn reduced_to_distance(reduced: &F) -> F {
+        reduced.sqrt()
+    }
+
+    fn distance_to_reduced(distance: &F) -> F {
+        distance.powi(2)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.iter().all(|c| c.is_finite())
+    }
+}
+
+impl<'a, F: Debug + Float> KdTree<ArrayView1<'a, F>> {
+    /// points の各行を点として k-d tree を構築する。座標は複製せずに points を参照する。
+    /// 座標に NaN や無限大を含む行があれば Error::NonFiniteInput を返す。
+    pub fn from_array(points: ArrayView2<'a, F>) -> Result<KdTree<ArrayView1<'a, F>>, Error> {
+        KdTree::construct(rows(points))
+    }
+}
+
+impl<F: Debug + Float + Sync> Dbscan<F> {
+    /// points の各行を点としてクラスタリングし、各行の DbscanLabel::to_code() を返す。
+    /// 入力は try_run() と同様に検証する。
+    pub fn try_run_array(&self, points: ArrayView2<'_, F>) -> Result<Array1<i64>, Error> {
+        let result = self.try_run(rows(points))?;
+        Ok(result.labels.iter().map(|&l| i64::from(l)).collect())
+    }
+}
+
+/// points の各行を点として DBSCAN を行い、各行のラベルをノイズを -1 とする整数で返す。
+/// 設定を増やす場合は Dbscan::try_run_array() を用いる。
+pub fn dbscan_array(points: ArrayView2<'_, f64>, epsilon: f64, min_points: usize) -> Result<Array1<i64>, Error> {
+    Dbscan::new(DbscanParams::new(epsilon, min_points)).try_run_array(points)
+}
+
+/// points の各行を、 points と同じ寿命を持つビューとして取り出す。行があれば列も 1 つ以上なければならない。
+pub(crate) fn rows<F>(points: ArrayView2<'_, F>) -> Vec<ArrayView1<'_, F>> {
+    assert!(
+        points.ncols() > 0 || points.nrows() == 0,
+        "points must have at least one column"
+    );
+    (0..points.nrows())
+        .map(|i| points.index_axis_move(Axis(0), i))
+        .collect()
+}
@@ -0,0 +1,168 @@
+use alloc::{collections::BinaryHeap, vec, vec::Vec};
+use core::{cmp::Ordering, num::NonZeroUsize};
+
+use crate::{
+    dbscan::{DbscanLabel, DbscanResult},
+    error::{check_radius, Error},
+    kdtree::{validate_items, KdTreeItem},
+    metric::ItemMetric,
+    slice_kdtree::SliceKdTree,
+};
+
+/// optics() の結果。
+#[derive(Debug, Clone)]
+pub struct OpticsResult<M> {
+    /// 要素を処理した順に並べた添字の列。
+    pub ordering: Vec<usize>,
+
+    /// 各要素の到達可能距離。 max_epsilon 以内から到達できなかった要素は None になる。
+    pub reachability: Vec<Option<M>>,
+
+    /// 各要素のコア距離。 max_epsilon 以内ではコア点にならない要素は None になる。
+    pub core_distances: Vec<Option<M>>,
+}
+
+impl<M: PartialOrd> OpticsResult<M> {
+    /// 任意の epsilon (max_epsilon 以下) について、 DBSCAN と同等のラベルを抽出する。
+    /// コア点のクラスター分けは dbscan() と一致するが、ボーダー点はノイズと判定される場合がある。
+    pub fn extract_dbscan(&self, epsilon: &M) -> DbscanResult {
+        let mut labels = vec![DbscanLabel::Noise; self.ordering.len()];
+        let cores: Vec<bool> = self
+            .core_distances
+            .iter()
+            .map(|c| c.as_ref().is_some_and(|c| c <= epsilon))
+            .collect();
+        let mut next_cluster_id = NonZeroUsize::new(1).expect("must be 1");
+        let mut current_label = DbscanLabel::Noise;
+
+        for &i in &self.ordering {
+            let reachable = self.reachability[i].as_ref().is_some_and(|r| r <= epsilon);
+            if !reachable {
+                // 到達できない点は、コア点であれば新しいクラスターの起点になる
+                current_label = if cores[i] {
+                    let label = DbscanLabel::Cluster(next_cluster_id);
+                    next_cluster_id = next_cluster_id.saturating_add(1);
+                    label
+                } else {
+                    DbscanLabel::Noise
+                };
+            }
+            labels[i] = current_label;
+        }
+
+        DbscanResult::from_labels_and_cores(labels, &cores)
+    }
+}
+
+#[derive(Debug)]
+struct Seed<M>(M, usize);
+
+impl<M: PartialOrd> PartialEq for Seed<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<M: PartialOrd> Eq for Seed<M> {}
+
+impl<M: PartialOrd> PartialOrd for Seed<M> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<M: PartialOrd> Ord for Seed<M> {
+    /// BinaryHeap から到達可能距離の小さい順に取り出せるよう逆順にする。
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.partial_cmp(&self.0).expect("not total order")
+    }
+}
+
+/// items に OPTICS を適用し、処理順序と到達可能距離を求める。
+/// max_epsilon は近傍探索の上限で、大きいほど抽出できる epsilon の範囲が広がる代わりに遅くなる。
+/// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を、 max_epsilon が NaN であれば Error::InvalidRadius を返す。
+pub fn optics<T>(
+    items: impl AsRef<[T]>,
+    max_epsilon: T::Measurement,
+    min_items: usize,
+) -> Result<OpticsResult<T::Measurement>, Error>
+where
+    T: KdTreeItem,
+    T::Measurement: Clone,
+{
+    let items = items.as_ref();
+    check_radius(&max_epsilon)?;
+    validate_items(items)?;
+    Ok(optics_unchecked(items, max_epsilon, min_items))
+}
+
+/// optics() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+pub fn optics_unchecked<T>(
+    items: impl AsRef<[T]>,
+    max_epsilon: T::Measurement,
+    min_items: usize,
+) -> OpticsResult<T::Measurement>
+where
+    T: KdTreeItem,
+    T::Measurement: Clone,
+{
+    let items = items.as_ref();
+    let kdtree = SliceKdTree::construct_with_metric(items, ItemMetric);
+
+    let mut ordering = Vec::with_capacity(items.len());
+    let mut reachability = vec![None; items.len()];
+    let mut core_distances = vec![None; items.len()];
+    let mut processed = vec![false; items.len()];
+    let mut seeds = BinaryHeap::new();
+
+    for item in 0..items.len() {
+        if processed[item] {
+            continue;
+        }
+
+        seeds.push(Seed(None, item));
+        while let Some(Seed(_, index)) = seeds.pop() {
+            if processed[index] {
+                continue;
+            }
+            processed[index] = true;
+            ordering.push(index);
+
+            let mut neighbors = kdtree.range_indices_with_distances(&items[index], &max_epsilon);
+            if neighbors.len() < min_items {
+                continue;
+            }
+
+            // コア距離は自身を含めて min_items 番目に近い要素までの距離
+            neighbors.sort_unstable_by(|lhs, rhs| lhs.1.partial_cmp(&rhs.1).expect("not total order"));
+            let core_distance = neighbors[min_items.max(1) - 1].1.clone();
+
+            // 未処理の近傍の到達可能距離を更新する
+            for (neighbor, distance) in neighbors {
+                if processed[neighbor] {
+                    continue;
+                }
+                let new_reachability = if distance < core_distance {
+                    core_distance.clone()
+                } else {
+                    distance
+                };
+                let improved = match &reachability[neighbor] {
+                    Some(current) => new_reachability < *current,
+                    None => true,
+                };
+                if improved {
+                    reachability[neighbor] = Some(new_reachability.clone());
+                    seeds.push(Seed(Some(new_reachability), neighbor));
+                }
+            }
+            core_distances[index] = Some(core_distance);
+        }
+    }
+
+    OpticsResult {
+        ordering,
+        reachability,
+        core_distances,
+    }
+}
@@ -0,0 +1,180 @@
+//! 主成分分析 (PCA) による次元削減。
+//!
+//! k-d tree は次元数が大きくなると枝刈りが効かなくなり、全探索と変わらなくなる。
+//! 入力の分散の大きい方向が少数に限られていれば、その方向へ射影した低次元の点から索引を構築した方が速い。
+//! 射影では分散の小さい方向の差が失われるため、射影後の距離は元の距離以下になる。
+
+use alloc::{vec, vec::Vec};
+
+use num_traits::Float;
+
+/// N 次元の点を、分散の大きい順に D 個の主成分へ射影する変換。 Pca::fit() や reduce() が入力から求める。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pca<F, const N: usize, const D: usize> {
+    mean: [F; N],
+
+    /// 各主成分の方向を表す単位ベクトル。
+    components: [[F; N]; D],
+
+    /// 各主成分の方向の分散。
+    variances: [F; D],
+}
+
+impl<F: Float, const N: usize, const D: usize> Pca<F, N, D> {
+    /// items の共分散行列を固有値分解し、固有値の大きい順に D 個の固有ベクトルを主成分とする。
+    /// 分散は要素数で割って求める。各主成分の向きは、絶対値の最も大きい成分が正になるように揃える。
+    /// D が N を超える場合は panic する。 items が空であれば、座標軸を主成分とする変換を返す。
+    pub fn fit(items: &[[F; N]]) -> Pca<F, N, D> {
+        assert!(D <= N, "output dimension must not exceed input dimension");
+
+        let mut mean = [F::zero(); N];
+        let mut covariance = vec![F::zero(); N * N];
+        if !items.is_empty() {
+            let count = F::from(items.len()).expect("item count must be representable");
+            for d in 0..N {
+                mean[d] = items.iter().fold(F::zero(), |sum, point| sum + point[d]) / count;
+            }
+            for point in items {
+                for i in 0..N {
+                    let di = point[i] - mean[i];
+                    for j in i..N {
+                        covariance[i * N + j] = covariance[i * N + j] + di * (point[j] - mean[j]);
+                    }
+                }
+            }
+            for i in 0..N {
+                for j in i..N {
+                    covariance[i * N + j] = covariance[i * N + j] / count;
+                    covariance[j * N + i] = covariance[i * N + j];
+                }
+            }
+        }
+
+        let (eigenvalues, eigenvectors) = symmetric_eigen(covariance, N);
+        let mut order: Vec<usize> = (0..N).collect();
+        order.sort_by(|&lhs, &rhs| {
+            eigenvalues[rhs]
+                .partial_cmp(&eigenvalues[lhs])
+                .expect("not total order")
+                .then(lhs.cmp(&rhs))
+        });
+
+        let components = core::array::from_fn(|c| {
+            let column = order[c];
+            let mut component: [F; N] = core::array::from_fn(|d| eigenvectors[d * N + column]);
+            let largest = component.iter().copied().fold(
+                F::zero(),
+                |largest, x| if x.abs() > largest.abs() { x } else { largest },
+            );
+            if largest < F::zero() {
+                component.iter_mut().for_each(|x| *x = -*x);
+            }
+            component
+        });
+        let variances = core::array::from_fn(|c| eigenvalues[order[c]].max(F::zero()));
+        Pca {
+            mean,
+            components,
+            variances,
+        }
+    }
+
+    /// 射影の前に各点から引く平均。
+    pub fn mean(&self) -> &[F; N] {
+        &self.mean
+    }
+
+    /// 各主成分の方向を表す単位ベクトルを、分散の大きい順に返す。
+    pub fn components(&self) -> &[[F; N]; D] {
+        &self.components
+    }
+
+    /// 各主成分の方向の分散を、大きい順に返す。
+    pub fn variances(&self) -> &[F; D] {
+        &self.variances
+    }
+
+    /// point を主成分へ射影する。入力から求めた変換であれば、新しい点も同じ空間に写せる。
+    pub fn transform(&self, point: &[F; N]) -> [F; D] {
+        core::array::from_fn(|c| {
+            (0..N).fold(F::zero(), |sum, d| {
+                sum + (point[d] - self.mean[d]) * self.components[c][d]
+            })
+        })
+    }
+
+    /// items のすべての点を主成分へ射影する。
+    pub fn transform_all(&self, items: &[[F; N]]) -> Vec<[F; D]> {
+        items.iter().map(|point| self.transform(point)).collect()
+    }
+
+    /// 射影した点 point を元の空間に戻す。捨てた主成分の方向の成分は平均の値になる。
+    pub fn inverse(&self, point: &[F; D]) -> [F; N] {
+        core::array::from_fn(|d| (0..D).fold(self.mean[d], |sum, c| sum + point[c] * self.components[c][d]))
+    }
+}
+
+/// items を D 個の主成分へ射影した点と、その変換を返す。
+pub fn reduce<F: Float, const N: usize, const D: usize>(items: &[[F; N]]) -> (Vec<[F; D]>, Pca<F, N, D>) {
+    let pca = Pca::fit(items);
+    (pca.transform_all(items), pca)
+}
+
+/// n 次の対称行列 matrix (行優先) を巡回 Jacobi 法で固有値分解し、固有値と、固有ベクトルを列に持つ行列を返す。
+fn symmetric_eigen<F: Float>(mut matrix: Vec<F>, n: usize) -> (Vec<F>, Vec<F>) {
+    let mut vectors = vec![F::zero(); n * n];
+    for i in 0..n {
+        vectors[i * n + i] = F::one();
+    }
+
+    let two = F::one() + F::one();
+    let scale = matrix.iter().fold(F::zero(), |sum, &x| sum + x * x);
+    for _ in 0..MAX_SWEEPS {
+        let off_diagonal = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .fold(F::zero(), |sum, (i, j)| sum + matrix[i * n + j] * matrix[i * n + j]);
+        if off_diagonal <= scale * F::epsilon() * F::epsilon() {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = matrix[p * n + q];
+                if apq.is_zero() {
+                    continue;
+                }
+
+                // A[p][q] を 0 にする回転角を求める
+                let theta = (matrix[q * n + q] - matrix[p * n + p]) / (two * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + F::one()).sqrt());
+                let c = F::one() / (t * t + F::one()).sqrt();
+                let s = t * c;
+
+                for k in 0..n {
+                    let akp = matrix[k * n + p];
+                    let akq = matrix[k * n + q];
+                    matrix[k * n + p] = c * akp - s * akq;
+                    matrix[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let apk = matrix[p * n + k];
+                    let aqk = matrix[q * n + k];
+                    matrix[p * n + k] = c * apk - s * aqk;
+                    matrix[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let vkp = vectors[k * n + p];
+                    let vkq = vectors[k * n + q];
+                    vectors[k * n + p] = c * vkp - s * vkq;
+                    vectors[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    let values = (0..n).map(|i| matrix[i * n + i]).collect();
+    (values, vectors)
+}
+
+/// Jacobi 法で行列全体を走査する回数の上限。
+const MAX_SWEEPS: usize = 64;
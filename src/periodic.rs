@@ -0,0 +1,106 @@
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use num_traits::Float;
+
+use crate::{
+    dbscan::{dbscan_with_metric, dbscan_with_metric_unchecked, DbscanResult},
+    error::Error,
+    kdtree::KdTree,
+    metric::Metric,
+};
+
+/// 周期境界条件の下でのユークリッド距離。各軸について最小イメージ規約で距離を計算する。
+/// 要素の各座標は 0 以上 box_size 未満でなければならない。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Periodic<T, const N: usize> {
+    pub box_size: [T; N],
+}
+
+/// 周期境界条件を持つ k-d tree 。
+pub type PeriodicKdTree<T, const N: usize> = KdTree<[T; N], Periodic<T, N>>;
+
+impl<T: Float, const N: usize> Periodic<T, N> {
+    pub fn new(box_size: [T; N]) -> Periodic<T, N> {
+        Periodic { box_size }
+    }
+
+    /// point の各座標を 0 以上 box_size 未満に折り返す。
+    pub fn wrap(&self, point: &[T; N]) -> [T; N] {
+        let mut wrapped = *point;
+        for (x, size) in wrapped.iter_mut().zip(self.box_size) {
+            *x = *x - size * (*x / size).floor();
+            // 丸め誤差で box_size ちょうどになった場合
+            if *x >= size {
+                *x = T::zero();
+            }
+        }
+        wrapped
+    }
+}
+
+impl<T: Debug + Float, const N: usize> Metric<[T; N]> for Periodic<T, N> {
+    type Measurement = T;
+
+    fn distance(&self, lhs: &[T; N], rhs: &[T; N]) -> T {
+        self.reduced_distance(lhs, rhs).sqrt()
+    }
+
+    fn distance_to_axis(&self, query: &[T; N], rhs: &[T; N], depth: usize) -> T {
+        let i = depth % N;
+        let (x, split, size) = (query[i], rhs[i], self.box_size[i]);
+
+        // 反対側の領域へは分割面を直接越えるか、箱の端を回り込むかのどちらかで到達する
+        if x >= split {
+            (x - split).min(size - x)
+        } else {
+            (split - x).min(x)
+        }
+    }
+
+    fn reduced_distance(&self, lhs: &[T; N], rhs: &[T; N]) -> T {
+        (0..N)
+            .map(|i| {
+                let d = (lhs[i] - rhs[i]).abs();
+                d.min(self.box_size[i] - d).powi(2)
+            })
+            .fold(T::zero(), |a, x| a + x)
+    }
+
+    fn reduced_distance_to_axis(&self, query: &[T; N], rhs: &[T; N], depth: usize) -> T {
+        self.distance_to_axis(query, rhs, depth).powi(2)
+    }
+
+    fn reduced_to_distance(&self, reduced: &T) -> T {
+        reduced.sqrt()
+    }
+
+    fn distance_to_reduced(&self, distance: &T) -> T {
+        distance.powi(2)
+    }
+}
+
+/// 周期境界条件の下で DBSCAN を行う。 items の座標は box_size の範囲に折り返した複製に対して処理される。
+/// 座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を、 epsilon が NaN であれば Error::InvalidRadius を返す。
+pub fn dbscan_periodic<T: Debug + Float, const N: usize>(
+    items: impl AsRef<[[T; N]]>,
+    box_size: [T; N],
+    epsilon: T,
+    min_items: usize,
+) -> Result<DbscanResult, Error> {
+    let metric = Periodic::new(box_size);
+    let wrapped: Vec<_> = items.as_ref().iter().map(|p| metric.wrap(p)).collect();
+    dbscan_with_metric(wrapped, epsilon, min_items, metric)
+}
+
+/// dbscan_periodic() と同様だが、入力を検証しない。 NaN を含む要素があると panic する。
+pub fn dbscan_periodic_unchecked<T: Debug + Float, const N: usize>(
+    items: impl AsRef<[[T; N]]>,
+    box_size: [T; N],
+    epsilon: T,
+    min_items: usize,
+) -> DbscanResult {
+    let metric = Periodic::new(box_size);
+    let wrapped: Vec<_> = items.as_ref().iter().map(|p| metric.wrap(p)).collect();
+    dbscan_with_metric_unchecked(wrapped, epsilon, min_items, metric)
+}
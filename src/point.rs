@@ -0,0 +1,325 @@
+use alloc::vec::Vec;
+use core::{cmp::Ordering, fmt::Debug};
+
+#[cfg(feature = "f16")]
+use half::f16;
+use num_traits::{Float, PrimInt};
+
+use crate::{error::Error, kdtree::KdTreeItem};
+
+/// f64 の 2 次元座標。
+pub type Point2 = [f64; 2];
+
+/// f64 の 3 次元座標。
+pub type Point3 = [f64; 3];
+
+/// f32 の 2 次元座標。
+pub type Point2F32 = [f32; 2];
+
+/// f32 の 3 次元座標。
+pub type Point3F32 = [f32; 3];
+
+/// 整数座標の点。固定小数点で表された座標などに用いる。
+/// 距離は平方根をとらない 2 乗のユークリッド距離で、 dbscan() などの epsilon も 2 乗した値で指定する。
+/// 2 乗の和が u128 に収まらない場合は u128::MAX に丸められる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct IntPoint<T, const N: usize>(pub [T; N]);
+
+impl<T: PrimInt + Debug, const N: usize> IntPoint<T, N> {
+    pub fn new(coordinates: [T; N]) -> IntPoint<T, N> {
+        IntPoint(coordinates)
+    }
+}
+
+impl<T: PrimInt + Debug, const N: usize> KdTreeItem for IntPoint<T, N> {
+    type Measurement = u128;
+
+    fn cmp_in_depth(&self, rhs: &Self, depth: usize) -> Ordering {
+        self.0[depth % N].cmp(&rhs.0[depth % N])
+    }
+
+    fn distance(&self, other: &Self) -> u128 {
+        (0..N).fold(0u128, |sum, i| {
+            sum.saturating_add(squared_difference(self.0[i], other.0[i]))
+        })
+    }
+
+    fn distance_to_axis(&self, other: &Self, depth: usize) -> u128 {
+        let i = depth % N;
+        squared_difference(self.0[i], other.0[i])
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for IntPoint<T, N> {
+    fn from(coordinates: [T; N]) -> IntPoint<T, N> {
+        IntPoint(coordinates)
+    }
+}
+
+fn squared_difference<T: PrimInt>(lhs: T, rhs: T) -> u128 {
+    // i128 に収まらないのは u128 の大きな値だけなので、その場合は u128 で差をとる
+    let difference = match (lhs.to_i128(), rhs.to_i128()) {
+        (Some(lhs), Some(rhs)) => lhs.abs_diff(rhs),
+        _ => {
+            let (lhs, rhs) = (lhs.to_u128(), rhs.to_u128());
+            lhs.expect("must be unsigned").abs_diff(rhs.expect("must be unsigned"))
+        }
+    };
+    difference.saturating_mul(difference)
+}
+
+/// 座標を f16 で保持する点。距離は座標を f32 に戻したユークリッド距離で計算する。
+/// [f32; N] の半分のメモリで済み、 1000 万点規模のツリーでもメモリに収めやすい。
+/// 各座標は相対誤差 2^-11 程度に丸められ、絶対値が 65504 を超える値は無限大になる。
+/// epsilon に比べて座標の値が大きいと丸めの影響が大きくなるため、必要なら原点付近に平行移動してから変換する。
+#[cfg(feature = "f16")]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct HalfPoint<const N: usize>(pub [f16; N]);
+
+#[cfg(feature = "f16")]
+impl<const N: usize> HalfPoint<N> {
+    /// 各座標を最も近い f16 に丸めた点を作る。
+    pub fn new(coordinates: [f32; N]) -> HalfPoint<N> {
+        HalfPoint(coordinates.map(f16::from_f32))
+    }
+
+    /// f32 に戻した座標。
+    pub fn to_f32(&self) -> [f32; N] {
+        self.0.map(f16::to_f32)
+    }
+}
+
+#[cfg(feature = "f16")]
+impl<const N: usize> KdTreeItem for HalfPoint<N> {
+    type Measurement = f32;
+
+    fn cmp_in_depth(&self, rhs: &Self, depth: usize) -> Ordering {
+        self.0[depth % N]
+            .partial_cmp(&rhs.0[depth % N])
+            .expect("not total order")
+    }
+
+    fn distance(&self, other: &Self) -> f32 {
+        Float::sqrt(self.reduced_distance(other))
+    }
+
+    fn distance_to_axis(&self, other: &Self, depth: usize) -> f32 {
+        let i = depth % N;
+        Float::abs(self.0[i].to_f32() - other.0[i].to_f32())
+    }
+
+    /// 平方根をとらない 2 乗のユークリッド距離。
+    fn reduced_distance(&self, other: &Self) -> f32 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(l, r)| Float::powi(l.to_f32() - r.to_f32(), 2))
+            .sum()
+    }
+
+    fn reduced_distance_to_axis(&self, other: &Self, depth: usize) -> f32 {
+        let i = depth % N;
+        Float::powi(self.0[i].to_f32() - other.0[i].to_f32(), 2)
+    }
+
+    fn reduced_to_distance(reduced: &f32) -> f32 {
+        Float::sqrt(*reduced)
+    }
+
+    fn distance_to_reduced(distance: &f32) -> f32 {
+        Float::powi(*distance, 2)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.0.iter().all(|c| c.is_finite())
+    }
+}
+
+#[cfg(feature = "f16")]
+impl<const N: usize> From<[f32; N]> for HalfPoint<N> {
+    fn from(coordinates: [f32; N]) -> HalfPoint<N> {
+        HalfPoint::new(coordinates)
+    }
+}
+
+/// 長さ 1 に正規化したベクトル。距離は 2 つのベクトルのなす角 (ラジアン、 0 以上 π 以下) で、
+/// 文章の埋め込みなど、大きさではなく向きで比べるベクトルのクラスタリングに用いる。
+/// 単位球面上の 2 点の弦の長さは座標の差の絶対値以上なので、分割面までの角度の下界が求まり KdTree の枝刈りが効く。
+/// コサイン距離 (1 - cos θ) の閾値は epsilon_from_cosine_distance() で角度に変換できる。
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct CosinePoint<F, const N: usize>([F; N]);
+
+impl<F: Float + Debug, const N: usize> CosinePoint<F, N> {
+    /// vector を長さ 1 に正規化した点を作る。 vector がゼロベクトルであれば Error::ZeroVector を返す。
+    /// NaN や無限大を含む vector からは is_finite() が false になる点ができ、 dbscan() などの検証で拒否される。
+    pub fn new(vector: [F; N]) -> Result<CosinePoint<F, N>, Error> {
+        let norm = vector.iter().fold(F::zero(), |sum, &c| sum + c * c).sqrt();
+        if norm.is_zero() {
+            return Err(Error::ZeroVector);
+        }
+        Ok(CosinePoint(vector.map(|c| c / norm)))
+    }
+
+    /// 正規化された座標。
+    pub fn coordinates(&self) -> &[F; N] {
+        &self.0
+    }
+
+    /// 2 つのベクトルのコサイン類似度。
+    pub fn cosine_similarity(&self, other: &Self) -> F {
+        let dot = (0..N).fold(F::zero(), |sum, i| sum + self.0[i] * other.0[i]);
+        dot.max(-F::one()).min(F::one())
+    }
+
+    /// コサイン距離 (1 - cos θ) の閾値 cosine_distance を、同じ点を選ぶ角度の epsilon に変換する。
+    /// cosine_distance は 0 以上 2 以下でなければならない。
+    pub fn epsilon_from_cosine_distance(cosine_distance: F) -> F {
+        (F::one() - cosine_distance).max(-F::one()).min(F::one()).acos()
+    }
+}
+
+impl<F: Float + Debug, const N: usize> KdTreeItem for CosinePoint<F, N> {
+    type Measurement = F;
+
+    fn cmp_in_depth(&self, rhs: &Self, depth: usize) -> Ordering {
+        self.0[depth % N]
+            .partial_cmp(&rhs.0[depth % N])
+            .expect("not total order")
+    }
+
+    fn distance(&self, other: &Self) -> F {
+        Self::reduced_to_distance(&self.reduced_distance(other))
+    }
+
+    fn distance_to_axis(&self, other: &Self, depth: usize) -> F {
+        Self::reduced_to_distance(&self.reduced_distance_to_axis(other, depth))
+    }
+
+    /// 弦の長さの 2 乗。なす角について単調に増える。
+    fn reduced_distance(&self, other: &Self) -> F {
+        (0..N).fold(F::zero(), |sum, i| sum + (self.0[i] - other.0[i]).powi(2))
+    }
+
+    fn reduced_distance_to_axis(&self, other: &Self, depth: usize) -> F {
+        let i = depth % N;
+        (self.0[i] - other.0[i]).powi(2)
+    }
+
+    /// 弦の長さ c の 2 乗から、なす角 2 asin(c / 2) を求める。
+    fn reduced_to_distance(reduced: &F) -> F {
+        let two = F::one() + F::one();
+        two * (reduced.sqrt() / two).min(F::one()).asin()
+    }
+
+    fn distance_to_reduced(distance: &F) -> F {
+        let two = F::one() + F::one();
+        let angle = distance.min(F::from(core::f64::consts::PI).expect("must be representable"));
+        (two * (angle / two).sin()).powi(2)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.0.iter().all(|c| c.is_finite())
+    }
+}
+
+/// 次元数を実行時に決める f64 の点。 CSV の列数など、コンパイル時に次元数が分からない場合に用いる。
+/// 距離はユークリッド距離で、同じ KdTree や dbscan() に渡す点はすべて同じ次元数でなければならない。
+/// 次元数の異なる点が混ざっていると、 validate_items() で検証する関数は Error::DimensionMismatch を返す。
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct DynPoint(Vec<f64>);
+
+impl DynPoint {
+    /// coordinates がちょうど dimensions 個の座標を持つ点を作る。
+    /// 数が異なれば、位置を 0 とする Error::DimensionMismatch を返す。 dimensions は 1 以上でなければならない。
+    pub fn new(coordinates: Vec<f64>, dimensions: usize) -> Result<DynPoint, Error> {
+        assert!(dimensions > 0, "dimensions must be positive");
+        if coordinates.len() != dimensions {
+            return Err(Error::DimensionMismatch {
+                index: 0,
+                expected: dimensions,
+                found: coordinates.len(),
+            });
+        }
+        Ok(DynPoint(coordinates))
+    }
+
+    /// row-major で並んだ座標 values を dimensions 個ずつ区切って点の列にする。
+    /// values の長さが dimensions で割り切れなければ、最後の半端な行について Error::DimensionMismatch を返す。
+    pub fn from_rows(values: &[f64], dimensions: usize) -> Result<Vec<DynPoint>, Error> {
+        assert!(dimensions > 0, "dimensions must be positive");
+        let rows = values.chunks_exact(dimensions);
+        if !rows.remainder().is_empty() {
+            return Err(Error::DimensionMismatch {
+                index: values.len() / dimensions,
+                expected: dimensions,
+                found: rows.remainder().len(),
+            });
+        }
+        Ok(rows.map(|row| DynPoint(row.to_vec())).collect())
+    }
+
+    pub fn coordinates(&self) -> &[f64] {
+        &self.0
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn into_coordinates(self) -> Vec<f64> {
+        self.0
+    }
+}
+
+impl KdTreeItem for DynPoint {
+    type Measurement = f64;
+
+    fn cmp_in_depth(&self, rhs: &Self, depth: usize) -> Ordering {
+        let i = depth % self.0.len();
+        self.0[i].partial_cmp(&rhs.0[i]).expect("not total order")
+    }
+
+    fn distance(&self, other: &Self) -> f64 {
+        Float::sqrt(self.reduced_distance(other))
+    }
+
+    fn distance_to_axis(&self, other: &Self, depth: usize) -> f64 {
+        let i = depth % self.0.len();
+        Float::abs(self.0[i] - other.0[i])
+    }
+
+    /// 平方根をとらない 2 乗のユークリッド距離。
+    fn reduced_distance(&self, other: &Self) -> f64 {
+        debug_assert_eq!(self.0.len(), other.0.len(), "dimensions must match");
+        self.0.iter().zip(&other.0).map(|(l, r)| Float::powi(l - r, 2)).sum()
+    }
+
+    fn reduced_distance_to_axis(&self, other: &Self, depth: usize) -> f64 {
+        let i = depth % self.0.len();
+        Float::powi(self.0[i] - other.0[i], 2)
+    }
+
+    fn reduced_to_distance(reduced: &f64) -> f64 {
+        Float::sqrt(*reduced)
+    }
+
+    fn distance_to_reduced(distance: &f64) -> f64 {
+        Float::powi(*distance, 2)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.0.iter().all(|c| c.is_finite())
+    }
+
+    fn runtime_dimensions(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+impl<const N: usize> From<[f64; N]> for DynPoint {
+    /// N は 1 以上でなければならない。
+    fn from(coordinates: [f64; N]) -> DynPoint {
+        assert!(N > 0, "dimensions must be positive");
+        DynPoint(coordinates.to_vec())
+    }
+}
@@ -0,0 +1,103 @@
+//! クラスタリングの前に座標の尺度を揃える前処理。
+//!
+//! epsilon はすべての軸に同じ長さとして効くため、軸ごとに単位や値の幅が大きく異なる入力では、
+//! 幅の大きい軸だけで近傍が決まってしまう。ここでの関数は各軸を同程度の幅に変換し、
+//! 変換に用いた値を Scaling として返す。クラスターの中心などを元の単位に戻すには Scaling::inverse() を用いる。
+
+use alloc::vec::Vec;
+
+use num_traits::Float;
+
+/// 各軸の座標 x を (x - offset) / scale に写す変換。 standardize() や min_max_scale() が入力から求める。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scaling<F, const N: usize> {
+    offset: [F; N],
+    scale: [F; N],
+}
+
+impl<F: Float, const N: usize> Scaling<F, N> {
+    /// offset と scale を直接指定して変換を作る。 scale の各値は 0 であってはならない。
+    pub fn new(offset: [F; N], scale: [F; N]) -> Scaling<F, N> {
+        assert!(scale.iter().all(|s| !s.is_zero()), "scale must not be zero");
+        Scaling { offset, scale }
+    }
+
+    /// 各軸から引く値。
+    pub fn offset(&self) -> &[F; N] {
+        &self.offset
+    }
+
+    /// offset を引いた後に各軸を割る値。
+    pub fn scale(&self) -> &[F; N] {
+        &self.scale
+    }
+
+    /// point を変換する。入力から求めた変換であれば、新しい点も同じ尺度に揃えられる。
+    pub fn transform(&self, point: &[F; N]) -> [F; N] {
+        core::array::from_fn(|d| (point[d] - self.offset[d]) / self.scale[d])
+    }
+
+    /// items のすべての点を変換する。
+    pub fn transform_all(&self, items: &[[F; N]]) -> Vec<[F; N]> {
+        items.iter().map(|point| self.transform(point)).collect()
+    }
+
+    /// 変換した点 point を元の座標に戻す。
+    pub fn inverse(&self, point: &[F; N]) -> [F; N] {
+        core::array::from_fn(|d| point[d] * self.scale[d] + self.offset[d])
+    }
+
+    /// items のすべての点を元の座標に戻す。
+    pub fn inverse_all(&self, items: &[[F; N]]) -> Vec<[F; N]> {
+        items.iter().map(|point| self.inverse(point)).collect()
+    }
+}
+
+/// 各軸を平均 0 、標準偏差 1 に揃えた点と、その変換を返す。標準偏差は要素数で割る母標準偏差を用いる。
+/// すべての点で値が等しい軸は平均を引くだけにする。 items が空であれば何もしない変換を返す。
+/// NaN や無限大を含む軸は、変換後の値もすべて NaN になる。
+pub fn standardize<F: Float, const N: usize>(items: &[[F; N]]) -> (Vec<[F; N]>, Scaling<F, N>) {
+    let count = F::from(items.len()).expect("item count must be representable");
+    let mut offset = [F::zero(); N];
+    let mut scale = [F::one(); N];
+    if !items.is_empty() {
+        for d in 0..N {
+            let mean = items.iter().fold(F::zero(), |sum, point| sum + point[d]) / count;
+            let variance = items
+                .iter()
+                .fold(F::zero(), |sum, point| sum + (point[d] - mean).powi(2))
+                / count;
+            offset[d] = mean;
+            scale[d] = nonzero_scale(variance.sqrt());
+        }
+    }
+    let scaling = Scaling { offset, scale };
+    (scaling.transform_all(items), scaling)
+}
+
+/// 各軸を最小値 0 、最大値 1 に揃えた点と、その変換を返す。
+/// すべての点で値が等しい軸は最小値を引くだけにする。 items が空であれば何もしない変換を返す。
+/// NaN は最小値と最大値の計算から除かれる。
+pub fn min_max_scale<F: Float, const N: usize>(items: &[[F; N]]) -> (Vec<[F; N]>, Scaling<F, N>) {
+    let mut offset = [F::zero(); N];
+    let mut scale = [F::one(); N];
+    if !items.is_empty() {
+        for d in 0..N {
+            let min = items.iter().fold(F::infinity(), |min, point| min.min(point[d]));
+            let max = items.iter().fold(F::neg_infinity(), |max, point| max.max(point[d]));
+            offset[d] = min;
+            scale[d] = nonzero_scale(max - min);
+        }
+    }
+    let scaling = Scaling { offset, scale };
+    (scaling.transform_all(items), scaling)
+}
+
+/// 幅 width で割る代わりに、幅が 0 であれば 1 で割る。
+fn nonzero_scale<F: Float>(width: F) -> F {
+    if width.is_zero() {
+        F::one()
+    } else {
+        width
+    }
+}
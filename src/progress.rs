@@ -0,0 +1,233 @@
+use alloc::sync::Arc;
+use core::{
+    error::Error,
+    fmt::{self, Display},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::{checkpoint::DbscanCheckpoint, dbscan::DbscanTimings};
+
+/// Dbscan::run_with_progress() が定期的に通知する進捗。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// 調べ終えた数。
+    pub processed: usize,
+
+    /// 調べる総数。コア点の判定と併合で各要素を 1 回ずつ調べるため、要素数の 2 倍になる。
+    pub total: usize,
+
+    /// 見つかったクラスターの数。クラスターの併合が終わるまで分からないため、最後の通知以外では 0 になる。
+    pub clusters: usize,
+
+    /// 開始からの経過時間。時刻を取得できない std 無しの環境や wasm32-unknown-unknown では常に 0 になる。
+    pub elapsed: Duration,
+
+    /// これまでの速さから見積もった残り時間。まだ何も処理していないか、経過時間が分からなければ None になる。
+    pub eta: Option<Duration>,
+}
+
+/// 時間のかかる処理を別のスレッドなどから中断するためのトークン。複製したトークン同士は状態を共有する。
+/// Dbscan::run_cancellable() や KdTree::try_construct_with_options() などが定期的に確認する。
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// 中断を要求する。実行中の処理は次に確認した時点で打ち切られる。
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// CancellationToken によって処理が中断されたことを表すエラー。
+/// partial には中断されるまでに得られた途中の結果が入る。途中の結果を持たない処理では () になる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Cancelled<P = ()> {
+    pub partial: P,
+}
+
+impl<P> Display for Cancelled<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "computation was cancelled")
+    }
+}
+
+impl<P: fmt::Debug> Error for Cancelled<P> {}
+
+/// 進捗の通知と中断の確認をまとめたもの。
+pub(crate) struct Monitor<'a> {
+    progress: &'a mut dyn FnMut(ProgressEvent),
+    cancellation: &'a CancellationToken,
+    start: Option<Instant>,
+    timings: Option<DbscanTimings>,
+    checkpoint: Option<Checkpointing<'a>>,
+}
+
+/// Monitor がチェックポイントを書き出すための設定。
+struct Checkpointing<'a> {
+    /// 再開する状態。 expand_clusters() が最初に取り出す。
+    resume: Option<DbscanCheckpoint>,
+
+    /// 書き出す間隔 (調べた要素の数) 。
+    interval: usize,
+
+    /// 最後に書き出したか再開した時点で調べ終えていた数。
+    last: usize,
+
+    save: &'a mut dyn FnMut(&DbscanCheckpoint),
+}
+
+impl<'a> Monitor<'a> {
+    pub fn new(progress: &'a mut dyn FnMut(ProgressEvent), cancellation: &'a CancellationToken) -> Monitor<'a> {
+        Monitor {
+            progress,
+            cancellation,
+            start: now(),
+            timings: None,
+            checkpoint: None,
+        }
+    }
+
+    /// 段階ごとの所要時間も記録する Monitor にする。
+    pub fn with_timings(mut self) -> Monitor<'a> {
+        self.timings = Some(DbscanTimings::default());
+        self
+    }
+
+    /// resume の状態から再開し、要素を interval 個調べるごとに save でチェックポイントを書き出す Monitor にする。
+    pub fn with_checkpoint(
+        mut self,
+        resume: Option<DbscanCheckpoint>,
+        interval: usize,
+        save: &'a mut dyn FnMut(&DbscanCheckpoint),
+    ) -> Monitor<'a> {
+        self.checkpoint = Some(Checkpointing {
+            last: resume.as_ref().map_or(0, |resume| resume.processed),
+            resume,
+            interval,
+            save,
+        });
+        self
+    }
+
+    /// 再開する状態があれば取り出す。
+    pub fn take_resume(&mut self) -> Option<DbscanCheckpoint> {
+        self.checkpoint.as_mut().and_then(|checkpoint| checkpoint.resume.take())
+    }
+
+    /// チェックポイントを書き出していれば true を返す。
+    pub fn is_checkpointing(&self) -> bool {
+        self.checkpoint.is_some()
+    }
+
+    /// processed 個を調べ終えた時点で、前回から間隔が空いていれば true を返す。
+    pub fn checkpoint_due(&self, processed: usize) -> bool {
+        self.checkpoint
+            .as_ref()
+            .is_some_and(|checkpoint| processed >= checkpoint.last + checkpoint.interval)
+    }
+
+    /// チェックポイントを書き出していれば checkpoint を渡す。
+    pub fn save_checkpoint(&mut self, checkpoint: &DbscanCheckpoint) {
+        if let Some(checkpointing) = self.checkpoint.as_mut() {
+            checkpointing.last = checkpoint.processed;
+            (checkpointing.save)(checkpoint);
+        }
+    }
+
+    /// 段階ごとの所要時間を記録していれば true を返す。
+    pub fn is_timing(&self) -> bool {
+        self.timings.is_some()
+    }
+
+    /// 所要時間を記録していれば、 stage で選んだ段階に elapsed を加える。
+    pub fn add_time(&mut self, elapsed: Duration, stage: fn(&mut DbscanTimings) -> &mut Duration) {
+        if let Some(timings) = self.timings.as_mut() {
+            *stage(timings) += elapsed;
+        }
+    }
+
+    /// これまでに記録した段階ごとの所要時間。
+    pub fn timings(&self) -> Option<DbscanTimings> {
+        self.timings
+    }
+
+    /// 中断が要求されていなければ進捗を通知して true を返す。要求されていれば false を返す。
+    pub fn report(&mut self, processed: usize, total: usize, clusters: usize) -> bool {
+        if self.cancellation.is_cancelled() {
+            return false;
+        }
+
+        let elapsed = self.start.map_or(Duration::ZERO, |start| start.elapsed());
+        let eta = self
+            .start
+            .filter(|_| processed > 0)
+            .map(|_| elapsed.mul_f64((total - processed) as f64 / processed as f64));
+        (self.progress)(ProgressEvent {
+            processed,
+            total,
+            clusters,
+            elapsed,
+            eta,
+        });
+        true
+    }
+}
+
+/// 経過時間を測るための開始時刻。時刻を取得できない環境では経過時間が常に 0 になる。
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stopwatch(Option<Instant>);
+
+impl Stopwatch {
+    pub fn start() -> Stopwatch {
+        Stopwatch(now())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.0.map_or(Duration::ZERO, |start| start.elapsed())
+    }
+}
+
+/// 現在時刻を返す。 wasm32-unknown-unknown では Instant::now() が panic するため None を返す。
+#[cfg(feature = "std")]
+fn now() -> Option<Instant> {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        None
+    } else {
+        Some(Instant::now())
+    }
+}
+
+/// std が無ければ時刻を取得できないため、常に None を返す。
+#[cfg(not(feature = "std"))]
+fn now() -> Option<Instant> {
+    None
+}
+
+/// std 無しの環境での Instant の代わり。値を作れないため elapsed() は呼ばれない。
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy)]
+enum Instant {}
+
+#[cfg(not(feature = "std"))]
+impl Instant {
+    fn elapsed(&self) -> Duration {
+        match *self {}
+    }
+}
+
+/// 進捗を通知する間隔 (調べた要素の数) 。
+pub(crate) const PROGRESS_INTERVAL: usize = 4096;
+
+/// 進捗を通知しない処理で CancellationToken を確認する間隔 (探索の回数) 。
+pub(crate) const CANCELLATION_CHECK_INTERVAL: usize = 1024;
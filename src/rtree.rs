@@ -0,0 +1,453 @@
+use alloc::{vec, vec::Vec};
+use core::fmt::Debug;
+
+use num_traits::Float;
+
+use crate::{
+    dbscan::{dbscan_with_index, DbscanResult},
+    error::{check_radius, Error},
+    index::SpatialIndex,
+    knn::KBest,
+};
+
+/// 各軸に平行な N 次元の直方体。点は min と max が等しい直方体として表す。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect<T, const N: usize> {
+    pub min: [T; N],
+    pub max: [T; N],
+}
+
+impl<T: Float, const N: usize> Rect<T, N> {
+    /// min と max を角に持つ直方体を作る。各軸で min が max 以下でなければならない。
+    pub fn new(min: [T; N], max: [T; N]) -> Rect<T, N> {
+        Rect { min, max }
+    }
+
+    /// すべての角の座標が有限であれば true を返す。
+    pub fn is_finite(&self) -> bool {
+        self.min.iter().chain(&self.max).all(|x| x.is_finite())
+    }
+
+    /// 1 点だけからなる直方体を作る。
+    pub fn point(point: [T; N]) -> Rect<T, N> {
+        Rect { min: point, max: point }
+    }
+
+    /// 2 つの直方体の間の最短のユークリッド距離を返す。重なっていれば 0 になる。
+    /// 三角不等式は満たさないが、 DBSCAN の近傍の判定には用いることができる。
+    pub fn distance(&self, other: &Rect<T, N>) -> T {
+        (0..N)
+            .map(|a| {
+                let gap = (self.min[a] - other.max[a])
+                    .max(other.min[a] - self.max[a])
+                    .max(T::zero());
+                gap * gap
+            })
+            .fold(T::zero(), |a, x| a + x)
+            .sqrt()
+    }
+
+    /// 両方を含む最小の直方体を返す。
+    pub fn union(&self, other: &Rect<T, N>) -> Rect<T, N> {
+        Rect {
+            min: core::array::from_fn(|a| self.min[a].min(other.min[a])),
+            max: core::array::from_fn(|a| self.max[a].max(other.max[a])),
+        }
+    }
+
+    /// 体積を返す。
+    pub fn area(&self) -> T {
+        (0..N).map(|a| self.max[a] - self.min[a]).fold(T::one(), |a, x| a * x)
+    }
+
+    /// 各辺の長さの合計を返す。
+    pub fn margin(&self) -> T {
+        (0..N).map(|a| self.max[a] - self.min[a]).fold(T::zero(), |a, x| a + x)
+    }
+
+    /// 共通部分の体積を返す。
+    pub fn overlap(&self, other: &Rect<T, N>) -> T {
+        (0..N)
+            .map(|a| (self.max[a].min(other.max[a]) - self.min[a].max(other.min[a])).max(T::zero()))
+            .fold(T::one(), |a, x| a * x)
+    }
+
+    fn center(&self, axis: usize) -> T {
+        (self.min[axis] + self.max[axis]) / (T::one() + T::one())
+    }
+}
+
+impl<T: Float, const N: usize> From<[T; N]> for Rect<T, N> {
+    fn from(point: [T; N]) -> Rect<T, N> {
+        Rect::point(point)
+    }
+}
+
+/// 直方体を格納する R*-tree 。
+/// 構築時は STR (Sort-Tile-Recursive) 法で一括して詰め込み、 insert() では R*-tree の規則で挿入先と分割を決める。
+/// 強制再挿入は行わない。
+pub struct RTree<T, const N: usize> {
+    items: Vec<Rect<T, N>>,
+    nodes: Vec<RNode<T, N>>,
+    root_index: Option<usize>,
+}
+
+#[derive(Debug)]
+struct RNode<T, const N: usize> {
+    /// children のすべてを含む最小の直方体。
+    mbr: Rect<T, N>,
+
+    /// true であれば children は items 上の位置、そうでなければ nodes 上の位置を表す。
+    leaf: bool,
+    children: Vec<usize>,
+}
+
+impl<T: Debug + Float, const N: usize> RTree<T, N> {
+    /// items を STR 法で詰め込んだ R-tree を構築する。要素は items 上の位置で参照される。
+    /// 座標に NaN や無限大を含む直方体があれば Error::NonFiniteInput を返す。
+    pub fn construct(items: impl Into<Vec<Rect<T, N>>>) -> Result<RTree<T, N>, Error> {
+        let items = items.into();
+        validate_rects(&items)?;
+        Ok(RTree::construct_unchecked(items))
+    }
+
+    /// construct() と同様だが、入力を検証しない。 NaN を含む直方体があると panic する。
+    pub fn construct_unchecked(items: impl Into<Vec<Rect<T, N>>>) -> RTree<T, N> {
+        let items = items.into();
+        let mut tree = RTree {
+            items,
+            nodes: Vec::new(),
+            root_index: None,
+        };
+        if tree.items.is_empty() {
+            return tree;
+        }
+
+        // 葉から 1 段ずつ詰め込み、 1 ノードになったら根とする
+        let mut level: Vec<usize> = (0..tree.items.len()).collect();
+        let mut leaf = true;
+        loop {
+            let groups = {
+                let rect_of = |i: usize| if leaf { tree.items[i] } else { tree.nodes[i].mbr };
+                str_groups(&mut level, &rect_of)
+            };
+            level = groups
+                .into_iter()
+                .map(|children| tree.allocate_node(leaf, children))
+                .collect();
+            leaf = false;
+            if level.len() == 1 {
+                break;
+            }
+        }
+        tree.root_index = Some(level[0]);
+
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 構築時、または insert() で index 番目に渡された直方体を返す。
+    pub fn get(&self, index: usize) -> Option<&Rect<T, N>> {
+        self.items.get(index)
+    }
+
+    /// 直方体を挿入し、割り当てられた位置を返す。入力を検証しないため、 NaN を含む直方体を挿入すると panic する。
+    pub fn insert(&mut self, rect: Rect<T, N>) -> usize {
+        let index = self.items.len();
+        self.items.push(rect);
+
+        let Some(root_index) = self.root_index else {
+            self.root_index = Some(self.allocate_node(true, vec![index]));
+            return index;
+        };
+
+        // 挿入する葉までの経路を選び、経路上の外接直方体を広げる
+        let mut path = vec![root_index];
+        loop {
+            let node_index = *path.last().expect("path must not be empty");
+            let node = &mut self.nodes[node_index];
+            node.mbr = node.mbr.union(&rect);
+            if node.leaf {
+                break;
+            }
+            let child = self.choose_subtree(node_index, &rect);
+            path.push(child);
+        }
+        let leaf_index = *path.last().expect("path must not be empty");
+        self.nodes[leaf_index].children.push(index);
+
+        // あふれたノードを葉から順に分割する
+        for depth in (0..path.len()).rev() {
+            let node_index = path[depth];
+            if self.nodes[node_index].children.len() <= MAX_ENTRIES {
+                break;
+            }
+
+            let sibling = self.split(node_index);
+            match depth.checked_sub(1) {
+                Some(parent_depth) => self.nodes[path[parent_depth]].children.push(sibling),
+                None => self.root_index = Some(self.allocate_node(false, vec![node_index, sibling])),
+            }
+        }
+
+        index
+    }
+
+    /// node の子のうち、 rect を加えたときに最も適するものを選ぶ。
+    /// 子が葉であれば重なりの増分、そうでなければ体積の増分が最小のものを選び、同じであれば体積の小さいものを選ぶ。
+    fn choose_subtree(&self, node_index: usize, rect: &Rect<T, N>) -> usize {
+        let node = &self.nodes[node_index];
+        let children_are_leaves = self.nodes[node.children[0]].leaf;
+        let cost = |child: usize| {
+            let mbr = &self.nodes[child].mbr;
+            let enlarged = mbr.union(rect);
+            let primary = if children_are_leaves {
+                node.children
+                    .iter()
+                    .filter(|&&other| other != child)
+                    .map(|&other| {
+                        let other = &self.nodes[other].mbr;
+                        enlarged.overlap(other) - mbr.overlap(other)
+                    })
+                    .fold(T::zero(), |a, x| a + x)
+            } else {
+                enlarged.area() - mbr.area()
+            };
+            (primary, enlarged.area() - mbr.area(), mbr.area())
+        };
+
+        *node
+            .children
+            .iter()
+            .min_by(|&&lhs, &&rhs| cost(lhs).partial_cmp(&cost(rhs)).expect("not total order"))
+            .expect("inner node must have children")
+    }
+
+    /// あふれた node を R*-tree の規則で 2 つに分け、新しく作った方のノードを返す。
+    /// 外接直方体の周長の合計が最小となる軸を選び、その軸で重なり、体積の順に最小となる分け方をとる。
+    fn split(&mut self, node_index: usize) -> usize {
+        let leaf = self.nodes[node_index].leaf;
+        let rect_of = |i: usize| if leaf { self.items[i] } else { self.nodes[i].mbr };
+        let children = &self.nodes[node_index].children;
+
+        let mut best_axis = (T::infinity(), Vec::new());
+        for axis in 0..N {
+            let mut margin = T::zero();
+            let mut sorts = Vec::with_capacity(2);
+            for by_max in [false, true] {
+                let mut sorted = children.clone();
+                sorted.sort_by(|&lhs, &rhs| {
+                    let (lhs, rhs) = (rect_of(lhs), rect_of(rhs));
+                    let (lhs, rhs) = if by_max {
+                        (lhs.max[axis], rhs.max[axis])
+                    } else {
+                        (lhs.min[axis], rhs.min[axis])
+                    };
+                    lhs.partial_cmp(&rhs).expect("not total order")
+                });
+                for k in MIN_ENTRIES..=sorted.len() - MIN_ENTRIES {
+                    let (first, second) = sorted.split_at(k);
+                    margin =
+                        margin + bounding_rect(first, &rect_of).margin() + bounding_rect(second, &rect_of).margin();
+                }
+                sorts.push(sorted);
+            }
+            if margin < best_axis.0 {
+                best_axis = (margin, sorts);
+            }
+        }
+
+        let mut best: Option<((T, T), Vec<usize>, usize)> = None;
+        for sorted in best_axis.1 {
+            for k in MIN_ENTRIES..=sorted.len() - MIN_ENTRIES {
+                let (first, second) = sorted.split_at(k);
+                let (first, second) = (bounding_rect(first, &rect_of), bounding_rect(second, &rect_of));
+                let cost = (first.overlap(&second), first.area() + second.area());
+                if best.as_ref().is_none_or(|(best_cost, _, _)| cost < *best_cost) {
+                    best = Some((cost, sorted.clone(), k));
+                }
+            }
+        }
+
+        let (_, mut sorted, k) = best.expect("split candidates must exist");
+        let second = sorted.split_off(k);
+        let first_mbr = bounding_rect(&sorted, &rect_of);
+        let node = &mut self.nodes[node_index];
+        node.children = sorted;
+        node.mbr = first_mbr;
+        self.allocate_node(leaf, second)
+    }
+
+    fn allocate_node(&mut self, leaf: bool, children: Vec<usize>) -> usize {
+        let mbr = if leaf {
+            bounding_rect(&children, &|i| self.items[i])
+        } else {
+            bounding_rect(&children, &|i| self.nodes[i].mbr)
+        };
+        self.nodes.push(RNode { mbr, leaf, children });
+        self.nodes.len() - 1
+    }
+}
+
+impl<T: Debug + Float, const N: usize> SpatialIndex<Rect<T, N>> for RTree<T, N> {
+    type Measurement = T;
+
+    fn range(&self, query: &Rect<T, N>, radius: &T) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack: Vec<_> = self.root_index.into_iter().collect();
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.mbr.distance(query) > *radius {
+                continue;
+            }
+
+            if node.leaf {
+                found.extend(
+                    node.children
+                        .iter()
+                        .filter(|&&i| self.items[i].distance(query) <= *radius),
+                );
+            } else {
+                stack.extend(&node.children);
+            }
+        }
+        found
+    }
+
+    fn nearest_n(&self, query: &Rect<T, N>, k: usize) -> Vec<(usize, T)> {
+        let mut best = KBest::new(k);
+        if k == 0 {
+            return best.into_vec();
+        }
+
+        // (ノード, query から外接直方体までの距離) を積み、近い子ノードから探索する
+        let mut stack: Vec<_> = self
+            .root_index
+            .map(|r| (r, self.nodes[r].mbr.distance(query)))
+            .into_iter()
+            .collect();
+        while let Some((node_index, lower_bound)) = stack.pop() {
+            if best.excludes(&lower_bound) {
+                continue;
+            }
+
+            let node = &self.nodes[node_index];
+            if node.leaf {
+                for &i in &node.children {
+                    best.offer(i, self.items[i].distance(query));
+                }
+            } else {
+                let mut children: Vec<_> = node
+                    .children
+                    .iter()
+                    .map(|&c| (c, self.nodes[c].mbr.distance(query)))
+                    .collect();
+                children.sort_by(|lhs, rhs| rhs.1.partial_cmp(&lhs.1).expect("not total order"));
+                stack.extend(children);
+            }
+        }
+
+        best.into_vec()
+    }
+}
+
+/// 直方体に DBSCAN を適用する。直方体同士の距離は Rect::distance() で計算される。
+/// epsilon が NaN であれば Error::InvalidRadius を、座標に NaN や無限大を含む直方体があれば Error::NonFiniteInput を返す。
+pub fn dbscan_rects<T: Debug + Float, const N: usize>(
+    rects: impl AsRef<[Rect<T, N>]>,
+    epsilon: T,
+    min_items: usize,
+) -> Result<DbscanResult, Error> {
+    let rects = rects.as_ref();
+    check_radius(&epsilon)?;
+    validate_rects(rects)?;
+    Ok(dbscan_rects_unchecked(rects, epsilon, min_items))
+}
+
+/// dbscan_rects() と同様だが、入力を検証しない。 NaN を含む直方体があると panic する。
+pub fn dbscan_rects_unchecked<T: Debug + Float, const N: usize>(
+    rects: impl AsRef<[Rect<T, N>]>,
+    epsilon: T,
+    min_items: usize,
+) -> DbscanResult {
+    let rects = rects.as_ref();
+    let rtree = RTree::construct_unchecked(rects);
+    dbscan_with_index(rects, &rtree, epsilon, min_items)
+}
+
+/// rects のすべての直方体が Rect::is_finite() を満たすかを調べる。
+/// 満たさない直方体があれば、その位置を Error::NonFiniteInput で返す。
+fn validate_rects<T: Float, const N: usize>(rects: &[Rect<T, N>]) -> Result<(), Error> {
+    let indices: Vec<_> = rects
+        .iter()
+        .enumerate()
+        .filter(|(_, rect)| !rect.is_finite())
+        .map(|(i, _)| i)
+        .collect();
+    if indices.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::NonFiniteInput { indices })
+    }
+}
+
+/// ノードが持つ子の数の上限。
+const MAX_ENTRIES: usize = 16;
+
+/// 分割後のノードが持つ子の数の下限。 MAX_ENTRIES の 40% とする。
+const MIN_ENTRIES: usize = 6;
+
+/// entries を STR 法で最大 MAX_ENTRIES 個ずつのまとまりに分ける。
+fn str_groups<T: Float, const N: usize>(
+    entries: &mut [usize],
+    rect_of: &impl Fn(usize) -> Rect<T, N>,
+) -> Vec<Vec<usize>> {
+    let mut groups = Vec::new();
+
+    // (範囲の先頭, 範囲の要素数, 軸) を積み、最後の軸まで来たら MAX_ENTRIES 個ずつに区切る
+    let mut stack = vec![(0, entries.len(), 0)];
+    while let Some((start, len, axis)) = stack.pop() {
+        let part = &mut entries[start..start + len];
+        part.sort_by(|&lhs, &rhs| {
+            rect_of(lhs)
+                .center(axis)
+                .partial_cmp(&rect_of(rhs).center(axis))
+                .expect("not total order")
+        });
+
+        if axis + 1 >= N {
+            groups.extend(part.chunks(MAX_ENTRIES).map(|c| c.to_vec()));
+            continue;
+        }
+
+        // 残りの軸数を d として、ノード数の d 乗根個の板に分ける
+        let node_count = len.div_ceil(MAX_ENTRIES);
+        let slab_count = (node_count as f64).powf(1.0 / (N - axis) as f64).ceil() as usize;
+        let slab_len = MAX_ENTRIES * node_count.div_ceil(slab_count.max(1));
+        let mut slabs = Vec::new();
+        let mut slab_start = start;
+        while slab_start < start + len {
+            let slab_end = (slab_start + slab_len).min(start + len);
+            slabs.push((slab_start, slab_end - slab_start, axis + 1));
+            slab_start = slab_end;
+        }
+        stack.extend(slabs.into_iter().rev());
+    }
+
+    groups
+}
+
+fn bounding_rect<T: Float, const N: usize>(entries: &[usize], rect_of: &impl Fn(usize) -> Rect<T, N>) -> Rect<T, N> {
+    entries
+        .iter()
+        .map(|&i| rect_of(i))
+        .reduce(|a, b| a.union(&b))
+        .expect("entries must not be empty")
+}
@@ -0,0 +1,92 @@
+use wide::{f32x8, f64x4};
+
+use crate::metric::{Euclidean, Metric};
+
+/// SIMD で距離を計算するユークリッド距離 (L2) 。距離の値は Euclidean と一致する。
+/// KdTree の範囲探索では葉ノードの要素をレーン数ずつまとめて計算するため、
+/// バケットの大きい木 (KdTreeOptions::bucket_size) で探索半径が大きい場合に効果がある。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimdEuclidean;
+
+macro_rules! impl_simd_euclidean {
+    ($scalar:ty, $vector:ty, $lanes:expr) => {
+        impl<const N: usize> Metric<[$scalar; N]> for SimdEuclidean {
+            type Measurement = $scalar;
+
+            fn distance(&self, lhs: &[$scalar; N], rhs: &[$scalar; N]) -> $scalar {
+                Euclidean.distance(lhs, rhs)
+            }
+
+            fn distance_to_axis(&self, query: &[$scalar; N], rhs: &[$scalar; N], depth: usize) -> $scalar {
+                Euclidean.distance_to_axis(query, rhs, depth)
+            }
+
+            fn reduced_distance(&self, lhs: &[$scalar; N], rhs: &[$scalar; N]) -> $scalar {
+                Euclidean.reduced_distance(lhs, rhs)
+            }
+
+            fn reduced_distance_to_axis(&self, query: &[$scalar; N], rhs: &[$scalar; N], depth: usize) -> $scalar {
+                Euclidean.reduced_distance_to_axis(query, rhs, depth)
+            }
+
+            fn reduced_to_distance(&self, reduced: &$scalar) -> $scalar {
+                reduced.sqrt()
+            }
+
+            fn distance_to_reduced(&self, distance: &$scalar) -> $scalar {
+                distance * distance
+            }
+
+            fn reduced_range_batch<'a>(
+                &self,
+                query: &[$scalar; N],
+                others: impl Iterator<Item = &'a [$scalar; N]>,
+                reduced_radius: &$scalar,
+                mut found: impl FnMut(usize, $scalar),
+            ) where
+                [$scalar; N]: 'a,
+            {
+                let radius = <$vector>::splat(*reduced_radius);
+                let mut scan = |coordinates: &[[$scalar; $lanes]; N], count: usize, base: usize| {
+                    // スカラー版と同じく軸の順に足し合わせ、値を一致させる
+                    let mut sum = <$vector>::splat(0.0);
+                    for (axis, lanes) in coordinates.iter().enumerate() {
+                        let difference = <$vector>::splat(query[axis]) - <$vector>::new(*lanes);
+                        sum += difference * difference;
+                    }
+
+                    // 詰めていない余りのレーンは捨てる
+                    let mut mask = sum.simd_le(radius).to_bitmask() & ((1 << count) - 1);
+                    let distances = sum.to_array();
+                    while mask != 0 {
+                        let lane = mask.trailing_zeros() as usize;
+                        found(base + lane, distances[lane]);
+                        mask &= mask - 1;
+                    }
+                };
+
+                // レーン数ずつ軸ごとの配列に詰め替えて計算する
+                let mut coordinates = [[0.0; $lanes]; N];
+                let (mut count, mut base) = (0, 0);
+                for other in others {
+                    for (axis, x) in other.iter().enumerate() {
+                        coordinates[axis][count] = *x;
+                    }
+                    count += 1;
+                    if count == $lanes {
+                        scan(&coordinates, count, base);
+                        base += count;
+                        count = 0;
+                    }
+                }
+                if count > 0 {
+                    scan(&coordinates, count, base);
+                }
+            }
+        }
+    };
+}
+
+impl_simd_euclidean!(f32, f32x8, 8);
+impl_simd_euclidean!(f64, f64x4, 4);
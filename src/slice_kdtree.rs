@@ -0,0 +1,429 @@
+use alloc::{collections::BinaryHeap, vec, vec::Vec};
+use core::{
+    cmp::Ordering,
+    ops::{ControlFlow, Range},
+};
+
+use crate::{
+    error::Error,
+    index::SpatialIndex,
+    kdtree::{count_until, validate_items, KdTreeItem, KdTreeOptions, DEFAULT_BUCKET_SIZE},
+    metric::{ItemMetric, Metric},
+};
+
+/// 要素の列を借用し、その位置だけを並べ替えて持つ静的な k-d tree 。
+/// ノードは持たず、 order の範囲の中央の要素で分割する。 bucket_size 以下の範囲は分割せずに葉とする。
+/// 要素を複製しないため、 KdTree に比べて構築が速く、要素ごとに 4 バイトしか使わない。要素数は u32::MAX までに制限される。
+pub struct SliceKdTree<'a, T, M = ItemMetric> {
+    items: &'a [T],
+
+    /// items の位置を木の順に並べたもの。
+    order: Vec<u32>,
+
+    metric: M,
+    bucket_size: usize,
+}
+
+impl<'a, T: KdTreeItem> SliceKdTree<'a, T> {
+    /// items から k-d tree を構築する。座標に NaN や無限大を含む要素があれば Error::NonFiniteInput を返す。
+    pub fn construct(items: &'a [T]) -> Result<SliceKdTree<'a, T>, Error> {
+        validate_items(items)?;
+        Ok(SliceKdTree::construct_with_metric(items, ItemMetric))
+    }
+}
+
+impl<'a, T: KdTreeItem, M: Metric<T>> SliceKdTree<'a, T, M> {
+    /// 距離の計算に metric を用いる k-d tree を構築する。入力は検証しない。
+    pub fn construct_with_metric(items: &'a [T], metric: M) -> SliceKdTree<'a, T, M> {
+        SliceKdTree::construct_with_options(items, metric, KdTreeOptions::default())
+    }
+
+    /// options の設定で k-d tree を構築する。
+    pub fn construct_with_options(items: &'a [T], metric: M, options: KdTreeOptions) -> SliceKdTree<'a, T, M> {
+        let mut tree = SliceKdTree::allocate(items, metric, options);
+        construct_part(items, &mut tree.order, 0, tree.bucket_size);
+        tree
+    }
+
+    /// construct_with_metric() の並列版。得られるツリーの構造は construct_with_metric() と同一になる。
+    #[cfg(feature = "parallel")]
+    pub fn construct_par_with_metric(items: &'a [T], metric: M) -> SliceKdTree<'a, T, M>
+    where
+        T: Sync,
+    {
+        let mut tree = SliceKdTree::allocate(items, metric, KdTreeOptions::default());
+        construct_part_par(items, &mut tree.order, 0, tree.bucket_size);
+        tree
+    }
+
+    fn allocate(items: &'a [T], metric: M, options: KdTreeOptions) -> SliceKdTree<'a, T, M> {
+        let len = u32::try_from(items.len()).expect("SliceKdTree supports at most u32::MAX items");
+        SliceKdTree {
+            items,
+            order: (0..len).collect(),
+            metric,
+            bucket_size: options.bucket_size.max(1),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// 構築時に渡された要素の列を返す。
+    pub fn items(&self) -> &'a [T] {
+        self.items
+    }
+
+    /// query に近い順に最大 k 個の要素の items 上の位置と距離を返す。
+    pub fn nearest_n_indices(&self, query: &T, k: usize) -> Vec<(usize, M::Measurement)> {
+        self.search_nearest_n(query, k)
+            .into_sorted_vec()
+            .into_iter()
+            .map(|c| (c.0, self.metric.reduced_to_distance(&c.1)))
+            .collect()
+    }
+
+    /// query から radius 以内 (境界を含む) にある要素の items 上の位置をすべて返す。順序は不定。
+    pub fn range_indices(&self, query: &T, radius: &M::Measurement) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.search_range(query, radius, |index, _| found.push(index));
+        found
+    }
+
+    /// range_indices() と同様だが、 query からの距離も返す。
+    pub fn range_indices_with_distances(&self, query: &T, radius: &M::Measurement) -> Vec<(usize, M::Measurement)> {
+        let mut found = Vec::new();
+        self.search_range(query, radius, |index, distance| {
+            found.push((index, self.metric.reduced_to_distance(&distance)))
+        });
+        found
+    }
+
+    /// query から radius 以内にある要素の位置を、 Metric::reduced_distance() の尺度の距離とともに found に渡す。
+    pub(crate) fn search_range(
+        &self,
+        query: &T,
+        radius: &M::Measurement,
+        mut found: impl FnMut(usize, M::Measurement),
+    ) {
+        self.search_range_while(query, radius, |index, distance| {
+            found(index, distance);
+            ControlFlow::Continue(())
+        });
+    }
+
+    /// search_range() と同様だが、 found が ControlFlow::Break を返したら探索を打ち切る。
+    /// 打ち切った後は found を呼ばない。
+    fn search_range_while(
+        &self,
+        query: &T,
+        radius: &M::Measurement,
+        mut found: impl FnMut(usize, M::Measurement) -> ControlFlow<()>,
+    ) {
+        let radius = self.metric.distance_to_reduced(radius);
+        let mut stack = vec![(0..self.order.len(), 0)];
+        while let Some((range, depth)) = stack.pop() {
+            if range.is_empty() {
+                continue;
+            }
+
+            // 葉であれば範囲内のすべての要素を、そうでなければ中央の要素を調べる
+            let Some(mid) = self.mid(&range) else {
+                let part = &self.order[range];
+                let others = part.iter().map(|&i| &self.items[i as usize]);
+                let mut flow = ControlFlow::Continue(());
+                self.metric
+                    .reduced_range_batch(query, others, &radius, |position, distance| {
+                        if flow.is_continue() {
+                            flow = found(part[position] as usize, distance);
+                        }
+                    });
+                if flow.is_break() {
+                    return;
+                }
+                continue;
+            };
+            let pivot = &self.items[self.order[mid] as usize];
+            let distance = self.metric.reduced_distance(query, pivot);
+            if distance <= radius && found(self.order[mid] as usize, distance).is_break() {
+                return;
+            }
+
+            // radius が分割面に届いていれば逆側も探索する。分割面上の要素はどちらの側にも入りうるので境界を含める
+            let (first, second) = split_ranges(&range, mid, query.cmp_in_depth(pivot, depth));
+            if self.metric.reduced_distance_to_axis(query, pivot, depth) <= radius {
+                stack.push((second, depth + 1));
+            }
+            stack.push((first, depth + 1));
+        }
+    }
+
+    /// 厳密な k 近傍探索で候補を集める。
+    fn search_nearest_n(&self, query: &T, max_count: usize) -> BinaryHeap<Candidate<M::Measurement>> {
+        let mut candidates = BinaryHeap::with_capacity(max_count);
+        if max_count == 0 {
+            return candidates;
+        }
+        // (範囲, 深さ, 範囲を分けた分割面の要素) を積む。逆側の範囲は query が属する側の探索が終わってから判定される
+        let mut stack: Vec<(Range<usize>, usize, Option<usize>)> = vec![(0..self.order.len(), 0, None)];
+        while let Some((range, depth, split)) = stack.pop() {
+            if range.is_empty() {
+                continue;
+            }
+            if let Some(split) = split {
+                let pivot = &self.items[self.order[split] as usize];
+                let axis_distance = self.metric.reduced_distance_to_axis(query, pivot, depth - 1);
+                let full = candidates.len() >= max_count;
+                if full && axis_distance >= candidates.peek().expect("must exist").1 {
+                    continue;
+                }
+            }
+
+            let Some(mid) = self.mid(&range) else {
+                for &i in &self.order[range] {
+                    let distance = self.metric.reduced_distance(query, &self.items[i as usize]);
+                    offer(&mut candidates, max_count, Candidate(i as usize, distance));
+                }
+                continue;
+            };
+            let pivot = &self.items[self.order[mid] as usize];
+            let distance = self.metric.reduced_distance(query, pivot);
+            offer(
+                &mut candidates,
+                max_count,
+                Candidate(self.order[mid] as usize, distance),
+            );
+
+            let (first, second) = split_ranges(&range, mid, query.cmp_in_depth(pivot, depth));
+            stack.push((second, depth + 1, Some(mid)));
+            stack.push((first, depth + 1, None));
+        }
+        candidates
+    }
+
+    /// query に最も近い要素のうち accept が true を返すものの位置と、 Metric::reduced_distance() の尺度の距離を返す。
+    /// 距離が bound 以上の要素は返さない。 skip が部分木を表す位置 (fold_subtrees() を参照) に true を返す部分木は調べない。
+    pub(crate) fn nearest_where(
+        &self,
+        query: &T,
+        bound: Option<M::Measurement>,
+        skip: impl Fn(usize) -> bool,
+        accept: impl Fn(usize) -> bool,
+    ) -> Option<(usize, M::Measurement)> {
+        let mut nearest = None;
+        let mut bound = bound;
+
+        let mut stack: Vec<(Range<usize>, usize, Option<usize>)> = vec![(0..self.order.len(), 0, None)];
+        while let Some((range, depth, split)) = stack.pop() {
+            if range.is_empty() || skip(self.node_key(&range)) {
+                continue;
+            }
+            if let (Some(split), Some(bound)) = (split, &bound) {
+                let pivot = &self.items[self.order[split] as usize];
+                if self.metric.reduced_distance_to_axis(query, pivot, depth - 1) >= *bound {
+                    continue;
+                }
+            }
+
+            let Some(mid) = self.mid(&range) else {
+                for &i in &self.order[range] {
+                    if accept(i as usize) {
+                        let distance = self.metric.reduced_distance(query, &self.items[i as usize]);
+                        offer_nearest(&mut nearest, &mut bound, i as usize, distance);
+                    }
+                }
+                continue;
+            };
+            let pivot = &self.items[self.order[mid] as usize];
+            if accept(self.order[mid] as usize) {
+                let distance = self.metric.reduced_distance(query, pivot);
+                offer_nearest(&mut nearest, &mut bound, self.order[mid] as usize, distance);
+            }
+
+            let (first, second) = split_ranges(&range, mid, query.cmp_in_depth(pivot, depth));
+            stack.push((second, depth + 1, Some(mid)));
+            stack.push((first, depth + 1, None));
+        }
+        nearest.zip(bound)
+    }
+
+    /// すべての空でない部分木について値を計算し、部分木を表す位置の values に書き込む。
+    /// 部分木を表す位置は、内部ノードでは分割面の要素の、葉では先頭の要素の木の順での位置で、部分木ごとに異なる。
+    /// 葉の値は leaf に要素の位置を渡して、内部ノードの値は node に左右の部分木の値と分割面の要素の位置を渡して求める。
+    /// 空の部分木の値は empty とする。 values は len() 以上の長さでなければならない。
+    pub(crate) fn fold_subtrees<S: Copy>(
+        &self,
+        empty: S,
+        leaf: impl Fn(&[u32]) -> S,
+        node: impl Fn(S, usize, S) -> S,
+        values: &mut [S],
+    ) {
+        fn fold<T: KdTreeItem, M: Metric<T>, S: Copy>(
+            tree: &SliceKdTree<'_, T, M>,
+            range: Range<usize>,
+            empty: S,
+            leaf: &impl Fn(&[u32]) -> S,
+            node: &impl Fn(S, usize, S) -> S,
+            values: &mut [S],
+        ) -> S {
+            if range.is_empty() {
+                return empty;
+            }
+            let Some(mid) = tree.mid(&range) else {
+                values[range.start] = leaf(&tree.order[range.clone()]);
+                return values[range.start];
+            };
+            let left = fold(tree, range.start..mid, empty, leaf, node, values);
+            let right = fold(tree, mid + 1..range.end, empty, leaf, node, values);
+            values[mid] = node(left, tree.order[mid] as usize, right);
+            values[mid]
+        }
+        fold(self, 0..self.order.len(), empty, &leaf, &node, values);
+    }
+
+    /// range の部分木を表す位置を返す。
+    fn node_key(&self, range: &Range<usize>) -> usize {
+        self.mid(range).unwrap_or(range.start)
+    }
+
+    /// range を分割する中央の要素の位置を返す。 range が葉であれば None を返す。
+    fn mid(&self, range: &Range<usize>) -> Option<usize> {
+        (range.len() > self.bucket_size).then(|| range.start + range.len() / 2)
+    }
+}
+
+impl<T: KdTreeItem, M: Metric<T>> SpatialIndex<T> for SliceKdTree<'_, T, M> {
+    type Measurement = M::Measurement;
+
+    fn range(&self, query: &T, radius: &Self::Measurement) -> Vec<usize> {
+        self.range_indices(query, radius)
+    }
+
+    fn range_into(&self, query: &T, radius: &Self::Measurement, found: &mut Vec<usize>) {
+        found.clear();
+        self.search_range(query, radius, |index, _| found.push(index));
+    }
+
+    fn count_within_limited(&self, query: &T, radius: &Self::Measurement, limit: usize) -> usize {
+        count_until(limit, |count| self.search_range_while(query, radius, |_, _| count()))
+    }
+
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, Self::Measurement)> {
+        self.nearest_n_indices(query, k)
+    }
+}
+
+/// k 近傍探索の候補。要素の位置と距離を持ち、距離だけで比較する。
+pub(crate) struct Candidate<D>(pub usize, pub D);
+
+impl<D: PartialOrd> PartialEq for Candidate<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.1 == other.1
+    }
+}
+
+impl<D: PartialOrd> Eq for Candidate<D> {}
+
+impl<D: PartialOrd> PartialOrd for Candidate<D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<D: PartialOrd> Ord for Candidate<D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.1.partial_cmp(&other.1).expect("not total order")
+    }
+}
+
+/// candidate が最も近い max_count 個に入るなら candidates に入れる。
+pub(crate) fn offer<D: PartialOrd>(
+    candidates: &mut BinaryHeap<Candidate<D>>,
+    max_count: usize,
+    candidate: Candidate<D>,
+) {
+    if candidates.len() < max_count {
+        candidates.push(candidate);
+    } else if candidate.1 < candidates.peek().expect("must exist").1 {
+        candidates.pop();
+        candidates.push(candidate);
+    }
+}
+
+/// distance が bound より小さければ nearest と bound を index と distance に置き換える。
+fn offer_nearest<D: PartialOrd>(nearest: &mut Option<usize>, bound: &mut Option<D>, index: usize, distance: D) {
+    if bound.as_ref().is_none_or(|b| distance < *b) {
+        *nearest = Some(index);
+        *bound = Some(distance);
+    }
+}
+
+/// range を mid で分けた左右の範囲を (query が属する側, 逆側) の順で返す。 ordering は query と mid の要素の比較になる。
+fn split_ranges(range: &Range<usize>, mid: usize, ordering: Ordering) -> (Range<usize>, Range<usize>) {
+    let (left, right) = (range.start..mid, mid + 1..range.end);
+    match ordering {
+        Ordering::Less => (left, right),
+        Ordering::Equal | Ordering::Greater => (right, left),
+    }
+}
+
+/// part の中央に depth の軸での中央値を置き、左右をそれ以下とそれ以上の要素に分けてその位置を返す。
+/// part が bucket_size 以下であれば分割せずに None を返す。
+fn split_part<T: KdTreeItem>(items: &[T], part: &mut [u32], depth: usize, bucket_size: usize) -> Option<usize> {
+    if part.len() <= bucket_size {
+        return None;
+    }
+    let mid = part.len() / 2;
+    part.select_nth_unstable_by(mid, |&lhs, &rhs| {
+        items[lhs as usize].cmp_in_depth(&items[rhs as usize], depth)
+    });
+    Some(mid)
+}
+
+/// part を木の順に並べ替える。各範囲の中央に分割面の要素を置き、左右の範囲を再帰的に並べ替える。
+fn construct_part<T: KdTreeItem>(items: &[T], part: &mut [u32], depth: usize, bucket_size: usize) {
+    let mut stack = vec![(0..part.len(), depth)];
+    while let Some((range, depth)) = stack.pop() {
+        if let Some(mid) = split_part(items, &mut part[range.clone()], depth, bucket_size) {
+            let mid = range.start + mid;
+            stack.push((range.start..mid, depth + 1));
+            stack.push((mid + 1..range.end, depth + 1));
+        }
+    }
+}
+
+/// items の位置を k-d tree の順に並べて返す。空間的に近い要素ほど近くに並ぶ。
+pub(crate) fn spatial_order<T: KdTreeItem>(items: &[T]) -> Vec<u32> {
+    let len = u32::try_from(items.len()).expect("spatial_order() supports at most u32::MAX items");
+    let mut order = (0..len).collect::<Vec<_>>();
+    construct_part(items, &mut order, 0, DEFAULT_BUCKET_SIZE);
+    order
+}
+
+/// construct_part_par() が逐次処理に切り替える範囲の大きさ。
+#[cfg(feature = "parallel")]
+const PARALLEL_CONSTRUCTION_CUTOFF: usize = 4096;
+
+/// construct_part() の並列版。左右の範囲は rayon で並列に処理する。
+#[cfg(feature = "parallel")]
+fn construct_part_par<T: KdTreeItem + Sync>(items: &[T], part: &mut [u32], depth: usize, bucket_size: usize) {
+    if part.len() <= PARALLEL_CONSTRUCTION_CUTOFF {
+        construct_part(items, part, depth, bucket_size);
+        return;
+    }
+
+    let Some(mid) = split_part(items, part, depth, bucket_size) else {
+        return;
+    };
+    let (left, mid_right) = part.split_at_mut(mid);
+    let right = &mut mid_right[1..];
+    rayon::join(
+        || construct_part_par(items, left, depth + 1, bucket_size),
+        || construct_part_par(items, right, depth + 1, bucket_size),
+    );
+}
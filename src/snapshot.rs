@@ -0,0 +1,469 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    fmt::{self, Display},
+    io::{self, BufWriter, Write},
+};
+
+use crate::{
+    error::{check_radius, Error},
+    index::SpatialIndex,
+    kdtree::{check_query, KdTree},
+};
+
+/// スナップショットの先頭に置かれる識別子。
+const MAGIC: [u8; 8] = *b"KDTSNAP\0";
+
+const VERSION: u32 = 1;
+
+/// ヘッダーの大きさ。識別子, 版, 次元数 (u32) と、ノード数, 要素数, 削除されていない要素数 (u64) が並ぶ。
+const HEADER_SIZE: usize = 40;
+
+/// ノード 1 つの大きさ。要素の開始位置, 要素数, 左の子, 右の子 (u32) が並ぶ。子がなければ 0 になる。
+const NODE_SIZE: usize = 16;
+
+/// 削除済みの要素の位置として書き込まれる値。
+const REMOVED: u32 = u32::MAX;
+
+/// KdTreeSnapshot::from_bytes() が返すエラー。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// 先頭の識別子が一致しない。
+    InvalidMagic,
+
+    /// 対応していない版で書き出されている。
+    UnsupportedVersion(u32),
+
+    /// 書き出されたツリーの次元数が読み出す型と一致しない。
+    DimensionMismatch { expected: usize, found: usize },
+
+    /// ヘッダーから計算される大きさとバイト列の長さが一致しない。
+    InvalidLength { expected: usize, found: usize },
+
+    /// 位置 node のノードが範囲外の要素や子を参照している。
+    InvalidNode { node: usize },
+
+    /// 書き出し順で entry 番目の要素の座標に NaN や無限大が含まれている。
+    NonFiniteCoordinate { entry: usize },
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidMagic => write!(f, "not a k-d tree snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {version}"),
+            SnapshotError::DimensionMismatch { expected, found } => {
+                write!(f, "snapshot has {found} dimensions, expected {expected}")
+            }
+            SnapshotError::InvalidLength { expected, found } => {
+                write!(f, "snapshot must be {expected} bytes, found {found}")
+            }
+            SnapshotError::InvalidNode { node } => write!(f, "snapshot node {node} is out of range"),
+            SnapshotError::NonFiniteCoordinate { entry } => {
+                write!(f, "snapshot entry {entry} has non-finite coordinates")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl<const N: usize> KdTree<[f32; N]> {
+    /// KdTreeSnapshot で読み出せる形式で writer に書き出す。
+    /// 形式はリトルエンディアンで、ヘッダー, ノードの配列, 要素の座標の配列, 要素の位置の配列の順に並ぶ。
+    /// ノードは根から幅優先の順に並べ直され、葉ノードの削除済みの要素は書き出されない。
+    /// ノード数や要素の位置が u32 に収まらない場合は io::ErrorKind::InvalidInput のエラーを返す。
+    pub fn write_snapshot(&self, writer: impl Write) -> io::Result<()> {
+        // 幅優先で並べると子は常に親より後ろに来るため、読み出し時に循環を検査しなくてよい
+        let mut order: Vec<_> = self.root_node().into_iter().collect();
+        let mut children = Vec::new();
+        let mut next = 0;
+        while let Some(&node) = order.get(next) {
+            let mut child = |index| match self.get_node(index) {
+                Some(child) => {
+                    order.push(child);
+                    to_u32(order.len() - 1)
+                }
+                None => Ok(0),
+            };
+            children.push((child(node.left_index)?, child(node.right_index)?));
+            next += 1;
+        }
+
+        // 内部ノードの要素は削除済みでも分割面として残す
+        let entries: Vec<_> = order
+            .iter()
+            .map(|node| {
+                let entries = node.entries().filter(move |e| !node.is_leaf() || !e.removed);
+                entries.collect::<Vec<_>>()
+            })
+            .collect();
+        let entry_count = entries.iter().map(Vec::len).sum::<usize>();
+        to_u32(entry_count)?;
+
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&to_u32(N)?.to_le_bytes())?;
+        for count in [order.len(), entry_count, self.len()] {
+            writer.write_all(&(count as u64).to_le_bytes())?;
+        }
+
+        let mut start = 0;
+        for (entries, (left, right)) in entries.iter().zip(children) {
+            for value in [to_u32(start)?, to_u32(entries.len())?, left, right] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            start += entries.len();
+        }
+        for entry in entries.iter().flatten() {
+            for coordinate in entry.item {
+                writer.write_all(&coordinate.to_le_bytes())?;
+            }
+        }
+        for entry in entries.iter().flatten() {
+            let index = if entry.removed { REMOVED } else { to_u32(entry.index())? };
+            writer.write_all(&index.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+}
+
+fn to_u32(value: usize) -> io::Result<u32> {
+    u32::try_from(value)
+        .ok()
+        .filter(|&v| v != REMOVED)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "snapshot values must fit in u32"))
+}
+
+/// KdTree::write_snapshot() で書き出されたバイト列をそのまま探索する読み出し専用の k-d tree 。
+/// 各値は探索時にバイト列から直接読むため、構築も逆シリアライズも行わない。
+/// メモリーマップしたファイルの上に作れば、大きなツリーでも読み込みを待たずに探索を始められる。
+/// 距離はユークリッド距離で、結果は元の KdTree と同じ要素の集合になる。
+#[derive(Debug, Clone, Copy)]
+pub struct KdTreeSnapshot<'a, const N: usize> {
+    nodes: &'a [u8],
+    coordinates: &'a [u8],
+    indices: &'a [u8],
+    len: usize,
+}
+
+/// スナップショットのノード。
+#[derive(Debug, Clone, Copy)]
+struct SnapshotNode {
+    start: usize,
+    count: usize,
+    left: usize,
+    right: usize,
+}
+
+impl SnapshotNode {
+    fn is_leaf(&self) -> bool {
+        self.left == 0 && self.right == 0
+    }
+}
+
+impl<'a, const N: usize> KdTreeSnapshot<'a, N> {
+    /// bytes をスナップショットとして読む。
+    /// ヘッダーと各ノードの参照先が範囲内にあることと、すべての座標が有限であることを検査するため、
+    /// ノード数と要素数に比例する時間がかかる。ファイルなど信頼できない出所のバイト列を渡してもよい。
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<KdTreeSnapshot<'a, N>, SnapshotError> {
+        if bytes.len() < HEADER_SIZE || bytes[..8] != MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+        let version = read_u32(bytes, 8);
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let dimensions = read_u32(bytes, 12) as usize;
+        if dimensions != N {
+            return Err(SnapshotError::DimensionMismatch {
+                expected: N,
+                found: dimensions,
+            });
+        }
+
+        let [node_count, entry_count, len] = [16, 24, 32].map(|offset| read_u64(bytes, offset));
+        // 桁あふれする大きさは usize::MAX に丸められ、どの長さとも一致しない
+        let node_bytes = node_count.saturating_mul(NODE_SIZE);
+        let coordinate_bytes = entry_count.saturating_mul(4 * N);
+        let index_bytes = entry_count.saturating_mul(4);
+        let expected = [node_bytes, coordinate_bytes, index_bytes]
+            .into_iter()
+            .fold(HEADER_SIZE, usize::saturating_add);
+        if bytes.len() != expected {
+            return Err(SnapshotError::InvalidLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let (nodes, rest) = bytes[HEADER_SIZE..].split_at(node_bytes);
+        let (coordinates, indices) = rest.split_at(coordinate_bytes);
+        let snapshot = KdTreeSnapshot {
+            nodes,
+            coordinates,
+            indices,
+            len,
+        };
+
+        // 子は親より後ろにあるものだけを許し、探索が必ず終わるようにする
+        for i in 0..node_count {
+            let node = snapshot.node(i);
+            let valid_child = |child: usize| child == 0 || (i < child && child < node_count);
+            let valid = node.start.checked_add(node.count).is_some_and(|end| end <= entry_count)
+                && valid_child(node.left)
+                && valid_child(node.right)
+                && (node.is_leaf() || node.count > 0);
+            if !valid {
+                return Err(SnapshotError::InvalidNode { node: i });
+            }
+        }
+
+        // 分割面の座標が NaN であると探索時の比較が定まらない
+        if let Some(entry) = (0..entry_count).find(|&entry| !snapshot.point(entry).iter().all(|x| x.is_finite())) {
+            return Err(SnapshotError::NonFiniteCoordinate { entry });
+        }
+        Ok(snapshot)
+    }
+
+    /// 削除されていない要素の数。
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// KdTree::find_range_into() と同様に、 query から radius 以内 (境界を含む) にある要素の位置を found に書き込む。
+    /// query の座標に NaN や無限大があれば Error::NonFiniteQuery を、 radius が NaN であれば Error::InvalidRadius を返す。
+    pub fn find_range_into(&self, query: &[f32; N], radius: f32, found: &mut Vec<usize>) -> Result<(), Error> {
+        check_query(query)?;
+        check_radius(&radius)?;
+        self.find_range_into_unchecked(query, radius, found);
+        Ok(())
+    }
+
+    /// find_range_into() と同様だが、 query と radius を検証しない。
+    pub fn find_range_into_unchecked(&self, query: &[f32; N], radius: f32, found: &mut Vec<usize>) {
+        found.clear();
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let range = radius.powi(2);
+        let mut stack = vec![(0, 0)];
+        while let Some((i, depth)) = stack.pop() {
+            let node = self.node(i);
+            for entry in node.start..node.start + node.count {
+                if reduced_distance(query, &self.point(entry)) <= range {
+                    found.extend(self.index(entry));
+                }
+            }
+            if node.is_leaf() {
+                continue;
+            }
+
+            // 分割面上の要素はどちらの側にも入りうるので境界を含める
+            let (first, second, axis_distance) = self.split_subtrees(&node, query, depth);
+            if axis_distance <= range && second != 0 {
+                stack.push((second, depth + 1));
+            }
+            if first != 0 {
+                stack.push((first, depth + 1));
+            }
+        }
+    }
+
+    /// query から radius 以内 (境界を含む) にある要素の位置を返す。検証は find_range_into() と同じ。
+    pub fn find_range_n_indices(&self, query: &[f32; N], radius: f32) -> Result<Vec<usize>, Error> {
+        let mut found = Vec::new();
+        self.find_range_into(query, radius, &mut found)?;
+        Ok(found)
+    }
+
+    /// find_range_n_indices() と同様だが、 query と radius を検証しない。
+    pub fn find_range_n_indices_unchecked(&self, query: &[f32; N], radius: f32) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.find_range_into_unchecked(query, radius, &mut found);
+        found
+    }
+
+    /// KdTree::find_nearest_n_indices() と同様に、 query に近い順に最大 max_count 個の要素の位置と距離を返す。
+    /// query の座標に NaN や無限大があれば Error::NonFiniteQuery を返す。
+    pub fn find_nearest_n_indices(&self, query: &[f32; N], max_count: usize) -> Result<Vec<(usize, f32)>, Error> {
+        check_query(query)?;
+        Ok(self.find_nearest_n_indices_unchecked(query, max_count))
+    }
+
+    /// find_nearest_n_indices() と同様だが、 query を検証しない。
+    pub fn find_nearest_n_indices_unchecked(&self, query: &[f32; N], max_count: usize) -> Vec<(usize, f32)> {
+        if max_count == 0 || self.nodes.is_empty() {
+            return Vec::new();
+        }
+        let mut candidates: BinaryHeap<Candidate> = BinaryHeap::with_capacity(max_count);
+
+        // (ノード, 深さ, 親の分割面までの距離) を積む。逆側の部分木は分割面までの距離とともに先に積んでおく
+        let mut stack = vec![(0, 0, None)];
+        while let Some((i, depth, axis_distance)) = stack.pop() {
+            // max_count に達していれば、最遠の候補が親の分割面を跨ぐときだけ逆側を探索する
+            if let Some(axis_distance) = axis_distance {
+                if candidates.len() >= max_count && axis_distance >= candidates.peek().expect("must exist").0 {
+                    continue;
+                }
+            }
+
+            let node = self.node(i);
+            for entry in node.start..node.start + node.count {
+                let Some(index) = self.index(entry) else {
+                    continue;
+                };
+                let distance = reduced_distance(query, &self.point(entry));
+                if candidates.len() < max_count {
+                    candidates.push(Candidate(distance, index));
+                } else if distance < candidates.peek().expect("must exist").0 {
+                    candidates.pop();
+                    candidates.push(Candidate(distance, index));
+                }
+            }
+            if node.is_leaf() {
+                continue;
+            }
+
+            let (first, second, axis_distance) = self.split_subtrees(&node, query, depth);
+            if second != 0 {
+                stack.push((second, depth + 1, Some(axis_distance)));
+            }
+            if first != 0 {
+                stack.push((first, depth + 1, None));
+            }
+        }
+
+        candidates
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Candidate(distance, index)| (index, distance.sqrt()))
+            .collect()
+    }
+
+    /// node の子を (query が属する側, 逆側, 分割面までの 2 乗の距離) の順で返す。
+    fn split_subtrees(&self, node: &SnapshotNode, query: &[f32; N], depth: usize) -> (usize, usize, f32) {
+        let axis = depth % N;
+        let split = self.point(node.start)[axis];
+        let axis_distance = (query[axis] - split).powi(2);
+        match query[axis].partial_cmp(&split).expect("not total order") {
+            Ordering::Less => (node.left, node.right, axis_distance),
+            Ordering::Equal | Ordering::Greater => (node.right, node.left, axis_distance),
+        }
+    }
+
+    fn node(&self, i: usize) -> SnapshotNode {
+        let [start, count, left, right] =
+            [0, 4, 8, 12].map(|offset| read_u32(self.nodes, i * NODE_SIZE + offset) as usize);
+        SnapshotNode {
+            start,
+            count,
+            left,
+            right,
+        }
+    }
+
+    fn point(&self, entry: usize) -> [f32; N] {
+        std::array::from_fn(|axis| {
+            let offset = (entry * N + axis) * 4;
+            f32::from_le_bytes(
+                self.coordinates[offset..offset + 4]
+                    .try_into()
+                    .expect("must be 4 bytes"),
+            )
+        })
+    }
+
+    /// entry 番目の要素の位置を返す。削除済みであれば None を返す。
+    fn index(&self, entry: usize) -> Option<usize> {
+        let index = read_u32(self.indices, entry * 4);
+        (index != REMOVED).then_some(index as usize)
+    }
+}
+
+impl<const N: usize> SpatialIndex<[f32; N]> for KdTreeSnapshot<'_, N> {
+    type Measurement = f32;
+
+    fn range(&self, query: &[f32; N], radius: &f32) -> Vec<usize> {
+        self.find_range_n_indices_unchecked(query, *radius)
+    }
+
+    fn range_into(&self, query: &[f32; N], radius: &f32, found: &mut Vec<usize>) {
+        self.find_range_into_unchecked(query, *radius, found)
+    }
+
+    fn nearest_n(&self, query: &[f32; N], k: usize) -> Vec<(usize, f32)> {
+        self.find_nearest_n_indices_unchecked(query, k)
+    }
+}
+
+/// (2 乗の距離, 位置) 。距離の大きいものが先に取り出される。
+#[derive(Debug, Clone, Copy)]
+struct Candidate(f32, usize);
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).expect("not total order")
+    }
+}
+
+/// KdTreeItem::reduced_distance() と同じ順序で計算した 2 乗のユークリッド距離。
+fn reduced_distance<const N: usize>(lhs: &[f32; N], rhs: &[f32; N]) -> f32 {
+    (0..N).map(|i| (lhs[i] - rhs[i]).powi(2)).fold(0.0, |a, x| a + x)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("must be 4 bytes"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> usize {
+    let value = u64::from_le_bytes(bytes[offset..offset + 8].try_into().expect("must be 8 bytes"));
+    usize::try_from(value).unwrap_or(usize::MAX)
+}
+
+/// メモリーマップしたスナップショットのファイル。 view() で KdTreeSnapshot として探索する。
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct MappedSnapshot {
+    map: memmap2::Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedSnapshot {
+    /// path のファイルを読み出し専用でメモリーマップする。
+    ///
+    /// # Safety
+    ///
+    /// MappedSnapshot が存在する間、ファイルが他から書き換えられたり切り詰められたりしてはならない。
+    /// 書き換えられた場合、探索結果が壊れたり panic したりする。
+    pub unsafe fn open(path: impl AsRef<std::path::Path>) -> io::Result<MappedSnapshot> {
+        let file = std::fs::File::open(path)?;
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(MappedSnapshot { map })
+    }
+
+    /// マップした内容を N 次元のスナップショットとして読む。
+    pub fn view<const N: usize>(&self) -> Result<KdTreeSnapshot<'_, N>, SnapshotError> {
+        KdTreeSnapshot::from_bytes(&self.map)
+    }
+}
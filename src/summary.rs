@@ -0,0 +1,95 @@
+use alloc::vec::Vec;
+use core::{fmt::Debug, num::NonZeroUsize};
+
+use num_traits::Float;
+
+use crate::{
+    dbscan::{DbscanLabel, DbscanResult},
+    kdtree::KdTreeItem,
+    metrics::Clusters,
+    rtree::Rect,
+};
+
+/// 1 つのクラスターの要素の統計。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterSummary<F, const N: usize> {
+    pub cluster_id: NonZeroUsize,
+
+    /// クラスターに属する要素の数。
+    pub count: usize,
+
+    /// 要素の座標の平均。
+    pub centroid: [F; N],
+
+    /// centroid から最も遠い要素までの距離。
+    pub radius: F,
+
+    /// 最も離れた 2 要素間の距離の推定値。ある要素から最も遠い要素を選び、さらにそこから最も遠い要素までの距離をとる。
+    /// 真の直径を d とすると d / 2 以上 d 以下になる。 d は radius の 2 倍以下でもある。
+    pub diameter: F,
+
+    /// 要素をすべて含む最小の軸に平行な直方体。
+    pub bounds: Rect<F, N>,
+}
+
+impl<F: Debug + Float, const N: usize> ClusterSummary<F, N> {
+    /// items と labels からクラスターごとの統計を計算し、クラスター番号の順に返す。ノイズの要素は含めない。
+    pub fn compute(items: impl AsRef<[[F; N]]>, labels: &[DbscanLabel]) -> Vec<ClusterSummary<F, N>> {
+        let items = items.as_ref();
+        assert_eq!(items.len(), labels.len(), "labels must have the same length as items");
+        let clusters = Clusters::new(labels);
+        clusters
+            .ids
+            .iter()
+            .zip(&clusters.members)
+            .map(|(&cluster_id, members)| ClusterSummary::from_members(cluster_id, items, members))
+            .collect()
+    }
+
+    /// items のうち members の位置にある要素の統計を計算する。 members は空であってはならない。
+    fn from_members(cluster_id: NonZeroUsize, items: &[[F; N]], members: &[usize]) -> ClusterSummary<F, N> {
+        let count = F::from(members.len()).expect("count must be representable");
+        let mut sum = [F::zero(); N];
+        let mut bounds = Rect::point(items[members[0]]);
+        for &i in members {
+            for (s, &x) in sum.iter_mut().zip(&items[i]) {
+                *s = *s + x;
+            }
+            bounds = bounds.union(&Rect::point(items[i]));
+        }
+        let centroid = sum.map(|s| s / count);
+        let radius = members
+            .iter()
+            .map(|&i| items[i].distance(&centroid))
+            .fold(F::zero(), F::max);
+
+        // 最も遠い要素を 2 回たどる
+        let farthest = |from: usize| {
+            members
+                .iter()
+                .map(|&i| (i, items[from].distance(&items[i])))
+                .fold((from, F::zero()), |max, e| if e.1 > max.1 { e } else { max })
+        };
+        let (end, _) = farthest(members[0]);
+        let (_, diameter) = farthest(end);
+
+        ClusterSummary {
+            cluster_id,
+            count: members.len(),
+            centroid,
+            radius,
+            diameter,
+            bounds,
+        }
+    }
+}
+
+impl DbscanResult {
+    /// items をクラスタリングした結果から、クラスターごとの統計を計算する。 ClusterSummary::compute() と同じ。
+    pub fn summaries<F: Debug + Float, const N: usize>(
+        &self,
+        items: impl AsRef<[[F; N]]>,
+    ) -> Vec<ClusterSummary<F, N>> {
+        ClusterSummary::compute(items, &self.labels)
+    }
+}
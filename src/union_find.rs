@@ -0,0 +1,108 @@
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "parallel")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 逐次処理用の Union-Find。 union by size と path halving を行う。
+pub(crate) struct UnionFind {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl UnionFind {
+    pub fn new(len: usize) -> UnionFind {
+        UnionFind {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+        }
+    }
+
+    /// parents() が返した親の列から Union-Find を作る。根の大きさは数え直す。
+    pub fn from_parents(parents: Vec<usize>) -> UnionFind {
+        let mut union_find = UnionFind {
+            sizes: vec![0; parents.len()],
+            parents,
+        };
+        for x in 0..union_find.parents.len() {
+            let root = union_find.find(x);
+            union_find.sizes[root] += 1;
+        }
+        union_find
+    }
+
+    /// 各要素の親の位置。根は自身を親とする。
+    pub fn parents(&self) -> &[usize] {
+        &self.parents
+    }
+
+    pub fn find(&mut self, mut x: usize) -> usize {
+        while self.parents[x] != x {
+            self.parents[x] = self.parents[self.parents[x]];
+            x = self.parents[x];
+        }
+        x
+    }
+
+    /// a と b を併合し、併合後の根を返す。
+    pub fn union(&mut self, a: usize, b: usize) -> usize {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return a;
+        }
+
+        let (large, small) = if self.sizes[a] >= self.sizes[b] { (a, b) } else { (b, a) };
+        self.parents[small] = large;
+        self.sizes[large] += self.sizes[small];
+        large
+    }
+}
+
+/// 複数スレッドから同時に union() できる Union-Find。
+/// 根は常に添字の小さい方へ繋ぐため、 CAS だけで閉路を作らずに併合できる。
+#[cfg(feature = "parallel")]
+pub(crate) struct ConcurrentUnionFind {
+    parents: Vec<AtomicUsize>,
+}
+
+#[cfg(feature = "parallel")]
+impl ConcurrentUnionFind {
+    pub fn new(len: usize) -> ConcurrentUnionFind {
+        ConcurrentUnionFind {
+            parents: (0..len).map(AtomicUsize::new).collect(),
+        }
+    }
+
+    pub fn find(&self, mut x: usize) -> usize {
+        loop {
+            let parent = self.parents[x].load(Ordering::Acquire);
+            if parent == x {
+                return x;
+            }
+
+            // path halving
+            let grandparent = self.parents[parent].load(Ordering::Acquire);
+            if grandparent != parent {
+                let _ = self.parents[x].compare_exchange_weak(parent, grandparent, Ordering::AcqRel, Ordering::Relaxed);
+            }
+            x = grandparent;
+        }
+    }
+
+    pub fn union(&self, a: usize, b: usize) {
+        let (mut a, mut b) = (a, b);
+        loop {
+            a = self.find(a);
+            b = self.find(b);
+            if a == b {
+                return;
+            }
+
+            let (low, high) = if a < b { (a, b) } else { (b, a) };
+            if self.parents[high]
+                .compare_exchange(high, low, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use crate::{index::SpatialIndex, knn::KBest, random::SplitMix64};
+
+/// vantage-point tree を表す。
+/// 各ノードは vantage point となる要素を 1 つ持ち、残りの要素をそこからの距離の中央値で内側と外側に分ける。
+/// 座標を持たず距離だけが定義された要素 (文字列の編集距離など) の近傍探索に用いる。
+/// distance は非負で対称で、三角不等式を満たさなければならない。満たさない場合は近傍を取りこぼしうる。
+pub struct VpTree<T, F = fn(&T, &T) -> f64> {
+    /// 各ノードの範囲が連続するように並べ替えた要素。
+    items: Vec<T>,
+
+    /// items のそれぞれが構築時に渡された位置。
+    indices: Vec<usize>,
+
+    nodes: Vec<VpNode>,
+    distance: F,
+}
+
+struct VpNode {
+    /// 部分木の要素の items 上の範囲。葉ノードでなければ先頭が vantage point になる。
+    range: Range<usize>,
+
+    /// 内側の部分木の要素の vantage point からの距離の最大値と、外側の部分木の要素の距離の最小値。
+    inside_max: f64,
+    outside_min: f64,
+
+    /// 内側と外側の子ノードの nodes 上の位置。葉ノードであれば None になる。
+    children: Option<[usize; 2]>,
+}
+
+impl<T, F: Fn(&T, &T) -> f64> VpTree<T, F> {
+    /// 距離の計算に distance を用いる vantage-point tree を構築する。
+    /// vantage point は固定の seed による擬似乱数で選ぶため、同じ入力からは同じ木が得られる。
+    /// distance が NaN を返すと panic する。
+    pub fn construct(items: impl Into<Vec<T>>, distance: F) -> VpTree<T, F> {
+        let items = items.into();
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        let mut nodes: Vec<VpNode> = Vec::new();
+        let mut rng = SplitMix64::new(VANTAGE_SEED);
+
+        // (範囲, 親ノードと内外のどちらの子か) を積み、親の children は子を作った時点で設定する
+        let mut pending = vec![];
+        if !items.is_empty() {
+            pending.push((0..items.len(), None::<(usize, usize)>));
+        }
+        while let Some((range, parent)) = pending.pop() {
+            let node_index = nodes.len();
+            nodes.push(VpNode {
+                range: range.clone(),
+                inside_max: 0.0,
+                outside_min: 0.0,
+                children: None,
+            });
+            if let Some((parent, side)) = parent {
+                nodes[parent].children.get_or_insert([0; 2])[side] = node_index;
+            }
+
+            let part = &mut order[range.clone()];
+            if part.len() <= LEAF_SIZE {
+                continue;
+            }
+
+            let vantage = rng.below(part.len());
+            part.swap(0, vantage);
+            let vantage = part[0];
+            let mut keyed: Vec<(usize, f64)> = part[1..]
+                .iter()
+                .map(|&i| (i, distance(&items[vantage], &items[i])))
+                .collect();
+
+            // 距離の中央値より近い要素を内側、それ以外を外側とする
+            let mid = keyed.len() / 2;
+            let compare = |lhs: &(usize, f64), rhs: &(usize, f64)| lhs.1.partial_cmp(&rhs.1).expect("not total order");
+            keyed.select_nth_unstable_by(mid, compare);
+            let node = &mut nodes[node_index];
+            node.inside_max = keyed[..mid].iter().map(|&(_, d)| d).fold(0.0, f64::max);
+            node.outside_min = keyed[mid].1;
+            for (slot, (i, _)) in part[1..].iter_mut().zip(keyed) {
+                *slot = i;
+            }
+
+            let inside = range.start + 1..range.start + 1 + mid;
+            pending.push((inside.end..range.end, Some((node_index, 1))));
+            pending.push((inside, Some((node_index, 0))));
+        }
+
+        let mut items: Vec<_> = items.into_iter().map(Some).collect();
+        VpTree {
+            items: order
+                .iter()
+                .map(|&i| items[i].take().expect("each item must be taken once"))
+                .collect(),
+            indices: order,
+            nodes,
+            distance,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 要素とその構築時の位置を順不同で返す。
+    pub fn iter_with_indices(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.indices.iter().copied().zip(&self.items)
+    }
+
+    /// query から radius 以内にある要素について、 found(構築時の位置) を呼ぶ。
+    /// found が false を返すと探索を打ち切る。
+    fn visit_range(&self, query: &T, radius: f64, mut found: impl FnMut(usize) -> bool) {
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            let Some(children) = node.children else {
+                for i in node.range.clone() {
+                    if (self.distance)(query, &self.items[i]) <= radius && !found(self.indices[i]) {
+                        return;
+                    }
+                }
+                continue;
+            };
+
+            let vantage = node.range.start;
+            let d = (self.distance)(query, &self.items[vantage]);
+            if d <= radius && !found(self.indices[vantage]) {
+                return;
+            }
+            // 三角不等式から、内側の要素は d - inside_max 以上、外側の要素は outside_min - d 以上離れている
+            if d - radius <= node.inside_max {
+                stack.push(children[0]);
+            }
+            if d + radius >= node.outside_min {
+                stack.push(children[1]);
+            }
+        }
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> f64> SpatialIndex<T> for VpTree<T, F> {
+    type Measurement = f64;
+
+    fn range(&self, query: &T, radius: &f64) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.range_into(query, radius, &mut found);
+        found
+    }
+
+    fn range_into(&self, query: &T, radius: &f64, found: &mut Vec<usize>) {
+        found.clear();
+        self.visit_range(query, *radius, |i| {
+            found.push(i);
+            true
+        });
+    }
+
+    fn count_within_limited(&self, query: &T, radius: &f64, limit: usize) -> usize {
+        let mut count = 0;
+        if limit > 0 {
+            self.visit_range(query, *radius, |_| {
+                count += 1;
+                count < limit
+            });
+        }
+        count
+    }
+
+    fn nearest_n(&self, query: &T, k: usize) -> Vec<(usize, f64)> {
+        let mut best = KBest::new(k);
+        if k == 0 || self.nodes.is_empty() {
+            return best.into_vec();
+        }
+
+        // (ノード, query から部分木の要素までの距離の下界) を積み、近い子ノードから探索する
+        let mut stack = vec![(0, 0.0)];
+        while let Some((node_index, lower_bound)) = stack.pop() {
+            if best.excludes(&lower_bound) {
+                continue;
+            }
+
+            let node = &self.nodes[node_index];
+            let Some(children) = node.children else {
+                for i in node.range.clone() {
+                    best.offer(self.indices[i], (self.distance)(query, &self.items[i]));
+                }
+                continue;
+            };
+
+            let vantage = node.range.start;
+            let d = (self.distance)(query, &self.items[vantage]);
+            best.offer(self.indices[vantage], d);
+            let mut children = [
+                (children[0], (d - node.inside_max).max(0.0)),
+                (children[1], (node.outside_min - d).max(0.0)),
+            ];
+            if children[0].1 < children[1].1 {
+                children.swap(0, 1);
+            }
+            stack.extend(children);
+        }
+
+        best.into_vec()
+    }
+}
+
+/// 葉ノードに格納する要素数の上限。
+const LEAF_SIZE: usize = 16;
+
+/// vantage point の選択に用いる擬似乱数の seed 。
+const VANTAGE_SEED: u64 = 0;
@@ -0,0 +1,51 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    dbscan::{Dbscan, DbscanParams, DbscanResult},
+    error::Error,
+};
+
+/// JS から扱える点の次元数の上限。
+pub const WASM_MAX_DIMENSIONS: usize = 8;
+
+/// points を dimensions 次元の点の並び (row-major) として DBSCAN し、各点のラベルを返す。
+/// JS からは Float32Array を渡し、 DbscanLabel::to_code() の整数 (ノイズは -1) の Int32Array を受け取る。
+/// wasm32-unknown-unknown ではスレッドを使えないため、 default features を無効にしてビルドすること。
+#[wasm_bindgen]
+pub fn dbscan(points: &[f32], dimensions: usize, epsilon: f32, min_points: usize) -> Result<Vec<i32>, JsError> {
+    if !(1..=WASM_MAX_DIMENSIONS).contains(&dimensions) {
+        return Err(JsError::new(&format!(
+            "dimensions must be between 1 and {WASM_MAX_DIMENSIONS}, got {dimensions}"
+        )));
+    }
+    if !points.len().is_multiple_of(dimensions) {
+        return Err(JsError::new(&format!(
+            "points length {} is not a multiple of dimensions {dimensions}",
+            points.len()
+        )));
+    }
+
+    let dbscan = Dbscan::new(DbscanParams::new(epsilon, min_points));
+    let result = match dimensions {
+        1 => run::<1>(&dbscan, points),
+        2 => run::<2>(&dbscan, points),
+        3 => run::<3>(&dbscan, points),
+        4 => run::<4>(&dbscan, points),
+        5 => run::<5>(&dbscan, points),
+        6 => run::<6>(&dbscan, points),
+        7 => run::<7>(&dbscan, points),
+        8 => run::<8>(&dbscan, points),
+        _ => unreachable!("dimensions must be checked"),
+    };
+    let result = result.map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(result.labels.iter().map(|label| label.to_code()).collect())
+}
+
+/// points を N 次元の点に分けて DBSCAN する。座標に NaN や無限大があればエラーを返す。
+fn run<const N: usize>(dbscan: &Dbscan<f32>, points: &[f32]) -> Result<DbscanResult, Error> {
+    let items: Vec<[f32; N]> = points
+        .chunks_exact(N)
+        .map(|chunk| chunk.try_into().expect("chunk must have N elements"))
+        .collect();
+    dbscan.try_run_points(items)
+}
@@ -0,0 +1,215 @@
+use dbscan_rust_test::{
+    adjusted_rand_index, datasets, dbscan, kmeans, knn_classify, knn_classify_with_index, knn_regress,
+    local_outlier_factor, meanshift, pca,
+    preprocess::{self, Scaling},
+    single_linkage, DbscanLabel, Error, FittedIndex, KdTree, KnnWeighting, Point2,
+};
+
+#[test]
+fn kmeans_separates_blobs() {
+    let mut points: Vec<Point2> = Vec::new();
+    for (cx, cy) in [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)] {
+        for i in 0..50 {
+            points.push([cx + (i % 7) as f64 * 0.1, cy + (i % 5) as f64 * 0.1]);
+        }
+    }
+    let result = kmeans(&points, 3, 100, 1).unwrap();
+    assert!(result.converged);
+    assert_eq!(result, kmeans(&points, 3, 100, 1).unwrap());
+    for blob in result.assignments.chunks(50) {
+        assert!(blob.iter().all(|&c| c == blob[0]));
+    }
+    let mut clusters: Vec<_> = result.assignments.iter().step_by(50).collect();
+    clusters.sort_unstable();
+    clusters.dedup();
+    assert_eq!(clusters.len(), 3);
+
+    let small = kmeans(&points[..2], 5, 10, 0).unwrap();
+    assert_eq!((small.centroids.len(), small.inertia), (2, 0.0));
+    assert!(matches!(
+        kmeans([[f64::NAN, 0.0]], 1, 10, 0),
+        Err(Error::NonFiniteInput { .. })
+    ));
+}
+
+#[test]
+fn meanshift_finds_blob_centers() {
+    let mut points: Vec<Point2> = Vec::new();
+    for (cx, cy, n) in [(0.0, 0.0, 40), (10.0, 0.0, 30), (0.0, 10.0, 20)] {
+        for i in 0..n {
+            points.push([cx + (i % 5) as f64 * 0.1 - 0.2, cy + (i / 5 % 5) as f64 * 0.1 - 0.2]);
+        }
+    }
+    let result = meanshift(&points, 2.0, 300).unwrap();
+    assert_eq!(result.centers.len(), 3);
+    // 要素の多いクラスターから順に並ぶ
+    for (center, expected) in result.centers.iter().zip([[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]]) {
+        assert!(
+            (center[0] - expected[0]).hypot(center[1] - expected[1]) < 0.2,
+            "{center:?}"
+        );
+    }
+    assert_eq!(result.assignments[..40], [0; 40]);
+    assert_eq!(result.assignments[40..70], [1; 30]);
+    assert_eq!(result.assignments[70..], [2; 20]);
+
+    assert!(meanshift::<f64, 2>([], 1.0, 10).unwrap().centers.is_empty());
+    assert!(matches!(meanshift(&points, f64::NAN, 10), Err(Error::InvalidRadius)));
+}
+
+#[test]
+fn single_linkage_cut_marks_small_clusters_as_noise() {
+    let points: Vec<Point2> = vec![
+        [0.0, 0.0],
+        [1.0, 0.0],
+        [2.0, 0.0],
+        [10.0, 0.0],
+        [11.0, 0.0],
+        [30.0, 0.0],
+    ];
+    let dendrogram = single_linkage(&points).unwrap();
+    assert_eq!(dendrogram.len(), 6);
+    let distances: Vec<f64> = dendrogram.merges().iter().map(|m| m.distance).collect();
+    assert_eq!(distances, vec![1.0, 1.0, 1.0, 8.0, 19.0]);
+    assert_eq!(dendrogram.merges().last().unwrap().size, 6);
+
+    let result = dendrogram.cut(&1.5, 2);
+    assert_eq!(result.cluster_count, 2);
+    assert_eq!(result.cluster_sizes, vec![3, 2]);
+    assert_eq!(result.labels[5], DbscanLabel::Noise);
+
+    let result = dendrogram.cut(&10.0, 1);
+    assert_eq!(result.cluster_sizes, vec![5, 1]);
+}
+
+#[test]
+fn lof_scores_outliers_above_their_neighbors() {
+    let mut points: Vec<Point2> = (0..25).map(|i| [(i % 5) as f64, (i / 5) as f64]).collect();
+    points.push([20.0, 20.0]);
+    let result = local_outlier_factor(&points, 4).unwrap();
+    assert_eq!(result.scores.len(), points.len());
+    assert_eq!(result.k_distances[12], 1.0);
+    assert_eq!(result.reachability_distance(12, 0.5), 1.0);
+    assert_eq!(result.outliers(2.0), vec![25]);
+    assert!(result.scores[..25].iter().all(|&s| s < 1.5));
+
+    let fitted = FittedIndex::fit(points.clone()).unwrap();
+    assert_eq!(fitted.lof(4).outliers(2.0), vec![25]);
+
+    let duplicates: Vec<Point2> = vec![[0.0, 0.0]; 4];
+    let result = local_outlier_factor(&duplicates, 2).unwrap();
+    assert_eq!(result.scores, vec![1.0; 4]);
+}
+
+#[test]
+fn knn_classify_and_regress_follow_nearest_training_points() {
+    let train: Vec<Point2> = vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [10.0, 10.0], [11.0, 10.0]];
+    let labels = ["a", "a", "a", "b", "b"];
+    let targets = [1.0, 1.0, 1.0, 5.0, 7.0];
+
+    let label = knn_classify(&train, labels, &[9.0, 9.0], 3, KnnWeighting::Uniform).unwrap();
+    assert_eq!(label, Some("b"));
+    let label = knn_classify(&train, labels, &[6.0, 6.0], 5, KnnWeighting::Uniform).unwrap();
+    assert_eq!(label, Some("a"));
+    let label = knn_classify(&train, labels, &[10.0, 10.0], 5, KnnWeighting::Distance).unwrap();
+    assert_eq!(label, Some("b"));
+    assert_eq!(
+        knn_classify(&train, labels, &[0.0, 0.0], 0, KnnWeighting::Uniform).unwrap(),
+        None
+    );
+
+    let value = knn_regress(&train, targets, &[10.5, 10.0], 2, KnnWeighting::Uniform).unwrap();
+    assert_eq!(value, Some(6.0));
+    let value = knn_regress(&train, targets, &[10.25, 10.0], 2, KnnWeighting::Distance).unwrap();
+    assert_eq!(value, Some(5.5));
+    let value = knn_regress(&train, targets, &[11.0, 10.0], 5, KnnWeighting::Distance).unwrap();
+    assert_eq!(value, Some(7.0));
+
+    let tree = KdTree::construct(train.clone()).unwrap();
+    assert_eq!(
+        knn_classify_with_index(&tree, labels, &[0.5, 0.5], 3, KnnWeighting::Distance),
+        Some("a")
+    );
+    assert_eq!(
+        knn_classify(&train, labels, &[f64::NAN, 0.0], 3, KnnWeighting::Uniform),
+        Err(Error::NonFiniteQuery)
+    );
+}
+
+#[test]
+fn pca_recovers_the_plane_of_embedded_clusters() {
+    // 2 次元のクラスターを 8 次元空間内の斜めの平面に埋め込み、残りの方向に小さな揺らぎを加える
+    let dataset = datasets::blobs(&[[0.0, 0.0], [4.0, 0.0], [0.0, 4.0]], 0.3, 100, 17);
+    let u = [1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0].map(|x: f64| x / 2.0);
+    let v = [0.0, 1.0, -1.0, 0.0, -1.0, 1.0, 0.0, 0.0].map(|x: f64| x / 2.0);
+    let embedded: Vec<[f64; 8]> = dataset
+        .points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| std::array::from_fn(|d| 3.0 + p[0] * u[d] + p[1] * v[d] + ((i * 8 + d) % 7) as f64 * 1e-3))
+        .collect();
+
+    let (reduced, pca) = pca::reduce::<f64, 8, 2>(&embedded);
+    assert!(pca.variances()[0] >= pca.variances()[1] && pca.variances()[1] > 1.0);
+    let dot = |a: &[f64; 8], b: &[f64; 8]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+    let [first, second] = pca.components();
+    assert!((dot(first, first) - 1.0).abs() < 1e-9 && (dot(second, second) - 1.0).abs() < 1e-9);
+    assert!(dot(first, second).abs() < 1e-9);
+    for component in pca.components() {
+        // 主成分は u と v の張る平面にある
+        assert!((dot(component, &u).powi(2) + dot(component, &v).powi(2) - 1.0).abs() < 1e-4);
+    }
+
+    let result = dbscan(&reduced, 0.5, 5).unwrap();
+    assert!(adjusted_rand_index(&result.labels, &dataset.labels) > 0.9);
+    for (original, projected) in embedded.iter().zip(&reduced) {
+        let restored = pca.inverse(projected);
+        assert!(original.iter().zip(&restored).all(|(a, b)| (a - b).abs() < 1e-2));
+    }
+    assert_eq!(pca.transform(&embedded[0]), reduced[0]);
+
+    // 直線 y = 2x 上の点の第 1 主成分は (1, 2, 0) / √5 になる
+    let line: Vec<[f64; 3]> = (0..10).map(|i| [i as f64, 2.0 * i as f64, 1.0]).collect();
+    let pca = pca::Pca::<f64, 3, 1>::fit(&line);
+    let expected = [1.0 / 5f64.sqrt(), 2.0 / 5f64.sqrt(), 0.0];
+    assert!(pca.components()[0]
+        .iter()
+        .zip(&expected)
+        .all(|(a, b)| (a - b).abs() < 1e-9));
+    assert!((pca.variances()[0] - 41.25).abs() < 1e-9);
+}
+
+#[test]
+fn scaling_equalizes_axes_and_inverts() {
+    // 2 つ目の軸だけ単位が 1000 倍大きく、そのままではクラスターが 2 つ目の軸でしか分かれない
+    let dataset = datasets::blobs(&[[0.0, 0.0], [3.0, 0.0], [0.0, 3.0], [3.0, 3.0]], 0.3, 100, 13);
+    let raw: Vec<[f64; 2]> = dataset.points.iter().map(|p| [p[0], p[1] * 1000.0]).collect();
+    assert!(adjusted_rand_index(&dbscan(&raw, 0.5, 5).unwrap().labels, &dataset.labels) < 0.5);
+
+    let (standardized, scaling) = preprocess::standardize(&raw);
+    for d in 0..2 {
+        let mean = standardized.iter().map(|p| p[d]).sum::<f64>() / raw.len() as f64;
+        let variance = standardized.iter().map(|p| (p[d] - mean).powi(2)).sum::<f64>() / raw.len() as f64;
+        assert!(mean.abs() < 1e-9);
+        assert!((variance - 1.0).abs() < 1e-9);
+    }
+    let result = dbscan(&standardized, 0.3, 5).unwrap();
+    assert!(adjusted_rand_index(&result.labels, &dataset.labels) > 0.9);
+    for (original, restored) in raw.iter().zip(scaling.inverse_all(&standardized)) {
+        assert!((original[0] - restored[0]).abs() < 1e-9 && (original[1] - restored[1]).abs() < 1e-6);
+    }
+
+    let (scaled, scaling) = preprocess::min_max_scale(&raw);
+    for d in 0..2 {
+        assert_eq!(scaled.iter().map(|p| p[d]).fold(f64::INFINITY, f64::min), 0.0);
+        assert!((scaled.iter().map(|p| p[d]).fold(f64::NEG_INFINITY, f64::max) - 1.0).abs() < 1e-12);
+    }
+    assert_eq!(scaling.transform(&raw[0]), scaled[0]);
+
+    let (constant, scaling) = preprocess::min_max_scale(&[[1.0, 5.0], [2.0, 5.0]]);
+    assert_eq!(constant, vec![[0.0, 0.0], [1.0, 0.0]]);
+    assert_eq!(scaling.scale(), &[1.0, 1.0]);
+    let (empty, scaling) = preprocess::standardize::<f64, 2>(&[]);
+    assert!(empty.is_empty());
+    assert_eq!(scaling, Scaling::new([0.0; 2], [1.0; 2]));
+}
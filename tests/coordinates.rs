@@ -0,0 +1,167 @@
+use dbscan_rust_test::{
+    adjusted_rand_index, datasets, dbscan,
+    metric::{Cosine, Metric},
+    CosinePoint, DbscanLabel, DynPoint, Error, IntPoint, KdTree, KdTreeItem, Point2, Point3F32,
+};
+
+#[test]
+fn f64_points_are_clustered() {
+    let mut points: Vec<Point2> = Vec::new();
+    for i in 0..10 {
+        points.push([i as f64 * 0.01, 0.0]);
+        points.push([10.0 + i as f64 * 0.01, 10.0]);
+    }
+    points.push([5.0, 5.0]);
+
+    let result = dbscan(&points, 0.05, 3).unwrap();
+    assert_eq!(result.cluster_count, 2);
+    assert_eq!(result.labels[points.len() - 1], DbscanLabel::Noise);
+    assert_eq!(result.cluster_sizes, vec![10, 10]);
+}
+
+#[test]
+fn f32_and_f64_agree() {
+    let points: Vec<Point3F32> = (0..200)
+        .map(|i| {
+            let t = i as f32;
+            [(t * 0.37).sin(), (t * 0.11).cos(), (i % 7) as f32 * 0.1]
+        })
+        .collect();
+    let points_f64: Vec<_> = points.iter().map(|p| p.map(f64::from)).collect();
+
+    let result = dbscan(&points, 0.2, 4).unwrap();
+    let result_f64 = dbscan(&points_f64, 0.2, 4).unwrap();
+    assert_eq!(result.labels, result_f64.labels);
+}
+
+#[cfg(feature = "f16")]
+#[test]
+fn half_points_agree_with_f32_within_rounding() {
+    use dbscan_rust_test::HalfPoint;
+
+    assert_eq!(
+        std::mem::size_of::<HalfPoint<3>>() * 2,
+        std::mem::size_of::<Point3F32>()
+    );
+
+    let dataset = datasets::blobs(&[[0.0, 0.0], [4.0, 0.0], [0.0, 4.0]], 0.4, 200, 5);
+    let points: Vec<[f32; 2]> = dataset.points.iter().map(|p| p.map(|x| x as f32)).collect();
+    let half: Vec<HalfPoint<2>> = points.iter().copied().map(HalfPoint::from).collect();
+    for (p, h) in points.iter().zip(&half) {
+        for (x, y) in p.iter().zip(h.to_f32()) {
+            assert!((x - y).abs() <= x.abs() * 2f32.powi(-11));
+        }
+    }
+    assert!((half[0].distance(&half[1]) - points[0].distance(&points[1])).abs() < 1e-2);
+
+    let result = dbscan(&points, 0.3, 5).unwrap();
+    let result_half = dbscan(&half, 0.3, 5).unwrap();
+    assert_eq!(result_half.cluster_count, result.cluster_count);
+    assert!(adjusted_rand_index(&result_half.labels, &result.labels) > 0.95);
+
+    assert!(!HalfPoint::new([1e5, 0.0]).is_finite());
+}
+
+#[test]
+fn cosine_points_cluster_by_direction() {
+    assert_eq!(CosinePoint::new([0.0, 0.0, 0.0]), Err(Error::ZeroVector));
+    let a = CosinePoint::new([3.0, 0.0, 0.0]).unwrap();
+    let b = CosinePoint::new([0.0, 0.5, 0.0]).unwrap();
+    assert_eq!(a.coordinates(), &[1.0, 0.0, 0.0]);
+    assert!((a.distance(&b) - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+    assert!((a.cosine_similarity(&b)).abs() < 1e-12);
+
+    // 大きさの異なる同じ向きのベクトルは同じクラスターになる
+    let dataset = datasets::blobs(&[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], 0.05, 100, 12);
+    let vectors: Vec<[f64; 3]> = dataset
+        .points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| p.map(|c| c * (1.0 + (i % 5) as f64 * 10.0)))
+        .collect();
+    let points: Vec<_> = vectors.iter().map(|&v| CosinePoint::new(v).unwrap()).collect();
+    let epsilon = CosinePoint::<f64, 3>::epsilon_from_cosine_distance(0.005);
+    let result = dbscan(&points, epsilon, 5).unwrap();
+    assert_eq!(result.cluster_count, 3);
+    assert!(adjusted_rand_index(&result.labels, &dataset.labels) > 0.95);
+
+    let tree = KdTree::construct(points.clone()).unwrap();
+    for (query, vector) in points.iter().zip(&vectors).step_by(17) {
+        let mut found = tree.find_range_n_indices(query, &epsilon);
+        found.sort_unstable();
+        let expected: Vec<usize> = (0..vectors.len())
+            .filter(|&i| Cosine.distance(vector, &vectors[i]) <= 0.005)
+            .collect();
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn integer_points_use_squared_distance() {
+    let points: Vec<IntPoint<i32, 2>> = [[0, 0], [3, 4], [6, 8], [100, 100], [-1_000_000, 1_000_000]]
+        .into_iter()
+        .map(IntPoint)
+        .collect();
+
+    // 距離の 2 乗が 25 以内を近傍とする
+    let result = dbscan(&points, 25, 2).unwrap();
+    assert_eq!(result.cluster_count, 1);
+    assert_eq!(result.cluster_sizes, vec![3]);
+
+    let tree = KdTree::construct(points.clone()).unwrap();
+    assert_eq!(tree.find_nearest(&IntPoint([99, 101])), Ok(Some(&IntPoint([100, 100]))));
+    assert_eq!(tree.find_range_n(&IntPoint([0, 0]), &24).unwrap().len(), 1);
+}
+
+#[test]
+fn integer_distance_saturates_instead_of_overflowing() {
+    let points = vec![IntPoint([i64::MIN, i64::MIN]), IntPoint([i64::MAX, i64::MAX])];
+    let tree = KdTree::construct(points).unwrap();
+    let nearest = tree.find_nearest_n_with_distances(&IntPoint([i64::MIN, i64::MIN]), 2);
+    assert_eq!(nearest[1].1, u128::MAX);
+}
+
+#[test]
+fn dynamic_points_agree_with_arrays() {
+    let points: Vec<Point3F32> = vec![[0.0, 0.0, 0.0], [0.3, 0.0, 0.1], [0.0, 0.4, 0.0], [9.0, 9.0, 9.0]];
+    let arrays: Vec<[f64; 3]> = points.iter().map(|p| p.map(f64::from)).collect();
+    let values: Vec<f64> = arrays.iter().flatten().copied().collect();
+    let dynamic = DynPoint::from_rows(&values, 3).unwrap();
+    assert_eq!(dynamic[1].coordinates(), &arrays[1]);
+    assert_eq!(
+        dbscan(&dynamic, 0.5, 2).unwrap().labels,
+        dbscan(&arrays, 0.5, 2).unwrap().labels
+    );
+
+    let tree = KdTree::construct(dynamic).unwrap();
+    let nearest = tree.find_nearest(&DynPoint::from([8.0, 8.0, 8.0])).unwrap();
+    assert_eq!(nearest.map(DynPoint::coordinates), Some(&arrays[3][..]));
+
+    assert_eq!(
+        DynPoint::new(vec![0.0, 1.0], 3),
+        Err(Error::DimensionMismatch {
+            index: 0,
+            expected: 3,
+            found: 2
+        })
+    );
+    assert_eq!(
+        DynPoint::from_rows(&values[..11], 3),
+        Err(Error::DimensionMismatch {
+            index: 3,
+            expected: 3,
+            found: 2
+        })
+    );
+
+    // 次元数の異なる点が混ざっていれば、木を構築する前に最初のものの位置を返す
+    let mut mixed = DynPoint::from_rows(&values, 3).unwrap();
+    mixed.insert(2, DynPoint::from([1.0, 2.0]));
+    let mismatch = Error::DimensionMismatch {
+        index: 2,
+        expected: 3,
+        found: 2,
+    };
+    assert_eq!(dbscan(&mixed, 0.5, 2), Err(mismatch.clone()));
+    assert_eq!(KdTree::construct(mixed).err(), Some(mismatch));
+}
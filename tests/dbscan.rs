@@ -0,0 +1,478 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use dbscan_rust_test::{
+    adjusted_rand_index, coalesce_duplicates, datasets, dbscan, dbscan_from_graph, dbscan_sweep, dbscan_with_index,
+    kmeans, morton_order, neighbor_graph, noise_ratio, BorderPolicy, CancellationToken, Dbscan, DbscanCheckpoint,
+    DbscanLabel, DbscanParams, Error, FittedIndex, IndexKind, KdTree, NeighborGraph, Parallelism, Point2, PointRole,
+};
+
+#[test]
+fn duplicates_are_coalesced() {
+    let points: Vec<Point2> = vec![
+        [1.0, 0.0],
+        [0.0, 0.0],
+        [1.0, 0.0],
+        [-0.0, 0.0],
+        [f64::NAN, 0.0],
+        [f64::NAN, 0.0],
+    ];
+    let coalesced = coalesce_duplicates(&points);
+    assert_eq!(coalesced.items.len(), 4);
+    assert_eq!(coalesced.multiplicities, vec![2, 2, 1, 1]);
+    assert_eq!(coalesced.groups, vec![0, 1, 0, 1, 2, 3]);
+    assert_eq!(coalesced.expand(&[10, 20, 30, 40]), vec![10, 20, 10, 20, 30, 40]);
+
+    // 重複した数がコア点の判定に数えられる
+    let points: Vec<Point2> = vec![[0.0, 0.0], [0.0, 0.0], [0.0, 0.0], [5.0, 5.0]];
+    let result = Dbscan::new(DbscanParams::new(0.5, 3).deduplicate(true)).run_points(&points);
+    assert_eq!(result.labels[..3], [DbscanLabel::Cluster(1.try_into().unwrap()); 3]);
+    assert_eq!(result.labels[3], DbscanLabel::Noise);
+}
+
+#[test]
+fn model_predicts_new_points() {
+    let points: Vec<Point2> = vec![
+        [0.0, 0.0],
+        [0.3, 0.0],
+        [0.6, 0.0],
+        [1.4, 0.0],
+        [9.0, 9.0],
+        [9.2, 9.0],
+        [20.0, 0.0],
+    ];
+    let model = Dbscan::new(DbscanParams::new(0.5, 2)).fit(&points);
+    let labels = model.result().labels.clone();
+    assert_eq!(labels, dbscan(&points, 0.5, 2).unwrap().labels);
+    assert_eq!(model.core_count(), 5);
+
+    let predicted = model.predict([[0.1, 0.1], [9.1, 9.4], [1.0, 0.0], [1.4, 0.1], [20.0, 0.0]]);
+    assert_eq!(predicted[..3], [labels[0], labels[4], labels[2]]);
+    // ボーダー点やノイズの近くでも、コア点から epsilon 以内でなければノイズになる
+    assert_eq!(predicted[3..], [DbscanLabel::Noise, DbscanLabel::Noise]);
+}
+
+#[test]
+fn roles_distinguish_core_border_and_noise() {
+    let points: Vec<Point2> = vec![[0.0, 0.0], [0.4, 0.0], [0.8, 0.0], [1.2, 0.0], [5.0, 5.0]];
+    let expected = [
+        PointRole::Border,
+        PointRole::Core,
+        PointRole::Core,
+        PointRole::Border,
+        PointRole::Noise,
+    ];
+    for parallelism in [
+        Parallelism::Sequential,
+        Parallelism::Parallel,
+        Parallelism::PrecomputedCounts,
+        Parallelism::PrecomputedNeighbors,
+    ] {
+        let params = DbscanParams::new(0.5, 3).parallelism(parallelism);
+        let result = Dbscan::new(params).run_points(&points);
+        assert_eq!(result.roles.as_deref(), Some(&expected[..]));
+    }
+    assert_eq!(dbscan(&points, 0.5, 3).unwrap().role(3), Some(PointRole::Border));
+
+    // DBSCAN* ではボーダー点もノイズになる
+    let params = DbscanParams::new(0.5, 3).border_policy(BorderPolicy::Noise);
+    let result = Dbscan::new(params).run_points(&points);
+    assert_eq!(result.role(0), Some(PointRole::Noise));
+    assert_eq!(result.role(1), Some(PointRole::Core));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_dbscan_matches_sequential() {
+    use dbscan_rust_test::dbscan_par;
+
+    // 添字 4 の点は 2 つのクラスターのコア点から届くボーダー点
+    let bridged: Vec<Point2> = vec![
+        [0.0, 0.0],
+        [0.2, 0.0],
+        [0.4, 0.0],
+        [0.6, 0.0],
+        [1.0, 0.0],
+        [1.4, 0.0],
+        [1.6, 0.0],
+        [1.8, 0.0],
+        [2.0, 0.0],
+        [5.0, 5.0],
+    ];
+    let sequential = dbscan(&bridged, 0.5, 4).unwrap();
+    let parallel = dbscan_par(&bridged, 0.5, 4);
+    assert_eq!(sequential.cluster_count, 2);
+    assert_ne!(sequential.labels[3], sequential.labels[5]);
+    assert_eq!(parallel.role(4), Some(PointRole::Border));
+    assert_eq!(parallel.labels[4], sequential.labels[3]);
+    assert_eq!(parallel.noise_indices().collect::<Vec<_>>(), [9]);
+    assert_eq!(parallel, sequential);
+
+    let dataset = datasets::blobs(&[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]], 0.3, 2000, 41);
+    for (epsilon, min_points) in [(0.05, 5), (0.1, 10), (0.2, 40)] {
+        let sequential = dbscan(&dataset.points, epsilon, min_points).unwrap();
+        let parallel = dbscan_par(&dataset.points, epsilon, min_points);
+        assert_eq!(parallel.roles, sequential.roles);
+        assert_eq!(
+            parallel.noise_indices().collect::<Vec<_>>(),
+            sequential.noise_indices().collect::<Vec<_>>()
+        );
+        assert_eq!(parallel, sequential);
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_noize_still_matches() {
+    let labels = dbscan([[0.0, 0.0], [9.0, 9.0]], 1.0, 2).unwrap().labels;
+    assert_eq!(labels, [DbscanLabel::Noize; 2]);
+    assert!(labels.iter().all(|&l| match l {
+        DbscanLabel::Cluster(_) => false,
+        DbscanLabel::Noize => true,
+    }));
+}
+
+#[test]
+fn labels_convert_to_and_from_codes() {
+    let labels = dbscan([[0.0, 0.0], [0.1, 0.0], [5.0, 5.0], [5.1, 5.0], [9.0, 0.0]], 0.5, 2)
+        .unwrap()
+        .labels;
+    let codes: Vec<i64> = labels.iter().map(|&l| i64::from(l)).collect();
+    assert_eq!(codes, [0, 0, 1, 1, -1]);
+    assert_eq!(
+        codes
+            .iter()
+            .map(|&c| DbscanLabel::try_from(c).unwrap())
+            .collect::<Vec<_>>(),
+        labels
+    );
+    assert_eq!(DbscanLabel::try_from(-2), Err(Error::InvalidLabelCode { code: -2 }));
+
+    assert_eq!(labels[2].to_string(), "cluster 2");
+    assert_eq!(labels[4].to_string(), "noise");
+}
+
+#[test]
+fn neighbor_graph_is_csr_adjacency() {
+    let points: Vec<Point2> = vec![[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [2.0, 0.0], [9.0, 9.0]];
+    let graph = neighbor_graph(&points, 1.0).unwrap();
+    assert_eq!(
+        graph,
+        NeighborGraph {
+            offsets: vec![0, 1, 4, 6, 8, 8],
+            neighbors: vec![1, 0, 2, 3, 1, 3, 1, 2],
+        }
+    );
+    assert_eq!((graph.len(), graph.edge_count()), (5, 4));
+    assert_eq!((graph.neighbors(1), graph.degree(4)), (&[0, 2, 3][..], 0));
+
+    assert!(neighbor_graph::<Point2>(&[], 1.0).unwrap().is_empty());
+    assert!(matches!(neighbor_graph(&points, f64::NAN), Err(Error::InvalidRadius)));
+}
+
+#[test]
+fn dbscan_from_graph_matches_dbscan() {
+    let points: Vec<Point2> = (0..300)
+        .map(|i| [(i * 37 % 101) as f64 * 0.2, (i * 53 % 89) as f64 * 0.2])
+        .collect();
+    for min_items in [1, 3, 6] {
+        let expected = dbscan(&points, 0.5, min_items).unwrap();
+        let result = dbscan_from_graph(&neighbor_graph(&points, 0.5).unwrap(), min_items);
+        assert_eq!(result.labels, expected.labels);
+        assert_eq!(result.roles, expected.roles);
+    }
+
+    // 外部で作ったグラフ。 0 - 1 - 2 が連結し、 3 は孤立する
+    let graph = NeighborGraph {
+        offsets: vec![0, 1, 3, 4, 4],
+        neighbors: vec![1, 0, 2, 1],
+    };
+    let result = dbscan_from_graph(&graph, 3);
+    assert_eq!(result.cluster_count, 1);
+    assert_eq!(result.role(1), Some(PointRole::Core));
+    assert_eq!(result.labels[3], DbscanLabel::Noise);
+}
+
+#[test]
+fn sampled_dbscan_assigns_points_to_sampled_clusters() {
+    let mut points: Vec<Point2> = Vec::new();
+    for i in 0..400 {
+        let (x, y) = ((i % 20) as f64 * 0.1, (i / 20) as f64 * 0.1);
+        points.push([x, y]);
+        points.push([x + 10.0, y]);
+    }
+    points.push([50.0, 50.0]);
+
+    let dbscan = Dbscan::new(DbscanParams::new(0.5, 5));
+    assert_eq!(dbscan.run_sampled(&points, points.len(), 0), dbscan.run(&points));
+
+    let result = dbscan.run_sampled(&points, 200, 7);
+    assert_eq!(result, dbscan.run_sampled(&points, 200, 7));
+    assert_eq!(result.cluster_count, 2);
+    assert_eq!(result.labels[points.len() - 1], DbscanLabel::Noise);
+    assert!(points[..points.len() - 1]
+        .iter()
+        .zip(&result.labels)
+        .all(|(point, &label)| label == result.labels[if point[0] < 5.0 { 0 } else { 1 }]));
+    assert!(result.roles.unwrap().iter().filter(|&&r| r == PointRole::Core).count() <= 200);
+}
+
+#[test]
+fn sweep_matches_dbscan_at_each_epsilon() {
+    let points: Vec<Point2> = (0..300)
+        .map(|i| [(i * 37 % 101) as f64 * 0.2, (i * 53 % 89) as f64 * 0.2])
+        .collect();
+    let epsilons = [0.5, 0.2, 1.5, 0.5];
+    let results = dbscan_sweep(&points, &epsilons, 4).unwrap();
+    assert_eq!(results.len(), epsilons.len());
+    for (result, &epsilon) in results.iter().zip(&epsilons) {
+        assert_eq!(*result, dbscan(&points, epsilon, 4).unwrap());
+    }
+
+    assert!(dbscan_sweep(&points, &[], 4).unwrap().is_empty());
+    assert!(matches!(
+        dbscan_sweep(&points, &[0.5, f64::NAN], 4),
+        Err(Error::InvalidRadius)
+    ));
+}
+
+#[test]
+fn fitted_index_is_shared_across_algorithms() {
+    let points: Vec<Point2> = (0..300)
+        .map(|i| [(i * 37 % 101) as f64 * 0.2, (i * 53 % 89) as f64 * 0.2])
+        .collect();
+    let fitted = FittedIndex::fit(points.clone()).unwrap();
+    let kdtree = KdTree::construct(points.clone()).unwrap();
+    assert_eq!(
+        fitted.dbscan(0.5, 4).unwrap(),
+        dbscan_with_index(&points, &kdtree, 0.5, 4)
+    );
+    assert_eq!(
+        fitted.dbscan(0.5, 4).unwrap(),
+        dbscan_with_index(&points, &fitted, 0.5, 4)
+    );
+    assert!(matches!(fitted.dbscan(f64::NAN, 4), Err(Error::InvalidRadius)));
+
+    let neighbors = fitted.knn(3);
+    let distances = fitted.kdist(3);
+    for (i, point) in points.iter().enumerate() {
+        assert_eq!(neighbors[i], kdtree.find_nearest_n_indices(point, 3));
+        assert_eq!(neighbors[i][0], (i, 0.0));
+        assert_eq!(distances[i], neighbors[i][2].1);
+    }
+}
+
+#[test]
+fn dbscan_recovers_generated_datasets() {
+    let blobs = datasets::blobs(&[[0.0, 0.0], [5.0, 0.0], [0.0, 5.0]], 0.3, 100, 1);
+    assert_eq!(
+        blobs,
+        datasets::blobs(&[[0.0, 0.0], [5.0, 0.0], [0.0, 5.0]], 0.3, 100, 1)
+    );
+    let result = dbscan(&blobs.points, 0.5, 5).unwrap();
+    assert!(adjusted_rand_index(&result.labels, &blobs.labels) > 0.95);
+
+    let moons = datasets::moons(200, 0.05, 2);
+    let result = dbscan(&moons.points, 0.2, 5).unwrap();
+    assert_eq!(result.cluster_count, 2);
+    assert!(adjusted_rand_index(&result.labels, &moons.labels) > 0.95);
+    let truth: Vec<i32> = moons.labels.iter().map(|l| l.to_code()).collect();
+    let kmeans_labels: Vec<i32> = kmeans(&moons.points, 2, 100, 0)
+        .unwrap()
+        .assignments
+        .iter()
+        .map(|&c| c as i32)
+        .collect();
+    assert!(adjusted_rand_index(&kmeans_labels, &truth) < 0.5);
+
+    let stretch = [[0.6, -0.6], [-0.4, 0.8]];
+    let anisotropic = datasets::anisotropic(&[[0.0, 0.0], [6.0, 6.0]], 0.4, 100, stretch, 3);
+    let result = dbscan(&anisotropic.points, 0.3, 5).unwrap();
+    assert!(adjusted_rand_index(&result.labels, &anisotropic.labels) > 0.9);
+
+    let mixed = blobs.with_noise(30, [-10.0, -10.0], [15.0, 15.0], 4);
+    assert_eq!(mixed.len(), 330);
+    let result = dbscan(&mixed.points, 0.5, 5).unwrap();
+    assert!(adjusted_rand_index(&result.labels, &mixed.labels) > 0.9);
+    assert!(noise_ratio(&result.labels) >= 0.05);
+}
+
+#[test]
+fn morton_order_groups_nearby_points() {
+    let points = [[0.9, 0.9], [0.1, 0.1], [0.9, 0.1], [0.1, 0.9], [0.2, 0.2], [0.8, 0.8]];
+    assert_eq!(morton_order(&points), vec![1, 4, 3, 2, 5, 0]);
+    assert_eq!(morton_order::<f64, 2>(&[]), Vec::<usize>::new());
+
+    let mut with_non_finite = points.to_vec();
+    with_non_finite.push([f64::NAN, f64::INFINITY]);
+    let mut order = morton_order(&with_non_finite);
+    order.sort_unstable();
+    assert_eq!(order, (0..with_non_finite.len()).collect::<Vec<_>>());
+
+    let dataset = datasets::blobs(&[[0.0, 0.0], [5.0, 0.0], [0.0, 5.0]], 0.3, 100, 8);
+    let params = DbscanParams::new(0.5, 5).index(IndexKind::KdTree);
+    let result = Dbscan::new(params.clone()).run_points(&dataset.points);
+    let morton = Dbscan::new(params.morton_order(true)).run_points(&dataset.points);
+    assert_eq!(morton.cluster_count, result.cluster_count);
+    assert_eq!(morton.roles, result.roles);
+    assert!(adjusted_rand_index(&morton.labels, &result.labels) > 0.99);
+}
+
+#[test]
+fn progress_is_reported_until_cancelled() {
+    let dataset = datasets::blobs(&[[0.0, 0.0], [3.0, 0.0]], 0.4, 10000, 43);
+    let len = dataset.len();
+    for parallelism in [Parallelism::Sequential, Parallelism::Parallel] {
+        let dbscan = Dbscan::new(DbscanParams::new(0.05, 5).parallelism(parallelism));
+
+        // 中断しなければ、進捗は減らずに総数まで進み、最後の通知でクラスターの数が分かる
+        let mut events = Vec::new();
+        let result = dbscan
+            .run_with_progress(&dataset.points, |event| events.push(event), &CancellationToken::new())
+            .unwrap();
+        assert_eq!(result, dbscan.run(&dataset.points));
+        assert!(events.len() > 2);
+        assert!(events.iter().all(|event| event.total == 2 * len));
+        assert!(events.windows(2).all(|w| w[0].processed <= w[1].processed));
+        let last = events.last().unwrap();
+        assert_eq!(last.processed, 2 * len);
+        assert_eq!(last.clusters, result.cluster_count);
+
+        // 最初の通知で中断を要求すると、それ以降は通知されずに Err(Cancelled) が返る
+        let cancellation = CancellationToken::new();
+        let mut events = Vec::new();
+        let cancelled = dbscan.run_with_progress(
+            &dataset.points,
+            |event| {
+                events.push(event);
+                cancellation.cancel();
+            },
+            &cancellation,
+        );
+        let partial = cancelled.unwrap_err().partial;
+        assert_eq!(partial.len(), len);
+        assert_eq!(events.len(), 1);
+
+        // コア点の判定の途中で止まればすべての要素がノイズのまま返り、
+        // 併合の途中で止まっても、同じクラスターに属する要素は最後まで実行した結果でも同じクラスターに属する
+        if events[0].processed < len {
+            assert!(partial.iter().all(|&label| label == DbscanLabel::Noise));
+        }
+        let mut clusters = BTreeMap::new();
+        for (&label, &expected) in partial.iter().zip(&result.labels) {
+            if label != DbscanLabel::Noise {
+                assert_eq!(*clusters.entry(label).or_insert(expected), expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn cancelled_run_keeps_merged_clusters() {
+    let dataset = datasets::blobs(&[[0.0, 0.0], [3.0, 0.0], [0.0, 3.0], [3.0, 3.0]], 0.4, 17000, 37);
+    let len = dataset.len();
+    for parallelism in [Parallelism::Sequential, Parallelism::Parallel] {
+        let dbscan = Dbscan::new(DbscanParams::new(0.05, 5).parallelism(parallelism));
+        let expected = dbscan.run(&dataset.points);
+
+        // 始める前から中断が要求されていれば、コア点の判定の途中で止まる
+        let cancelled = CancellationToken::new();
+        cancelled.cancel();
+        let partial = dbscan.run_cancellable(&dataset.points, &cancelled).unwrap_err().partial;
+        assert!(partial.iter().all(|&label| label == DbscanLabel::Noise));
+
+        // コア点の判定を終えた時点で中断を要求すると、併合の途中で止まる
+        let cancellation = CancellationToken::new();
+        let cancelled = dbscan.run_with_progress(
+            &dataset.points,
+            |event| {
+                if event.processed >= len {
+                    cancellation.cancel();
+                }
+            },
+            &cancellation,
+        );
+        let partial = cancelled.unwrap_err().partial;
+        assert_eq!(partial.len(), len);
+        assert!(partial.iter().any(|&label| label != DbscanLabel::Noise));
+
+        // 途中のラベルで同じクラスターに属する要素は、最後まで実行した結果でも同じクラスターに属する
+        let mut clusters = BTreeMap::new();
+        for (&label, &expected) in partial.iter().zip(&expected.labels) {
+            if label != DbscanLabel::Noise {
+                assert_ne!(expected, DbscanLabel::Noise);
+                assert_eq!(*clusters.entry(label).or_insert(expected), expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn checkpointed_run_resumes_after_cancellation() {
+    let dataset = datasets::blobs(&[[0.0, 0.0], [3.0, 0.0], [0.0, 3.0], [3.0, 3.0]], 0.4, 5000, 29);
+    let dbscan = Dbscan::new(DbscanParams::new(0.1, 5).parallelism(Parallelism::Sequential));
+    let expected = dbscan.run(&dataset.points);
+
+    // コア点の併合の途中にあたる 25 回目の書き出しで中断を要求すると、次に中断を確認した時点で止まり、その時点の状態も書き出される
+    let cancellation = CancellationToken::new();
+    let mut checkpoints: Vec<DbscanCheckpoint> = Vec::new();
+    let cancelled = dbscan.run_checkpointed(
+        &dataset.points,
+        None,
+        1000,
+        |checkpoint| {
+            checkpoints.push(checkpoint.clone());
+            if checkpoints.len() == 25 {
+                cancellation.cancel();
+            }
+        },
+        &cancellation,
+    );
+    let partial = cancelled.unwrap_err().partial;
+    let last = checkpoints.last().unwrap();
+    assert!(checkpoints.len() > 25);
+    assert_eq!(last.labels(), partial);
+    assert!(last.processed() > dataset.points.len() && last.processed() < 2 * dataset.points.len());
+    assert!(partial.iter().any(|&label| label != DbscanLabel::Noise));
+    assert!(checkpoints[0].labels().iter().all(|&label| label == DbscanLabel::Noise));
+    assert!(checkpoints.windows(2).all(|w| w[0].processed() < w[1].processed()));
+
+    // 中断した時点からも、それより前の時点からも同じ結果に至る
+    for checkpoint in [&checkpoints[0], last] {
+        assert_eq!(checkpoint.validate(dataset.points.len()), Ok(()));
+        let mut saved = 0;
+        let resumed = dbscan.run_checkpointed(
+            &dataset.points,
+            Some(checkpoint.clone()),
+            1000,
+            |_| saved += 1,
+            &CancellationToken::new(),
+        );
+        assert_eq!(resumed.unwrap(), expected);
+        assert!(saved > 0);
+    }
+
+    assert_eq!(last.validate(dataset.points.len() - 1), Err(Error::InvalidCheckpoint));
+}
+
+#[test]
+fn run_timed_matches_run_and_reports_stages() {
+    let dataset = datasets::blobs(&[[0.0, 0.0], [5.0, 5.0]], 0.5, 200, 5);
+    for parallelism in [Parallelism::Sequential, Parallelism::Parallel] {
+        let dbscan = Dbscan::new(DbscanParams::new(0.5, 5).parallelism(parallelism));
+        let (result, timings) = dbscan.run_timed(&dataset.points);
+        assert_eq!(result, dbscan.run(&dataset.points));
+        assert_eq!(
+            timings.total(),
+            timings.index_build + timings.neighbor_queries + timings.expansion
+        );
+
+        // std 無しでは時刻を取得できず、所要時間は常に 0 になる
+        #[cfg(feature = "std")]
+        assert!(timings.neighbor_queries > Duration::ZERO);
+    }
+
+    let brute_force = Dbscan::new(DbscanParams::new(0.5, 5).index(IndexKind::BruteForce));
+    let (result, timings) = brute_force.run_timed(&dataset.points);
+    assert_eq!(result, brute_force.run(&dataset.points));
+    assert_eq!(timings.index_build, Duration::ZERO);
+}
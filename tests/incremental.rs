@@ -0,0 +1,69 @@
+use dbscan_rust_test::{adjusted_rand_index, dbscan, DbscanLabel, Error, IncrementalDbscan, Point2};
+
+#[test]
+fn incremental_dbscan_tracks_inserts_and_removes() {
+    // 間隔 1 の点列で epsilon 1.5, min_items 2 とし、近傍を持つ点がすべてコア点になるようにする (ボーダー点の揺れがない)
+    fn check(incremental: &IncrementalDbscan<Point2>, ids: &[usize]) {
+        let points: Vec<Point2> = ids.iter().map(|&id| *incremental.get(id).unwrap()).collect();
+        let labels: Vec<DbscanLabel> = ids.iter().map(|&id| incremental.label(id).unwrap()).collect();
+        let expected = dbscan(&points, 1.5, 2).unwrap();
+        assert_eq!(adjusted_rand_index(&labels, &expected.labels), 1.0);
+        assert_eq!(incremental.cluster_count(), expected.cluster_count);
+        for (label, expected) in labels.iter().zip(&expected.labels) {
+            assert_eq!(*label == DbscanLabel::Noise, *expected == DbscanLabel::Noise);
+        }
+    }
+
+    let mut incremental = IncrementalDbscan::new(1.5, 2).unwrap();
+    let mut ids: Vec<usize> = (0..4).map(|x| incremental.insert([x as f64, 0.0]).unwrap()).collect();
+    ids.extend((6..10).map(|x| incremental.insert([x as f64, 0.0]).unwrap()));
+    ids.push(incremental.insert([20.0, 0.0]).unwrap());
+    check(&incremental, &ids);
+    assert_eq!(incremental.cluster_count(), 2);
+
+    // 間を埋めると 2 つのクラスターが併合される
+    let bridge: Vec<usize> = [4.0, 5.0]
+        .iter()
+        .map(|&x| incremental.insert([x, 0.0]).unwrap())
+        .collect();
+    ids.extend(&bridge);
+    check(&incremental, &ids);
+    assert_eq!(incremental.cluster_count(), 1);
+
+    // 橋の片方を削除すると再び分裂する
+    incremental.remove(bridge[0]).unwrap();
+    ids.retain(|&id| id != bridge[0]);
+    check(&incremental, &ids);
+    assert_eq!(incremental.cluster_count(), 2);
+
+    // ノイズだった点に近傍ができるとクラスターになり、その近傍を削除するとノイズに戻る
+    let near = incremental.insert([21.0, 0.0]).unwrap();
+    ids.push(near);
+    check(&incremental, &ids);
+    incremental.remove(near).unwrap();
+    ids.retain(|&id| id != near);
+    check(&incremental, &ids);
+    assert_eq!(incremental.remove(near), None);
+
+    // min_items が 1 であれば孤立した点は自身だけのクラスターになり、削除するとクラスターも消える
+    let mut singletons = IncrementalDbscan::new(1.5, 1).unwrap();
+    let a = singletons.insert([0.0, 0.0]).unwrap();
+    singletons.insert([10.0, 0.0]).unwrap();
+    assert_eq!(singletons.cluster_count(), 2);
+    singletons.remove(a).unwrap();
+    assert_eq!(singletons.cluster_count(), 1);
+
+    // 比較できない半径と有限でない要素は拒み、拒んだ要素は ID も状態も変えない
+    assert!(matches!(
+        IncrementalDbscan::<Point2>::new(f64::NAN, 2),
+        Err(Error::InvalidRadius)
+    ));
+    let labels = singletons.labels().to_vec();
+    assert_eq!(
+        singletons.insert([f64::INFINITY, 0.0]),
+        Err(Error::NonFiniteInput { indices: vec![2] })
+    );
+    assert_eq!(singletons.labels(), labels);
+    assert_eq!(singletons.len(), 1);
+    assert_eq!(singletons.insert([20.0, 0.0]), Ok(2));
+}
@@ -0,0 +1,229 @@
+use dbscan_rust_test::{
+    adjusted_rand_index, datasets, dbscan_with_index, dbscan_with_index_kind,
+    metric::{Chebyshev, Haversine, Manhattan, Metric},
+    ApproxDbscan, BruteForceIndex, CoverTree, Dbscan, DbscanLabel, DbscanParams, Error, HnswIndex, HnswOptions,
+    IndexKind, KdTree, KdTreeItem, SpatialIndex, VpTree,
+};
+
+#[test]
+fn cover_tree_matches_kdtree_on_clustered_high_dimensional_points() {
+    let centers: Vec<[f64; 16]> = (0..4)
+        .map(|c| std::array::from_fn(|d| (c * 16 + d) as f64 % 7.0))
+        .collect();
+    let dataset = datasets::blobs(&centers, 0.2, 200, 9);
+    let cover_tree = CoverTree::construct(dataset.points.clone()).unwrap();
+    let brute_force = BruteForceIndex::new(dataset.points.clone());
+    for query in [dataset.points[0], dataset.points[450], [3.0; 16]] {
+        assert_eq!(
+            cover_tree
+                .nearest_n(&query, 10)
+                .iter()
+                .map(|&(_, d)| d)
+                .collect::<Vec<_>>(),
+            brute_force
+                .nearest_n(&query, 10)
+                .iter()
+                .map(|&(_, d)| d)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    let result = dbscan_with_index_kind(&dataset.points, 1.2, 5, IndexKind::CoverTree);
+    assert_eq!(
+        result,
+        dbscan_with_index_kind(&dataset.points, 1.2, 5, IndexKind::KdTree)
+    );
+    assert!(adjusted_rand_index(&result.labels, &dataset.labels) > 0.95);
+
+    let manhattan = CoverTree::construct_with_metric(dataset.points.clone(), Manhattan);
+    let mut found = manhattan.range(&dataset.points[0], &2.0);
+    found.sort_unstable();
+    let expected: Vec<usize> = (0..dataset.len())
+        .filter(|&i| Manhattan.distance(&dataset.points[0], &dataset.points[i]) <= 2.0)
+        .collect();
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn hnsw_finds_most_neighbors_and_approx_dbscan_matches_exact() {
+    let centers: Vec<[f64; 16]> = (0..4)
+        .map(|c| std::array::from_fn(|d| ((c * 5 + d) % 4) as f64))
+        .collect();
+    let dataset = datasets::blobs(&centers, 0.2, 250, 10).with_noise(50, [-1.0; 16], [4.0; 16], 11);
+    let index = HnswIndex::construct(dataset.points.clone()).unwrap();
+    assert_eq!(index.len(), dataset.len());
+    let brute_force = BruteForceIndex::new(dataset.points.clone());
+
+    let mut hits = 0;
+    let mut total = 0;
+    for query in dataset.points.iter().step_by(10) {
+        let expected = brute_force.nearest_n(query, 10);
+        let found = index.nearest_n(query, 10);
+        assert!(found.windows(2).all(|w| w[0].1 <= w[1].1));
+        hits += found.iter().filter(|f| expected.iter().any(|e| e.0 == f.0)).count();
+        total += expected.len();
+
+        let range = index.range(query, &1.0);
+        assert!(range.iter().all(|&i| query.distance(&dataset.points[i]) <= 1.0));
+    }
+    assert!(hits as f64 >= 0.9 * total as f64, "recall {hits}/{total}");
+
+    let params = DbscanParams::new(1.0, 5);
+    let exact = Dbscan::new(params.clone()).run(&dataset.points);
+    let approx_dbscan = ApproxDbscan::new(params, HnswOptions::default());
+    let approx = approx_dbscan.try_run(&dataset.points).unwrap();
+    assert!(adjusted_rand_index(&approx.labels, &exact.labels) > 0.95);
+    assert!(adjusted_rand_index(&approx.labels, &dataset.labels) > 0.9);
+
+    let mut dirty = dataset.points.clone();
+    dirty[3][1] = f64::NAN;
+    let error = Error::NonFiniteInput { indices: vec![3] };
+    assert_eq!(approx_dbscan.try_run(&dirty).unwrap_err(), error);
+    assert_eq!(HnswIndex::construct(dirty).err().unwrap(), error);
+}
+
+#[test]
+fn vp_tree_clusters_strings_by_edit_distance() {
+    fn edit_distance(lhs: &&str, rhs: &&str) -> f64 {
+        let rhs: Vec<char> = rhs.chars().collect();
+        let mut row: Vec<usize> = (0..=rhs.len()).collect();
+        for (i, l) in lhs.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, r) in rhs.iter().enumerate() {
+                let substituted = diagonal + usize::from(l != *r);
+                diagonal = row[j + 1];
+                row[j + 1] = substituted.min(row[j] + 1).min(row[j + 1] + 1);
+            }
+        }
+        row[rhs.len()] as f64
+    }
+
+    let words = [
+        "kitten", "sitten", "sittin", "sitting", "mitten", "bitten", "apple", "apply", "ample", "maple", "zebra",
+    ];
+    let index = VpTree::construct(words.to_vec(), edit_distance);
+    assert_eq!(index.len(), words.len());
+
+    let mut found = index.range(&"kitten", &1.0);
+    found.sort_unstable();
+    assert_eq!(found, vec![0, 1, 4, 5]);
+    assert_eq!(
+        index.nearest_n(&"appl", 3).iter().map(|&(_, d)| d).collect::<Vec<_>>(),
+        vec![1.0, 1.0, 2.0]
+    );
+
+    let result = dbscan_with_index(words, &index, 2.0, 3);
+    assert_eq!(result.cluster_count, 2);
+    assert_eq!(result.labels[..6], [result.labels[0]; 6]);
+    assert_eq!(result.labels[6..10], [result.labels[6]; 4]);
+    assert_ne!(result.labels[0], result.labels[6]);
+    assert_eq!(result.labels[10], DbscanLabel::Noise);
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+fn gpu_index_matches_brute_force() {
+    use dbscan_rust_test::{GpuError, GpuIndex, KdTree};
+
+    let dataset = datasets::blobs(&[[0.0, 0.0], [4.0, 0.0]], 0.5, 150, 6).with_noise(20, [-3.0, -3.0], [7.0, 3.0], 7);
+    let points: Vec<[f32; 2]> = dataset.points.iter().map(|p| p.map(|x| x as f32)).collect();
+    let index = match GpuIndex::new(points.clone()) {
+        Ok(index) => index,
+        // GPU もソフトウェアの実装も無い環境では確かめられない
+        Err(GpuError::NoAdapter(_)) => return,
+        Err(e) => panic!("{e}"),
+    };
+
+    let reduced = |a: &[f32; 2], b: &[f32; 2]| (a[0] - b[0]) * (a[0] - b[0]) + (a[1] - b[1]) * (a[1] - b[1]);
+    let queries = [[0.0, 0.0], [2.0, 0.5], [10.0, 10.0]];
+    let ranges = index.range_batch(&queries, 0.6);
+    for (query, range) in queries.iter().zip(&ranges) {
+        let expected: Vec<usize> = (0..points.len())
+            .filter(|&i| reduced(query, &points[i]) <= 0.36)
+            .collect();
+        assert_eq!(range, &expected);
+        assert_eq!(&index.range(query, &0.6), range);
+    }
+    let counts: Vec<usize> = ranges.iter().map(Vec::len).collect();
+    assert_eq!(index.count_batch(&queries, 0.6), counts);
+
+    let nearest = index.nearest_n(&[4.0, 0.0], 5);
+    let mut expected: Vec<usize> = (0..points.len()).collect();
+    expected.sort_by(|&a, &b| reduced(&[4.0, 0.0], &points[a]).total_cmp(&reduced(&[4.0, 0.0], &points[b])));
+    assert_eq!(nearest.iter().map(|&(i, _)| i).collect::<Vec<_>>(), expected[..5]);
+
+    let result = index.dbscan(0.5, 5);
+    assert_eq!(
+        result,
+        dbscan_with_index(&points, &KdTree::construct(points.clone()).unwrap(), 0.5, 5)
+    );
+    assert!(adjusted_rand_index(&result.labels, &dataset.labels) > 0.9);
+}
+
+#[test]
+fn chebyshev_and_haversine_searches_match_brute_force() {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    // 低食い違い列で偏りなく点を置く
+    let fract = |x: f64| x - x.floor();
+    let cube: Vec<[f64; 3]> = (0..2000)
+        .map(|i| std::array::from_fn(|d| fract(i as f64 * [0.8191725134, 0.6710436067, 0.5497004779][d]) * 4.0))
+        .collect();
+    check_against_brute_force(
+        &cube,
+        Chebyshev,
+        &[[0.0; 3], [2.0; 3], [1.0, 3.9, 0.5]],
+        &[0.05, 0.3, 1.0],
+    );
+
+    // ±180° の経線の両側と極の近くにも点を置き、そこから探索する
+    let edges: Vec<[f64; 2]> = vec![
+        [0.0, PI],
+        [0.0, -PI],
+        [0.3, PI - 1e-3],
+        [0.3, -PI + 1e-3],
+        [-0.7, PI - 0.02],
+        [-0.7, -PI + 0.02],
+        [FRAC_PI_2, 0.0],
+        [FRAC_PI_2 - 1e-3, 2.0],
+        [FRAC_PI_2 - 1e-3, -1.0],
+        [-FRAC_PI_2, 1.0],
+        [-FRAC_PI_2 + 1e-3, -3.0],
+        [-FRAC_PI_2 + 1e-3, 3.0],
+    ];
+    let sphere: Vec<[f64; 2]> = (0..2000)
+        .map(|i| {
+            let (u, v) = (fract(i as f64 * 0.7548776662), fract(i as f64 * 0.5698402910));
+            [(2.0 * u - 1.0).asin(), PI * (2.0 * v - 1.0)]
+        })
+        .chain(edges.iter().copied())
+        .collect();
+    check_against_brute_force(&sphere, Haversine, &edges, &[0.01, 0.1, 0.5]);
+
+    // ±180° の経線や極を挟んだ点同士は近い
+    assert!((Haversine.distance(&[0.0, PI - 0.01], &[0.0, -PI + 0.01]) - 0.02).abs() < 1e-12);
+    assert!((Haversine.distance(&[FRAC_PI_2 - 0.01, 0.0], &[FRAC_PI_2 - 0.01, PI]) - 0.02).abs() < 1e-12);
+}
+
+/// metric を用いた KdTree の範囲探索と k 近傍探索が、全要素との距離を調べた結果と一致することを確かめる。
+fn check_against_brute_force<T, M>(items: &[T], metric: M, queries: &[T], radii: &[f64])
+where
+    T: KdTreeItem + Clone,
+    M: Metric<T, Measurement = f64> + Clone,
+{
+    let tree = KdTree::construct_with_metric(items.to_vec(), metric.clone());
+    for query in queries {
+        let mut distances: Vec<f64> = items.iter().map(|item| metric.distance(query, item)).collect();
+        for &radius in radii {
+            let mut found = tree.find_range_n_indices(query, &radius);
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..items.len()).filter(|&i| distances[i] <= radius).collect();
+            assert_eq!(found, expected);
+        }
+
+        distances.sort_by(f64::total_cmp);
+        let nearest: Vec<f64> = tree.find_nearest_n_indices(query, 10).iter().map(|&(_, d)| d).collect();
+        assert_eq!(nearest, distances[..10]);
+    }
+}
@@ -0,0 +1,143 @@
+#[cfg(feature = "ndarray")]
+#[test]
+fn ndarray_rows_are_points() {
+    use dbscan_rust_test::{dbscan, dbscan_array, Error, KdTree, Point2};
+    use ndarray::{array, Array2};
+
+    let points = array![[0.0, 0.0], [0.3, 0.0], [0.0, 0.4], [9.0, 9.0], [9.2, 9.0]];
+    let labels = dbscan_array(points.view(), 0.5, 2).unwrap();
+    let arrays: Vec<Point2> = points.rows().into_iter().map(|r| [r[0], r[1]]).collect();
+    let expected: Vec<i64> = dbscan(&arrays, 0.5, 2)
+        .unwrap()
+        .labels
+        .iter()
+        .map(|l| l.to_code().into())
+        .collect();
+    assert_eq!(labels.to_vec(), expected);
+
+    // 列優先の配列や転置したビューも行を点として扱う
+    let transposed = points.t().to_owned();
+    assert_eq!(dbscan_array(transposed.t(), 0.5, 2).unwrap(), labels);
+
+    let query = ndarray::arr1(&[8.0, 8.0]);
+    let tree = KdTree::from_array(points.view()).unwrap();
+    let nearest = tree.find_nearest(&query.view()).unwrap();
+    assert_eq!(nearest.map(|r| r.to_vec()), Some(vec![9.0, 9.0]));
+
+    let mut invalid = Array2::<f64>::zeros((3, 2));
+    invalid[[1, 0]] = f64::NAN;
+    assert_eq!(
+        dbscan_array(invalid.view(), 0.5, 2),
+        Err(Error::NonFiniteInput { indices: vec![1] })
+    );
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn arrow_columns_are_coordinates() {
+    use std::sync::Arc;
+
+    use arrow_array::{ArrayRef, Float32Array, Float64Array, Int32Array, RecordBatch};
+    use dbscan_rust_test::{dbscan, dbscan_arrow, DbscanParams, Point2};
+
+    let x: ArrayRef = Arc::new(Float64Array::from(vec![0.0, 0.3, 0.0, 9.0, 9.2]));
+    let y: ArrayRef = Arc::new(Float64Array::from(vec![0.0, 0.0, 0.4, 9.0, 9.0]));
+    let y32: ArrayRef = Arc::new(Float32Array::from(vec![0.0, 0.0, 0.4, 9.0, 9.0]));
+    let name: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3, 4, 5]));
+    let batch = RecordBatch::try_from_iter([("x", x), ("y", y), ("y32", y32), ("name", name)]).unwrap();
+
+    let labels = dbscan_arrow(&batch, &["x", "y"], DbscanParams::new(0.5, 2)).unwrap();
+    let points: Vec<Point2> = vec![[0.0, 0.0], [0.3, 0.0], [0.0, 0.4], [9.0, 9.0], [9.2, 9.0]];
+    let expected: Vec<i32> = dbscan(&points, 0.5, 2)
+        .unwrap()
+        .labels
+        .iter()
+        .map(|l| l.to_code())
+        .collect();
+    assert_eq!(labels.values().to_vec(), expected);
+
+    // 1 列だけならその列の座標だけで判定される
+    let labels = dbscan_arrow(&batch, &["y32"], DbscanParams::new(0.5, 2)).unwrap();
+    assert_eq!(labels.values().to_vec(), vec![0, 0, 0, 1, 1]);
+
+    assert!(dbscan_arrow(&batch, &["x", "missing"], DbscanParams::new(0.5, 2)).is_err());
+    assert!(dbscan_arrow(&batch, &["x", "y32"], DbscanParams::new(0.5, 2)).is_err());
+    assert!(dbscan_arrow(&batch, &["name"], DbscanParams::new(0.5, 2)).is_err());
+    assert!(dbscan_arrow(&batch, &[], DbscanParams::new(0.5, 2)).is_err());
+}
+
+#[cfg(feature = "cabi")]
+#[test]
+fn c_abi_validates_arguments() {
+    use std::ptr;
+
+    use dbscan_rust_test::cabi::{
+        dbscan_f32, dbscan_f64, kdtree_free, kdtree_len, kdtree_new, kdtree_query_radius, DbscanStatus,
+    };
+
+    let points: [f32; 10] = [0.0, 0.0, 0.3, 0.0, 0.0, 0.4, 9.0, 9.0, 9.2, 9.0];
+    let mut labels = [0; 5];
+    let status = unsafe { dbscan_f32(points.as_ptr(), 5, 2, 0.5, 2, labels.as_mut_ptr()) };
+    assert_eq!(status, DbscanStatus::Ok);
+    assert_eq!(labels, [0, 0, 0, 1, 1]);
+
+    // 座標の数が桁あふれする場合は、ポインターを読む前に拒否する
+    let status = unsafe { dbscan_f32(points.as_ptr(), usize::MAX / 2 + 1, 2, 0.5, 2, labels.as_mut_ptr()) };
+    assert_eq!(status, DbscanStatus::TooManyPoints);
+    let status = unsafe { dbscan_f64(ptr::dangling(), usize::MAX / 8, 1, 0.5, 2, labels.as_mut_ptr()) };
+    assert_eq!(status, DbscanStatus::TooManyPoints);
+    assert!(unsafe { kdtree_new(points.as_ptr(), usize::MAX / 2 + 1, 2) }.is_null());
+
+    for epsilon in [f32::NAN, -0.5] {
+        let status = unsafe { dbscan_f32(points.as_ptr(), 5, 2, epsilon, 2, labels.as_mut_ptr()) };
+        assert_eq!(status, DbscanStatus::NonFiniteInput);
+    }
+    let status = unsafe { dbscan_f32(points.as_ptr(), 5, 0, 0.5, 2, labels.as_mut_ptr()) };
+    assert_eq!(status, DbscanStatus::InvalidDimensions);
+    let status = unsafe { dbscan_f32(ptr::null(), 5, 2, 0.5, 2, labels.as_mut_ptr()) };
+    assert_eq!(status, DbscanStatus::NullPointer);
+
+    let tree = unsafe { kdtree_new(points.as_ptr(), 5, 2) };
+    assert!(!tree.is_null());
+    assert_eq!(unsafe { kdtree_len(tree) }, 5);
+    let (mut indices, mut count) = ([0; 5], 0);
+    let status = unsafe { kdtree_query_radius(tree, points.as_ptr(), 0.35, indices.as_mut_ptr(), 5, &mut count) };
+    assert_eq!(status, DbscanStatus::Ok);
+    indices[..count].sort_unstable();
+    assert_eq!(&indices[..count], &[0, 1]);
+    for radius in [f32::NAN, -1.0] {
+        let status = unsafe { kdtree_query_radius(tree, points.as_ptr(), radius, indices.as_mut_ptr(), 5, &mut count) };
+        assert_eq!(status, DbscanStatus::NonFiniteInput);
+    }
+    unsafe { kdtree_free(tree) };
+}
+
+#[cfg(feature = "linfa")]
+#[test]
+fn linfa_traits_agree_with_linfa_clustering() {
+    use dbscan_rust_test::{Dbscan, DbscanParams};
+    use linfa::{
+        traits::{Fit, Transformer},
+        DatasetBase, ParamGuard,
+    };
+    use ndarray::array;
+
+    let observations = array![[0.0, 0.0], [0.3, 0.0], [0.0, 0.4], [9.0, 9.0], [9.2, 9.0], [20.0, 0.0]];
+    let dbscan = Dbscan::new(DbscanParams::new(0.5, 2));
+    let memberships = dbscan.transform(&observations);
+    let expected = linfa_clustering::Dbscan::params(2)
+        .tolerance(0.5)
+        .check_unwrap()
+        .transform(&observations);
+    assert_eq!(memberships, expected);
+
+    let dataset = dbscan.transform(DatasetBase::from(observations.clone()));
+    assert_eq!(dataset.targets, memberships);
+
+    // 固有メソッドの Dbscan::fit() と区別するため、トレイトを明示して呼ぶ
+    let result = Fit::fit(&dbscan, &DatasetBase::from(observations)).unwrap();
+    assert_eq!(result.cluster_count, 2);
+
+    let invalid = DatasetBase::from(array![[0.0, f64::NAN]]);
+    assert!(Fit::fit(&dbscan, &invalid).is_err());
+}
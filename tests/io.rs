@@ -0,0 +1,32 @@
+use dbscan_rust_test::{
+    dbscan,
+    io::{write_csv, write_json},
+    Point2,
+};
+
+#[test]
+fn labels_are_written_as_csv_and_json() {
+    let points: Vec<Point2> = vec![[0.0, 0.0], [0.3, 0.0], [9.0, 9.0], [20.0, 0.0], [9.2, 9.0]];
+    let result = dbscan(&points, 1.0, 2).unwrap();
+
+    let mut csv = Vec::new();
+    write_csv(&result, &mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "index,label\n0,0\n1,0\n2,1\n3,-1\n4,1\n"
+    );
+
+    let mut json = Vec::new();
+    write_json(&result, &mut json).unwrap();
+    let expected = r#"[{"index":0,"label":0},{"index":1,"label":0},{"index":2,"label":1},{"index":3,"label":-1},{"index":4,"label":1}]"#;
+    assert_eq!(String::from_utf8(json).unwrap(), format!("{expected}\n"));
+
+    // 要素が無ければヘッダーと空の配列だけになる
+    let empty = dbscan(Vec::<Point2>::new(), 1.0, 2).unwrap();
+    let mut csv = Vec::new();
+    write_csv(&empty, &mut csv).unwrap();
+    assert_eq!(csv, b"index,label\n");
+    let mut json = Vec::new();
+    write_json(&empty, &mut json).unwrap();
+    assert_eq!(json, b"[]\n");
+}
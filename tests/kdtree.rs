@@ -0,0 +1,248 @@
+use dbscan_rust_test::{
+    metric::ItemMetric, CancellationToken, Cancelled, DynPoint, Error, KdTree, KdTreeOptions, Point2,
+};
+
+#[test]
+fn inserted_duplicates_are_found() {
+    let mut tree = KdTree::construct(vec![[0.0, 0.0]]).unwrap();
+    for _ in 0..1000 {
+        tree.insert([0.0, 0.0]);
+    }
+    tree.insert([1.0, 1.0]);
+    assert_eq!(tree.find_range_n(&[0.0, 0.0], &0.5).unwrap().len(), 1001);
+    assert_eq!(tree.find_nearest(&[0.9, 0.9]), Ok(Some(&[1.0, 1.0])));
+
+    // 検証に失敗した要素は挿入されず、位置も消費しない
+    assert_eq!(
+        tree.try_insert([f64::NAN, 0.0]),
+        Err(Error::NonFiniteInput { indices: vec![1002] })
+    );
+    assert_eq!(tree.try_insert([2.0, 2.0]), Ok(1002));
+    assert_eq!(tree.len(), 1003);
+    tree.rebalance();
+    assert_eq!(tree.find_nearest(&[1.9, 1.9]), Ok(Some(&[2.0, 2.0])));
+
+    let mut dynamic = KdTree::construct(vec![DynPoint::from([0.0, 0.0])]).unwrap();
+    assert_eq!(
+        dynamic.try_insert(DynPoint::from([1.0, 1.0, 1.0])),
+        Err(Error::DimensionMismatch {
+            index: 1,
+            expected: 2,
+            found: 3
+        })
+    );
+    assert_eq!(dynamic.try_insert(DynPoint::from([1.0, 1.0])), Ok(1));
+}
+
+#[test]
+fn range_batch_matches_single_queries() {
+    let points: Vec<Point2> = (0..500)
+        .map(|i| [(i * 37 % 101) as f64 * 0.1, (i * 53 % 89) as f64 * 0.1])
+        .collect();
+    let queries: Vec<Point2> = (0..200)
+        .map(|i| [(i * 17 % 97) as f64 * 0.1, (i * 29 % 83) as f64 * 0.1])
+        .collect();
+    let tree = KdTree::construct(points).unwrap();
+    let expected: Vec<_> = queries
+        .iter()
+        .map(|query| tree.find_range_n_indices(query, &0.5))
+        .collect();
+    assert_eq!(tree.find_range_batch(&queries, &0.5), expected);
+    #[cfg(feature = "parallel")]
+    assert_eq!(tree.find_range_batch_par(&queries, &0.5), expected);
+    assert!(tree.find_range_batch(&[], &0.5).is_empty());
+}
+
+#[test]
+fn cancelled_construction_and_knn_stop_early() {
+    let points: Vec<Point2> = (0..5000)
+        .map(|i| [(i * 37 % 101) as f64 * 0.1, (i * 53 % 89) as f64 * 0.1])
+        .collect();
+    let options = KdTreeOptions::default();
+
+    // 中断が要求されたトークンでは構築しない
+    let cancelled = CancellationToken::new();
+    cancelled.cancel();
+    assert!(KdTree::try_construct_with_options(points.clone(), ItemMetric, options, &cancelled).is_err());
+    let tree =
+        KdTree::try_construct_with_options(points.clone(), ItemMetric, options, &CancellationToken::new()).unwrap();
+
+    let expected = tree.knn(&points, 3);
+    assert_eq!(
+        tree.knn_cancellable(&points, 3, &CancellationToken::new()),
+        Ok(expected.clone())
+    );
+    assert_eq!(
+        tree.knn_cancellable(&points, 3, &cancelled).unwrap_err().partial.len(),
+        0
+    );
+
+    #[cfg(feature = "parallel")]
+    {
+        assert_eq!(
+            tree.knn_par_cancellable(&points, 3, &CancellationToken::new()),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            tree.knn_par_cancellable(&points, 3, &cancelled)
+                .unwrap_err()
+                .partial
+                .len(),
+            0
+        );
+    }
+
+    // 別のスレッドから中断すると、 partial には先頭から処理を終えた query の結果だけが入る
+    #[allow(unused_mut)]
+    let mut results = vec![cancel_shortly(|cancellation| {
+        tree.knn_cancellable(&points, 3, cancellation)
+    })];
+    #[cfg(feature = "parallel")]
+    results.push(cancel_shortly(|cancellation| {
+        tree.knn_par_cancellable(&points, 3, cancellation)
+    }));
+    for result in results {
+        match result {
+            Ok(results) => assert_eq!(results, expected),
+            Err(Cancelled { partial }) => {
+                assert!(partial.len() < expected.len());
+                assert_eq!(partial[..], expected[..partial.len()]);
+            }
+        }
+    }
+}
+
+/// 別のスレッドから少し後に中断を要求しながら run を実行する。
+fn cancel_shortly<R>(run: impl FnOnce(&CancellationToken) -> R) -> R {
+    let cancellation = CancellationToken::new();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            cancellation.cancel();
+        });
+        run(&cancellation)
+    })
+}
+
+#[test]
+fn find_in_box_returns_items_inside_the_box() {
+    let points: Vec<Point2> = (0..100).map(|i| [(i % 10) as f64, (i / 10) as f64]).collect();
+    let mut tree = KdTree::construct(points).unwrap();
+    let mut found = tree.find_in_box_indices(&[2.0, 3.0], &[4.5, 4.0]);
+    found.sort_unstable();
+    assert_eq!(found, vec![32, 33, 34, 42, 43, 44]);
+
+    tree.remove(&[3.0, 3.0]);
+    assert_eq!(tree.find_in_box(&[2.0, 3.0], &[4.5, 4.0]).len(), 5);
+    assert!(tree.find_in_box(&[5.0, 5.0], &[4.0, 6.0]).is_empty());
+    assert!(tree.find_in_box(&[f64::NAN, 0.0], &[9.0, 9.0]).is_empty());
+}
+
+#[test]
+fn find_nearest_n_excluding_self_skips_only_the_query_item() {
+    let points: Vec<Point2> = vec![[0.0, 0.0], [0.0, 0.0], [1.0, 0.0], [3.0, 0.0]];
+    let tree = KdTree::construct(points.clone()).unwrap();
+    assert_eq!(tree.find_nearest_n_excluding_self(&points[3], 3, 1), vec![(2, 2.0)]);
+    assert_eq!(
+        tree.find_nearest_n_excluding_self(&points[0], 0, 2),
+        vec![(1, 0.0), (2, 1.0)]
+    );
+    assert_eq!(tree.find_nearest_n_excluding_self(&points[3], 3, 10).len(), 3);
+}
+
+#[test]
+fn filtered_searches_only_return_matching_items() {
+    let points: Vec<Point2> = (0..50).map(|i| [i as f64, 0.0]).collect();
+    let tree = KdTree::construct(points).unwrap();
+    let odd = |p: &Point2| p[0] as i64 % 2 == 1;
+
+    let found = tree.find_nearest_n_filtered(&[10.0, 0.0], 3, odd);
+    assert!(found.iter().all(|&(p, _)| odd(p)));
+    assert_eq!(found.iter().map(|&(_, d)| d).collect::<Vec<_>>(), vec![1.0, 1.0, 3.0]);
+    assert!(tree
+        .find_nearest_n_filtered(&[10.0, 0.0], 3, |p| p[0] > 100.0)
+        .is_empty());
+
+    let mut found: Vec<_> = tree
+        .find_range_n_filtered(&[10.0, 0.0], &2.0, odd)
+        .into_iter()
+        .map(|p| p[0])
+        .collect();
+    found.sort_by(f64::total_cmp);
+    assert_eq!(found, vec![9.0, 11.0]);
+}
+
+#[test]
+fn construct_from_iter_matches_construct() {
+    let points = (0..200).map(|i| [(i % 20) as f64, (i / 20) as f64]);
+    let tree = KdTree::construct_from_iter(points.clone()).unwrap();
+    let expected = KdTree::construct(points.clone().collect::<Vec<Point2>>()).unwrap();
+    assert_eq!(tree.len(), 200);
+    assert_eq!(
+        tree.find_nearest_n_indices(&[3.2, 4.1], 5),
+        expected.find_nearest_n_indices(&[3.2, 4.1], 5)
+    );
+
+    let streamed = points.filter(|p| p[0] < 10.0);
+    let tree = KdTree::construct_from_iter_with_metric(streamed, ItemMetric, Some(100));
+    assert_eq!(tree.len(), 100);
+
+    let invalid = [[0.0, 0.0], [f64::NAN, 1.0], [2.0, f64::INFINITY]];
+    assert!(matches!(
+        KdTree::construct_from_iter(invalid),
+        Err(Error::NonFiniteInput { indices }) if indices == [1, 2]
+    ));
+}
+
+#[test]
+fn rebalance_restores_a_degraded_tree() {
+    let mut tree = KdTree::construct_with_options(Vec::<Point2>::new(), ItemMetric, KdTreeOptions { bucket_size: 1 });
+    assert_eq!(tree.depth(), 0);
+    assert!(!tree.needs_rebalance());
+
+    for i in 0..300 {
+        tree.insert([i as f64, i as f64]);
+    }
+    for i in 0..200 {
+        tree.remove(&[i as f64, i as f64]);
+    }
+    tree.rebalance();
+    assert!(!tree.needs_rebalance());
+    assert_eq!(tree.len(), 100);
+    assert!(tree.depth() <= 7);
+    assert_eq!(tree.find_nearest_n_indices(&[250.0, 250.0], 1), vec![(250, 0.0)]);
+
+    // 削除済みの要素が生きている要素より多くなれば構築し直す価値がある
+    let points: Vec<Point2> = (0..100).map(|i| [i as f64, 0.0]).collect();
+    let mut tree = KdTree::construct(points.clone()).unwrap();
+    for point in &points[..60] {
+        tree.remove(point);
+    }
+    assert!(tree.needs_rebalance());
+    tree.rebalance();
+    assert!(!tree.needs_rebalance());
+}
+
+#[test]
+fn stats_and_dump_describe_the_tree() {
+    let points: Vec<Point2> = (0..7).map(|i| [i as f64, 0.0]).collect();
+    let mut tree = KdTree::construct_with_options(points, ItemMetric, KdTreeOptions { bucket_size: 1 });
+    let stats = tree.stats();
+    assert_eq!(
+        (stats.len, stats.removed, stats.node_count, stats.leaf_count),
+        (7, 0, 7, 4)
+    );
+    assert_eq!((stats.depth, stats.balanced_depth, stats.balance_factor), (3, 3, 1.0));
+    assert!(stats.memory_bytes > 0);
+
+    tree.remove(&[0.0, 0.0]);
+    let mut dump = String::new();
+    tree.dump(&mut dump).unwrap();
+    let lines: Vec<_> = dump.lines().collect();
+    assert_eq!(lines.len(), 7);
+    assert_eq!(lines[0], "split at depth 0: #3 [3.0, 0.0]");
+    assert!(lines.contains(&"    leaf: #0 [0.0, 0.0] (removed)"));
+
+    let empty = KdTree::construct(Vec::<Point2>::new()).unwrap();
+    assert_eq!((empty.stats().depth, empty.stats().balance_factor), (0, 1.0));
+}
@@ -0,0 +1,72 @@
+use dbscan_rust_test::{
+    adjusted_rand_index, davies_bouldin_index, dbscan, noise_ratio, normalized_mutual_information, silhouette_score,
+    ClusterSummary, DbscanLabel, Point2,
+};
+
+#[test]
+fn cluster_quality_metrics() {
+    let points: Vec<Point2> = vec![
+        [0.0, 0.0],
+        [0.3, 0.0],
+        [0.0, 0.4],
+        [9.0, 9.0],
+        [9.2, 9.0],
+        [9.0, 9.5],
+        [20.0, 0.0],
+    ];
+    let labels = dbscan(&points, 1.0, 2).unwrap().labels;
+    assert_eq!(labels[6], DbscanLabel::Noise);
+
+    // ノイズを除いた 6 点について別に計算した値と比べる
+    let silhouette = silhouette_score(&points, &labels, usize::MAX).unwrap();
+    assert!((silhouette - 0.9680918418455504).abs() < 1e-12);
+    let sampled = silhouette_score(&points, &labels, 2).unwrap();
+    assert!(sampled > 0.9 && sampled <= 1.0);
+    let davies_bouldin = davies_bouldin_index(&points, &labels).unwrap();
+    assert!((davies_bouldin - 0.03731179110152339).abs() < 1e-12);
+    assert!((noise_ratio(&labels) - 1.0 / 7.0).abs() < 1e-12);
+
+    let single = vec![labels[0]; points.len()];
+    assert_eq!(silhouette_score(&points, &single, 10), None);
+    assert_eq!(davies_bouldin_index(&points, &single), None);
+    assert_eq!(noise_ratio(&[]), 0.0);
+}
+
+#[test]
+fn label_agreement_metrics() {
+    let a = [0, 0, 0, 1, 1, 1, -1];
+    let b = [0, 0, 1, 1, 2, 2, 2];
+    assert!((adjusted_rand_index(&a, &b) - 0.14035087719298245).abs() < 1e-12);
+    assert!((normalized_mutual_information(&a, &b) - 0.5120965390369828).abs() < 1e-12);
+
+    // 番号の振り方によらず、 DbscanLabel でも同じ値になる
+    let points: Vec<Point2> = vec![[0.0, 0.0], [0.3, 0.0], [9.0, 9.0], [9.2, 9.0], [20.0, 0.0]];
+    let labels = dbscan(&points, 1.0, 2).unwrap().labels;
+    let renumbered = [1, 1, 0, 0, -1];
+    let codes: Vec<i32> = labels.iter().map(|l| l.to_code()).collect();
+    assert_eq!(adjusted_rand_index(&codes, &renumbered), 1.0);
+    assert!((normalized_mutual_information(&codes, &renumbered) - 1.0).abs() < 1e-12);
+    assert_eq!(adjusted_rand_index(&labels, &labels), 1.0);
+    assert_eq!(adjusted_rand_index(&[0, 0, 0], &[1, 1, 1]), 1.0);
+    assert_eq!(normalized_mutual_information(&[0, 0, 0], &[1, 1, 1]), 1.0);
+}
+
+#[test]
+fn cluster_summaries() {
+    let points: Vec<Point2> = vec![[0.0, 0.0], [2.0, 0.0], [1.0, 1.0], [9.0, 9.0], [9.0, 10.0], [20.0, 0.0]];
+    let result = dbscan(&points, 1.5, 2).unwrap();
+    let summaries = result.summaries(&points);
+    assert_eq!(summaries, ClusterSummary::compute(&points, &result.labels));
+    assert_eq!(summaries.len(), 2);
+
+    let first = &summaries[0];
+    assert_eq!(first.cluster_id.get(), 1);
+    assert_eq!(first.count, 3);
+    assert_eq!(first.centroid, [1.0, 1.0 / 3.0]);
+    assert!((first.radius - (1.0f64 + 1.0 / 9.0).sqrt()).abs() < 1e-12);
+    assert_eq!(first.diameter, 2.0);
+    assert_eq!((first.bounds.min, first.bounds.max), ([0.0, 0.0], [2.0, 1.0]));
+
+    let second = &summaries[1];
+    assert_eq!((second.count, second.centroid, second.diameter), (2, [9.0, 9.5], 1.0));
+}
@@ -0,0 +1,669 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use dbscan_rust_test::{
+    dbscan_with_index, dbscan_with_index_kind, metric::ItemMetric, single_linkage, BorderPolicy, BruteForceIndex,
+    ClusterOrder, CoverTree, Dbscan, DbscanLabel, DbscanParams, DbscanResult, ImplicitKdTree, IndexKind, KdTree,
+    KdTreeItem, KdTreeOptions, Parallelism, SliceKdTree, SpatialIndex, VpTree,
+};
+use proptest::{prelude::*, test_runner::TestCaseError};
+
+/// 座標の値。重複する点や等距離の組を作るため、半分程度は粗い格子の上に置く。
+fn coordinate() -> impl Strategy<Value = f64> {
+    prop_oneof![-50.0..50.0, (-4i32..=4).prop_map(|c| c as f64 * 0.5)]
+}
+
+fn point<const N: usize>() -> impl Strategy<Value = [f64; N]> {
+    prop::array::uniform(coordinate())
+}
+
+fn points<const N: usize>() -> impl Strategy<Value = Vec<[f64; N]>> {
+    prop::collection::vec(point::<N>(), 0..120)
+}
+
+/// 点とその並べ替え。 permutation[j] は並べ替えた後の j 番目の点の元の位置を表す。
+fn permuted_points<const N: usize>() -> impl Strategy<Value = (Vec<[f64; N]>, Vec<usize>)> {
+    points::<N>().prop_flat_map(|items| {
+        let permutation = Just((0..items.len()).collect::<Vec<_>>()).prop_shuffle();
+        (Just(items), permutation)
+    })
+}
+
+/// query からの距離が radius にごく近い点があるか。 KdTree は 2 乗した距離で比べるため、境界上の点は丸め誤差で結果が変わりうる。
+fn is_ambiguous<const N: usize>(items: &[[f64; N]], query: &[f64; N], radius: f64) -> bool {
+    items
+        .iter()
+        .any(|item| (item.distance(query) - radius).abs() <= 1e-9 * radius.max(1.0))
+}
+
+fn check_nearest_n<const N: usize>(
+    items: &[[f64; N]],
+    query: &[f64; N],
+    k: usize,
+    bucket_size: usize,
+) -> Result<(), TestCaseError> {
+    let tree = KdTree::construct_with_options(items.to_vec(), ItemMetric, KdTreeOptions { bucket_size });
+    let expected = BruteForceIndex::new(items.to_vec()).nearest_n(query, k);
+    let expected_distances: Vec<_> = expected.iter().map(|&(_, d)| d).collect();
+
+    // 等距離の点はどれが選ばれてもよいため、距離の列だけを比べる
+    let found = tree.find_nearest_n_indices(query, k);
+    prop_assert_eq!(
+        found.iter().map(|&(_, d)| d).collect::<Vec<_>>(),
+        expected_distances.clone()
+    );
+    for &(index, distance) in &found {
+        prop_assert_eq!(items[index].distance(query), distance);
+    }
+    let mut indices: Vec<_> = found.iter().map(|&(i, _)| i).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    prop_assert_eq!(indices.len(), found.len());
+
+    let found = tree.find_nearest_n(query, k).expect("query must be finite");
+    prop_assert_eq!(
+        found.iter().map(|item| item.distance(query)).collect::<Vec<_>>(),
+        expected_distances.clone()
+    );
+
+    let slice_tree = SliceKdTree::construct_with_options(items, ItemMetric, KdTreeOptions { bucket_size });
+    let implicit_tree = ImplicitKdTree::construct_with_options(items, ItemMetric, KdTreeOptions { bucket_size });
+    let vp_tree = VpTree::construct(items.to_vec(), |lhs: &[f64; N], rhs: &[f64; N]| lhs.distance(rhs));
+    for found in [
+        slice_tree.nearest_n(query, k),
+        implicit_tree.nearest_n(query, k),
+        vp_tree.nearest_n(query, k),
+        CoverTree::construct(items.to_vec()).unwrap().nearest_n(query, k),
+    ] {
+        prop_assert_eq!(
+            found.iter().map(|&(_, d)| d).collect::<Vec<_>>(),
+            expected_distances.clone()
+        );
+        for &(index, distance) in &found {
+            prop_assert_eq!(items[index].distance(query), distance);
+        }
+    }
+
+    // 先頭の要素を query として、それ自身を除いた近傍を求める
+    if let Some(first) = items.first() {
+        let others = BruteForceIndex::new(items[1..].to_vec()).nearest_n(first, k);
+        let found = tree.find_nearest_n_excluding_self(first, 0, k);
+        prop_assert!(found.iter().all(|&(i, _)| i != 0));
+        prop_assert_eq!(
+            found.iter().map(|&(_, d)| d).collect::<Vec<_>>(),
+            others.iter().map(|&(_, d)| d).collect::<Vec<_>>()
+        );
+    }
+    Ok(())
+}
+
+fn check_nearest_approx<const N: usize>(
+    items: &[[f64; N]],
+    query: &[f64; N],
+    k: usize,
+    epsilon: f64,
+    bucket_size: usize,
+) -> Result<(), TestCaseError> {
+    let tree = KdTree::construct_with_options(items.to_vec(), ItemMetric, KdTreeOptions { bucket_size });
+    let expected: Vec<_> = tree.find_nearest_n_indices(query, k).iter().map(|&(_, d)| d).collect();
+    let distances = |found: &[(&[f64; N], f64)]| found.iter().map(|&(_, d)| d).collect::<Vec<_>>();
+
+    // epsilon が 0 で訪問数を制限しなければ厳密な探索と同じ距離の列になる
+    let exact = tree.find_nearest_approx(query, k, 0.0, None);
+    prop_assert_eq!(distances(&exact), expected.clone());
+
+    // 返される i 番目の距離は、真の i 番目の距離の (1 + epsilon) 倍以内に収まる
+    let approx = tree.find_nearest_approx(query, k, epsilon, None);
+    prop_assert_eq!(approx.len(), expected.len());
+    for (&(item, distance), &bound) in approx.iter().zip(&expected) {
+        prop_assert_eq!(item.distance(query), distance);
+        prop_assert!(
+            distance <= bound * (1.0 + epsilon),
+            "{} > (1 + {}) * {}",
+            distance,
+            epsilon,
+            bound
+        );
+    }
+
+    // 訪問数を制限すると、訪問したノードの要素からしか選ばれない
+    for max_visits in [0, 1, 3] {
+        let limited = tree.find_nearest_approx(query, k, epsilon, Some(max_visits));
+        prop_assert!(limited.len() <= (max_visits * bucket_size).min(k));
+        for (&(_, distance), &bound) in limited.iter().zip(&expected) {
+            prop_assert!(distance >= bound);
+        }
+    }
+    Ok(())
+}
+
+fn check_range_n<const N: usize>(
+    items: &[[f64; N]],
+    query: &[f64; N],
+    radius: f64,
+    bucket_size: usize,
+) -> Result<(), TestCaseError> {
+    prop_assume!(!is_ambiguous(items, query, radius));
+
+    let tree = KdTree::construct_with_options(items.to_vec(), ItemMetric, KdTreeOptions { bucket_size });
+    let mut expected = BruteForceIndex::new(items.to_vec()).range(query, &radius);
+    expected.sort_unstable();
+
+    let mut found = tree.find_range_n_indices(query, &radius);
+    found.sort_unstable();
+    prop_assert_eq!(&found, &expected);
+    prop_assert_eq!(tree.count_within(query, &radius), expected.len());
+    prop_assert_eq!(tree.any_within(query, &radius), !expected.is_empty());
+    for limit in [0, 1, 3] {
+        prop_assert_eq!(
+            tree.count_within_limited(query, &radius, limit),
+            expected.len().min(limit)
+        );
+    }
+
+    let slice_tree = SliceKdTree::construct_with_options(items, ItemMetric, KdTreeOptions { bucket_size });
+    let implicit_tree = ImplicitKdTree::construct_with_options(items, ItemMetric, KdTreeOptions { bucket_size });
+    for mut found in [slice_tree.range(query, &radius), implicit_tree.range(query, &radius)] {
+        found.sort_unstable();
+        prop_assert_eq!(&found, &expected);
+    }
+    let mut found = slice_tree.range_indices_with_distances(query, &radius);
+    found.sort_unstable_by_key(|&(i, _)| i);
+    prop_assert_eq!(found.iter().map(|&(i, _)| i).collect::<Vec<_>>(), expected.clone());
+    for &(index, distance) in &found {
+        prop_assert_eq!(items[index].distance(query), distance);
+    }
+
+    let vp_tree = VpTree::construct(items.to_vec(), |lhs: &[f64; N], rhs: &[f64; N]| lhs.distance(rhs));
+    let cover_tree = CoverTree::construct(items.to_vec()).unwrap();
+    for mut found in [vp_tree.range(query, &radius), cover_tree.range(query, &radius)] {
+        found.sort_unstable();
+        prop_assert_eq!(&found, &expected);
+    }
+
+    let brute_force = BruteForceIndex::new(items.to_vec());
+    for limit in [0, 1, 3] {
+        let counts = [
+            slice_tree.count_within_limited(query, &radius, limit),
+            implicit_tree.count_within_limited(query, &radius, limit),
+            brute_force.count_within_limited(query, &radius, limit),
+            vp_tree.count_within_limited(query, &radius, limit),
+            cover_tree.count_within_limited(query, &radius, limit),
+        ];
+        prop_assert_eq!(counts, [expected.len().min(limit); 5]);
+    }
+
+    let mut found: Vec<_> = tree
+        .find_range_n(query, &radius)
+        .expect("query must be finite")
+        .into_iter()
+        .copied()
+        .collect();
+    let mut expected: Vec<_> = expected.iter().map(|&i| items[i]).collect();
+    found.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).expect("not total order"));
+    expected.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).expect("not total order"));
+    prop_assert_eq!(found, expected);
+    Ok(())
+}
+
+fn check_in_box<const N: usize>(
+    items: &[[f64; N]],
+    corner: &[f64; N],
+    other_corner: &[f64; N],
+    bucket_size: usize,
+) -> Result<(), TestCaseError> {
+    let min_corner: [f64; N] = std::array::from_fn(|i| corner[i].min(other_corner[i]));
+    let max_corner: [f64; N] = std::array::from_fn(|i| corner[i].max(other_corner[i]));
+    let expected: Vec<_> = (0..items.len())
+        .filter(|&i| (0..N).all(|a| min_corner[a] <= items[i][a] && items[i][a] <= max_corner[a]))
+        .collect();
+
+    let tree = KdTree::construct_with_options(items.to_vec(), ItemMetric, KdTreeOptions { bucket_size });
+    let mut found = tree.find_in_box_indices(&min_corner, &max_corner);
+    found.sort_unstable();
+    prop_assert_eq!(&found, &expected);
+    prop_assert_eq!(tree.find_in_box(&min_corner, &max_corner).len(), expected.len());
+    Ok(())
+}
+
+fn check_iter_in_order<const N: usize>(
+    items: &[[f64; N]],
+    inserted: &[[f64; N]],
+    remove_count: usize,
+    bucket_size: usize,
+) -> Result<(), TestCaseError> {
+    let sort = |mut items: Vec<[f64; N]>| {
+        items.sort_by(|a, b| a.partial_cmp(b).expect("must be finite"));
+        items
+    };
+
+    for bucket_size in [1, bucket_size] {
+        let mut tree = KdTree::construct_with_options(items.to_vec(), ItemMetric, KdTreeOptions { bucket_size });
+        let mut expected: Vec<_> = items.iter().chain(inserted).copied().collect();
+        for item in inserted {
+            tree.insert(*item);
+        }
+        for item in items.iter().take(remove_count) {
+            prop_assert!(tree.remove(item).is_some());
+            let position = expected.iter().position(|e| e == item).expect("must be present");
+            expected.swap_remove(position);
+        }
+
+        // 削除されていない要素がちょうど 1 回ずつ現れる
+        let in_order: Vec<&[f64; N]> = tree.iter_in_order().collect();
+        prop_assert_eq!(in_order.len(), tree.len());
+        prop_assert_eq!(sort(in_order.iter().map(|&&item| item).collect()), sort(expected));
+        if bucket_size > 1 {
+            continue;
+        }
+
+        // 根の分割軸で根より後ろにある要素が現れた後は、根より手前にある要素は現れない。根が削除されていても同じ
+        if let Some(root) = tree.root() {
+            let orders: Vec<_> = in_order.iter().map(|item| item.cmp_in_depth(root, 0)).collect();
+            let first_greater = orders.iter().position(|o| o.is_gt()).unwrap_or(orders.len());
+            prop_assert!(orders[first_greater..].iter().all(|o| o.is_ge()));
+        }
+
+        // 1 次元であればどの深さでも同じ軸で分割されるため、全体が整列する
+        if N == 1 {
+            prop_assert!(in_order.windows(2).all(|w| w[0].cmp_in_depth(w[1], 0).is_le()));
+        }
+    }
+    Ok(())
+}
+
+/// 総当たりで求めた近傍から DBSCAN の結果が満たすべき性質を確かめる。
+/// コア点の連結成分とノイズは一意に決まり、ボーダー点は近傍のいずれかのコア点と同じクラスターに属する。
+fn check_dbscan<const N: usize>(
+    items: &[[f64; N]],
+    epsilon: f64,
+    min_points: usize,
+    border_policy: BorderPolicy,
+    labels: &[DbscanLabel],
+) -> Result<(), TestCaseError> {
+    let index = BruteForceIndex::new(items.to_vec());
+    let neighbors: Vec<_> = items.iter().map(|item| index.range(item, &epsilon)).collect();
+    let cores: Vec<_> = neighbors.iter().map(|n| n.len() >= min_points).collect();
+    prop_assert_eq!(labels.len(), items.len());
+
+    // コア点を幅優先探索で連結成分に分ける
+    let mut components = vec![None; items.len()];
+    let mut component_count = 0;
+    for start in (0..items.len()).filter(|&i| cores[i]) {
+        if components[start].is_some() {
+            continue;
+        }
+        components[start] = Some(component_count);
+        let mut queue = VecDeque::from([start]);
+        while let Some(i) = queue.pop_front() {
+            for &j in neighbors[i].iter().filter(|&&j| cores[j]) {
+                if components[j].is_none() {
+                    components[j] = Some(component_count);
+                    queue.push_back(j);
+                }
+            }
+        }
+        component_count += 1;
+    }
+
+    // コア点のラベルと連結成分が 1 対 1 に対応する
+    let mut component_labels = BTreeMap::new();
+    let mut label_components = BTreeMap::new();
+    for i in (0..items.len()).filter(|&i| cores[i]) {
+        let component = components[i].expect("core must have component");
+        prop_assert_ne!(labels[i], DbscanLabel::Noise, "core {} is noise", i);
+        prop_assert_eq!(*component_labels.entry(component).or_insert(labels[i]), labels[i]);
+        prop_assert_eq!(*label_components.entry(labels[i]).or_insert(component), component);
+    }
+
+    for i in (0..items.len()).filter(|&i| !cores[i]) {
+        let core_labels: Vec<_> = neighbors[i].iter().filter(|&&j| cores[j]).map(|&j| labels[j]).collect();
+        if core_labels.is_empty() || border_policy == BorderPolicy::Noise {
+            prop_assert_eq!(labels[i], DbscanLabel::Noise, "point {} must be noise", i);
+        } else {
+            prop_assert!(
+                core_labels.contains(&labels[i]),
+                "border {} has label {:?}",
+                i,
+                labels[i]
+            );
+        }
+    }
+    Ok(())
+}
+
+/// BorderPolicy::FirstWins のボーダー点が、自身を近傍に含むコア点のうち添字の最も小さいもののクラスターに属することを確かめる。
+fn check_first_wins<const N: usize>(
+    items: &[[f64; N]],
+    epsilon: f64,
+    min_points: usize,
+    labels: &[DbscanLabel],
+) -> Result<(), TestCaseError> {
+    let index = BruteForceIndex::new(items.to_vec());
+    let neighbors: Vec<_> = items.iter().map(|item| index.range(item, &epsilon)).collect();
+    let cores: Vec<_> = neighbors.iter().map(|n| n.len() >= min_points).collect();
+    for i in (0..items.len()).filter(|&i| !cores[i]) {
+        if let Some(&core) = neighbors[i].iter().filter(|&&j| cores[j]).min() {
+            prop_assert_eq!(
+                labels[i],
+                labels[core],
+                "border {} is not in the cluster of core {}",
+                i,
+                core
+            );
+        }
+    }
+    Ok(())
+}
+
+/// labels を最初に現れた順にクラスター番号を振り直した列にする。並べ替えや番号の振り方によらず比べられる。
+fn canonical_labels(labels: &[DbscanLabel]) -> Vec<Option<usize>> {
+    let mut ids = BTreeMap::new();
+    labels
+        .iter()
+        .map(|&label| {
+            let next = ids.len();
+            (label != DbscanLabel::Noise).then(|| *ids.entry(label).or_insert(next))
+        })
+        .collect()
+}
+
+fn check_dbscan_index_kinds<const N: usize>(
+    items: &[[f64; N]],
+    epsilon: f64,
+    min_points: usize,
+) -> Result<(), TestCaseError> {
+    prop_assume!(items.iter().all(|item| !is_ambiguous(items, item, epsilon)));
+
+    let result = dbscan_with_index(items, &BruteForceIndex::new(items.to_vec()), epsilon, min_points);
+    check_result(&result)?;
+    check_dbscan(items, epsilon, min_points, BorderPolicy::FirstWins, &result.labels)?;
+
+    for kind in [
+        IndexKind::Auto,
+        IndexKind::KdTree,
+        IndexKind::ImplicitKdTree,
+        IndexKind::Grid,
+        IndexKind::BallTree,
+        IndexKind::CoverTree,
+    ] {
+        let result = dbscan_with_index_kind(items, epsilon, min_points, kind);
+        check_result(&result)?;
+        check_dbscan(items, epsilon, min_points, BorderPolicy::FirstWins, &result.labels)?;
+        check_first_wins(items, epsilon, min_points, &result.labels)?;
+    }
+    Ok(())
+}
+
+fn check_dbscan_permutation<const N: usize>(
+    items: &[[f64; N]],
+    permutation: &[usize],
+    epsilon: f64,
+    min_points: usize,
+) -> Result<(), TestCaseError> {
+    prop_assume!(items.iter().all(|item| !is_ambiguous(items, item, epsilon)));
+
+    let permuted: Vec<_> = permutation.iter().map(|&i| items[i]).collect();
+    for border_policy in [BorderPolicy::FirstWins, BorderPolicy::NearestCore, BorderPolicy::Noise] {
+        let dbscan = Dbscan::new(DbscanParams::new(epsilon, min_points).border_policy(border_policy));
+        let result = dbscan.run(items);
+        let permuted_result = dbscan.run(&permuted);
+        check_result(&permuted_result)?;
+
+        let mut restored = vec![DbscanLabel::Noise; items.len()];
+        for (j, &i) in permutation.iter().enumerate() {
+            restored[i] = permuted_result.labels[j];
+        }
+        check_dbscan(items, epsilon, min_points, border_policy, &restored)?;
+        prop_assert_eq!(result.cluster_count, permuted_result.cluster_count);
+
+        // ボーダー点を持たなければラベルは並べ替えによらず一意に決まる
+        if border_policy == BorderPolicy::Noise {
+            prop_assert_eq!(canonical_labels(&restored), canonical_labels(&result.labels));
+        }
+    }
+
+    // ClusterOrder は分割を変えず、番号だけを入力の順序によらない規則で振る
+    let restore = |labels: &[DbscanLabel]| {
+        let mut restored = vec![DbscanLabel::Noise; labels.len()];
+        for (j, &i) in permutation.iter().enumerate() {
+            restored[i] = labels[j];
+        }
+        restored
+    };
+    let params = || DbscanParams::new(epsilon, min_points).border_policy(BorderPolicy::Noise);
+    let discovery = Dbscan::new(params()).run(items);
+    for order in [ClusterOrder::SmallestIndex, ClusterOrder::SizeDescending] {
+        let dbscan = Dbscan::new(params().cluster_order(order));
+        let result = dbscan.run(items);
+        let permuted_result = dbscan.run(&permuted);
+        check_result(&result)?;
+        check_result(&permuted_result)?;
+        let restored = restore(&permuted_result.labels);
+        prop_assert_eq!(canonical_labels(&result.labels), canonical_labels(&discovery.labels));
+        prop_assert_eq!(canonical_labels(&restored), canonical_labels(&result.labels));
+
+        match order {
+            // 最小の添字の順は最初に現れた順と同じになる
+            ClusterOrder::SmallestIndex => {
+                for labels in [&result.labels, &permuted_result.labels] {
+                    let ids: Vec<_> = labels.iter().map(|l| usize::try_from(l.to_code()).ok()).collect();
+                    prop_assert_eq!(ids, canonical_labels(labels));
+                }
+            }
+            // 要素数がすべて異なれば、並べ替える前と同じ番号になる
+            _ => {
+                let mut sizes = result.cluster_sizes.clone();
+                sizes.dedup();
+                prop_assert!(result.cluster_sizes.windows(2).all(|w| w[0] >= w[1]));
+                if sizes.len() == result.cluster_sizes.len() {
+                    prop_assert_eq!(&restored, &result.labels);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_dbscan_deduplicated<const N: usize>(
+    items: &[[f64; N]],
+    epsilon: f64,
+    min_points: usize,
+) -> Result<(), TestCaseError> {
+    prop_assume!(items.iter().all(|item| !is_ambiguous(items, item, epsilon)));
+
+    for border_policy in [BorderPolicy::FirstWins, BorderPolicy::NearestCore, BorderPolicy::Noise] {
+        let params = DbscanParams::new(epsilon, min_points).border_policy(border_policy);
+        let result = Dbscan::new(params.clone()).run_points(items);
+        let deduplicated = Dbscan::new(params.clone().deduplicate(true)).run_points(items);
+        check_result(&deduplicated)?;
+        check_dbscan(items, epsilon, min_points, border_policy, &deduplicated.labels)?;
+        prop_assert_eq!(result.cluster_count, deduplicated.cluster_count);
+        if border_policy == BorderPolicy::Noise {
+            prop_assert_eq!(canonical_labels(&deduplicated.labels), canonical_labels(&result.labels));
+        }
+
+        // 番号は元の順で振り直されるため、ボーダー点を持たなければラベルまで一致する
+        let morton = Dbscan::new(params.clone().morton_order(true)).run_points(items);
+        check_result(&morton)?;
+        check_dbscan(items, epsilon, min_points, border_policy, &morton.labels)?;
+        prop_assert_eq!(&morton.roles, &result.roles);
+        if border_policy == BorderPolicy::Noise {
+            prop_assert_eq!(&morton.labels, &result.labels);
+        }
+
+        // ボーダー点の割り当ては近傍を求める順序によらないため、並列に求めても同じ k-d tree を用いた逐次版と一致する
+        for deduplicate in [false, true] {
+            let params = params.clone().index(IndexKind::KdTree).deduplicate(deduplicate);
+            let sequential = Dbscan::new(params.clone()).run_points(items);
+            for parallelism in [
+                Parallelism::Parallel,
+                Parallelism::PrecomputedCounts,
+                Parallelism::PrecomputedNeighbors,
+            ] {
+                let dbscan = Dbscan::new(params.clone().parallelism(parallelism));
+                prop_assert_eq!(&dbscan.run_points(items), &sequential);
+                if !deduplicate {
+                    prop_assert_eq!(&dbscan.run(items), &sequential);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// DbscanResult の各フィールドが labels と食い違っていないかを確かめる。
+/// 単連結法の併合木が最小全域木の辺からなり、 epsilon での切断が min_points = 1 の DBSCAN と一致することを確かめる。
+fn check_single_linkage<const N: usize>(items: &[[f64; N]], epsilon: f64) -> Result<(), TestCaseError> {
+    prop_assume!(items.iter().all(|item| !is_ambiguous(items, item, epsilon)));
+
+    let dendrogram = single_linkage(items).expect("items must be finite");
+    let merges = dendrogram.merges();
+    prop_assert_eq!(merges.len(), items.len().saturating_sub(1));
+    prop_assert!(merges.windows(2).all(|w| w[0].distance <= w[1].distance));
+    if let Some(last) = merges.last() {
+        prop_assert_eq!(last.size, items.len());
+    }
+
+    // Prim 法で求めた最小全域木と重みの合計を比べる
+    let mut best = vec![f64::INFINITY; items.len()];
+    let mut in_tree = vec![false; items.len()];
+    let mut expected = 0.0;
+    for step in 0..items.len() {
+        let next = (0..items.len())
+            .filter(|&j| !in_tree[j])
+            .min_by(|&a, &b| best[a].total_cmp(&best[b]))
+            .expect("unvisited item must exist");
+        if step > 0 {
+            expected += best[next];
+        }
+        in_tree[next] = true;
+        for j in 0..items.len() {
+            best[j] = best[j].min(items[next].distance(&items[j]));
+        }
+    }
+    let total: f64 = merges.iter().map(|m| m.distance).sum();
+    prop_assert!(
+        (total - expected).abs() <= 1e-9 * expected.max(1.0),
+        "{} != {}",
+        total,
+        expected
+    );
+
+    let result = dendrogram.cut(&epsilon, 1);
+    check_result(&result)?;
+    let expected = dbscan_with_index(items, &BruteForceIndex::new(items.to_vec()), epsilon, 1);
+    prop_assert_eq!(canonical_labels(&result.labels), canonical_labels(&expected.labels));
+    Ok(())
+}
+
+fn check_result(result: &DbscanResult) -> Result<(), TestCaseError> {
+    prop_assert_eq!(result.cluster_members.len(), result.cluster_count);
+    prop_assert_eq!(result.cluster_sizes.len(), result.cluster_count);
+    for (c, members) in result.cluster_members.iter().enumerate() {
+        prop_assert_eq!(members.len(), result.cluster_sizes[c]);
+        for &i in members {
+            prop_assert_eq!(result.labels[i].to_code(), c as i32);
+        }
+    }
+    let clustered = result.labels.iter().filter(|&&l| l != DbscanLabel::Noise).count();
+    prop_assert_eq!(clustered, result.cluster_sizes.iter().sum::<usize>());
+    Ok(())
+}
+
+macro_rules! properties {
+    ($($name:ident: $n:literal),* $(,)?) => {$(
+        mod $name {
+            use super::*;
+
+            proptest! {
+                #[test]
+                fn find_nearest_n_matches_brute_force(
+                    items in points::<$n>(),
+                    query in point::<$n>(),
+                    k in 0usize..20,
+                    bucket_size in 1usize..16,
+                ) {
+                    check_nearest_n(&items, &query, k, bucket_size)?;
+                }
+
+                #[test]
+                fn find_nearest_approx_is_within_bound(
+                    items in points::<$n>(),
+                    query in point::<$n>(),
+                    k in 0usize..20,
+                    epsilon in 0.0..2.0,
+                    bucket_size in 1usize..16,
+                ) {
+                    check_nearest_approx(&items, &query, k, epsilon, bucket_size)?;
+                }
+
+                #[test]
+                fn find_range_n_matches_brute_force(
+                    items in points::<$n>(),
+                    query in point::<$n>(),
+                    radius in 0.0..20.0,
+                    bucket_size in 1usize..16,
+                ) {
+                    check_range_n(&items, &query, radius, bucket_size)?;
+                }
+
+                #[test]
+                fn find_in_box_matches_brute_force(
+                    items in points::<$n>(),
+                    corner in point::<$n>(),
+                    other_corner in point::<$n>(),
+                    bucket_size in 1usize..16,
+                ) {
+                    check_in_box(&items, &corner, &other_corner, bucket_size)?;
+                }
+
+                #[test]
+                fn iter_in_order_visits_each_item_once_in_split_order(
+                    items in points::<$n>(),
+                    inserted in points::<$n>(),
+                    remove_count in 0usize..60,
+                    bucket_size in 1usize..16,
+                ) {
+                    check_iter_in_order(&items, &inserted, remove_count, bucket_size)?;
+                }
+
+                #[test]
+                fn dbscan_is_consistent_across_indices(
+                    items in points::<$n>(),
+                    epsilon in 0.1..3.0,
+                    min_points in 1usize..6,
+                ) {
+                    check_dbscan_index_kinds(&items, epsilon, min_points)?;
+                }
+
+                #[test]
+                fn dbscan_is_permutation_consistent(
+                    (items, permutation) in permuted_points::<$n>(),
+                    epsilon in 0.1..3.0,
+                    min_points in 1usize..6,
+                ) {
+                    check_dbscan_permutation(&items, &permutation, epsilon, min_points)?;
+                }
+
+                #[test]
+                fn single_linkage_matches_mst_and_dbscan(
+                    items in points::<$n>(),
+                    epsilon in 0.1..3.0,
+                ) {
+                    check_single_linkage(&items, epsilon)?;
+                }
+
+                #[test]
+                fn dbscan_deduplicated_matches_plain(
+                    items in points::<$n>(),
+                    epsilon in 0.1..3.0,
+                    min_points in 1usize..6,
+                ) {
+                    check_dbscan_deduplicated(&items, epsilon, min_points)?;
+                }
+            }
+        }
+    )*};
+}
+
+properties!(d1: 1, d2: 2, d3: 3, d5: 5);
@@ -0,0 +1,45 @@
+use dbscan_rust_test::{datasets, dbscan, DbscanLabel, DbscanResult, KdTree, Point2};
+
+#[test]
+fn labels_and_results_round_trip() {
+    let points: Vec<Point2> = vec![[0.0, 0.0], [0.3, 0.0], [0.6, 0.0], [9.0, 9.0], [9.2, 9.0], [20.0, 0.0]];
+    let result = dbscan(&points, 1.0, 2).unwrap();
+    assert!(result.roles.is_some());
+    let json = serde_json::to_string(&result).unwrap();
+    assert_eq!(serde_json::from_str::<DbscanResult>(&json).unwrap(), result);
+
+    let labels = [DbscanLabel::Noise, result.labels[0], result.labels[3]];
+    let json = serde_json::to_string(&labels).unwrap();
+    assert_eq!(json, r#"["Noise",{"Cluster":1},{"Cluster":2}]"#);
+    assert_eq!(serde_json::from_str::<[DbscanLabel; 3]>(&json).unwrap(), labels);
+
+    // 以前の綴りも受け付けるが、クラスター番号 0 は受け付けない
+    assert_eq!(
+        serde_json::from_str::<DbscanLabel>(r#""Noize""#).unwrap(),
+        DbscanLabel::Noise
+    );
+    assert!(serde_json::from_str::<DbscanLabel>(r#"{"Cluster":0}"#).is_err());
+}
+
+#[test]
+fn kdtree_round_trip_keeps_searches() {
+    let dataset = datasets::blobs(&[[0.0, 0.0], [3.0, 3.0]], 0.5, 300, 47);
+    let mut tree = KdTree::construct(dataset.points.clone()).unwrap();
+    tree.remove(&dataset.points[7]).unwrap();
+    tree.insert([1.5, 1.5]);
+
+    let json = serde_json::to_string(&tree).unwrap();
+    let restored: KdTree<Point2> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.len(), tree.len());
+    for query in dataset.points.iter().step_by(20).chain([&[1.5, 1.5]]) {
+        assert_eq!(
+            restored.find_range_n_indices(query, &0.4),
+            tree.find_range_n_indices(query, &0.4)
+        );
+        assert_eq!(
+            restored.find_nearest_n_indices(query, 5),
+            tree.find_nearest_n_indices(query, 5)
+        );
+    }
+    assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+}
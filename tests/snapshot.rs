@@ -0,0 +1,51 @@
+use dbscan_rust_test::{datasets, Error, KdTree, KdTreeSnapshot, SnapshotError};
+
+#[test]
+fn snapshot_round_trip_matches_kdtree() {
+    let dataset = datasets::blobs(&[[0.0, 0.0, 0.0], [4.0, 4.0, 4.0]], 1.0, 500, 31);
+    let points: Vec<[f32; 3]> = dataset.points.iter().map(|p| p.map(|x| x as f32)).collect();
+    let mut tree = KdTree::construct(points.clone()).unwrap();
+    tree.remove(&points[10]).unwrap();
+    tree.insert([2.0, 2.0, 2.0]);
+
+    let mut bytes = Vec::new();
+    tree.write_snapshot(&mut bytes).unwrap();
+    let snapshot = KdTreeSnapshot::<3>::from_bytes(&bytes).unwrap();
+    assert_eq!(snapshot.len(), tree.len());
+    for query in points.iter().step_by(25).chain([&[2.0, 2.0, 2.0]]) {
+        let mut expected = tree.find_range_n_indices(query, &0.8);
+        let mut found = snapshot.find_range_n_indices(query, 0.8).unwrap();
+        expected.sort_unstable();
+        found.sort_unstable();
+        assert_eq!(found, expected);
+        assert!(!found.contains(&10));
+
+        let nearest = snapshot.find_nearest_n_indices(query, 5).unwrap();
+        let expected = tree.find_nearest_n_indices(query, 5);
+        let distances = |n: &[(usize, f32)]| n.iter().map(|&(_, d)| d).collect::<Vec<_>>();
+        assert_eq!(distances(&nearest), distances(&expected));
+    }
+
+    assert_eq!(
+        snapshot.find_range_n_indices(&[f32::NAN, 0.0, 0.0], 1.0),
+        Err(Error::NonFiniteQuery)
+    );
+    assert_eq!(
+        snapshot.find_range_n_indices(&[0.0; 3], f32::NAN),
+        Err(Error::InvalidRadius)
+    );
+    assert_eq!(
+        snapshot.find_nearest_n_indices(&[0.0, f32::INFINITY, 0.0], 1),
+        Err(Error::NonFiniteQuery)
+    );
+
+    // 根の分割面の座標を NaN に書き換えたバイト列は読み込みの時点で拒否する
+    let node_count = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+    let root_start = u32::from_le_bytes(bytes[40..44].try_into().unwrap()) as usize;
+    let offset = 40 + node_count * 16 + root_start * 3 * 4;
+    bytes[offset..offset + 4].copy_from_slice(&f32::NAN.to_le_bytes());
+    assert_eq!(
+        KdTreeSnapshot::<3>::from_bytes(&bytes).unwrap_err(),
+        SnapshotError::NonFiniteCoordinate { entry: root_start }
+    );
+}
@@ -0,0 +1,62 @@
+use dbscan_rust_test::{
+    dbscan, dbscan_codes, dbscan_geo, dbscan_geo_unchecked, dbscan_periodic, dbscan_rects, dbscan_rects_unchecked,
+    dbscan_weighted, dbscan_with_metric, dbscan_with_options, hdbscan, metric::Manhattan, optics, BallTree, CoverTree,
+    Dbscan, DbscanLabel, DbscanOptions, DbscanParams, Error, GeoPoint, GridIndex, KdTree, Point2, RTree, Rect,
+};
+
+#[test]
+fn non_finite_coordinates_are_rejected() {
+    let points: Vec<Point2> = vec![[0.0, 0.0], [f64::NAN, 0.0], [0.1, 0.0], [0.0, f64::INFINITY]];
+    let params = Dbscan::new(DbscanParams::new(0.5, 2));
+    let error = params.try_run(&points).unwrap_err();
+    assert_eq!(error, Error::NonFiniteInput { indices: vec![1, 3] });
+    assert_eq!(params.try_run_points(&points).unwrap_err(), error);
+
+    let result = params.try_run(&points[..1]).unwrap();
+    assert_eq!(result.labels, vec![DbscanLabel::Noise]);
+
+    assert_eq!(KdTree::construct(points.clone()).err(), Some(error.clone()));
+    assert_eq!(dbscan(&points[..1], f64::NAN, 2), Err(Error::InvalidRadius));
+    let tree = KdTree::construct(&points[..1]).unwrap();
+    assert_eq!(tree.find_nearest(&[f64::NAN, 0.0]), Err(Error::NonFiniteQuery));
+    assert_eq!(tree.find_range_n(&[0.0, 0.0], &f64::NAN), Err(Error::InvalidRadius));
+
+    // 入力を検証する関数はどれも同じエラーを返す
+    let weights = [1.0; 4];
+    assert_eq!(dbscan_weighted(&points, weights, 0.5, 2.0).unwrap_err(), error);
+    assert_eq!(dbscan_with_metric(&points, 0.5, 2, Manhattan).unwrap_err(), error);
+    assert_eq!(
+        dbscan_with_options(&points, 0.5, 2, DbscanOptions::default()).unwrap_err(),
+        error
+    );
+    assert_eq!(dbscan_codes(&points, 0.5, 2).unwrap_err(), error);
+    assert_eq!(dbscan_periodic(&points, [1.0, 1.0], 0.5, 2).unwrap_err(), error);
+    assert_eq!(hdbscan(&points, 2, 2).unwrap_err(), error);
+    assert_eq!(optics(&points, 0.5, 2).unwrap_err(), error);
+    assert_eq!(optics(&points[..1], f64::NAN, 2).unwrap_err(), Error::InvalidRadius);
+    let geo = [GeoPoint::new(35.0, 139.0), GeoPoint::new(f64::NAN, 139.0)];
+    assert_eq!(
+        dbscan_geo(geo, 100.0, 2).unwrap_err(),
+        Error::NonFiniteInput { indices: vec![1] }
+    );
+    assert_eq!(
+        dbscan_geo_unchecked(&geo[..1], 100.0, 2).labels,
+        vec![DbscanLabel::Noise]
+    );
+
+    assert_eq!(CoverTree::construct(points.clone()).err(), Some(error.clone()));
+    assert_eq!(BallTree::construct(points.clone()).err(), Some(error.clone()));
+    assert_eq!(GridIndex::new(points.clone(), 0.5).err(), Some(error.clone()));
+    assert_eq!(GridIndex::new(&points[..1], 0.0).err(), Some(Error::InvalidRadius));
+    let rects: Vec<_> = points.iter().map(|&point| Rect::point(point)).collect();
+    assert_eq!(RTree::construct(rects.clone()).err(), Some(error.clone()));
+    assert_eq!(dbscan_rects(&rects, 0.5, 2).unwrap_err(), error);
+    assert_eq!(
+        dbscan_rects_unchecked(&rects[..1], 0.5, 2).labels,
+        vec![DbscanLabel::Noise]
+    );
+
+    #[allow(deprecated)]
+    let renamed: dbscan_rust_test::DbscanError = error;
+    assert!(matches!(renamed, Error::NonFiniteInput { .. }));
+}